@@ -0,0 +1,78 @@
+use log::{error, warn};
+use tonic::metadata::MetadataMap;
+
+use crate::SETTINGS;
+
+use super::authenticator::AuthHandler;
+
+/// Reads the ids of the users with access to the administrative endpoints from Authentication.AdminUsers
+/// No user is an admin if the list is not configured, the service account is an admin if its user id is listed
+pub fn admin_users_from_config() -> Result<Vec<String>, tonic::Status> {
+    match SETTINGS
+        .read()
+        .unwrap()
+        .get::<Vec<String>>("Authentication.AdminUsers")
+    {
+        Ok(value) => return Ok(value),
+        Err(config::ConfigError::NotFound(_)) => return Ok(Vec::new()),
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(tonic::Status::failed_precondition(
+                "could not parse Authentication.AdminUsers, expected a list of user ids",
+            ));
+        }
+    }
+}
+
+/// Authorizes access to administrative endpoints, returns the user id of the requesting admin
+/// Fails with permission_denied if the requesting user is not listed in Authentication.AdminUsers
+pub async fn authorize_admin(
+    auth_handler: &dyn AuthHandler,
+    metadata: &MetadataMap,
+) -> Result<String, tonic::Status> {
+    let user_id = auth_handler.user_id(metadata).await?;
+    if !admin_users_from_config()?.contains(&user_id) {
+        warn!("user {} requested an admin endpoint", user_id);
+        return Err(tonic::Status::permission_denied(
+            "the requested action is restricted to admins",
+        ));
+    }
+
+    return Ok(user_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::metadata::MetadataMap;
+
+    use crate::{
+        auth::test_authenticator::TestAuthenticator, test_util::init::test_init, SETTINGS,
+    };
+
+    use super::authorize_admin;
+
+    #[tokio::test]
+    async fn admin_users() {
+        test_init();
+        let auth_handler = TestAuthenticator {};
+        let metadata = MetadataMap::new();
+
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Authentication.AdminUsers", vec!["admin"])
+            .unwrap();
+        let error = authorize_admin(&auth_handler, &metadata).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::PermissionDenied);
+
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Authentication.AdminUsers", vec!["admin", "testuser"])
+            .unwrap();
+        assert_eq!(
+            authorize_admin(&auth_handler, &metadata).await.unwrap(),
+            "testuser"
+        );
+    }
+}
@@ -17,6 +17,22 @@ pub trait AuthHandler: Send + Sync {
         right: Right,
         id: String,
     ) -> std::result::Result<(), tonic::Status>;
+    /// Authorize access to a set of resources at once
+    /// Implementations should resolve the associated projects in bulk and check the rights only once per project
+    async fn authorize_batch(
+        &self,
+        metadata: &MetadataMap,
+        requests: Vec<(Resource, Right, String)>,
+    ) -> std::result::Result<(), tonic::Status>;
+    /// Authorize read access to revisions that are part of a released dataset version
+    /// Read access on the dataset version implies read access on all of its revisions, only the membership of
+    /// the revisions in the version is checked
+    async fn authorize_version_revisions(
+        &self,
+        metadata: &MetadataMap,
+        version_id: String,
+        revision_ids: Vec<String>,
+    ) -> std::result::Result<(), tonic::Status>;
     /// Returns the user_id of the user based on the authentication information in the metadata map
     async fn user_id(&self, metadata: &MetadataMap) -> std::result::Result<String, tonic::Status>;
     async fn project_id_from_api_token(
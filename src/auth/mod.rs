@@ -1,5 +1,7 @@
+pub mod admin;
 pub mod authenticator;
 pub mod project_authorization_handler;
+pub mod service_account_handler;
 pub mod test_authenticator;
 
 mod oauth2_handler;
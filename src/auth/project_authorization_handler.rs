@@ -1,15 +1,16 @@
 use std::{error::Error, fmt, sync::Arc};
 
 use log::error;
-use mongodb::bson::doc;
-use std::collections::HashSet;
+use mongodb::bson::{doc, to_bson};
+use std::collections::{HashMap, HashSet};
 use tonic::metadata::MetadataMap;
 
 use crate::{
+    clock::{Clock, SystemClock},
     database::database::Database,
     models::{
         apitoken::APIToken,
-        common_models::Right,
+        common_models::{Resource, Right},
         dataset_model::DatasetEntry,
         dataset_object_group::{ObjectGroup, ObjectGroupRevision},
         dataset_version::DatasetVersion,
@@ -28,6 +29,15 @@ enum TokenType {
 
 pub const API_TOKEN_ENTRY_KEY: &str = "API_TOKEN";
 pub const USER_TOKEN_ENTRY_KEY: &str = "AccessToken";
+/// Minimum time between two updates of the last use of an api token
+/// Tokens that authorize many requests are only written to once per interval
+const LAST_USED_AT_INTERVAL_SECS: i64 = 60;
+
+/// Authenticated caller of a request
+enum Caller {
+    User(String),
+    ApiToken(APIToken),
+}
 
 type ResultWrapper<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
@@ -37,80 +47,212 @@ type ResultWrapper<T> = std::result::Result<T, Box<dyn std::error::Error + Send
 pub struct ProjectAuthzHandler<T: Database> {
     oauth2_handler: oauth2_handler::OAuth2Handler,
     database_handler: Arc<T>,
+    clock: Arc<dyn Clock>,
 }
 
-impl<T: Database> ProjectAuthzHandler<T> {
+impl<T: Database + 'static> ProjectAuthzHandler<T> {
     pub fn new(database: Arc<T>) -> ResultWrapper<ProjectAuthzHandler<T>> {
         let oauth2 = oauth2_handler::OAuth2Handler::new()?;
         Ok(ProjectAuthzHandler {
             oauth2_handler: oauth2,
             database_handler: database,
+            clock: Arc::new(SystemClock {}),
         })
     }
 
+    /// Authenticates the caller with the token in the metadata
+    /// Callers are authenticated before any resource is resolved, so that unauthenticated callers can not probe
+    /// for the existence of resources
+    async fn authenticate(&self, metadata: &MetadataMap) -> Result<Caller, tonic::Status> {
+        if metadata.contains_key(USER_TOKEN_ENTRY_KEY) {
+            let user_id = self.user_id_from_access_token(metadata).await?;
+            return Ok(Caller::User(user_id));
+        } else if metadata.contains_key(API_TOKEN_ENTRY_KEY) {
+            let db_token = self.project_id_from_api_token(metadata).await?;
+            return Ok(Caller::ApiToken(db_token));
+        }
+
+        return Err(tonic::Status::unauthenticated(format!("could not find authentication token, please provide a token in metadata either with {} or {}", USER_TOKEN_ENTRY_KEY, API_TOKEN_ENTRY_KEY)));
+    }
+
+    /// Checks that the authenticated caller has all requested rights on the resources of the project
+    async fn authorize_caller(
+        &self,
+        caller: &Caller,
+        project_id: &str,
+        requested_rights: &[(Resource, Right)],
+    ) -> Result<(), tonic::Status> {
+        match caller {
+            Caller::User(user_id) => {
+                for (resource, right) in requested_rights {
+                    self.authorize_from_user_token(project_id, user_id.as_str(), resource, right)
+                        .await?;
+                }
+            }
+            Caller::ApiToken(db_token) => {
+                check_api_token_rights(db_token, project_id, requested_rights)?
+            }
+        }
+
+        return Ok(());
+    }
+
     async fn authorize_from_user_token(
         &self,
-        id: String,
-        metadata: &MetadataMap,
-        right: crate::models::common_models::Right,
+        project_id: &str,
+        user_id: &str,
+        resource: &Resource,
+        right: &Right,
     ) -> Result<(), tonic::Status> {
         let query = doc! {
-            "id": &id,
+            "id": project_id,
         };
 
         let project: ProjectEntry = match self.database_handler.find_one_by_key(query).await {
             Ok(value) => value,
             Err(_) => {
-                return Err(tonic::Status::internal(
-                    "could not authorize requested action",
-                ));
+                return Err(permission_denied(resource, right));
             }
         };
 
-        let user_id = self.user_id(metadata).await?;
-
         for user in project.users {
             if user.user_id == user_id {
                 for user_right in user.rights {
-                    if user_right == right {
+                    if user_right == *right {
                         return Ok(());
                     }
                 }
             }
         }
 
-        return Err(tonic::Status::permission_denied(
-            "could not authorize requested action",
-        ));
+        return Err(permission_denied(resource, right));
     }
 
-    async fn authorize_from_api_token(
+    /// Updates the last_used_at timestamp of the api token
+    /// The timestamp is only written if the stored one is older than LAST_USED_AT_INTERVAL_SECS. The update runs in
+    /// the background to not delay the request, failures are only logged
+    fn record_api_token_use(&self, db_token: &APIToken) {
+        let now = self.clock.now();
+        if let Some(last_used_at) = db_token.last_used_at {
+            if now - last_used_at < chrono::Duration::seconds(LAST_USED_AT_INTERVAL_SECS) {
+                return;
+            }
+        }
+
+        let database = self.database_handler.clone();
+        let query = doc! {
+            "id": db_token.id.as_str()
+        };
+
+        tokio::spawn(async move {
+            let last_used_at = match to_bson(&now) {
+                Ok(value) => value,
+                Err(e) => {
+                    error!("{:?}", e);
+                    return;
+                }
+            };
+            let update = doc! {
+                "$set": {
+                    "last_used_at": last_used_at
+                }
+            };
+
+            if let Err(e) = database.update_field::<APIToken>(query, update).await {
+                error!("could not update last use of api token: {:?}", e);
+            }
+        });
+    }
+
+    /// Resolves the project ids of a set of resources of the same type
+    /// Returns a map from each requested resource id to its project id, ids that could not be resolved are missing
+    async fn project_ids_of_resources(
         &self,
-        metadata: &MetadataMap,
-        project_id: String,
-        requested_rights: Vec<Right>,
-    ) -> Result<(), tonic::Status> {
-        let db_token = self.project_id_from_api_token(metadata).await?;
-        if db_token.project_id != project_id {
-            return Err(tonic::Status::permission_denied(
-                "could not authorize for requested project",
-            ));
+        resource: &Resource,
+        ids: &Vec<String>,
+    ) -> Result<HashMap<String, String>, tonic::Status> {
+        let mut dataset_ids = HashMap::new();
+
+        for id_chunk in ids.chunks(1000) {
+            match resource {
+                Resource::Project => {
+                    for id in id_chunk {
+                        dataset_ids.insert(id.clone(), id.clone());
+                    }
+                }
+                Resource::Dataset => {
+                    for id in id_chunk {
+                        dataset_ids.insert(id.clone(), id.clone());
+                    }
+                }
+                Resource::DatasetVersion => {
+                    let query = doc! {"id": {"$in": id_chunk}};
+                    let versions: Vec<DatasetVersion> =
+                        self.database_handler.find_by_key(query).await?;
+                    for version in versions {
+                        dataset_ids.insert(version.id, version.dataset_id);
+                    }
+                }
+                Resource::ObjectGroup => {
+                    let query = doc! {"id": {"$in": id_chunk}};
+                    let object_groups: Vec<ObjectGroup> =
+                        self.database_handler.find_by_key(query).await?;
+                    for object_group in object_groups {
+                        dataset_ids.insert(object_group.id, object_group.dataset_id);
+                    }
+                }
+                Resource::ObjectGroupRevision => {
+                    let query = doc! {"id": {"$in": id_chunk}};
+                    let revisions: Vec<ObjectGroupRevision> =
+                        self.database_handler.find_by_key(query).await?;
+                    for revision in revisions {
+                        dataset_ids.insert(revision.id, revision.datasete_id);
+                    }
+                }
+                Resource::Object => {
+                    let query = doc! {"objects.id": {"$in": id_chunk}};
+                    let revisions: Vec<ObjectGroupRevision> =
+                        self.database_handler.find_by_key(query).await?;
+                    let requested_ids: HashSet<&String> = id_chunk.iter().collect();
+                    for revision in revisions {
+                        for object in revision.objects {
+                            if requested_ids.contains(&object.id) {
+                                dataset_ids.insert(object.id, revision.datasete_id.clone());
+                            }
+                        }
+                    }
+                }
+            }
         }
 
-        let mut rights_hash_set = HashSet::new();
-        for right in db_token.rights {
-            rights_hash_set.insert(right);
+        if *resource == Resource::Project {
+            return Ok(dataset_ids);
         }
 
-        for requested_right in requested_rights {
-            if !rights_hash_set.contains(&requested_right) {
-                return Err(tonic::Status::permission_denied(
-                    "could not authorize for request project",
-                ));
-            };
+        let unique_dataset_ids: Vec<String> = dataset_ids
+            .values()
+            .cloned()
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+
+        let mut dataset_project_ids = HashMap::new();
+        for dataset_id_chunk in unique_dataset_ids.chunks(1000) {
+            let query = doc! {"id": {"$in": dataset_id_chunk}};
+            let datasets: Vec<DatasetEntry> = self.database_handler.find_by_key(query).await?;
+            for dataset in datasets {
+                dataset_project_ids.insert(dataset.id, dataset.project_id);
+            }
         }
 
-        return Ok(());
+        let mut project_ids = HashMap::new();
+        for (id, dataset_id) in dataset_ids {
+            if let Some(project_id) = dataset_project_ids.get(&dataset_id) {
+                project_ids.insert(id, project_id.clone());
+            }
+        }
+
+        return Ok(project_ids);
     }
 
     async fn project_id_of_dataset(&self, id: String) -> Result<String, tonic::Status> {
@@ -240,7 +382,7 @@ impl<T: Database> ProjectAuthzHandler<T> {
 }
 
 #[async_trait]
-impl<T: Database> AuthHandler for ProjectAuthzHandler<T> {
+impl<T: Database + 'static> AuthHandler for ProjectAuthzHandler<T> {
     async fn authorize(
         &self,
         metadata: &tonic::metadata::MetadataMap,
@@ -248,6 +390,8 @@ impl<T: Database> AuthHandler for ProjectAuthzHandler<T> {
         right: crate::models::common_models::Right,
         id: String,
     ) -> std::result::Result<(), tonic::Status> {
+        let caller = self.authenticate(metadata).await?;
+
         let project_id_result = match resource {
             crate::models::common_models::Resource::Project => Ok(id.clone()),
             crate::models::common_models::Resource::Dataset => {
@@ -267,27 +411,122 @@ impl<T: Database> AuthHandler for ProjectAuthzHandler<T> {
             }
         };
 
+        // Resources that do not exist are denied like resources of foreign projects
         let project_id = match project_id_result {
             Ok(id) => id,
             Err(e) => {
                 log::error!("{:?}", e);
-                return Err(tonic::Status::unauthenticated(
-                    "could not authorize requested action",
-                ));
+                return Err(permission_denied(&resource, &right));
             }
         };
 
-        let requested_rights = vec![right.clone()];
+        self.authorize_caller(&caller, project_id.as_str(), &[(resource, right)])
+            .await?;
+        if let Caller::ApiToken(db_token) = &caller {
+            self.record_api_token_use(db_token);
+        }
 
-        if metadata.contains_key(USER_TOKEN_ENTRY_KEY) {
-            return self.authorize_from_user_token(id, metadata, right).await;
-        } else if metadata.contains_key(API_TOKEN_ENTRY_KEY) {
-            return self
-                .authorize_from_api_token(metadata, project_id, requested_rights)
-                .await;
+        return Ok(());
+    }
+
+    async fn authorize_batch(
+        &self,
+        metadata: &MetadataMap,
+        requests: Vec<(Resource, Right, String)>,
+    ) -> std::result::Result<(), tonic::Status> {
+        let caller = self.authenticate(metadata).await?;
+
+        let mut resource_ids: HashMap<Resource, Vec<String>> = HashMap::new();
+        for (resource, _, id) in &requests {
+            resource_ids
+                .entry(resource.clone())
+                .or_insert_with(Vec::new)
+                .push(id.clone());
         }
 
-        return Err(tonic::Status::unauthenticated(format!("could not find authentication token, please provide a token in metadata either with {} or {}", USER_TOKEN_ENTRY_KEY, API_TOKEN_ENTRY_KEY)));
+        let mut resolved_project_ids = HashMap::new();
+        for (resource, ids) in &resource_ids {
+            // Missing resources are left out of the result, errors are only returned if the database failed
+            let project_ids = match self.project_ids_of_resources(resource, ids).await {
+                Ok(value) => value,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    return Err(tonic::Status::internal(
+                        "could not resolve the projects of the requested resources",
+                    ));
+                }
+            };
+
+            resolved_project_ids.insert(resource.clone(), project_ids);
+        }
+
+        let mut project_rights: HashMap<String, HashSet<(Resource, Right)>> = HashMap::new();
+        for (resource, right, id) in requests {
+            let project_id = match resolved_project_ids
+                .get(&resource)
+                .and_then(|project_ids| project_ids.get(&id))
+            {
+                Some(value) => value.clone(),
+                None => {
+                    log::error!("could not resolve project of {:?} {}", resource, id);
+                    return Err(permission_denied(&resource, &right));
+                }
+            };
+
+            project_rights
+                .entry(project_id)
+                .or_insert_with(HashSet::new)
+                .insert((resource, right));
+        }
+
+        for (project_id, rights) in project_rights {
+            let requested_rights: Vec<(Resource, Right)> = rights.into_iter().collect();
+            self.authorize_caller(&caller, project_id.as_str(), &requested_rights)
+                .await?;
+        }
+        if let Caller::ApiToken(db_token) = &caller {
+            self.record_api_token_use(db_token);
+        }
+
+        return Ok(());
+    }
+
+    async fn authorize_version_revisions(
+        &self,
+        metadata: &MetadataMap,
+        version_id: String,
+        revision_ids: Vec<String>,
+    ) -> std::result::Result<(), tonic::Status> {
+        self.authorize(
+            metadata,
+            Resource::DatasetVersion,
+            Right::Read,
+            version_id.clone(),
+        )
+        .await?;
+
+        let unique_revision_ids: Vec<String> = revision_ids
+            .into_iter()
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+
+        for revision_id_chunk in unique_revision_ids.chunks(1000) {
+            let query = doc! {
+                "id": {"$in": revision_id_chunk},
+                "dataset_versions": version_id.as_str(),
+            };
+
+            let revisions: Vec<ObjectGroupRevision> =
+                self.database_handler.find_by_key(query).await?;
+            if revisions.len() != revision_id_chunk.len() {
+                return Err(tonic::Status::permission_denied(
+                    "requested revisions are not part of the dataset version",
+                ));
+            }
+        }
+
+        return Ok(());
     }
 
     async fn user_id(
@@ -345,6 +584,36 @@ impl<T: Database> AuthHandler for ProjectAuthzHandler<T> {
     }
 }
 
+/// Checks if the api token grants all requested rights on the given project
+fn check_api_token_rights(
+    db_token: &APIToken,
+    project_id: &str,
+    requested_rights: &[(Resource, Right)],
+) -> Result<(), tonic::Status> {
+    let mut rights_hash_set = HashSet::new();
+    for right in &db_token.rights {
+        rights_hash_set.insert(right);
+    }
+
+    for (resource, requested_right) in requested_rights {
+        if db_token.project_id != project_id || !rights_hash_set.contains(requested_right) {
+            return Err(permission_denied(resource, requested_right));
+        };
+    }
+
+    return Ok(());
+}
+
+/// Error of authenticated callers that lack the right on the resource, it names the resource type and the right
+/// Resources that do not exist are denied with the same error as resources of other projects, so that the
+/// message does not reveal whether the resource exists
+fn permission_denied(resource: &Resource, right: &Right) -> tonic::Status {
+    return tonic::Status::permission_denied(format!(
+        "could not authorize requested action, missing {:?} right on {:?}",
+        right, resource
+    ));
+}
+
 #[derive(Debug)]
 struct InvalidError {
     details: String,
@@ -370,3 +639,482 @@ impl Error for InvalidError {
         &self.details
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tonic::metadata::MetadataMap;
+
+    use crate::{
+        auth::authenticator::AuthHandler,
+        clock::{Clock, MockClock},
+        database::{
+            database::Database, memory_database::MemoryDatabase, mongo_connector::MongoHandler,
+        },
+        models::{
+            apitoken::APIToken,
+            common_models::{Resource, Right, Status},
+            dataset_model::DatasetEntry,
+            dataset_object_group::ObjectGroupRevision,
+            dataset_version::DatasetVersion,
+            project_model::ProjectEntry,
+        },
+        test_util::init::test_init,
+        SETTINGS,
+    };
+
+    use super::{ProjectAuthzHandler, API_TOKEN_ENTRY_KEY};
+
+    #[tokio::test]
+    async fn authorize_batch_revisions() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set(
+                "Authentication.OAuth2Auth.UserInfoEndpoint",
+                "http://localhost/userinfo",
+            )
+            .unwrap();
+
+        let database = Arc::new(MemoryDatabase::new());
+
+        let project = database
+            .store(ProjectEntry {
+                id: uuid::Uuid::new_v4().to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let dataset = database
+            .store(DatasetEntry {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: "testdataset".to_string(),
+                description: "".to_string(),
+                is_public: false,
+                created: chrono::Utc::now(),
+                status: Status::Available,
+                project_id: project.id.clone(),
+                labels: Vec::new(),
+                metadata: Vec::new(),
+                deleted_at: None,
+                rev_version: 0,
+            })
+            .await
+            .unwrap();
+
+        let mut revision_ids = Vec::new();
+        for _ in 0..500 {
+            let revision = database
+                .store(ObjectGroupRevision {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    datasete_id: dataset.id.clone(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            revision_ids.push(revision.id);
+        }
+
+        let api_token = database
+            .store(
+                APIToken::new(
+                    "testuser",
+                    vec![Right::Read, Right::Write],
+                    project.id.as_str(),
+                )
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(API_TOKEN_ENTRY_KEY, api_token.token.parse().unwrap());
+
+        let authz_handler = ProjectAuthzHandler::new(database.clone()).unwrap();
+
+        let requests = revision_ids
+            .into_iter()
+            .map(|id| (Resource::ObjectGroupRevision, Right::Write, id))
+            .collect();
+        authz_handler
+            .authorize_batch(&metadata, requests)
+            .await
+            .unwrap();
+
+        assert_eq!(database.lookups::<APIToken>().unwrap(), 1);
+        assert_eq!(database.lookups::<ObjectGroupRevision>().unwrap(), 1);
+        assert_eq!(database.lookups::<DatasetEntry>().unwrap(), 1);
+        assert_eq!(database.lookups::<ProjectEntry>().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn authorize_version_revisions() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set(
+                "Authentication.OAuth2Auth.UserInfoEndpoint",
+                "http://localhost/userinfo",
+            )
+            .unwrap();
+
+        let database = Arc::new(MemoryDatabase::new());
+
+        let project = database
+            .store(ProjectEntry {
+                id: uuid::Uuid::new_v4().to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let dataset = database
+            .store(DatasetEntry {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: "testdataset".to_string(),
+                description: "".to_string(),
+                is_public: false,
+                created: chrono::Utc::now(),
+                status: Status::Available,
+                project_id: project.id.clone(),
+                labels: Vec::new(),
+                metadata: Vec::new(),
+                deleted_at: None,
+                rev_version: 0,
+            })
+            .await
+            .unwrap();
+
+        let version = database
+            .store(DatasetVersion {
+                id: uuid::Uuid::new_v4().to_string(),
+                dataset_id: dataset.id.clone(),
+                description: "".to_string(),
+                labels: Vec::new(),
+                metadata: Vec::new(),
+                created: chrono::Utc::now(),
+                version: Default::default(),
+                object_group_ids: Vec::new(),
+                object_count: 0,
+                status: Status::Available,
+                deleted_at: None,
+            })
+            .await
+            .unwrap();
+
+        let released_revision = database
+            .store(ObjectGroupRevision {
+                id: uuid::Uuid::new_v4().to_string(),
+                datasete_id: dataset.id.clone(),
+                dataset_versions: vec![version.id.clone()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let unreleased_revision = database
+            .store(ObjectGroupRevision {
+                id: uuid::Uuid::new_v4().to_string(),
+                datasete_id: dataset.id.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let api_token = database
+            .store(APIToken::new("testuser", vec![Right::Read], project.id.as_str()).unwrap())
+            .await
+            .unwrap();
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(API_TOKEN_ENTRY_KEY, api_token.token.parse().unwrap());
+
+        let authz_handler = ProjectAuthzHandler::new(database.clone()).unwrap();
+
+        authz_handler
+            .authorize_version_revisions(
+                &metadata,
+                version.id.clone(),
+                vec![released_revision.id.clone()],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(database.lookups::<DatasetVersion>().unwrap(), 1);
+        assert_eq!(database.lookups::<ObjectGroupRevision>().unwrap(), 1);
+
+        let read_revision: ObjectGroupRevision = database
+            .find_one_by_key(mongodb::bson::doc! {"id": released_revision.id.as_str()})
+            .await
+            .unwrap();
+        assert_eq!(read_revision, released_revision);
+
+        let error = authz_handler
+            .authorize_version_revisions(
+                &metadata,
+                version.id.clone(),
+                vec![unreleased_revision.id.clone()],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn api_token_last_used() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set(
+                "Authentication.OAuth2Auth.UserInfoEndpoint",
+                "http://localhost/userinfo",
+            )
+            .unwrap();
+
+        let uuid = uuid::Uuid::new_v4();
+        let database = Arc::new(
+            MongoHandler::new_with_db_name(uuid.to_string())
+                .await
+                .unwrap(),
+        );
+
+        let project_id = uuid::Uuid::new_v4().to_string();
+        let api_token = database
+            .store(APIToken::new("testuser", vec![Right::Read], project_id.as_str()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(api_token.last_used_at, None);
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(API_TOKEN_ENTRY_KEY, api_token.token.parse().unwrap());
+
+        let authz_handler = ProjectAuthzHandler::new(database.clone()).unwrap();
+        authz_handler
+            .authorize(&metadata, Resource::Project, Right::Read, project_id)
+            .await
+            .unwrap();
+
+        // The last use is recorded in the background
+        let mut last_used_at = None;
+        for _ in 0..50 {
+            let read_api_token: APIToken = database
+                .find_one_by_key(mongodb::bson::doc! {"id": api_token.id.as_str()})
+                .await
+                .unwrap();
+            last_used_at = read_api_token.last_used_at;
+            if last_used_at.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        assert!(last_used_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn api_token_last_used_interval() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set(
+                "Authentication.OAuth2Auth.UserInfoEndpoint",
+                "http://localhost/userinfo",
+            )
+            .unwrap();
+
+        let database = Arc::new(MemoryDatabase::new());
+        let project_id = uuid::Uuid::new_v4().to_string();
+        let api_token = database
+            .store(APIToken::new("testuser", vec![Right::Read], project_id.as_str()).unwrap())
+            .await
+            .unwrap();
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(API_TOKEN_ENTRY_KEY, api_token.token.parse().unwrap());
+
+        let start = chrono::DateTime::parse_from_rfc3339("2021-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let clock = Arc::new(MockClock::new(start));
+        let mut authz_handler = ProjectAuthzHandler::new(database.clone()).unwrap();
+        authz_handler.clock = clock.clone();
+
+        let wait_for_last_use = |expected: chrono::DateTime<chrono::Utc>| {
+            let database = database.clone();
+            let token_id = api_token.id.clone();
+            async move {
+                for _ in 0..50 {
+                    let read_api_token: APIToken = database
+                        .find_one_by_key(mongodb::bson::doc! {"id": token_id.as_str()})
+                        .await
+                        .unwrap();
+                    if read_api_token.last_used_at == Some(expected) {
+                        return;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                }
+                panic!("last use of the api token has not been recorded");
+            }
+        };
+
+        let first_use = clock.now();
+        authz_handler
+            .authorize(
+                &metadata,
+                Resource::Project,
+                Right::Read,
+                project_id.clone(),
+            )
+            .await
+            .unwrap();
+        wait_for_last_use(first_use).await;
+        assert_eq!(database.update_matches::<APIToken>().unwrap(), 1);
+
+        // Uses within the interval are not written
+        clock.advance(chrono::Duration::seconds(10));
+        authz_handler
+            .authorize(
+                &metadata,
+                Resource::Project,
+                Right::Read,
+                project_id.clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(database.update_matches::<APIToken>().unwrap(), 1);
+
+        clock.advance(chrono::Duration::seconds(super::LAST_USED_AT_INTERVAL_SECS));
+        let second_use = clock.now();
+        authz_handler
+            .authorize(&metadata, Resource::Project, Right::Read, project_id)
+            .await
+            .unwrap();
+        wait_for_last_use(second_use).await;
+        assert_eq!(database.update_matches::<APIToken>().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn missing_and_foreign_revisions_are_indistinguishable() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set(
+                "Authentication.OAuth2Auth.UserInfoEndpoint",
+                "http://localhost/userinfo",
+            )
+            .unwrap();
+
+        let uuid = uuid::Uuid::new_v4();
+        let database = Arc::new(
+            MongoHandler::new_with_db_name(uuid.to_string())
+                .await
+                .unwrap(),
+        );
+
+        let foreign_dataset = database
+            .store(DatasetEntry {
+                id: uuid::Uuid::new_v4().to_string(),
+                name: "foreigndataset".to_string(),
+                description: "".to_string(),
+                is_public: false,
+                created: chrono::Utc::now(),
+                status: Status::Available,
+                project_id: uuid::Uuid::new_v4().to_string(),
+                labels: Vec::new(),
+                metadata: Vec::new(),
+                deleted_at: None,
+                rev_version: 0,
+            })
+            .await
+            .unwrap();
+
+        let foreign_revision = database
+            .store(ObjectGroupRevision {
+                id: uuid::Uuid::new_v4().to_string(),
+                datasete_id: foreign_dataset.id.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let project_id = uuid::Uuid::new_v4().to_string();
+        let api_token = database
+            .store(APIToken::new("testuser", vec![Right::Read], project_id.as_str()).unwrap())
+            .await
+            .unwrap();
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(API_TOKEN_ENTRY_KEY, api_token.token.parse().unwrap());
+
+        let authz_handler = ProjectAuthzHandler::new(database.clone()).unwrap();
+
+        let foreign_error = authz_handler
+            .authorize(
+                &metadata,
+                Resource::ObjectGroupRevision,
+                Right::Read,
+                foreign_revision.id.clone(),
+            )
+            .await
+            .unwrap_err();
+        let missing_error = authz_handler
+            .authorize(
+                &metadata,
+                Resource::ObjectGroupRevision,
+                Right::Read,
+                uuid::Uuid::new_v4().to_string(),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(foreign_error.code(), tonic::Code::PermissionDenied);
+        assert_eq!(missing_error.code(), foreign_error.code());
+        assert_eq!(missing_error.message(), foreign_error.message());
+    }
+
+    #[tokio::test]
+    async fn permission_denied_names_missing_right() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set(
+                "Authentication.OAuth2Auth.UserInfoEndpoint",
+                "http://localhost/userinfo",
+            )
+            .unwrap();
+
+        let uuid = uuid::Uuid::new_v4();
+        let database = Arc::new(
+            MongoHandler::new_with_db_name(uuid.to_string())
+                .await
+                .unwrap(),
+        );
+
+        let project_id = uuid::Uuid::new_v4().to_string();
+        let api_token = database
+            .store(APIToken::new("testuser", vec![Right::Read], project_id.as_str()).unwrap())
+            .await
+            .unwrap();
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(API_TOKEN_ENTRY_KEY, api_token.token.parse().unwrap());
+
+        let authz_handler = ProjectAuthzHandler::new(database.clone()).unwrap();
+        let error = authz_handler
+            .authorize(&metadata, Resource::Project, Right::Write, project_id)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.code(), tonic::Code::PermissionDenied);
+        assert!(error.message().contains("Write"));
+        assert!(error.message().contains("Project"));
+    }
+}
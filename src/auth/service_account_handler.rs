@@ -0,0 +1,254 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::{error, warn};
+use tonic::metadata::MetadataMap;
+
+use crate::{
+    models::{
+        apitoken::APIToken,
+        common_models::{Resource, Right},
+    },
+    SETTINGS,
+};
+
+use super::authenticator::AuthHandler;
+
+pub const SERVICE_KEY_ENTRY_KEY: &str = "SERVICE_KEY";
+/// User id that is returned for requests that are authenticated with the service key
+pub const SERVICE_ACCOUNT_USER_ID: &str = "service-account";
+
+/// Authorizes requests of service accounts, e.g. CI systems, with a static key from the configuration
+/// Requests with the service key in the metadata are granted the configured rights on all resources, all other
+/// requests are handed over to the wrapped handler. The key is not stored in the database.
+pub struct ServiceAccountAuthHandler {
+    service_key: String,
+    rights: Vec<Right>,
+    inner: Arc<dyn AuthHandler>,
+}
+
+impl ServiceAccountAuthHandler {
+    pub fn new(service_key: String, rights: Vec<Right>, inner: Arc<dyn AuthHandler>) -> Self {
+        return ServiceAccountAuthHandler {
+            service_key: service_key,
+            rights: rights,
+            inner: inner,
+        };
+    }
+
+    /// Wraps the handler with a service account handler if Authentication.ServiceAccount.Key is configured
+    /// The rights are read from Authentication.ServiceAccount.Rights and default to Read
+    pub fn wrap_from_config(
+        inner: Arc<dyn AuthHandler>,
+    ) -> Result<Arc<dyn AuthHandler>, tonic::Status> {
+        let settings = SETTINGS.read().unwrap();
+        let service_key = match settings.get_str("Authentication.ServiceAccount.Key") {
+            Ok(value) => value,
+            Err(_) => return Ok(inner),
+        };
+        if service_key.is_empty() {
+            return Err(tonic::Status::failed_precondition(
+                "the configured service account key must not be empty",
+            ));
+        }
+
+        let rights = match settings.get::<Vec<Right>>("Authentication.ServiceAccount.Rights") {
+            Ok(value) => value,
+            Err(config::ConfigError::NotFound(_)) => vec![Right::Read],
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::failed_precondition(
+                    "could not parse Authentication.ServiceAccount.Rights, expected a list of Read and Write",
+                ));
+            }
+        };
+
+        return Ok(Arc::new(ServiceAccountAuthHandler::new(
+            service_key,
+            rights,
+            inner,
+        )));
+    }
+
+    /// Checks if the request carries a service key, returns an error if the key does not match the configured key
+    fn is_service_account(&self, metadata: &MetadataMap) -> Result<bool, tonic::Status> {
+        let key = match metadata.get(SERVICE_KEY_ENTRY_KEY) {
+            Some(value) => value.as_bytes(),
+            None => return Ok(false),
+        };
+
+        if !keys_match(key, self.service_key.as_bytes()) {
+            warn!("request with unknown service key");
+            return Err(tonic::Status::unauthenticated("unknown service key"));
+        }
+
+        return Ok(true);
+    }
+
+    fn authorize_rights<'a>(
+        &self,
+        mut rights: impl Iterator<Item = &'a Right>,
+    ) -> Result<(), tonic::Status> {
+        if rights.all(|right| self.rights.contains(right)) {
+            return Ok(());
+        }
+
+        return Err(tonic::Status::permission_denied(
+            "could not authorize requested action",
+        ));
+    }
+}
+
+/// Compares the keys in constant time, so that the key can not be guessed from the response times
+fn keys_match(key: &[u8], expected: &[u8]) -> bool {
+    if key.len() != expected.len() {
+        return false;
+    }
+
+    return key
+        .iter()
+        .zip(expected.iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0;
+}
+
+#[async_trait]
+impl AuthHandler for ServiceAccountAuthHandler {
+    async fn authorize(
+        &self,
+        metadata: &MetadataMap,
+        resource: Resource,
+        right: Right,
+        id: String,
+    ) -> std::result::Result<(), tonic::Status> {
+        if self.is_service_account(metadata)? {
+            return self.authorize_rights(std::iter::once(&right));
+        }
+
+        return self.inner.authorize(metadata, resource, right, id).await;
+    }
+
+    async fn authorize_batch(
+        &self,
+        metadata: &MetadataMap,
+        requests: Vec<(Resource, Right, String)>,
+    ) -> std::result::Result<(), tonic::Status> {
+        if self.is_service_account(metadata)? {
+            return self.authorize_rights(requests.iter().map(|(_, right, _)| right));
+        }
+
+        return self.inner.authorize_batch(metadata, requests).await;
+    }
+
+    async fn authorize_version_revisions(
+        &self,
+        metadata: &MetadataMap,
+        version_id: String,
+        revision_ids: Vec<String>,
+    ) -> std::result::Result<(), tonic::Status> {
+        if self.is_service_account(metadata)? {
+            return self.authorize_rights(std::iter::once(&Right::Read));
+        }
+
+        return self
+            .inner
+            .authorize_version_revisions(metadata, version_id, revision_ids)
+            .await;
+    }
+
+    async fn user_id(&self, metadata: &MetadataMap) -> std::result::Result<String, tonic::Status> {
+        if self.is_service_account(metadata)? {
+            return Ok(SERVICE_ACCOUNT_USER_ID.to_string());
+        }
+
+        return self.inner.user_id(metadata).await;
+    }
+
+    async fn project_id_from_api_token(
+        &self,
+        metadata: &MetadataMap,
+    ) -> std::result::Result<APIToken, tonic::Status> {
+        if self.is_service_account(metadata)? {
+            return Err(tonic::Status::invalid_argument(
+                "service accounts are not bound to a project",
+            ));
+        }
+
+        return self.inner.project_id_from_api_token(metadata).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tonic::metadata::MetadataMap;
+
+    use crate::{
+        auth::{authenticator::AuthHandler, test_authenticator::TestAuthenticator},
+        models::common_models::{Resource, Right},
+    };
+
+    use super::{ServiceAccountAuthHandler, SERVICE_ACCOUNT_USER_ID, SERVICE_KEY_ENTRY_KEY};
+
+    #[tokio::test]
+    async fn service_account_key() {
+        let handler = ServiceAccountAuthHandler::new(
+            "test-service-key".to_string(),
+            vec![Right::Read],
+            Arc::new(TestAuthenticator {}),
+        );
+        let project_id = uuid::Uuid::new_v4().to_string();
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert(SERVICE_KEY_ENTRY_KEY, "test-service-key".parse().unwrap());
+        handler
+            .authorize(
+                &metadata,
+                Resource::Project,
+                Right::Read,
+                project_id.clone(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            handler.user_id(&metadata).await.unwrap(),
+            SERVICE_ACCOUNT_USER_ID
+        );
+
+        let error = handler
+            .authorize(
+                &metadata,
+                Resource::Project,
+                Right::Write,
+                project_id.clone(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::PermissionDenied);
+
+        let mut unknown_metadata = MetadataMap::new();
+        unknown_metadata.insert(SERVICE_KEY_ENTRY_KEY, "unknown-key".parse().unwrap());
+        let error = handler
+            .authorize(
+                &unknown_metadata,
+                Resource::Project,
+                Right::Read,
+                project_id.clone(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Unauthenticated);
+
+        // Requests without a service key are authorized by the wrapped handler
+        handler
+            .authorize(
+                &MetadataMap::new(),
+                Resource::Project,
+                Right::Write,
+                project_id,
+            )
+            .await
+            .unwrap();
+    }
+}
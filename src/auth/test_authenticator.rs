@@ -23,6 +23,23 @@ impl AuthHandler for TestAuthenticator {
         Ok(())
     }
 
+    async fn authorize_batch(
+        &self,
+        _metadata: &MetadataMap,
+        _requests: Vec<(Resource, Right, String)>,
+    ) -> std::result::Result<(), tonic::Status> {
+        Ok(())
+    }
+
+    async fn authorize_version_revisions(
+        &self,
+        _metadata: &MetadataMap,
+        _version_id: String,
+        _revision_ids: Vec<String>,
+    ) -> std::result::Result<(), tonic::Status> {
+        Ok(())
+    }
+
     async fn user_id(&self, _metadata: &MetadataMap) -> std::result::Result<String, tonic::Status> {
         Ok("testuser".to_string())
     }
@@ -0,0 +1,47 @@
+use chrono::{DateTime, Utc};
+
+#[cfg(test)]
+use std::sync::Mutex;
+
+/// Source of the current time of the handlers
+/// Time based logic reads the time from the clock instead of the system, so that it can be tested with a MockClock
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock that returns the system time
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SystemClock {}
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        return Utc::now();
+    }
+}
+
+/// Clock that only moves when it is set or advanced explicitly
+#[cfg(test)]
+pub struct MockClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        return MockClock {
+            now: Mutex::new(now),
+        };
+    }
+
+    pub fn advance(&self, duration: chrono::Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now = now.checked_add_signed(duration).unwrap();
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        return *self.now.lock().unwrap();
+    }
+}
@@ -1,19 +1,20 @@
 use async_trait::async_trait;
-use mongodb::bson::Document;
+use chrono::{DateTime, Utc};
+use mongodb::bson::{Bson, Document};
 
 use serde::{Deserialize, Serialize};
 
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::AddUserToProjectRequest;
 
 use crate::models::{
-    common_models::{DatabaseModel, Status},
-    dataset_object_group::DatasetObject,
+    common_models::{DatabaseModel, SortOrder, Status},
+    dataset_object_group::{DatasetObject, ObjectGroupRevision},
 };
 
 #[allow(dead_code)]
 pub enum ObjectGroupIDType {
     ObjectGroup,
-    ObjectGroupVersion,
+    ObjectGroupRevision,
 }
 
 trait DatabaseSearchValue<'de>: Deserialize<'de> + Serialize + Send + Sync {}
@@ -28,17 +29,36 @@ pub trait Database: Send + Sync {
         &self,
         query: Document,
     ) -> Result<Vec<T>, tonic::Status>;
+    /// Reads a page of objects that match the query in the given sort order
+    /// At most limit objects are returned
+    async fn find_page<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        sort: &SortOrder,
+        limit: i64,
+    ) -> Result<Vec<T>, tonic::Status>;
     /// Reads a single object from the database based on the query
     async fn find_one_by_key<'de, T: DatabaseModel<'de>>(
         &self,
         query: Document,
     ) -> Result<T, tonic::Status>;
+    /// Checks if an object matching the query exists without reading the whole object
+    async fn exists<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<bool, tonic::Status>;
     /// Stores an object in the underlaying database
     async fn store<'de, T: DatabaseModel<'de>>(&self, value: T) -> Result<T, tonic::Status>;
     /// Adds a user to the database
     async fn add_user(&self, request: &AddUserToProjectRequest) -> Result<(), tonic::Status>;
     /// Finds a stored object based on the id from a object revision entry
     async fn find_object(&self, id: &str) -> Result<DatasetObject, tonic::Status>;
+    /// Finds the revisions that contain an object stored under the given object storage key
+    /// Forked objects share the key of their source, a key can therefore belong to several revisions
+    async fn find_object_by_key(
+        &self,
+        key: &str,
+    ) -> Result<Vec<ObjectGroupRevision>, tonic::Status>;
     /// Updates a field based on the query and update document
     async fn update_field<'de, T: DatabaseModel<'de>>(
         &self,
@@ -51,20 +71,78 @@ pub trait Database: Send + Sync {
         query: Document,
         update: Document,
     ) -> Result<u64, tonic::Status>;
+    /// Updates the entry that matches the query, a new entry is inserted if no entry matches
+    async fn upsert<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        update: Document,
+    ) -> Result<(), tonic::Status>;
     async fn update_on_field<'de, T: DatabaseModel<'de>>(
         &self,
         query: Document,
         update: Document,
     ) -> Result<T, tonic::Status>;
-    // Updates the status of a database entry
+    // Updates the status of a database entry, now is stored as deletion time if the entry is moved to Deleting
     async fn update_status<'de, T: DatabaseModel<'de>>(
         &self,
         id: &str,
         status: Status,
+        now: DateTime<Utc>,
     ) -> Result<(), tonic::Status>;
+    /// Atomically updates the status of the entry with the given id only if its current status is from
+    /// Returns whether the status has been changed, concurrent status transitions can therefore not overwrite
+    /// each other. now is stored as deletion time if the entry is moved to Deleting.
+    async fn compare_and_set_status<'de, T: DatabaseModel<'de>>(
+        &self,
+        id: &str,
+        from: Status,
+        to: Status,
+        now: DateTime<Utc>,
+    ) -> Result<bool, tonic::Status>;
+    /// Replaces the first entry that matches the query with the given value and returns the stored value
+    /// The id of the replaced entry is preserved, the id of the value is ignored. Fails with not_found if no entry
+    /// matches the query.
+    async fn replace<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        value: T,
+    ) -> Result<T, tonic::Status>;
     // Deletes a stored database entry
     async fn delete<'de, T: DatabaseModel<'de>>(
         &self,
         query: Document,
     ) -> Result<(), tonic::Status>;
+    /// Deletes all entries that match the query in a single operation, returns the number of deleted entries
+    async fn delete_many<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<u64, tonic::Status>;
+    /// Atomically deletes the first entry that matches the query and returns it
+    /// Returns None if no entry matches, concurrent callers can therefore never claim the same entry
+    async fn find_one_and_delete<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<Option<T>, tonic::Status>;
+    /// Atomically moves the first entry that matches the query to a new parent by setting the given parent fields
+    /// Fields that depend on the parent, e.g. the revision number within an object group, are set in the same update
+    /// Returns the updated entry, None if no entry matches the query
+    async fn reparent<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        parent_fields: Document,
+    ) -> Result<Option<T>, tonic::Status>;
+    /// Runs an aggregation pipeline on the collection of the model and returns the resulting documents
+    async fn aggregate<'de, T: DatabaseModel<'de>>(
+        &self,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, tonic::Status>;
+    /// Reads the distinct values of a field of all entries that match the query
+    /// Array values are flattened like MongoDB does, each element is a separate value
+    async fn distinct<'de, T: DatabaseModel<'de>>(
+        &self,
+        field: &str,
+        query: Document,
+    ) -> Result<Vec<Bson>, tonic::Status>;
+    /// Checks if the database backend is reachable
+    async fn ping(&self) -> Result<(), tonic::Status>;
 }
@@ -0,0 +1,1199 @@
+use std::{cmp::Ordering, collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::error;
+use mongodb::bson::{doc, from_bson, to_bson, to_document, Bson, Document};
+use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::AddUserToProjectRequest;
+
+use super::database::Database;
+
+use crate::models::{
+    common_models::{
+        to_status_update, DatabaseModel, Right, SortDirection, SortOrder, Status, User,
+    },
+    dataset_object_group::{DatasetObject, ObjectGroupRevision},
+    project_model::ProjectEntry,
+};
+
+/// In-memory implementation of the database trait to exercise the handlers without a running MongoDB
+/// Entries are stored as documents per model and queried with the subset of MongoDB query and update operators
+/// that is used by the handlers: $in, $ne, $gt, $lt, $exists, $size, $regex, $or for queries and $set, $setOnInsert, $inc,
+/// $addToSet, $push, $pull for updates including the positional $ operator. Aggregations support $match stages and
+/// $group stages with $sum accumulators. Unsupported operators return unimplemented.
+#[derive(Default)]
+pub struct MemoryDatabase {
+    collections: Mutex<HashMap<String, Vec<Document>>>,
+    /// Number of documents per model that have been matched by the query of an update
+    update_matches: Mutex<HashMap<String, usize>>,
+    /// Number of queries per model that have read documents
+    lookups: Mutex<HashMap<String, usize>>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        return MemoryDatabase::default();
+    }
+
+    /// Returns the number of stored entries of a model
+    pub fn count<'de, T: DatabaseModel<'de>>(&self) -> Result<usize, tonic::Status> {
+        let collections = self.collections.lock().unwrap();
+        return Ok(collections
+            .get(&T::get_model_name()?)
+            .map(|documents| documents.len())
+            .unwrap_or(0));
+    }
+
+    /// Returns the number of entries of a model that have been matched by update queries so far
+    /// Entries are counted even if the update did not change them, this shows how many entries an update touched
+    pub fn update_matches<'de, T: DatabaseModel<'de>>(&self) -> Result<usize, tonic::Status> {
+        let update_matches = self.update_matches.lock().unwrap();
+        return Ok(*update_matches.get(&T::get_model_name()?).unwrap_or(&0));
+    }
+
+    /// Returns the number of queries that have read entries of a model so far
+    /// Every lookup is counted once independent of the number of matched entries
+    pub fn lookups<'de, T: DatabaseModel<'de>>(&self) -> Result<usize, tonic::Status> {
+        let lookups = self.lookups.lock().unwrap();
+        return Ok(*lookups.get(&T::get_model_name()?).unwrap_or(&0));
+    }
+
+    /// Returns all documents of the model that match the query
+    fn find_documents<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: &Document,
+    ) -> Result<Vec<Document>, tonic::Status> {
+        *self
+            .lookups
+            .lock()
+            .unwrap()
+            .entry(T::get_model_name()?)
+            .or_insert(0) += 1;
+
+        let collections = self.collections.lock().unwrap();
+        let documents = match collections.get(&T::get_model_name()?) {
+            Some(value) => value,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut found_documents = Vec::new();
+        for document in documents {
+            if matches(document, query)? {
+                found_documents.push(document.clone());
+            }
+        }
+
+        return Ok(found_documents);
+    }
+
+    /// Removes the first document that matches the query and returns it
+    fn remove_document<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: &Document,
+    ) -> Result<Option<Document>, tonic::Status> {
+        let mut collections = self.collections.lock().unwrap();
+        let documents = match collections.get_mut(&T::get_model_name()?) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        let mut deleted_index = None;
+        for (index, document) in documents.iter().enumerate() {
+            if matches(document, query)? {
+                deleted_index = Some(index);
+                break;
+            }
+        }
+
+        return Ok(deleted_index.map(|index| documents.remove(index)));
+    }
+
+    /// Applies the update to the first (or every, if update_many is set) document that matches the query
+    /// Returns the documents before the update and the number of modified documents
+    fn update_documents<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: &Document,
+        update: &Document,
+        update_many: bool,
+        upsert: bool,
+    ) -> Result<(Vec<Document>, u64), tonic::Status> {
+        let mut collections = self.collections.lock().unwrap();
+        let documents = collections.entry(T::get_model_name()?).or_default();
+
+        let mut matched_documents = Vec::new();
+        let mut modified_count = 0;
+        for document in documents.iter_mut() {
+            if !matches(document, query)? {
+                continue;
+            }
+
+            let mut updated_document = document.clone();
+            apply_update(&mut updated_document, update, query, false)?;
+            if updated_document != *document {
+                modified_count += 1;
+            }
+
+            matched_documents.push(std::mem::replace(document, updated_document));
+            if !update_many {
+                break;
+            }
+        }
+
+        *self
+            .update_matches
+            .lock()
+            .unwrap()
+            .entry(T::get_model_name()?)
+            .or_insert(0) += matched_documents.len();
+
+        if matched_documents.is_empty() && upsert {
+            let mut inserted_document = Document::new();
+            for (key, value) in query {
+                if key.starts_with('$') || is_operator_document(value) {
+                    continue;
+                }
+                set_path(&mut inserted_document, &split_path(key), value.clone())?;
+            }
+            apply_update(&mut inserted_document, update, query, true)?;
+            documents.push(inserted_document);
+        }
+
+        return Ok((matched_documents, modified_count));
+    }
+}
+
+#[async_trait]
+impl Database for MemoryDatabase {
+    async fn find_by_key<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<Vec<T>, tonic::Status> {
+        return self
+            .find_documents::<T>(&query)?
+            .into_iter()
+            .map(T::new_from_document)
+            .collect();
+    }
+
+    async fn find_page<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        sort: &SortOrder,
+        limit: i64,
+    ) -> Result<Vec<T>, tonic::Status> {
+        let mut documents = self.find_documents::<T>(&query)?;
+        documents.sort_by(|a, b| {
+            let ordering = [sort.field.as_str(), "id"]
+                .iter()
+                .map(|field| {
+                    compare_bson(
+                        a.get(field).unwrap_or(&Bson::Null),
+                        b.get(field).unwrap_or(&Bson::Null),
+                    )
+                    .unwrap_or(Ordering::Equal)
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal);
+
+            match sort.direction {
+                SortDirection::Ascending => ordering,
+                SortDirection::Descending => ordering.reverse(),
+            }
+        });
+        if limit > 0 {
+            documents.truncate(limit as usize);
+        }
+
+        return documents.into_iter().map(T::new_from_document).collect();
+    }
+
+    async fn find_one_by_key<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<T, tonic::Status> {
+        match self.find_documents::<T>(&query)?.into_iter().next() {
+            Some(value) => return T::new_from_document(value),
+            None => {
+                return Err(tonic::Status::not_found(format!(
+                    "could not find requested document. type: {} with query {}",
+                    T::get_model_name()?,
+                    query.to_string()
+                )))
+            }
+        }
+    }
+
+    async fn exists<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<bool, tonic::Status> {
+        return Ok(!self.find_documents::<T>(&query)?.is_empty());
+    }
+
+    async fn store<'de, T: DatabaseModel<'de>>(&self, value: T) -> Result<T, tonic::Status> {
+        let document = match value.to_document() {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "error when converting request to document"
+                )));
+            }
+        };
+
+        let mut collections = self.collections.lock().unwrap();
+        collections
+            .entry(T::get_model_name()?)
+            .or_default()
+            .push(document.clone());
+
+        return T::new_from_document(document);
+    }
+
+    async fn add_user(&self, request: &AddUserToProjectRequest) -> Result<(), tonic::Status> {
+        let user = User {
+            user_id: request.user_id.clone(),
+            rights: vec![Right::Read, Right::Write],
+        };
+
+        let user_document = match to_document(&user) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "could not convert user object to internal representation"
+                )));
+            }
+        };
+
+        let query = doc! {
+            "id": request.project_id.clone(),
+        };
+        let update = doc! {
+            "$addToSet": {"users": user_document}
+        };
+
+        self.update_documents::<ProjectEntry>(&query, &update, false, false)?;
+
+        return Ok(());
+    }
+
+    async fn find_object_by_key(
+        &self,
+        key: &str,
+    ) -> Result<Vec<ObjectGroupRevision>, tonic::Status> {
+        let query = doc! {
+            "objects.location.key": key
+        };
+
+        return self.find_by_key::<ObjectGroupRevision>(query).await;
+    }
+
+    async fn find_object(&self, id: &str) -> Result<DatasetObject, tonic::Status> {
+        let query = doc! {
+            "objects.id": id
+        };
+
+        let document = self
+            .find_documents::<ObjectGroupRevision>(&query)?
+            .into_iter()
+            .next()
+            .ok_or(tonic::Status::internal(
+                "could not find requested dataset object",
+            ))?;
+
+        let objects = document.get_array("objects").map_err(|e| {
+            error!("{:?}", e);
+            tonic::Status::internal("could not read requested dataset object")
+        })?;
+
+        for bson_object in objects {
+            let object: DatasetObject = from_bson(bson_object.clone()).map_err(|e| {
+                error!("{:?}", e);
+                tonic::Status::internal("could not read requested dataset object")
+            })?;
+
+            if object.id == id {
+                return Ok(object);
+            }
+        }
+
+        error!("object_id {} not found in objects list of revision", id);
+        return Err(tonic::Status::internal(
+            "could not read requested dataset object",
+        ));
+    }
+
+    async fn update_field<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        update: Document,
+    ) -> Result<u64, tonic::Status> {
+        let (_, modified_count) = self.update_documents::<T>(&query, &update, false, false)?;
+        return Ok(modified_count);
+    }
+
+    async fn update_fields<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        update: Document,
+    ) -> Result<u64, tonic::Status> {
+        let (_, modified_count) = self.update_documents::<T>(&query, &update, true, false)?;
+        return Ok(modified_count);
+    }
+
+    async fn upsert<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        update: Document,
+    ) -> Result<(), tonic::Status> {
+        self.update_documents::<T>(&query, &update, false, true)?;
+        return Ok(());
+    }
+
+    async fn update_on_field<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        update: Document,
+    ) -> Result<T, tonic::Status> {
+        let (documents, _) = self.update_documents::<T>(&query, &update, false, false)?;
+
+        match documents.into_iter().next() {
+            Some(value) => return T::new_from_document(value),
+            None => {
+                return Err(tonic::Status::internal(format!(
+                    "could not find value during update"
+                )));
+            }
+        }
+    }
+
+    async fn update_status<'de, T: DatabaseModel<'de>>(
+        &self,
+        id: &str,
+        status: Status,
+        now: DateTime<Utc>,
+    ) -> Result<(), tonic::Status> {
+        let query = doc! {
+            "id": id
+        };
+        let update = to_status_update(&status, now)?;
+
+        self.update_documents::<T>(&query, &update, false, false)?;
+
+        return Ok(());
+    }
+
+    async fn replace<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        value: T,
+    ) -> Result<T, tonic::Status> {
+        let mut replacement = match value.to_document() {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "error when converting request to document"
+                )));
+            }
+        };
+
+        let mut collections = self.collections.lock().unwrap();
+        let documents = collections.entry(T::get_model_name()?).or_default();
+        for document in documents.iter_mut() {
+            if !matches(document, &query)? {
+                continue;
+            }
+
+            if let Some(id) = document.get("id") {
+                replacement.insert("id", id.clone());
+            }
+            *document = replacement.clone();
+            return T::new_from_document(replacement);
+        }
+
+        return Err(tonic::Status::not_found(format!(
+            "could not find requested document. type: {} with query {}",
+            T::get_model_name()?,
+            query.to_string()
+        )));
+    }
+
+    async fn delete<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<(), tonic::Status> {
+        self.remove_document::<T>(&query)?;
+
+        return Ok(());
+    }
+
+    async fn delete_many<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<u64, tonic::Status> {
+        let mut collections = self.collections.lock().unwrap();
+        let documents = match collections.get_mut(&T::get_model_name()?) {
+            Some(value) => value,
+            None => return Ok(0),
+        };
+
+        // The query is evaluated for all documents first, so that an invalid query does not delete anything
+        let mut is_deleted = Vec::with_capacity(documents.len());
+        for document in documents.iter() {
+            is_deleted.push(matches(document, &query)?);
+        }
+        let mut deleted = is_deleted.iter();
+        documents.retain(|_| !deleted.next().unwrap());
+
+        return Ok(is_deleted.iter().filter(|value| **value).count() as u64);
+    }
+
+    async fn compare_and_set_status<'de, T: DatabaseModel<'de>>(
+        &self,
+        id: &str,
+        from: Status,
+        to: Status,
+        now: DateTime<Utc>,
+    ) -> Result<bool, tonic::Status> {
+        let from_value = match to_bson(&from) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal("error on status update"));
+            }
+        };
+        let query = doc! {
+            "id": id,
+            "status": from_value
+        };
+        let update = to_status_update(&to, now)?;
+
+        let (matched_documents, _) = self.update_documents::<T>(&query, &update, false, false)?;
+
+        return Ok(!matched_documents.is_empty());
+    }
+
+    async fn find_one_and_delete<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<Option<T>, tonic::Status> {
+        return match self.remove_document::<T>(&query)? {
+            Some(document) => Ok(Some(T::new_from_document(document)?)),
+            None => Ok(None),
+        };
+    }
+
+    async fn reparent<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        parent_fields: Document,
+    ) -> Result<Option<T>, tonic::Status> {
+        let update = doc! {
+            "$set": parent_fields
+        };
+
+        let (documents, _) = self.update_documents::<T>(&query, &update, false, false)?;
+
+        // The updated document might not match the query anymore, the update is applied to the returned document
+        match documents.into_iter().next() {
+            Some(mut document) => {
+                apply_update(&mut document, &update, &query, false)?;
+                return Ok(Some(T::new_from_document(document)?));
+            }
+            None => return Ok(None),
+        }
+    }
+
+    async fn aggregate<'de, T: DatabaseModel<'de>>(
+        &self,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, tonic::Status> {
+        let mut documents = self.find_documents::<T>(&doc! {})?;
+        for stage in &pipeline {
+            documents = apply_stage(documents, stage)?;
+        }
+
+        return Ok(documents);
+    }
+
+    async fn distinct<'de, T: DatabaseModel<'de>>(
+        &self,
+        field: &str,
+        query: Document,
+    ) -> Result<Vec<Bson>, tonic::Status> {
+        let documents = self.find_documents::<T>(&query)?;
+        let path = split_path(field);
+
+        let mut distinct_values: Vec<Bson> = Vec::new();
+        for document in &documents {
+            let mut values = Vec::new();
+            resolve_values(document, &path, &mut values);
+
+            for value in values {
+                let elements = match value {
+                    Bson::Array(array) => array.iter().collect(),
+                    _ => vec![value],
+                };
+                for element in elements {
+                    if !distinct_values
+                        .iter()
+                        .any(|distinct_value| bson_equal(distinct_value, element))
+                    {
+                        distinct_values.push(element.clone());
+                    }
+                }
+            }
+        }
+
+        return Ok(distinct_values);
+    }
+
+    async fn ping(&self) -> Result<(), tonic::Status> {
+        return Ok(());
+    }
+}
+
+fn split_path(path: &str) -> Vec<String> {
+    return path.split('.').map(|part| part.to_string()).collect();
+}
+
+fn is_operator_document(value: &Bson) -> bool {
+    match value {
+        Bson::Document(document) => document.keys().any(|key| key.starts_with('$')),
+        _ => false,
+    }
+}
+
+/// Collects all values at the dotted path, arrays of documents along the path are traversed like MongoDB does
+fn resolve_values<'a>(document: &'a Document, path: &[String], values: &mut Vec<&'a Bson>) {
+    let (first, rest) = match path.split_first() {
+        Some(value) => value,
+        None => return,
+    };
+
+    match document.get(first) {
+        Some(value) => resolve_bson_values(value, rest, values),
+        None => (),
+    }
+}
+
+fn resolve_bson_values<'a>(value: &'a Bson, path: &[String], values: &mut Vec<&'a Bson>) {
+    if path.is_empty() {
+        values.push(value);
+        return;
+    }
+
+    match value {
+        Bson::Document(document) => resolve_values(document, path, values),
+        Bson::Array(array) => {
+            for element in array {
+                if let Bson::Document(document) = element {
+                    resolve_values(document, path, values);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+fn as_number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(value) => Some(*value as f64),
+        Bson::Int64(value) => Some(*value as f64),
+        Bson::Double(value) => Some(*value),
+        _ => None,
+    }
+}
+
+/// Compares two values, numbers are compared by value independent of their type
+fn bson_equal(a: &Bson, b: &Bson) -> bool {
+    match (as_number(a), as_number(b)) {
+        (Some(a), Some(b)) => return a == b,
+        _ => return a == b,
+    }
+}
+
+fn compare_bson(a: &Bson, b: &Bson) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (as_number(a), as_number(b)) {
+        return a.partial_cmp(&b);
+    }
+
+    match (a, b) {
+        (Bson::String(a), Bson::String(b)) => return Some(a.cmp(b)),
+        (Bson::DateTime(a), Bson::DateTime(b)) => {
+            return Some(a.timestamp_millis().cmp(&b.timestamp_millis()))
+        }
+        (Bson::Null, Bson::Null) => return Some(Ordering::Equal),
+        (Bson::Null, _) => return Some(Ordering::Less),
+        (_, Bson::Null) => return Some(Ordering::Greater),
+        _ => return None,
+    }
+}
+
+/// Checks if the value or, for arrays, one of its elements equals the expected value
+fn value_matches(value: &Bson, expected: &Bson) -> bool {
+    if bson_equal(value, expected) {
+        return true;
+    }
+
+    match value {
+        Bson::Array(array) => array.iter().any(|element| bson_equal(element, expected)),
+        _ => false,
+    }
+}
+
+/// Checks if the document matches the query
+fn matches(document: &Document, query: &Document) -> Result<bool, tonic::Status> {
+    for (key, condition) in query {
+        let is_match = match key.as_str() {
+            "$or" => {
+                let conditions = match condition {
+                    Bson::Array(value) => value,
+                    _ => return Err(tonic::Status::invalid_argument("$or requires an array")),
+                };
+
+                let mut any_match = false;
+                for condition in conditions {
+                    if let Bson::Document(condition) = condition {
+                        if matches(document, condition)? {
+                            any_match = true;
+                            break;
+                        }
+                    }
+                }
+                any_match
+            }
+            _ if key.starts_with('$') => {
+                return Err(tonic::Status::unimplemented(format!(
+                    "query operator {} is not supported",
+                    key
+                )))
+            }
+            _ => field_matches(document, key, condition)?,
+        };
+
+        if !is_match {
+            return Ok(false);
+        }
+    }
+
+    return Ok(true);
+}
+
+fn field_matches(document: &Document, path: &str, condition: &Bson) -> Result<bool, tonic::Status> {
+    let mut values = Vec::new();
+    resolve_values(document, &split_path(path), &mut values);
+
+    let operators = match condition {
+        Bson::Document(operators) if is_operator_document(condition) => operators,
+        _ => return Ok(values.iter().any(|value| value_matches(value, condition))),
+    };
+
+    for (operator, operand) in operators {
+        let is_match = match operator.as_str() {
+            "$in" => {
+                let expected_values = match operand {
+                    Bson::Array(value) => value,
+                    _ => return Err(tonic::Status::invalid_argument("$in requires an array")),
+                };
+                values.iter().any(|value| {
+                    expected_values
+                        .iter()
+                        .any(|expected| value_matches(value, expected))
+                })
+            }
+            // A missing field equals null, {"$ne": null} therefore only matches fields that are set
+            "$ne" => {
+                !values.iter().any(|value| value_matches(value, operand))
+                    && !(values.is_empty() && *operand == Bson::Null)
+            }
+            "$gt" => values
+                .iter()
+                .any(|value| compare_bson(value, operand) == Some(Ordering::Greater)),
+            "$lt" => values
+                .iter()
+                .any(|value| compare_bson(value, operand) == Some(Ordering::Less)),
+            "$exists" => match operand {
+                Bson::Boolean(exists) => values.is_empty() != *exists,
+                _ => return Err(tonic::Status::invalid_argument("$exists requires a bool")),
+            },
+            "$size" => values
+                .iter()
+                .any(|value| match (value, as_number(operand)) {
+                    (Bson::Array(array), Some(size)) => array.len() as f64 == size,
+                    _ => false,
+                }),
+            "$regex" => {
+                let pattern = match operand {
+                    Bson::String(value) => value,
+                    _ => return Err(tonic::Status::invalid_argument("$regex requires a string")),
+                };
+                let ignore_case = operators
+                    .get_str("$options")
+                    .map(|options| options.contains('i'))
+                    .unwrap_or(false);
+                values.iter().any(|value| match value {
+                    Bson::String(value) => regex_literal_matches(value, pattern, ignore_case),
+                    _ => false,
+                })
+            }
+            "$options" => true,
+            _ => {
+                return Err(tonic::Status::unimplemented(format!(
+                    "query operator {} is not supported",
+                    operator
+                )))
+            }
+        };
+
+        if !is_match {
+            return Ok(false);
+        }
+    }
+
+    return Ok(true);
+}
+
+/// Matches regular expressions that consist of an escaped literal, which is the only form the handlers create
+fn regex_literal_matches(value: &str, pattern: &str, ignore_case: bool) -> bool {
+    let mut literal = String::with_capacity(pattern.len());
+    let mut escaped = false;
+    for character in pattern.chars() {
+        if character == '\\' && !escaped {
+            escaped = true;
+            continue;
+        }
+        escaped = false;
+        literal.push(character);
+    }
+
+    if ignore_case {
+        return value.to_lowercase().contains(&literal.to_lowercase());
+    }
+
+    return value.contains(&literal);
+}
+
+/// Replaces the positional $ operator in the path with the index of the first array element that matches the query
+fn resolve_positional_path(
+    path: &str,
+    document: &Document,
+    query: &Document,
+) -> Result<Vec<String>, tonic::Status> {
+    let mut parts = split_path(path);
+    let position = match parts.iter().position(|part| part == "$") {
+        Some(value) => value,
+        None => return Ok(parts),
+    };
+
+    let array_path = parts[..position].join(".");
+    let mut array_values = Vec::new();
+    resolve_values(document, &parts[..position], &mut array_values);
+    let array = match array_values.first() {
+        Some(Bson::Array(value)) => value,
+        _ => {
+            return Err(tonic::Status::internal(format!(
+                "positional operator requires an array at {}",
+                array_path
+            )))
+        }
+    };
+
+    let prefix = format!("{}.", array_path);
+    let mut element_query = Document::new();
+    for (key, condition) in query {
+        if let Some(element_key) = key.strip_prefix(prefix.as_str()) {
+            element_query.insert(element_key, condition.clone());
+        }
+    }
+    if element_query.is_empty() {
+        return Err(tonic::Status::internal(format!(
+            "positional operator requires a query on {}",
+            array_path
+        )));
+    }
+
+    for (index, element) in array.iter().enumerate() {
+        if let Bson::Document(element) = element {
+            if matches(element, &element_query)? {
+                parts[position] = index.to_string();
+                return Ok(parts);
+            }
+        }
+    }
+
+    return Err(tonic::Status::internal(format!(
+        "no element of {} matches the query",
+        array_path
+    )));
+}
+
+/// Returns the value at the path, missing fields are created if create is set
+fn get_path_mut<'a>(
+    document: &'a mut Document,
+    path: &[String],
+    create: bool,
+) -> Option<&'a mut Bson> {
+    let (first, rest) = path.split_first()?;
+    if !document.contains_key(first) {
+        if !create {
+            return None;
+        }
+        let placeholder = match rest.is_empty() {
+            true => Bson::Null,
+            false => Bson::Document(Document::new()),
+        };
+        document.insert(first.clone(), placeholder);
+    }
+
+    return get_bson_path_mut(document.get_mut(first)?, rest, create);
+}
+
+fn get_bson_path_mut<'a>(
+    value: &'a mut Bson,
+    path: &[String],
+    create: bool,
+) -> Option<&'a mut Bson> {
+    if path.is_empty() {
+        return Some(value);
+    }
+
+    match value {
+        Bson::Document(document) => return get_path_mut(document, path, create),
+        Bson::Array(array) => {
+            let index: usize = path[0].parse().ok()?;
+            return get_bson_path_mut(array.get_mut(index)?, &path[1..], create);
+        }
+        _ => return None,
+    }
+}
+
+fn set_path(document: &mut Document, path: &[String], value: Bson) -> Result<(), tonic::Status> {
+    match get_path_mut(document, path, true) {
+        Some(target) => {
+            *target = value;
+            return Ok(());
+        }
+        None => {
+            return Err(tonic::Status::internal(format!(
+                "could not set field {}",
+                path.join(".")
+            )))
+        }
+    }
+}
+
+/// Applies the update operators to the document, $setOnInsert is only applied if the document is inserted
+fn apply_update(
+    document: &mut Document,
+    update: &Document,
+    query: &Document,
+    inserted: bool,
+) -> Result<(), tonic::Status> {
+    for (operator, fields) in update {
+        let fields = match fields {
+            Bson::Document(value) => value,
+            _ => {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "update operator {} requires a document",
+                    operator
+                )))
+            }
+        };
+
+        for (path, value) in fields {
+            let path = resolve_positional_path(path, document, query)?;
+            match operator.as_str() {
+                "$set" => set_path(document, &path, value.clone())?,
+                "$setOnInsert" => {
+                    if inserted {
+                        set_path(document, &path, value.clone())?;
+                    }
+                }
+                "$inc" => {
+                    let current = get_path_mut(document, &path, false)
+                        .cloned()
+                        .unwrap_or(Bson::Null);
+                    let incremented = match (&current, as_number(&current), as_number(value)) {
+                        (Bson::Null, _, _) => value.clone(),
+                        (_, Some(a), Some(b)) => match (&current, value) {
+                            (Bson::Int32(_), Bson::Int32(_)) => Bson::Int32((a + b) as i32),
+                            (Bson::Double(_), _) | (_, Bson::Double(_)) => Bson::Double(a + b),
+                            _ => Bson::Int64((a + b) as i64),
+                        },
+                        _ => {
+                            return Err(tonic::Status::invalid_argument(
+                                "$inc requires numeric values",
+                            ))
+                        }
+                    };
+                    set_path(document, &path, incremented)?;
+                }
+                "$addToSet" => {
+                    let target = match get_path_mut(document, &path, true) {
+                        Some(value) => value,
+                        None => {
+                            return Err(tonic::Status::invalid_argument(
+                                "$addToSet requires an array",
+                            ))
+                        }
+                    };
+                    match target {
+                        Bson::Array(array) => {
+                            if !array.iter().any(|element| bson_equal(element, value)) {
+                                array.push(value.clone());
+                            }
+                        }
+                        Bson::Null => *target = Bson::Array(vec![value.clone()]),
+                        _ => {
+                            return Err(tonic::Status::invalid_argument(
+                                "$addToSet requires an array",
+                            ))
+                        }
+                    }
+                }
+                "$push" => {
+                    let target = match get_path_mut(document, &path, true) {
+                        Some(value) => value,
+                        None => {
+                            return Err(tonic::Status::invalid_argument("$push requires an array"))
+                        }
+                    };
+                    match target {
+                        Bson::Array(array) => array.push(value.clone()),
+                        Bson::Null => *target = Bson::Array(vec![value.clone()]),
+                        _ => {
+                            return Err(tonic::Status::invalid_argument("$push requires an array"))
+                        }
+                    }
+                }
+                "$pull" => {
+                    if let Some(Bson::Array(array)) = get_path_mut(document, &path, false) {
+                        let mut retained = Vec::with_capacity(array.len());
+                        for element in array.drain(..) {
+                            let pulled = match (&element, value) {
+                                (Bson::Document(element), Bson::Document(condition))
+                                    if !is_operator_document(value) =>
+                                {
+                                    matches(element, condition)?
+                                }
+                                _ => bson_equal(&element, value),
+                            };
+                            if !pulled {
+                                retained.push(element);
+                            }
+                        }
+                        *array = retained;
+                    }
+                }
+                _ => {
+                    return Err(tonic::Status::unimplemented(format!(
+                        "update operator {} is not supported",
+                        operator
+                    )))
+                }
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// Applies a single aggregation stage to the documents
+fn apply_stage(documents: Vec<Document>, stage: &Document) -> Result<Vec<Document>, tonic::Status> {
+    let (operator, operand) = match stage.iter().next() {
+        Some(value) if stage.len() == 1 => value,
+        _ => {
+            return Err(tonic::Status::invalid_argument(
+                "aggregation stages require exactly one operator",
+            ))
+        }
+    };
+    let operand = match operand {
+        Bson::Document(value) => value,
+        _ => {
+            return Err(tonic::Status::invalid_argument(format!(
+                "aggregation stage {} requires a document",
+                operator
+            )))
+        }
+    };
+
+    match operator.as_str() {
+        "$match" => {
+            let mut matched_documents = Vec::new();
+            for document in documents {
+                if matches(&document, operand)? {
+                    matched_documents.push(document);
+                }
+            }
+            return Ok(matched_documents);
+        }
+        "$group" => return group_documents(documents, operand),
+        _ => {
+            return Err(tonic::Status::unimplemented(format!(
+                "aggregation stage {} is not supported",
+                operator
+            )))
+        }
+    }
+}
+
+/// Groups the documents by the value of the _id expression and sums up the accumulator fields per group
+fn group_documents(
+    documents: Vec<Document>,
+    group: &Document,
+) -> Result<Vec<Document>, tonic::Status> {
+    let group_id = group.get("_id").unwrap_or(&Bson::Null);
+
+    let mut groups: Vec<(Bson, Document)> = Vec::new();
+    for document in &documents {
+        let id = match group_id {
+            Bson::String(path) if path.starts_with('$') => {
+                let mut values = Vec::new();
+                resolve_values(document, &split_path(&path[1..]), &mut values);
+                values
+                    .first()
+                    .map(|value| (*value).clone())
+                    .unwrap_or(Bson::Null)
+            }
+            _ => group_id.clone(),
+        };
+
+        let index = match groups
+            .iter()
+            .position(|(existing_id, _)| bson_equal(existing_id, &id))
+        {
+            Some(value) => value,
+            None => {
+                groups.push((id.clone(), doc! {"_id": id}));
+                groups.len() - 1
+            }
+        };
+        let (_, group_document) = &mut groups[index];
+
+        for (field, accumulator) in group {
+            if field == "_id" {
+                continue;
+            }
+            let expression = match accumulator {
+                Bson::Document(value) => match value.get("$sum") {
+                    Some(expression) if value.len() == 1 => expression,
+                    _ => {
+                        return Err(tonic::Status::unimplemented(format!(
+                            "accumulator of {} is not supported",
+                            field
+                        )))
+                    }
+                },
+                _ => {
+                    return Err(tonic::Status::invalid_argument(format!(
+                        "accumulator of {} requires a document",
+                        field
+                    )))
+                }
+            };
+
+            let current = group_document.get(field).and_then(as_number).unwrap_or(0.0);
+            let sum = current + sum_expression(document, expression)?;
+            group_document.insert(field.clone(), Bson::Int64(sum as i64));
+        }
+    }
+
+    return Ok(groups.into_iter().map(|(_, document)| document).collect());
+}
+
+/// Evaluates a $sum expression, field paths that resolve to arrays are summed up element wise
+fn sum_expression(document: &Document, expression: &Bson) -> Result<f64, tonic::Status> {
+    match expression {
+        Bson::String(path) if path.starts_with('$') => {
+            let mut values = Vec::new();
+            resolve_values(document, &split_path(&path[1..]), &mut values);
+
+            let mut sum = 0.0;
+            for value in values {
+                match value {
+                    Bson::Array(array) => sum += array.iter().filter_map(as_number).sum::<f64>(),
+                    _ => sum += as_number(value).unwrap_or(0.0),
+                }
+            }
+            return Ok(sum);
+        }
+        Bson::Document(value) => match value.get("$sum") {
+            Some(expression) if value.len() == 1 => return sum_expression(document, expression),
+            _ => {
+                return Err(tonic::Status::unimplemented(
+                    "only $sum expressions are supported",
+                ))
+            }
+        },
+        _ => return Ok(as_number(expression).unwrap_or(0.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::{apply_stage, apply_update, matches};
+
+    #[test]
+    fn query_and_update_operators() {
+        let mut document = doc! {
+            "id": "revision",
+            "objects_count": 2,
+            "dataset_versions": [],
+            "objects": [
+                {"id": "object_1", "filename": "a.txt"},
+                {"id": "object_2", "filename": "b.txt"},
+            ]
+        };
+
+        assert!(matches(&document, &doc! {"objects.id": "object_2"}).unwrap());
+        assert!(matches(&document, &doc! {"dataset_versions": {"$size": 0}}).unwrap());
+        assert!(matches(&document, &doc! {"id": {"$in": ["other", "revision"]}}).unwrap());
+        assert!(matches(&document, &doc! {"objects_count": {"$gt": 1_i64}}).unwrap());
+        assert!(!matches(&document, &doc! {"rev_version": {"$exists": true}}).unwrap());
+        assert!(!matches(&document, &doc! {"rev_version": {"$ne": null}}).unwrap());
+        assert!(matches(&document, &doc! {"id": {"$ne": "other"}}).unwrap());
+        assert!(matches(
+            &document,
+            &doc! {"$or": [{"rev_version": 0}, {"rev_version": {"$exists": false}}]}
+        )
+        .unwrap());
+
+        let query = doc! {"objects.id": "object_2"};
+        let update = doc! {
+            "$set": {"objects.$.filename": "c.txt"},
+            "$inc": {"objects_count": -1},
+            "$addToSet": {"dataset_versions": "version"},
+        };
+        apply_update(&mut document, &update, &query, false).unwrap();
+        assert!(matches(&document, &doc! {"objects.filename": "c.txt"}).unwrap());
+        assert!(matches(&document, &doc! {"objects_count": 1}).unwrap());
+        assert!(matches(&document, &doc! {"dataset_versions": "version"}).unwrap());
+
+        let update = doc! {"$pull": {"objects": {"id": "object_1"}}};
+        apply_update(&mut document, &update, &query, false).unwrap();
+        assert_eq!(document.get_array("objects").unwrap().len(), 1);
+
+        let groups = apply_stage(
+            vec![document.clone(), document.clone()],
+            &doc! {"$group": {
+                "_id": null,
+                "objects_count": {"$sum": "$objects_count"},
+                "revisions": {"$sum": 1},
+            }},
+        )
+        .unwrap();
+        assert_eq!(
+            groups,
+            vec![doc! {"_id": null, "objects_count": 2_i64, "revisions": 2_i64}]
+        );
+
+        let error = matches(&document, &doc! {"id": {"$nin": ["z"]}}).unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Unimplemented);
+    }
+}
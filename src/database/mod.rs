@@ -1,2 +1,4 @@
 pub mod database;
+#[cfg(any(test, feature = "memory-database"))]
+pub mod memory_database;
 pub mod mongo_connector;
@@ -1,11 +1,15 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 
 use std::convert::TryFrom;
 
 use futures::stream::StreamExt;
 use mongodb::{
-    bson::{from_document, to_document, Bson, Document},
-    options::{FindOptions, ServerAddress, UpdateOptions},
+    bson::{from_document, to_bson, to_document, Bson, Document},
+    options::{
+        AggregateOptions, DistinctOptions, FindOneAndDeleteOptions, FindOneAndUpdateOptions,
+        FindOptions, ReturnDocument, ServerAddress, UpdateOptions,
+    },
     Client,
 };
 use std::{env, time::Duration};
@@ -21,8 +25,9 @@ use mongodb::{bson::doc, options::FindOneOptions};
 use super::database::Database;
 
 use crate::{
+    deadline,
     models::{
-        common_models::{DatabaseModel, Right, Status, User},
+        common_models::{to_status_update, DatabaseModel, Right, SortOrder, Status, User},
         dataset_object_group::{DatasetObject, ObjectGroupRevision},
         project_model::ProjectEntry,
     },
@@ -33,9 +38,15 @@ use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
 
 type ResultWrapper<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Former name of the collection that stores object group revisions
+const LEGACY_REVISION_COLLECTION: &str = "ObjectGroupVersion";
+
 pub struct MongoHandler {
     database_name: String,
     mongo_client: mongodb::Client,
+    collection_prefix: String,
 }
 
 impl MongoHandler {
@@ -60,16 +71,28 @@ impl MongoHandler {
             .unwrap()
             .get_str("Database.Mongo.Host")
             .unwrap_or("localhost".to_string());
-        let username = SETTINGS
-            .read()
-            .unwrap()
-            .get_str("Database.Mongo.Username")
-            .unwrap_or("root".to_string());
         let port = SETTINGS
             .read()
             .unwrap()
             .get_int("Database.Mongo.Port")
             .unwrap_or(27017);
+
+        return MongoHandler::new_with_address(database_name, host, port).await;
+    }
+
+    /// Initiates a new MongoDB handler
+    /// The name of the mongo database and the address of the MongoDB server are provided
+    /// All other parameters are read from the configuration file
+    pub async fn new_with_address(
+        database_name: String,
+        host: String,
+        port: i64,
+    ) -> Result<Self, tonic::Status> {
+        let username = SETTINGS
+            .read()
+            .unwrap()
+            .get_str("Database.Mongo.Username")
+            .unwrap_or("root".to_string());
         let source = SETTINGS
             .read()
             .unwrap()
@@ -110,12 +133,142 @@ impl MongoHandler {
             }
         };
 
+        let collection_prefix = SETTINGS
+            .read()
+            .unwrap()
+            .get_str("Database.Mongo.CollectionPrefix")
+            .unwrap_or_default();
+
         Ok(MongoHandler {
             database_name: database_name,
             mongo_client: client,
+            collection_prefix: collection_prefix,
         })
     }
 
+    /// Returns the name of the collection that stores the given model type
+    /// The name is prefixed with Database.Mongo.CollectionPrefix, e.g. "staging_Object", to allow multiple
+    /// instances to share a database, without a prefix the model name is used
+    pub fn collection_name<'de, T: DatabaseModel<'de>>(&self) -> Result<String, tonic::Status> {
+        return Ok(self.prefixed_collection_name(T::get_model_name()?.as_str()));
+    }
+
+    fn prefixed_collection_name(&self, name: &str) -> String {
+        if self.collection_prefix.is_empty() {
+            return name.to_string();
+        }
+
+        return format!("{}_{}", self.collection_prefix, name);
+    }
+
+    /// Renames the legacy ObjectGroupVersion collection to the canonical revision collection
+    /// Has to run before create_indexes, an empty canonical collection is replaced by the legacy collection
+    /// If both collections contain revisions they have to be merged manually
+    pub async fn migrate_legacy_collections(&self) -> Result<(), tonic::Status> {
+        let database_name = self.database_name.as_str();
+        let database = self.mongo_client.database(database_name);
+        let legacy_name = self.prefixed_collection_name(LEGACY_REVISION_COLLECTION);
+        let canonical_name = self.collection_name::<ObjectGroupRevision>()?;
+
+        let collection_names = match database.list_collection_names(None).await {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(
+                    "could not list database collections",
+                ));
+            }
+        };
+
+        if !collection_names.contains(&legacy_name) {
+            return Ok(());
+        }
+
+        if collection_names.contains(&canonical_name) {
+            let canonical_count = match database
+                .collection::<Document>(&canonical_name)
+                .count_documents(doc! {}, None)
+                .await
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(tonic::Status::internal("could not count stored revisions"));
+                }
+            };
+
+            if canonical_count > 0 {
+                return Err(tonic::Status::failed_precondition(format!(
+                    "revisions are stored in both {} and {}, please merge the collections",
+                    legacy_name, canonical_name
+                )));
+            }
+        }
+
+        log::info!(
+            "renaming collection {} to {} in database {}",
+            legacy_name,
+            canonical_name,
+            database_name
+        );
+
+        let command = doc! {
+            "renameCollection": format!("{}.{}", database_name, legacy_name),
+            "to": format!("{}.{}", database_name, canonical_name),
+            "dropTarget": true,
+        };
+
+        match self
+            .mongo_client
+            .database("admin")
+            .run_command(command, None)
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(
+                    "could not rename legacy revision collection",
+                ));
+            }
+        }
+    }
+
+    /// Creates the indexes required by the stored models
+    /// Object ids are generated as UUIDs, the unique index on objects.id guards against duplicated ids across
+    /// revisions which would make the lookup of a single object ambiguous
+    /// The index on objects.location.key serves the lookup of the revisions that reference a storage key
+    pub async fn create_indexes(&self) -> Result<(), tonic::Status> {
+        let command = doc! {
+            "createIndexes": self.collection_name::<ObjectGroupRevision>()?,
+            "indexes": [
+                {
+                    "key": {"objects.id": 1},
+                    "name": "objects_id_unique",
+                    "unique": true,
+                    "partialFilterExpression": {"objects.id": {"$exists": true}},
+                },
+                {
+                    "key": {"objects.location.key": 1},
+                    "name": "objects_location_key",
+                }
+            ]
+        };
+
+        match self
+            .mongo_client
+            .database(&self.database_name)
+            .run_command(command, None)
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal("could not create database indexes"));
+            }
+        }
+    }
+
     /// Returns an entry based on the internal ID of an inserted object
     /// This can be used to get the model of an inserted object since MongoDB will only return the ObjectID of the inserted object
     async fn get_model_entry_internal_id<'de, T: DatabaseModel<'de>>(
@@ -127,15 +280,17 @@ impl MongoHandler {
         let projection = doc! {"_id": 0};
 
         filter_option.projection = Some(projection);
+        filter_option.max_time = deadline::max_time()?;
 
-        let collection_name = T::get_model_name().unwrap();
+        let collection_name = self.collection_name::<T>()?;
 
-        let data = match self
-            .mongo_client
-            .database(&self.database_name)
-            .collection(&collection_name)
-            .find_one(query, filter_option)
-            .await
+        let data = match deadline::timeout(
+            self.mongo_client
+                .database(&self.database_name)
+                .collection(&collection_name)
+                .find_one(query, filter_option),
+        )
+        .await?
         {
             Ok(value) => value,
             Err(e) => {
@@ -191,7 +346,7 @@ impl MongoHandler {
     {
         self.mongo_client
             .database(&self.database_name)
-            .collection(&T::get_model_name().unwrap())
+            .collection(&self.collection_name::<T>().unwrap())
     }
 }
 
@@ -202,23 +357,24 @@ impl Database for MongoHandler {
         query: Document,
     ) -> Result<Vec<T>, tonic::Status> {
         let mut entries = Vec::new();
-        let filter_options = FindOptions::default();
-
-        let mut csr = match self
-            .collection::<T, Document>()
-            .find(query, filter_options)
-            .await
-        {
-            Ok(value) => value,
-            Err(e) => {
-                error!("{}", e);
-                return Err(tonic::Status::internal(format!(
-                    "error when searching found documents"
-                )));
-            }
-        };
+        let mut filter_options = FindOptions::default();
+        filter_options.projection = T::get_projection();
+        filter_options.max_time = deadline::max_time()?;
+
+        let mut csr =
+            match deadline::timeout(self.collection::<T, Document>().find(query, filter_options))
+                .await?
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    error!("{}", e);
+                    return Err(tonic::Status::internal(format!(
+                        "error when searching found documents"
+                    )));
+                }
+            };
 
-        while let Some(result) = csr.next().await {
+        while let Some(result) = deadline::timeout(csr.next()).await? {
             match result {
                 Ok(document) => {
                     let datasetentry = T::new_from_document(document)?;
@@ -236,6 +392,50 @@ impl Database for MongoHandler {
         Ok(entries)
     }
 
+    async fn find_page<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        sort: &SortOrder,
+        limit: i64,
+    ) -> Result<Vec<T>, tonic::Status> {
+        let mut entries = Vec::new();
+        let mut filter_options = FindOptions::default();
+        filter_options.sort = Some(sort.to_document());
+        filter_options.limit = Some(limit);
+        filter_options.projection = T::get_projection();
+        filter_options.max_time = deadline::max_time()?;
+
+        let mut csr =
+            match deadline::timeout(self.collection::<T, Document>().find(query, filter_options))
+                .await?
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    error!("{}", e);
+                    return Err(tonic::Status::internal(format!(
+                        "error when searching found documents"
+                    )));
+                }
+            };
+
+        while let Some(result) = deadline::timeout(csr.next()).await? {
+            match result {
+                Ok(document) => {
+                    let entry = T::new_from_document(document)?;
+                    entries.push(entry);
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    return Err(tonic::Status::internal(format!(
+                        "error when parsing documents"
+                    )));
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
     async fn store<'de, T: DatabaseModel<'de>>(&self, value: T) -> Result<T, tonic::Status> {
         let data_document = match value.to_document() {
             Ok(value) => value,
@@ -247,10 +447,11 @@ impl Database for MongoHandler {
             }
         };
 
-        let result = match self
-            .collection::<T, Document>()
-            .insert_one(data_document, None)
-            .await
+        let result = match deadline::timeout(
+            self.collection::<T, Document>()
+                .insert_one(data_document, None),
+        )
+        .await?
         {
             Ok(value) => value,
             Err(e) => {
@@ -312,7 +513,7 @@ impl Database for MongoHandler {
 
         let options = UpdateOptions::default();
 
-        match collection.update_one(filter, insert, options).await {
+        match deadline::timeout(collection.update_one(filter, insert, options)).await? {
             Ok(value) => value,
             Err(e) => {
                 error!("{:?}", e);
@@ -325,6 +526,17 @@ impl Database for MongoHandler {
         return Ok(());
     }
 
+    async fn find_object_by_key(
+        &self,
+        key: &str,
+    ) -> Result<Vec<ObjectGroupRevision>, tonic::Status> {
+        let query = doc! {
+            "objects.location.key": key
+        };
+
+        return self.find_by_key::<ObjectGroupRevision>(query).await;
+    }
+
     async fn find_object(&self, id: &str) -> Result<DatasetObject, tonic::Status> {
         let filter = doc! {
             "objects.id": id
@@ -335,12 +547,15 @@ impl Database for MongoHandler {
             "_id": 0,
         };
 
-        let options = FindOneOptions::builder().projection(projection).build();
+        let mut options = FindOneOptions::default();
+        options.projection = Some(projection);
+        options.max_time = deadline::max_time()?;
 
-        let csr = match self
-            .collection::<ObjectGroupRevision, Document>()
-            .find_one(filter, options)
-            .await
+        let csr = match deadline::timeout(
+            self.collection::<ObjectGroupRevision, Document>()
+                .find_one(filter, options),
+        )
+        .await?
         {
             Ok(value) => value,
             Err(e) => {
@@ -364,29 +579,28 @@ impl Database for MongoHandler {
             }
         };
 
-        if objects_list.len() != 1 {
-            error!(
-                "wrong number of objects found in objects list: found {} objects for object_id {}",
-                objects_list.len(),
-                id
-            );
-            return Err(tonic::Status::internal(
-                "could not read requested dataset object",
-            ));
-        }
+        // The positional projection returns only the first matching object, the objects are still filtered
+        // by id in case the full objects list is returned
+        for bson_object in objects_list {
+            let object: DatasetObject = match bson::from_bson(bson_object) {
+                Ok(value) => value,
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(tonic::Status::internal(
+                        "could not read requested dataset object",
+                    ));
+                }
+            };
 
-        let bson_object = &objects_list[0];
-        let object: DatasetObject = match bson::from_bson(bson_object.to_owned()) {
-            Ok(value) => value,
-            Err(e) => {
-                error!("{:?}", e);
-                return Err(tonic::Status::internal(
-                    "could not read requested dataset object",
-                ));
+            if object.id == id {
+                return Ok(object);
             }
-        };
+        }
 
-        return Ok(object);
+        error!("object_id {} not found in objects list of revision", id);
+        return Err(tonic::Status::internal(
+            "could not read requested dataset object",
+        ));
     }
 
     async fn update_field<'de, T: DatabaseModel<'de>>(
@@ -394,10 +608,11 @@ impl Database for MongoHandler {
         query: Document,
         update: Document,
     ) -> Result<u64, tonic::Status> {
-        match self
-            .collection::<T, Document>()
-            .update_one(query, update, None)
-            .await
+        match deadline::timeout(
+            self.collection::<T, Document>()
+                .update_one(query, update, None),
+        )
+        .await?
         {
             Ok(value) => return Ok(value.modified_count),
             Err(e) => {
@@ -413,12 +628,14 @@ impl Database for MongoHandler {
         &self,
         query: Document,
     ) -> Result<T, tonic::Status> {
-        let filter_options = FindOneOptions::default();
-
-        let csr = match self
-            .collection::<T, Document>()
-            .find_one(query.clone(), filter_options)
-            .await
+        let mut filter_options = FindOneOptions::default();
+        filter_options.max_time = deadline::max_time()?;
+
+        let csr = match deadline::timeout(
+            self.collection::<T, Document>()
+                .find_one(query.clone(), filter_options),
+        )
+        .await?
         {
             Ok(value) => value,
             Err(e) => {
@@ -443,15 +660,53 @@ impl Database for MongoHandler {
         Ok(entry)
     }
 
+    async fn exists<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<bool, tonic::Status> {
+        let mut filter_options = FindOptions::default();
+        filter_options.projection = Some(doc! {"_id": 1});
+        filter_options.limit = Some(1);
+        filter_options.max_time = deadline::max_time()?;
+
+        let mut csr =
+            match deadline::timeout(self.collection::<T, Document>().find(query, filter_options))
+                .await?
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    return Err(tonic::Status::internal(format!(
+                        "error when trying to find entry"
+                    )));
+                }
+            };
+
+        match deadline::timeout(csr.next()).await? {
+            Some(Ok(_)) => return Ok(true),
+            Some(Err(e)) => {
+                log::error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "error when trying to find entry"
+                )));
+            }
+            None => return Ok(false),
+        }
+    }
+
     async fn update_on_field<'de, T: DatabaseModel<'de>>(
         &self,
         query: Document,
         update: Document,
     ) -> Result<T, tonic::Status> {
-        let option_document = match self
-            .collection::<T, Document>()
-            .find_one_and_update(query, update, None)
-            .await
+        let mut options = FindOneAndUpdateOptions::default();
+        options.max_time = deadline::max_time()?;
+
+        let option_document = match deadline::timeout(
+            self.collection::<T, Document>()
+                .find_one_and_update(query, update, options),
+        )
+        .await?
         {
             Ok(value) => value,
             Err(e) => {
@@ -484,15 +739,105 @@ impl Database for MongoHandler {
         return Ok(option_value);
     }
 
-    async fn delete<'de, T: DatabaseModel<'de>>(
+    async fn upsert<'de, T: DatabaseModel<'de>>(
         &self,
         query: Document,
+        update: Document,
     ) -> Result<(), tonic::Status> {
-        match self
-            .collection::<T, Document>()
-            .delete_one(query, None)
-            .await
+        let mut options = UpdateOptions::default();
+        options.upsert = Some(true);
+
+        match deadline::timeout(
+            self.collection::<T, Document>()
+                .update_one(query, update, options),
+        )
+        .await?
+        {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "error when trying to upsert document"
+                )));
+            }
+        }
+    }
+
+    async fn replace<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        value: T,
+    ) -> Result<T, tonic::Status> {
+        let mut replacement = match value.to_document() {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "error when converting request to document"
+                )));
+            }
+        };
+
+        let not_found = || {
+            tonic::Status::not_found(format!(
+                "could not find requested document. type: {} with query {}",
+                T::get_model_name().unwrap_or_default(),
+                query.to_string()
+            ))
+        };
+
+        let mut options = FindOneOptions::default();
+        options.projection = Some(doc! {"id": 1});
+        options.max_time = deadline::max_time()?;
+        let id = match deadline::timeout(
+            self.collection::<T, Document>()
+                .find_one(query.clone(), options),
+        )
+        .await?
+        {
+            Ok(Some(document)) => match document.get_str("id") {
+                Ok(value) => value.to_string(),
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(tonic::Status::internal("could not read id of document"));
+                }
+            },
+            Ok(None) => return Err(not_found()),
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "error when trying to find entry"
+                )));
+            }
+        };
+        replacement.insert("id", id.as_str());
+
+        // The entry could have been changed in the meantime, it is only replaced if it still matches the query
+        let mut replace_query = query.clone();
+        replace_query.insert("id", id.as_str());
+        match deadline::timeout(self.collection::<T, Document>().replace_one(
+            replace_query,
+            replacement.clone(),
+            None,
+        ))
+        .await?
         {
+            Ok(result) if result.matched_count == 0 => return Err(not_found()),
+            Ok(_) => return T::new_from_document(replacement),
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "error when replacing document"
+                )));
+            }
+        }
+    }
+
+    async fn delete<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<(), tonic::Status> {
+        match deadline::timeout(self.collection::<T, Document>().delete_one(query, None)).await? {
             Ok(_) => (),
             Err(e) => {
                 log::error!("{:?}", e);
@@ -503,44 +848,224 @@ impl Database for MongoHandler {
         return Ok(());
     }
 
+    async fn delete_many<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<u64, tonic::Status> {
+        match deadline::timeout(self.collection::<T, Document>().delete_many(query, None)).await? {
+            Ok(value) => return Ok(value.deleted_count),
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(tonic::Status::internal("could not delete objects"));
+            }
+        }
+    }
+
     async fn update_status<'de, T: DatabaseModel<'de>>(
         &self,
         id: &str,
         status: Status,
+        now: DateTime<Utc>,
     ) -> Result<(), tonic::Status> {
         let query = doc! {
             "id": id
         };
 
-        let value = match mongodb::bson::to_bson(&status) {
+        let update = to_status_update(&status, now)?;
+
+        match deadline::timeout(
+            self.collection::<T, Document>()
+                .update_one(query, update, None),
+        )
+        .await?
+        {
+            Ok(_) => (),
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(format!("error on status update")));
+            }
+        }
+
+        return Ok(());
+    }
+
+    async fn compare_and_set_status<'de, T: DatabaseModel<'de>>(
+        &self,
+        id: &str,
+        from: Status,
+        to: Status,
+        now: DateTime<Utc>,
+    ) -> Result<bool, tonic::Status> {
+        let from_value = match to_bson(&from) {
             Ok(value) => value,
             Err(e) => {
                 error!("{:?}", e);
+                return Err(tonic::Status::internal(format!("error on status update")));
+            }
+        };
+        let query = doc! {
+            "id": id,
+            "status": from_value
+        };
+
+        let update = to_status_update(&to, now)?;
+
+        match deadline::timeout(
+            self.collection::<T, Document>()
+                .update_one(query, update, None),
+        )
+        .await?
+        {
+            Ok(value) => return Ok(value.matched_count > 0),
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(format!("error on status update")));
+            }
+        }
+    }
+
+    async fn find_one_and_delete<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+    ) -> Result<Option<T>, tonic::Status> {
+        let mut options = FindOneAndDeleteOptions::default();
+        options.max_time = deadline::max_time()?;
+
+        let option_document = match deadline::timeout(
+            self.collection::<T, Document>()
+                .find_one_and_delete(query, options),
+        )
+        .await?
+        {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("{:?}", e);
                 return Err(tonic::Status::internal(format!(
-                    "error when converting request to document"
+                    "error when trying to delete document"
                 )));
             }
         };
 
+        return match option_document {
+            Some(document) => Ok(Some(T::new_from_document(document)?)),
+            None => Ok(None),
+        };
+    }
+
+    async fn reparent<'de, T: DatabaseModel<'de>>(
+        &self,
+        query: Document,
+        parent_fields: Document,
+    ) -> Result<Option<T>, tonic::Status> {
         let update = doc! {
-            "$set": {
-                "status":  value
+            "$set": parent_fields
+        };
+
+        let mut options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        options.max_time = deadline::max_time()?;
+
+        let option_document = match deadline::timeout(
+            self.collection::<T, Document>()
+                .find_one_and_update(query, update, options),
+        )
+        .await?
+        {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "error when trying to update parent of document"
+                )));
             }
         };
 
-        match self
-            .collection::<T, Document>()
-            .update_one(query, update, None)
-            .await
+        return match option_document {
+            Some(document) => Ok(Some(T::new_from_document(document)?)),
+            None => Ok(None),
+        };
+    }
+
+    async fn aggregate<'de, T: DatabaseModel<'de>>(
+        &self,
+        pipeline: Vec<Document>,
+    ) -> Result<Vec<Document>, tonic::Status> {
+        let mut options = AggregateOptions::default();
+        options.max_time = deadline::max_time()?;
+
+        let mut csr = match deadline::timeout(
+            self.collection::<T, Document>()
+                .aggregate(pipeline, options),
+        )
+        .await?
         {
-            Ok(_) => (),
+            Ok(value) => value,
             Err(e) => {
                 error!("{:?}", e);
-                return Err(tonic::Status::internal(format!("error on status update")));
+                return Err(tonic::Status::internal(format!(
+                    "error when running aggregation"
+                )));
+            }
+        };
+
+        let mut documents = Vec::new();
+        while let Some(result) = deadline::timeout(csr.next()).await? {
+            match result {
+                Ok(document) => documents.push(document),
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(tonic::Status::internal(format!(
+                        "error when reading aggregation results"
+                    )));
+                }
             }
         }
 
-        return Ok(());
+        return Ok(documents);
+    }
+
+    async fn distinct<'de, T: DatabaseModel<'de>>(
+        &self,
+        field: &str,
+        query: Document,
+    ) -> Result<Vec<Bson>, tonic::Status> {
+        let mut options = DistinctOptions::default();
+        options.max_time = deadline::max_time()?;
+
+        match deadline::timeout(
+            self.collection::<T, Document>()
+                .distinct(field, query, options),
+        )
+        .await?
+        {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(format!(
+                    "error when reading distinct values"
+                )));
+            }
+        }
+    }
+
+    async fn ping(&self) -> Result<(), tonic::Status> {
+        let ping = self
+            .mongo_client
+            .database("admin")
+            .run_command(doc! {"ping": 1}, None);
+
+        match tokio::time::timeout(PING_TIMEOUT, ping).await {
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(e)) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::unavailable("database is not reachable"));
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::unavailable("database ping timed out"));
+            }
+        }
     }
 
     async fn update_fields<'de, T: DatabaseModel<'de>>(
@@ -548,10 +1073,11 @@ impl Database for MongoHandler {
         query: Document,
         update: Document,
     ) -> Result<u64, tonic::Status> {
-        match self
-            .collection::<T, Document>()
-            .update_many(query, update, None)
-            .await
+        match deadline::timeout(
+            self.collection::<T, Document>()
+                .update_many(query, update, None),
+        )
+        .await?
         {
             Ok(value) => return Ok(value.modified_count),
             Err(e) => {
@@ -588,3 +1114,348 @@ impl Error for SimpleError {
         &self.details
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bson::{doc, Bson};
+    use chrono::Utc;
+    use scienceobjectsdb_rust_api::sciobjectsdbapi::{models, services};
+
+    use crate::{
+        database::database::Database,
+        deadline::with_deadline,
+        models::{
+            common_models::{DatabaseModel, Label},
+            dataset_model::DatasetEntry,
+            dataset_object_group::ObjectGroupRevision,
+        },
+        test_util::init::test_init,
+    };
+
+    use super::{MongoHandler, LEGACY_REVISION_COLLECTION};
+
+    #[tokio::test]
+    async fn test_ping() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let mongo_handler = MongoHandler::new_with_db_name(uuid.to_string())
+            .await
+            .unwrap();
+        mongo_handler.ping().await.unwrap();
+
+        let unreachable_handler =
+            MongoHandler::new_with_address(uuid.to_string(), "127.0.0.1".to_string(), 1)
+                .await
+                .unwrap();
+        let error = unreachable_handler.ping().await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn exists() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let mongo_handler = MongoHandler::new_with_db_name(uuid.to_string())
+            .await
+            .unwrap();
+
+        let dataset = DatasetEntry::new_from_proto_create(
+            &services::v1::CreateDatasetRequest {
+                ..Default::default()
+            },
+            Utc::now(),
+        )
+        .unwrap();
+        let dataset = mongo_handler.store(dataset).await.unwrap();
+
+        assert!(mongo_handler
+            .exists::<DatasetEntry>(doc! {"id": dataset.id.as_str()})
+            .await
+            .unwrap());
+        assert!(!mongo_handler
+            .exists::<DatasetEntry>(doc! {"id": uuid::Uuid::new_v4().to_string()})
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn replace() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let mongo_handler = MongoHandler::new_with_db_name(uuid.to_string())
+            .await
+            .unwrap();
+
+        let dataset = DatasetEntry::new_from_proto_create(
+            &services::v1::CreateDatasetRequest {
+                name: "dataset".to_string(),
+                ..Default::default()
+            },
+            Utc::now(),
+        )
+        .unwrap();
+        let dataset = mongo_handler.store(dataset).await.unwrap();
+
+        // The id of the replacement is ignored, the replaced entry keeps its id
+        let replacement = DatasetEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: "replaced".to_string(),
+            description: "replaced dataset".to_string(),
+            labels: vec![Label {
+                key: "key".to_string(),
+                value: "value".to_string(),
+            }],
+            ..dataset.clone()
+        };
+        let replaced_dataset = mongo_handler
+            .replace(doc! {"id": dataset.id.as_str()}, replacement.clone())
+            .await
+            .unwrap();
+        assert_eq!(replaced_dataset.id, dataset.id);
+
+        let read_dataset: DatasetEntry = mongo_handler
+            .find_one_by_key(doc! {"id": dataset.id.as_str()})
+            .await
+            .unwrap();
+        assert_eq!(read_dataset, replaced_dataset);
+        assert_eq!(read_dataset.name, "replaced");
+        assert_eq!(read_dataset.description, "replaced dataset");
+        assert_eq!(read_dataset.labels, replacement.labels);
+        assert!(!mongo_handler
+            .exists::<DatasetEntry>(doc! {"id": replacement.id.as_str()})
+            .await
+            .unwrap());
+
+        let error = mongo_handler
+            .replace(
+                doc! {"id": uuid::Uuid::new_v4().to_string()},
+                replacement.clone(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn distinct() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let mongo_handler = MongoHandler::new_with_db_name(uuid.to_string())
+            .await
+            .unwrap();
+
+        for label_keys in vec![vec!["species", "assay"], vec!["assay"]] {
+            let labels = label_keys
+                .into_iter()
+                .map(|key| models::v1::Label {
+                    key: key.to_string(),
+                    value: "value".to_string(),
+                })
+                .collect();
+            let dataset = DatasetEntry::new_from_proto_create(
+                &services::v1::CreateDatasetRequest {
+                    project_id: "project".to_string(),
+                    labels: labels,
+                    ..Default::default()
+                },
+                Utc::now(),
+            )
+            .unwrap();
+            mongo_handler.store(dataset).await.unwrap();
+        }
+
+        let mut label_keys = mongo_handler
+            .distinct::<DatasetEntry>("labels.key", doc! {"project_id": "project"})
+            .await
+            .unwrap();
+        label_keys.sort_by_key(|key| key.to_string());
+        assert_eq!(
+            label_keys,
+            vec![
+                Bson::String("assay".to_string()),
+                Bson::String("species".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn find_one_and_delete() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let mongo_handler = MongoHandler::new_with_db_name(uuid.to_string())
+            .await
+            .unwrap();
+
+        let dataset = DatasetEntry::new_from_proto_create(
+            &services::v1::CreateDatasetRequest {
+                ..Default::default()
+            },
+            Utc::now(),
+        )
+        .unwrap();
+        let dataset = mongo_handler.store(dataset).await.unwrap();
+
+        let deleted_dataset = mongo_handler
+            .find_one_and_delete::<DatasetEntry>(doc! {"id": dataset.id.as_str()})
+            .await
+            .unwrap();
+        assert_eq!(deleted_dataset, Some(dataset.clone()));
+        assert!(!mongo_handler
+            .exists::<DatasetEntry>(doc! {"id": dataset.id.as_str()})
+            .await
+            .unwrap());
+
+        let missing_dataset = mongo_handler
+            .find_one_and_delete::<DatasetEntry>(doc! {"id": dataset.id.as_str()})
+            .await
+            .unwrap();
+        assert_eq!(missing_dataset, None);
+    }
+
+    #[tokio::test]
+    async fn collection_prefix() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let mut mongo_handler = MongoHandler::new_with_db_name(uuid.to_string())
+            .await
+            .unwrap();
+        mongo_handler.collection_prefix = "staging".to_string();
+        assert_eq!(
+            mongo_handler.collection_name::<DatasetEntry>().unwrap(),
+            format!("staging_{}", DatasetEntry::get_model_name().unwrap())
+        );
+
+        let dataset = DatasetEntry::new_from_proto_create(
+            &services::v1::CreateDatasetRequest {
+                ..Default::default()
+            },
+            Utc::now(),
+        )
+        .unwrap();
+        let stored_dataset = mongo_handler.store(dataset).await.unwrap();
+
+        let read_dataset: DatasetEntry = mongo_handler
+            .find_one_by_key(doc! {"id": stored_dataset.id.as_str()})
+            .await
+            .unwrap();
+        assert_eq!(read_dataset.id, stored_dataset.id);
+
+        let database = mongo_handler.mongo_client.database(&uuid.to_string());
+        let prefixed_documents = database
+            .collection::<bson::Document>(&format!(
+                "staging_{}",
+                DatasetEntry::get_model_name().unwrap()
+            ))
+            .count_documents(doc! {"id": stored_dataset.id.as_str()}, None)
+            .await
+            .unwrap();
+        assert_eq!(prefixed_documents, 1);
+
+        let unprefixed_documents = database
+            .collection::<bson::Document>(&DatasetEntry::get_model_name().unwrap())
+            .count_documents(doc! {}, None)
+            .await
+            .unwrap();
+        assert_eq!(unprefixed_documents, 0);
+    }
+
+    #[tokio::test]
+    async fn migrate_legacy_revisions() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let mongo_handler = MongoHandler::new_with_db_name(uuid.to_string())
+            .await
+            .unwrap();
+        mongo_handler.migrate_legacy_collections().await.unwrap();
+
+        let revision = ObjectGroupRevision {
+            id: uuid::Uuid::new_v4().to_string(),
+            ..Default::default()
+        };
+        mongo_handler
+            .mongo_client
+            .database(&uuid.to_string())
+            .collection::<bson::Document>(LEGACY_REVISION_COLLECTION)
+            .insert_one(bson::to_document(&revision).unwrap(), None)
+            .await
+            .unwrap();
+
+        mongo_handler.migrate_legacy_collections().await.unwrap();
+        mongo_handler.create_indexes().await.unwrap();
+
+        let read_revision: ObjectGroupRevision = mongo_handler
+            .find_one_by_key(doc! {"id": revision.id.as_str()})
+            .await
+            .unwrap();
+        assert_eq!(read_revision.id, revision.id);
+
+        let collection_names = mongo_handler
+            .mongo_client
+            .database(&uuid.to_string())
+            .list_collection_names(None)
+            .await
+            .unwrap();
+        assert!(!collection_names.contains(&LEGACY_REVISION_COLLECTION.to_string()));
+        assert!(collection_names.contains(&ObjectGroupRevision::get_model_name().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn writes_respect_request_deadline() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let mongo_handler = MongoHandler::new_with_db_name(uuid.to_string())
+            .await
+            .unwrap();
+
+        let dataset = DatasetEntry::new_from_proto_create(
+            &services::v1::CreateDatasetRequest {
+                name: "dataset".to_string(),
+                ..Default::default()
+            },
+            Utc::now(),
+        )
+        .unwrap();
+        let stored_dataset = mongo_handler.store(dataset.clone()).await.unwrap();
+
+        // The deadline has passed before the writes are sent, none of them may reach the database
+        let expired_deadline = tokio::time::Instant::now();
+        let store_error = with_deadline(expired_deadline, mongo_handler.store(dataset))
+            .await
+            .unwrap_err();
+        assert_eq!(store_error.code(), tonic::Code::DeadlineExceeded);
+
+        let update_error = with_deadline(
+            expired_deadline,
+            mongo_handler.update_field::<DatasetEntry>(
+                doc! {"id": stored_dataset.id.as_str()},
+                doc! {"$set": {"name": "updated"}},
+            ),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(update_error.code(), tonic::Code::DeadlineExceeded);
+
+        let delete_error = with_deadline(
+            expired_deadline,
+            mongo_handler.delete::<DatasetEntry>(doc! {"id": stored_dataset.id.as_str()}),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(delete_error.code(), tonic::Code::DeadlineExceeded);
+
+        let datasets = mongo_handler
+            .find_by_key::<DatasetEntry>(doc! {})
+            .await
+            .unwrap();
+        assert_eq!(datasets.len(), 1);
+        assert_eq!(datasets[0].name, "dataset");
+    }
+}
@@ -0,0 +1,140 @@
+use std::{future::Future, time::Duration};
+
+use tokio::time::Instant;
+
+/// Header in which gRPC clients send the deadline of a request as remaining time, e.g. 100m for 100 milliseconds
+pub const GRPC_TIMEOUT_HEADER: &str = "grpc-timeout";
+
+tokio::task_local! {
+    /// Deadline of the request that is currently handled by the task
+    static REQUEST_DEADLINE: Instant;
+}
+
+/// Parses the value of a grpc-timeout header, at most 8 digits followed by the unit H, M, S, m, u or n
+/// Returns None for invalid values
+pub fn parse_grpc_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 || !value.is_char_boundary(value.len() - 1) {
+        return None;
+    }
+
+    let (amount, unit) = value.split_at(value.len() - 1);
+    if !amount.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = amount.parse().ok()?;
+
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 60 * 60)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
+    }
+}
+
+/// Runs the future with the given request deadline, the database and object storage calls within it are aborted
+/// once the deadline has passed
+pub async fn with_deadline<F: Future>(deadline: Instant, future: F) -> F::Output {
+    return REQUEST_DEADLINE.scope(deadline, future).await;
+}
+
+/// Returns the time left until the deadline of the current request, None if the request has no deadline
+pub fn remaining() -> Option<Duration> {
+    return REQUEST_DEADLINE
+        .try_with(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .ok();
+}
+
+/// Returns the time left until the deadline of the current request as server side time limit of a database query
+/// Fails with deadline_exceeded if no time is left, MongoDB would treat a time limit of zero as no limit
+pub fn max_time() -> Result<Option<Duration>, tonic::Status> {
+    match remaining() {
+        Some(value) if value.as_millis() == 0 => {
+            return Err(tonic::Status::deadline_exceeded(
+                "request deadline exceeded",
+            ))
+        }
+        value => return Ok(value),
+    }
+}
+
+/// Awaits a downstream call within the deadline of the current request
+/// Fails with deadline_exceeded if the deadline passes first, calls without a request deadline are not limited
+pub async fn timeout<F: Future>(future: F) -> Result<F::Output, tonic::Status> {
+    let remaining = match max_time()? {
+        Some(value) => value,
+        None => return Ok(future.await),
+    };
+
+    match tokio::time::timeout(remaining, future).await {
+        Ok(value) => return Ok(value),
+        Err(_) => {
+            return Err(tonic::Status::deadline_exceeded(
+                "request deadline exceeded",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{max_time, parse_grpc_timeout, remaining, timeout, with_deadline};
+
+    #[test]
+    fn grpc_timeout() {
+        assert_eq!(parse_grpc_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_grpc_timeout("2M"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_grpc_timeout("3S"), Some(Duration::from_secs(3)));
+        assert_eq!(parse_grpc_timeout("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(parse_grpc_timeout("5u"), Some(Duration::from_micros(5)));
+        assert_eq!(
+            parse_grpc_timeout("99999999n"),
+            Some(Duration::from_nanos(99999999))
+        );
+
+        assert_eq!(parse_grpc_timeout(""), None);
+        assert_eq!(parse_grpc_timeout("m"), None);
+        assert_eq!(parse_grpc_timeout("100"), None);
+        assert_eq!(parse_grpc_timeout("100x"), None);
+        assert_eq!(parse_grpc_timeout("-100m"), None);
+        assert_eq!(parse_grpc_timeout("123456789m"), None);
+    }
+
+    #[tokio::test]
+    async fn deadline_aborts_slow_calls() {
+        assert_eq!(remaining(), None);
+        assert_eq!(timeout(async { 1 }).await.unwrap(), 1);
+
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let result = with_deadline(deadline, async {
+            assert!(remaining().unwrap() <= Duration::from_millis(20));
+            assert_eq!(timeout(async { 1 }).await.unwrap(), 1);
+
+            return timeout(tokio::time::sleep(Duration::from_secs(5))).await;
+        })
+        .await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    async fn expired_deadline() {
+        assert_eq!(max_time().unwrap(), None);
+
+        let result = with_deadline(Instant::now(), async {
+            assert_eq!(
+                max_time().unwrap_err().code(),
+                tonic::Code::DeadlineExceeded
+            );
+
+            return timeout(async { 1 }).await;
+        })
+        .await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::DeadlineExceeded);
+    }
+}
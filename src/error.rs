@@ -0,0 +1,233 @@
+use std::{error::Error, fmt};
+
+use tonic::metadata::{MetadataMap, MetadataValue};
+
+use crate::validation::{decode_field_violations, encode_status_details, FieldViolation};
+
+/// Response metadata key that holds the stable kind of a returned error
+/// Clients can use it to branch on the kind of an error independently of the error message
+pub const ERROR_KIND_KEY: &str = "error-kind";
+
+/// Errors returned by the handlers
+/// Each variant is mapped to a fixed gRPC code, the kind is additionally returned in the error-kind metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoreError {
+    NotFound(String),
+    InvalidArgument(String),
+    /// Invalid argument with the paths of all invalid fields of the request
+    /// The violations are returned as google.rpc.BadRequest in the details of the status
+    InvalidFields(String, Vec<FieldViolation>),
+    OutOfRange(String),
+    Unauthenticated(String),
+    PermissionDenied(String),
+    FailedPrecondition(String),
+    Conflict(String),
+    Unavailable(String),
+    /// The deadline of the request passed before a database or object storage call completed
+    DeadlineExceeded(String),
+    /// The client cancelled the request
+    Cancelled(String),
+    ResourceExhausted(String),
+    Backend(String),
+}
+
+impl CoreError {
+    pub fn code(&self) -> tonic::Code {
+        match self {
+            CoreError::NotFound(_) => tonic::Code::NotFound,
+            CoreError::InvalidArgument(_) | CoreError::InvalidFields(_, _) => {
+                tonic::Code::InvalidArgument
+            }
+            CoreError::OutOfRange(_) => tonic::Code::OutOfRange,
+            CoreError::Unauthenticated(_) => tonic::Code::Unauthenticated,
+            CoreError::PermissionDenied(_) => tonic::Code::PermissionDenied,
+            CoreError::FailedPrecondition(_) => tonic::Code::FailedPrecondition,
+            CoreError::Conflict(_) => tonic::Code::Aborted,
+            CoreError::Unavailable(_) => tonic::Code::Unavailable,
+            CoreError::DeadlineExceeded(_) => tonic::Code::DeadlineExceeded,
+            CoreError::Cancelled(_) => tonic::Code::Cancelled,
+            CoreError::ResourceExhausted(_) => tonic::Code::ResourceExhausted,
+            CoreError::Backend(_) => tonic::Code::Internal,
+        }
+    }
+
+    /// Stable identifier of the error kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CoreError::NotFound(_) => "not_found",
+            CoreError::InvalidArgument(_) | CoreError::InvalidFields(_, _) => "invalid_argument",
+            CoreError::OutOfRange(_) => "out_of_range",
+            CoreError::Unauthenticated(_) => "unauthenticated",
+            CoreError::PermissionDenied(_) => "permission_denied",
+            CoreError::FailedPrecondition(_) => "failed_precondition",
+            CoreError::Conflict(_) => "conflict",
+            CoreError::Unavailable(_) => "unavailable",
+            CoreError::DeadlineExceeded(_) => "deadline_exceeded",
+            CoreError::Cancelled(_) => "cancelled",
+            CoreError::ResourceExhausted(_) => "resource_exhausted",
+            CoreError::Backend(_) => "backend",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            CoreError::NotFound(message)
+            | CoreError::InvalidArgument(message)
+            | CoreError::InvalidFields(message, _)
+            | CoreError::OutOfRange(message)
+            | CoreError::Unauthenticated(message)
+            | CoreError::PermissionDenied(message)
+            | CoreError::FailedPrecondition(message)
+            | CoreError::Conflict(message)
+            | CoreError::Unavailable(message)
+            | CoreError::DeadlineExceeded(message)
+            | CoreError::Cancelled(message)
+            | CoreError::ResourceExhausted(message)
+            | CoreError::Backend(message) => message.as_str(),
+        }
+    }
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.kind(), self.message())
+    }
+}
+
+impl Error for CoreError {}
+
+impl From<CoreError> for tonic::Status {
+    fn from(error: CoreError) -> Self {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(ERROR_KIND_KEY, MetadataValue::from_static(error.kind()));
+
+        if let CoreError::InvalidFields(message, field_violations) = &error {
+            let details = encode_status_details(error.code(), message, field_violations);
+            return tonic::Status::with_details_and_metadata(
+                error.code(),
+                message.as_str(),
+                details.into(),
+                metadata,
+            );
+        }
+
+        return tonic::Status::with_metadata(error.code(), error.message(), metadata);
+    }
+}
+
+/// Errors of the database and object storage backends are still returned as tonic::Status
+/// Codes without a matching variant are treated as backend errors
+impl From<tonic::Status> for CoreError {
+    fn from(status: tonic::Status) -> Self {
+        let message = status.message().to_string();
+
+        match status.code() {
+            tonic::Code::NotFound => CoreError::NotFound(message),
+            tonic::Code::InvalidArgument => {
+                let field_violations = decode_field_violations(status.details());
+                if field_violations.is_empty() {
+                    CoreError::InvalidArgument(message)
+                } else {
+                    CoreError::InvalidFields(message, field_violations)
+                }
+            }
+            tonic::Code::OutOfRange => CoreError::OutOfRange(message),
+            tonic::Code::Unauthenticated => CoreError::Unauthenticated(message),
+            tonic::Code::PermissionDenied => CoreError::PermissionDenied(message),
+            tonic::Code::FailedPrecondition => CoreError::FailedPrecondition(message),
+            tonic::Code::Aborted | tonic::Code::AlreadyExists => CoreError::Conflict(message),
+            tonic::Code::Unavailable => CoreError::Unavailable(message),
+            tonic::Code::DeadlineExceeded => CoreError::DeadlineExceeded(message),
+            tonic::Code::Cancelled => CoreError::Cancelled(message),
+            tonic::Code::ResourceExhausted => CoreError::ResourceExhausted(message),
+            _ => CoreError::Backend(message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CoreError, ERROR_KIND_KEY};
+    use crate::validation::FieldViolation;
+
+    #[test]
+    fn error_codes() {
+        let test_cases = vec![
+            (
+                CoreError::NotFound("not found".to_string()),
+                tonic::Code::NotFound,
+            ),
+            (
+                CoreError::InvalidArgument("invalid".to_string()),
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                CoreError::InvalidFields(
+                    "invalid request: name: name must not be empty".to_string(),
+                    vec![FieldViolation {
+                        field: "name".to_string(),
+                        description: "name must not be empty".to_string(),
+                    }],
+                ),
+                tonic::Code::InvalidArgument,
+            ),
+            (
+                CoreError::OutOfRange("out of range".to_string()),
+                tonic::Code::OutOfRange,
+            ),
+            (
+                CoreError::Unauthenticated("unauthenticated".to_string()),
+                tonic::Code::Unauthenticated,
+            ),
+            (
+                CoreError::PermissionDenied("denied".to_string()),
+                tonic::Code::PermissionDenied,
+            ),
+            (
+                CoreError::FailedPrecondition("precondition".to_string()),
+                tonic::Code::FailedPrecondition,
+            ),
+            (
+                CoreError::Conflict("conflict".to_string()),
+                tonic::Code::Aborted,
+            ),
+            (
+                CoreError::Unavailable("unavailable".to_string()),
+                tonic::Code::Unavailable,
+            ),
+            (
+                CoreError::DeadlineExceeded("deadline exceeded".to_string()),
+                tonic::Code::DeadlineExceeded,
+            ),
+            (
+                CoreError::Cancelled("cancelled".to_string()),
+                tonic::Code::Cancelled,
+            ),
+            (
+                CoreError::ResourceExhausted("resource exhausted".to_string()),
+                tonic::Code::ResourceExhausted,
+            ),
+            (
+                CoreError::Backend("backend".to_string()),
+                tonic::Code::Internal,
+            ),
+        ];
+
+        for (error, code) in test_cases {
+            let status = tonic::Status::from(error.clone());
+            assert_eq!(status.code(), code);
+            assert_eq!(status.message(), error.message());
+            assert_eq!(
+                status
+                    .metadata()
+                    .get(ERROR_KIND_KEY)
+                    .unwrap()
+                    .to_str()
+                    .unwrap(),
+                error.kind()
+            );
+
+            assert_eq!(CoreError::from(status), error);
+        }
+    }
+}
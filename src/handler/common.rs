@@ -1,10 +1,21 @@
 use std::sync::Arc;
 
-use crate::{database::database::Database, objectstorage::objectstorage::StorageHandler};
+use log::error;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    database::database::Database,
+    models::common_models::DatabaseModel,
+    notifications::{
+        events::{new_event_emitter, EventEmitter, EventType, LifecycleEvent},
+        webhook::WebhookDispatcher,
+    },
+    objectstorage::objectstorage::StorageHandler,
+};
 
 use super::{
     create::CreateHandler, delete::DeleteHandler, load::LoadHandler, read::ReadHandler,
-    update::UpdateHandler,
+    stats_cache::DatasetStatsCache, update::UpdateHandler,
 };
 
 /// Handles the standard actions required by the API
@@ -12,17 +23,44 @@ use super::{
 pub struct CommonHandler<T: Database + 'static> {
     pub database_client: Arc<T>,
     pub object_handler: Arc<dyn StorageHandler>,
+    pub webhook_dispatcher: Arc<WebhookDispatcher>,
+    pub event_emitter: Arc<dyn EventEmitter>,
+    pub clock: Arc<dyn Clock>,
+    pub dataset_stats_cache: Arc<DatasetStatsCache>,
 }
 
 impl<T: Database + 'static> CommonHandler<T> {
     pub async fn new(database_client: Arc<T>, object_storage: Arc<dyn StorageHandler>) -> Self {
+        let event_emitter = new_event_emitter().await;
+
         let common_handler = CommonHandler {
             database_client: database_client,
             object_handler: object_storage,
+            webhook_dispatcher: Arc::new(WebhookDispatcher::new()),
+            event_emitter: event_emitter,
+            clock: Arc::new(SystemClock {}),
+            dataset_stats_cache: Arc::new(DatasetStatsCache::new_from_config()),
         };
 
         return common_handler;
     }
+
+    /// Publishes a lifecycle event for the entry of type K with the given id
+    pub fn emit_event<'de, K: DatabaseModel<'de>>(&self, event_type: EventType, id: &str) {
+        let resource = match K::get_model_name() {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return;
+            }
+        };
+
+        self.event_emitter.emit(LifecycleEvent {
+            event_type: event_type,
+            resource: resource,
+            id: id.to_string(),
+        });
+    }
 }
 
 /// Wraps the specific handler into a single sturct
@@ -39,26 +77,51 @@ impl<T: Database + 'static> HandlerWrapper<T> {
         database_client: Arc<T>,
         object_handler: Arc<dyn StorageHandler>,
     ) -> Result<Self, tonic::Status> {
+        let webhook_dispatcher = Arc::new(WebhookDispatcher::new());
+        let event_emitter = new_event_emitter().await;
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock {});
+        let dataset_stats_cache = Arc::new(DatasetStatsCache::new_from_config());
+
         let handler_wrapper: HandlerWrapper<T> = HandlerWrapper {
             read_handler: ReadHandler {
                 database_client: database_client.clone(),
                 object_handler: object_handler.clone(),
+                webhook_dispatcher: webhook_dispatcher.clone(),
+                event_emitter: event_emitter.clone(),
+                clock: clock.clone(),
+                dataset_stats_cache: dataset_stats_cache.clone(),
             },
             update_handler: UpdateHandler {
                 database_client: database_client.clone(),
                 object_handler: object_handler.clone(),
+                webhook_dispatcher: webhook_dispatcher.clone(),
+                event_emitter: event_emitter.clone(),
+                clock: clock.clone(),
+                dataset_stats_cache: dataset_stats_cache.clone(),
             },
             delete_handler: DeleteHandler {
                 database_client: database_client.clone(),
                 object_handler: object_handler.clone(),
+                webhook_dispatcher: webhook_dispatcher.clone(),
+                event_emitter: event_emitter.clone(),
+                clock: clock.clone(),
+                dataset_stats_cache: dataset_stats_cache.clone(),
             },
             load_handler: LoadHandler {
                 database_client: database_client.clone(),
                 object_handler: object_handler.clone(),
+                webhook_dispatcher: webhook_dispatcher.clone(),
+                event_emitter: event_emitter.clone(),
+                clock: clock.clone(),
+                dataset_stats_cache: dataset_stats_cache.clone(),
             },
             create_handler: CreateHandler {
                 database_client: database_client.clone(),
                 object_handler: object_handler.clone(),
+                webhook_dispatcher: webhook_dispatcher.clone(),
+                event_emitter: event_emitter.clone(),
+                clock: clock.clone(),
+                dataset_stats_cache: dataset_stats_cache.clone(),
             },
         };
 
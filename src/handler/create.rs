@@ -1,21 +1,32 @@
 use crate::database::database::Database;
+use crate::error::CoreError;
 use crate::models::apitoken::APIToken;
-use crate::models::common_models::Right;
+use crate::models::common_models::{add_default_labels, Right, Status};
 use crate::models::dataset_model::DatasetEntry;
+use crate::models::dataset_object_group::DatasetObject;
 use crate::models::dataset_object_group::ObjectGroup;
 use crate::models::dataset_object_group::ObjectGroupRevision;
 use crate::models::dataset_version::DatasetVersion;
 use crate::models::project_model::ProjectEntry;
+use crate::models::storage_reference::StorageReference;
+use crate::notifications::events::EventType;
+use crate::validation::{
+    validate_create_objects, validate_metadata, validate_release_request, FieldViolations,
+};
 use bson::doc;
+use chrono::{DateTime, Utc};
 use futures::stream::FuturesUnordered;
 use futures::stream::StreamExt;
+use log::{error, warn};
+use std::time::Duration;
 
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::{
     CreateDatasetRequest, CreateObjectGroupRequest, CreateObjectGroupRevisionRequest,
-    CreateProjectRequest, ReleaseDatasetVersionRequest,
+    CreateObjectRequest, CreateProjectRequest, ReleaseDatasetVersionRequest,
 };
 
 use super::common::CommonHandler;
+use super::update::check_mutable;
 
 /// Handles create associated tasks for the individual models
 pub type CreateHandler<T> = CommonHandler<T>;
@@ -28,32 +39,155 @@ where
         &self,
         project: &CreateProjectRequest,
         user_id: String,
-    ) -> Result<ProjectEntry, tonic::Status> {
+    ) -> Result<ProjectEntry, CoreError> {
+        let mut violations = FieldViolations::new();
+        validate_metadata(&project.metadata, "metadata", &mut violations);
+        violations.into_result()?;
+
         let project_entry = ProjectEntry::new_from_proto_create(project, user_id)?;
-        return self.database_client.store(project_entry).await;
+        let inserted_project = self.database_client.store(project_entry).await?;
+        self.emit_event::<ProjectEntry>(EventType::Created, inserted_project.id.as_str());
+
+        return Ok(inserted_project);
+    }
+
+    /// Creates a project unless the user already has a project with the same name
+    /// In that case the existing project is returned, which allows provisioning scripts to create projects idempotently
+    pub async fn create_project_if_absent(
+        &self,
+        project: &CreateProjectRequest,
+        user_id: String,
+    ) -> Result<ProjectEntry, CoreError> {
+        let existing_project_query = doc! {
+            "users.user_id": user_id.as_str(),
+            "name": project.name.as_str(),
+        };
+        if self
+            .database_client
+            .exists::<ProjectEntry>(existing_project_query.clone())
+            .await?
+        {
+            let existing_project = self
+                .database_client
+                .find_one_by_key::<ProjectEntry>(existing_project_query)
+                .await?;
+            return Ok(existing_project);
+        }
+
+        return self.create_project(project, user_id).await;
     }
 
     pub async fn create_dataset(
         &self,
         dataset: &CreateDatasetRequest,
-    ) -> Result<DatasetEntry, tonic::Status> {
-        let dataset_entry = DatasetEntry::new_from_proto_create(dataset)?;
-        return self.database_client.store(dataset_entry).await;
+    ) -> Result<DatasetEntry, CoreError> {
+        let mut violations = FieldViolations::new();
+        validate_metadata(&dataset.metadata, "metadata", &mut violations);
+        violations.into_result()?;
+
+        let mut dataset_entry = DatasetEntry::new_from_proto_create(dataset, self.clock.now())?;
+        add_default_labels(&mut dataset_entry.labels);
+        let inserted_dataset = self.database_client.store(dataset_entry).await?;
+        self.emit_event::<DatasetEntry>(EventType::Created, inserted_dataset.id.as_str());
+
+        return Ok(inserted_dataset);
     }
 
     pub async fn create_object_group(
         &self,
         object_group_request: &CreateObjectGroupRequest,
-    ) -> Result<ObjectGroup, tonic::Status> {
-        let object_group = ObjectGroup::new_from_proto_create(object_group_request)?;
-        return self.database_client.store(object_group).await;
+    ) -> Result<ObjectGroup, CoreError> {
+        let mut violations = FieldViolations::new();
+        validate_metadata(&object_group_request.metadata, "metadata", &mut violations);
+        // The initial revision is validated before the object group is stored, it is created afterwards
+        if let Some(revision_request) = &object_group_request.object_group_revision {
+            validate_metadata(
+                &revision_request.metadata,
+                "object_group_revision.metadata",
+                &mut violations,
+            );
+            validate_create_objects(
+                &revision_request.objects,
+                "object_group_revision.objects",
+                &mut violations,
+            );
+        }
+        violations.into_result()?;
+
+        let dataset_query = doc! {
+            "id": object_group_request.dataset_id.as_str()
+        };
+        if !self
+            .database_client
+            .exists::<DatasetEntry>(dataset_query)
+            .await?
+        {
+            return Err(CoreError::NotFound(format!(
+                "dataset {} not found",
+                object_group_request.dataset_id
+            )));
+        }
+
+        let mut object_group = ObjectGroup::new_from_proto_create(object_group_request)?;
+        add_default_labels(&mut object_group.labels);
+        let inserted_object_group = self.database_client.store(object_group).await?;
+        self.emit_event::<ObjectGroup>(EventType::Created, inserted_object_group.id.as_str());
+
+        return Ok(inserted_object_group);
     }
 
     pub async fn create_revision_for_group(
         &self,
         revision_request: &CreateObjectGroupRevisionRequest,
         parent_object_group_id: &str,
-    ) -> Result<ObjectGroupRevision, tonic::Status> {
+    ) -> Result<ObjectGroupRevision, CoreError> {
+        return self
+            .create_revision(revision_request, parent_object_group_id, None)
+            .await;
+    }
+
+    /// Creates a temporary revision that is deleted together with its objects once the ttl has passed
+    /// The expiry is removed when the revision becomes part of a released dataset version
+    pub async fn create_expiring_revision_for_group(
+        &self,
+        revision_request: &CreateObjectGroupRevisionRequest,
+        parent_object_group_id: &str,
+        ttl: Duration,
+    ) -> Result<ObjectGroupRevision, CoreError> {
+        if ttl.as_secs() == 0 {
+            return Err(CoreError::InvalidArgument(
+                "ttl has to be at least one second".to_string(),
+            ));
+        }
+
+        let ttl = match chrono::Duration::from_std(ttl) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(CoreError::InvalidArgument("ttl out of range".to_string()));
+            }
+        };
+
+        return self
+            .create_revision(
+                revision_request,
+                parent_object_group_id,
+                Some(self.clock.now() + ttl),
+            )
+            .await;
+    }
+
+    async fn create_revision(
+        &self,
+        revision_request: &CreateObjectGroupRevisionRequest,
+        parent_object_group_id: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ObjectGroupRevision, CoreError> {
+        let mut violations = FieldViolations::new();
+        validate_metadata(&revision_request.metadata, "metadata", &mut violations);
+        validate_create_objects(&revision_request.objects, "objects", &mut violations);
+        violations.into_result()?;
+
         let query = doc! {
             "id": parent_object_group_id
         };
@@ -70,24 +204,295 @@ where
             .update_on_field::<ObjectGroup>(query, update)
             .await?;
 
-        let revision_entry = ObjectGroupRevision::new_from_proto_create(
+        // The project is part of the object storage key of the created objects
+        let dataset = self
+            .read_entry_by_id::<DatasetEntry>(object_group.dataset_id.as_str())
+            .await?;
+
+        let mut revision_entry = ObjectGroupRevision::new_from_proto_create(
             revision_request,
             &object_group,
+            dataset.project_id.as_str(),
             self.object_handler.get_bucket(),
+            self.clock.now(),
         )?;
-        return self.database_client.store(revision_entry).await;
+        revision_entry.expires_at = expires_at;
+        add_default_labels(&mut revision_entry.labels);
+        let inserted_revision = self.database_client.store(revision_entry).await?;
+        self.update_head(&inserted_revision).await?;
+        self.emit_event::<ObjectGroupRevision>(EventType::Created, inserted_revision.id.as_str());
+
+        return Ok(inserted_revision);
+    }
+
+    /// Points the head of the object group of the revision to the revision
+    /// The head is only moved if no newer revision has been created in the meantime, so that concurrently created
+    /// revisions can not move the head back to an older revision
+    pub async fn update_head(&self, revision: &ObjectGroupRevision) -> Result<(), CoreError> {
+        let query = doc! {
+            "id": revision.object_group_id.as_str(),
+            "revision_counter": revision.revision + 1
+        };
+        let update = doc! {
+            "$set": {
+                "head_id": revision.id.as_str()
+            }
+        };
+
+        self.database_client
+            .update_field::<ObjectGroup>(query, update)
+            .await?;
+
+        return Ok(());
+    }
+
+    /// Adds a new object to a revision that is not yet part of a released dataset version
+    #[allow(dead_code)]
+    pub async fn add_object_to_revision(
+        &self,
+        revision_id: &str,
+        object_request: &CreateObjectRequest,
+    ) -> Result<DatasetObject, CoreError> {
+        let revision: ObjectGroupRevision = self.read_entry_by_id(revision_id).await?;
+        check_mutable(&revision)?;
+
+        // The project is part of the object storage key of the created object
+        let dataset = self
+            .read_entry_by_id::<DatasetEntry>(revision.datasete_id.as_str())
+            .await?;
+        let object = DatasetObject::new_from_proto_create(
+            object_request,
+            dataset.project_id.as_str(),
+            dataset.id.clone(),
+            self.object_handler.get_bucket(),
+            self.clock.now(),
+        )?;
+
+        // Guards against a concurrent release of the revision between the check above and the update
+        let query = doc! {
+            "id": revision_id,
+            "dataset_versions": {"$size": 0}
+        };
+        let update = ObjectGroupRevision::add_object_update(&object)?;
+
+        let modified = self
+            .database_client
+            .update_field::<ObjectGroupRevision>(query, update)
+            .await?;
+        if modified == 0 {
+            let revision: ObjectGroupRevision = self.read_entry_by_id(revision_id).await?;
+            check_mutable(&revision)?;
+            return Err(CoreError::NotFound(format!(
+                "object group revision {} not found",
+                revision_id
+            )));
+        }
+
+        return Ok(object);
+    }
+
+    /// Forks an object group into another dataset without copying the stored data
+    /// The fork consists of a new object group with a copy of the current revision of the source group,
+    /// the objects of the copy reference the same storage locations as the source objects
+    /// The caller has to be authorized to read the source and to write the target dataset
+    pub async fn fork_object_group(
+        &self,
+        source_group_id: &str,
+        target_dataset_id: &str,
+    ) -> Result<(ObjectGroup, Option<ObjectGroupRevision>), CoreError> {
+        let dataset_query = doc! {
+            "id": target_dataset_id
+        };
+        if !self
+            .database_client
+            .exists::<DatasetEntry>(dataset_query)
+            .await?
+        {
+            return Err(CoreError::NotFound(format!(
+                "dataset {} not found",
+                target_dataset_id
+            )));
+        }
+
+        let (source_group, source_revision) = self
+            .read_object_group_with_current_revision(source_group_id)
+            .await?;
+
+        let object_group = source_group.new_fork(target_dataset_id, source_revision.is_some());
+        let mut inserted_object_group = self.database_client.store(object_group).await?;
+        self.emit_event::<ObjectGroup>(EventType::Created, inserted_object_group.id.as_str());
+
+        let inserted_revision = match source_revision {
+            Some(source_revision) => {
+                let revision = source_revision.new_fork(&inserted_object_group, self.clock.now());
+                for object in &revision.objects {
+                    self.add_storage_reference(object).await?;
+                }
+                let inserted_revision = self.database_client.store(revision).await?;
+                self.update_head(&inserted_revision).await?;
+                // The fork does not expire, the shared objects must not be removed as temporary objects by the
+                // object storage
+                if source_revision.expires_at.is_some() {
+                    if let Err(e) = self
+                        .set_revision_storage_tags(&inserted_revision, true)
+                        .await
+                    {
+                        warn!(
+                            "could not remove expiry tags of forked revision {}: {:?}",
+                            inserted_revision.id, e
+                        );
+                    }
+                }
+                inserted_object_group.head_id = inserted_revision.id.clone();
+                self.emit_event::<ObjectGroupRevision>(
+                    EventType::Created,
+                    inserted_revision.id.as_str(),
+                );
+                Some(inserted_revision)
+            }
+            None => None,
+        };
+
+        return Ok((inserted_object_group, inserted_revision));
+    }
+
+    /// Clones a dataset into a new dataset of the same project without copying the stored data
+    /// Every object group of the source dataset is forked into the clone together with its current revision, the
+    /// objects of the clone reference the same storage locations as the source objects. Versions are not cloned.
+    /// The caller has to be authorized to read the source dataset and to write its project
+    pub async fn clone_dataset(
+        &self,
+        source_dataset_id: &str,
+        new_name: &str,
+    ) -> Result<(DatasetEntry, Vec<ObjectGroup>), CoreError> {
+        let source_dataset = self
+            .read_entry_by_id::<DatasetEntry>(source_dataset_id)
+            .await?;
+        let source_object_groups = self
+            .read_from_parent_entry::<ObjectGroup>(source_dataset_id)
+            .await?;
+
+        let dataset = source_dataset.new_clone(new_name, self.clock.now());
+        let inserted_dataset = self.database_client.store(dataset).await?;
+        self.emit_event::<DatasetEntry>(EventType::Created, inserted_dataset.id.as_str());
+
+        let mut object_groups = Vec::with_capacity(source_object_groups.len());
+        // Object groups that are being deleted are not cloned
+        for source_object_group in source_object_groups
+            .into_iter()
+            .filter(|object_group| object_group.status != Status::Deleting)
+        {
+            let (object_group, _) = self
+                .fork_object_group(
+                    source_object_group.id.as_str(),
+                    inserted_dataset.id.as_str(),
+                )
+                .await?;
+            object_groups.push(object_group);
+        }
+
+        return Ok((inserted_dataset, object_groups));
+    }
+
+    /// Counts an additional reference to the storage location of a shared object
+    async fn add_storage_reference(&self, object: &DatasetObject) -> Result<(), CoreError> {
+        // Linked objects are not stored in the object storage
+        if object.origin.is_link() {
+            return Ok(());
+        }
+
+        let query = doc! {
+            "id": StorageReference::id_for_location(&object.location)
+        };
+        let update = doc! {
+            "$setOnInsert": {
+                "bucket": object.location.bucket.as_str(),
+                "key": object.location.key.as_str(),
+            },
+            "$inc": {
+                "ref_count": 1
+            }
+        };
+
+        self.database_client
+            .upsert::<StorageReference>(query, update)
+            .await?;
+
+        return Ok(());
+    }
+
+    /// Checks that none of the revisions belongs to another dataset, so that a version can not bundle revisions of
+    /// foreign datasets even if the caller is authorized to access them
+    async fn ensure_revisions_of_dataset(
+        &self,
+        dataset_id: &str,
+        revision_ids: &[String],
+    ) -> Result<(), CoreError> {
+        for revision_id_chunk in revision_ids.chunks(1000) {
+            let query = doc! {
+                "id": {
+                    "$in": revision_id_chunk
+                },
+                "datasete_id": {
+                    "$ne": dataset_id
+                }
+            };
+
+            let foreign_revisions = self
+                .database_client
+                .find_by_key::<ObjectGroupRevision>(query)
+                .await?;
+            if !foreign_revisions.is_empty() {
+                let foreign_revision_ids: Vec<String> = foreign_revisions
+                    .into_iter()
+                    .map(|revision| revision.id)
+                    .collect();
+                return Err(CoreError::InvalidArgument(format!(
+                    "revisions {} do not belong to dataset {}",
+                    foreign_revision_ids.join(", "),
+                    dataset_id
+                )));
+            }
+        }
+
+        return Ok(());
     }
 
     pub async fn create_datatset_version(
         &self,
         version_request: &ReleaseDatasetVersionRequest,
-    ) -> Result<DatasetVersion, tonic::Status> {
-        let dataset_version_entry = DatasetVersion::new_from_proto_create(version_request)?;
+    ) -> Result<DatasetVersion, CoreError> {
+        validate_release_request(version_request)?;
+        self.ensure_revisions_of_dataset(
+            version_request.dataset_id.as_str(),
+            &version_request.revision_ids,
+        )
+        .await?;
+
+        let dataset_version_entry =
+            DatasetVersion::new_from_proto_create(version_request, self.clock.now())?;
         let inserted_dataset_version = self.database_client.store(dataset_version_entry).await?;
 
+        // Released revisions must never expire, the expiry of temporary revisions is therefore removed
+        let mut expiring_revisions = Vec::new();
         let mut poll_revision_version_add = FuturesUnordered::new();
 
         for revision_id_chunk in version_request.revision_ids.chunks(1000) {
+            let expiring_query = doc! {
+                "id": {
+                    "$in": revision_id_chunk
+                },
+                "expires_at": {
+                    "$ne": null
+                }
+            };
+            expiring_revisions.append(
+                &mut self
+                    .database_client
+                    .find_by_key::<ObjectGroupRevision>(expiring_query)
+                    .await?,
+            );
+
             let query = doc! {
                 "id": {
                     "$in": revision_id_chunk
@@ -97,12 +502,15 @@ where
             let update = doc! {
                 "$addToSet": {
                     "dataset_versions": inserted_dataset_version.id.clone()
+                },
+                "$set": {
+                    "expires_at": null
                 }
             };
 
             let update_request = self
                 .database_client
-                .update_field::<ObjectGroupRevision>(query, update);
+                .update_fields::<ObjectGroupRevision>(query, update);
 
             poll_revision_version_add.push(update_request)
         }
@@ -111,6 +519,19 @@ where
             value?;
         }
 
+        for mut revision in expiring_revisions {
+            revision.expires_at = None;
+            // Objects that have not been uploaded yet have no tags that could be removed
+            if let Err(e) = self.set_revision_storage_tags(&revision, true).await {
+                warn!(
+                    "could not remove expiry tags of revision {}: {:?}",
+                    revision.id, e
+                );
+            }
+        }
+
+        self.emit_event::<DatasetVersion>(EventType::Created, inserted_dataset_version.id.as_str());
+
         return Ok(inserted_dataset_version);
     }
 
@@ -119,10 +540,41 @@ where
         user_id: &str,
         rights: Vec<Right>,
         project_id: &str,
-    ) -> Result<APIToken, tonic::Status> {
+    ) -> Result<APIToken, CoreError> {
         let api_token = APIToken::new(user_id, rights, project_id)?;
         let inserted_api_token = self.database_client.store::<APIToken>(api_token).await?;
 
         Ok(inserted_api_token)
     }
+
+    /// Replaces the secret of the api token with the given id
+    /// The previous secret is invalidated immediately, the returned token contains the new secret
+    pub async fn rotate_api_token(&self, token_id: &str) -> Result<APIToken, CoreError> {
+        let api_token = self.read_entry_by_id::<APIToken>(token_id).await?;
+        let rotated_api_token = api_token.new_rotated();
+
+        // Only replaces the secret that has been read to detect concurrent rotations
+        let query = doc! {
+            "id": token_id,
+            "token": api_token.token.as_str(),
+        };
+        let update = doc! {
+            "$set": {
+                "token": rotated_api_token.token.as_str()
+            }
+        };
+
+        let modified = self
+            .database_client
+            .update_field::<APIToken>(query, update)
+            .await?;
+        if modified == 0 {
+            return Err(CoreError::Conflict(format!(
+                "api token {} has been rotated concurrently",
+                token_id
+            )));
+        }
+
+        return Ok(rotated_api_token);
+    }
 }
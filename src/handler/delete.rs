@@ -1,13 +1,46 @@
-use bson::doc;
+use std::{collections::HashSet, time::Duration};
+
+use bson::{doc, to_bson};
+use chrono::{DateTime, SecondsFormat, Utc};
 use futures::stream::FuturesUnordered;
 use futures::stream::StreamExt;
+use log::error;
 
+use crate::error::CoreError;
+use crate::models::apitoken::APIToken;
+use crate::models::common_models::{DatabaseModel, SoftDeletable, Status};
 use crate::models::dataset_model::DatasetEntry;
-use crate::models::dataset_object_group::ObjectGroup;
+use crate::models::dataset_object_group::{DatasetObject, ObjectGroup};
 use crate::models::dataset_version::DatasetVersion;
+use crate::models::storage_reference::StorageReference;
+use crate::notifications::events::EventType;
+use crate::SETTINGS;
 use crate::{database::database::Database, models::dataset_object_group::ObjectGroupRevision};
 
 use super::common::CommonHandler;
+use super::update::check_mutable;
+
+/// Number of revisions the history compaction keeps if Revisions.Compaction.KeepRevisions is not configured
+const DEFAULT_KEEP_REVISIONS: i64 = 100;
+/// Number of times the status of an object group is read again if it is changed concurrently during a deletion
+const MAX_STATUS_UPDATE_ATTEMPTS: usize = 10;
+
+/// Reads the number of most recent revisions the history compaction keeps per object group
+/// Returns None unless Revisions.Compaction.Enabled is set, at least the head revision is always kept
+pub fn keep_revisions_from_config() -> Option<i64> {
+    let settings = SETTINGS.read().unwrap();
+    if !settings
+        .get_bool("Revisions.Compaction.Enabled")
+        .unwrap_or(false)
+    {
+        return None;
+    }
+
+    let keep_revisions = settings
+        .get_int("Revisions.Compaction.KeepRevisions")
+        .unwrap_or(DEFAULT_KEEP_REVISIONS);
+    return Some(keep_revisions.max(1));
+}
 
 /// Handles delete associated tasks for the individual models
 pub type DeleteHandler<T> = CommonHandler<T>;
@@ -16,23 +49,19 @@ impl<T> DeleteHandler<T>
 where
     T: Database,
 {
-    pub async fn delete_object_revision(&self, id: String) -> Result<(), tonic::Status> {
+    pub async fn delete_object_revision(&self, id: String) -> Result<(), CoreError> {
+        let object_revision: ObjectGroupRevision = self.read_entry_by_id(id.as_str()).await?;
+        check_mutable(&object_revision)?;
+
         self.update_status::<ObjectGroupRevision>(
             id.as_str(),
             &crate::models::common_models::Status::Deleting,
         )
         .await?;
-        let object_revision: ObjectGroupRevision = self.read_entry_by_id(id.as_str()).await?;
-
-        if object_revision.dataset_versions.len() != 0 {
-            return Err(tonic::Status::invalid_argument(
-                "object group revision could not be deleted, still has associated versions",
-            ));
-        }
 
         let mut delete_object_futures = FuturesUnordered::new();
         for object in object_revision.objects {
-            delete_object_futures.push(self.object_handler.delete_object(object.location));
+            delete_object_futures.push(self.delete_stored_object(object));
         }
 
         while let Some(value) = delete_object_futures.next().await {
@@ -40,22 +69,95 @@ where
         }
 
         let query = doc! {
-            "id": id
+            "id": id.as_str()
         };
 
         self.database_client
             .delete::<ObjectGroupRevision>(query)
             .await?;
+        self.refresh_head(object_revision.object_group_id.as_str())
+            .await?;
+        self.emit_event::<ObjectGroupRevision>(EventType::Deleted, id.as_str());
 
         return Ok(());
     }
 
-    pub async fn delete_object_group(&self, id: String) -> Result<(), tonic::Status> {
-        self.update_status::<ObjectGroup>(
-            id.as_str(),
-            &crate::models::common_models::Status::Deleting,
-        )
-        .await?;
+    /// Removes a single object from a revision that is not yet part of a released dataset version
+    /// The object is removed from the revision first, so that a failed storage deletion never leaves a dangling reference
+    #[allow(dead_code)]
+    pub async fn delete_object_from_revision(
+        &self,
+        revision_id: &str,
+        object_id: &str,
+    ) -> Result<(), CoreError> {
+        let revision: ObjectGroupRevision = self.read_entry_by_id(revision_id).await?;
+        check_mutable(&revision)?;
+
+        let object = match revision
+            .objects
+            .iter()
+            .find(|object| object.id == object_id)
+        {
+            Some(object) => object.clone(),
+            None => {
+                return Err(CoreError::NotFound(format!(
+                    "object {} not found in object group revision {}",
+                    object_id, revision_id
+                )))
+            }
+        };
+
+        // Guards against a concurrent release of the revision between the check above and the update
+        let query = doc! {
+            "id": revision_id,
+            "objects.id": object_id,
+            "dataset_versions": {"$size": 0}
+        };
+        let update = ObjectGroupRevision::remove_object_update(object_id);
+
+        let modified = self
+            .database_client
+            .update_field::<ObjectGroupRevision>(query, update)
+            .await?;
+        if modified == 0 {
+            let revision: ObjectGroupRevision = self.read_entry_by_id(revision_id).await?;
+            check_mutable(&revision)?;
+            return Err(CoreError::NotFound(format!(
+                "object {} not found in object group revision {}",
+                object_id, revision_id
+            )));
+        }
+
+        self.delete_stored_object(object).await?;
+
+        return Ok(());
+    }
+
+    pub async fn delete_object_group(&self, id: String) -> Result<(), CoreError> {
+        // The status is only replaced if it has not been changed in the meantime, a concurrent finish therefore
+        // either fails or is overwritten after the status has been read again
+        let mut attempts = 0;
+        let mut object_group = self.read_entry_by_id::<ObjectGroup>(id.as_str()).await?;
+        while object_group.status != Status::Deleting
+            && !self
+                .database_client
+                .compare_and_set_status::<ObjectGroup>(
+                    id.as_str(),
+                    object_group.status.clone(),
+                    Status::Deleting,
+                    self.clock.now(),
+                )
+                .await?
+        {
+            attempts += 1;
+            if attempts >= MAX_STATUS_UPDATE_ATTEMPTS {
+                return Err(CoreError::Conflict(format!(
+                    "the status of object group {} is changed concurrently, please retry the deletion",
+                    id
+                )));
+            }
+            object_group = self.read_entry_by_id::<ObjectGroup>(id.as_str()).await?;
+        }
         let revisions: Vec<ObjectGroupRevision> = self.read_from_parent_entry(id.as_str()).await?;
 
         let mut delete_object_futures = FuturesUnordered::new();
@@ -68,23 +170,23 @@ where
         }
 
         let query = doc! {
-            "id": id
+            "id": id.as_str()
         };
 
         self.database_client.delete::<ObjectGroup>(query).await?;
+        self.emit_event::<ObjectGroup>(EventType::Deleted, id.as_str());
 
         return Ok(());
     }
 
-    pub async fn delete_dataset_version(&self, id: String) -> Result<(), tonic::Status> {
-        self.database_client
-            .update_status::<DatasetVersion>(
-                id.as_str(),
-                crate::models::common_models::Status::Deleting,
-            )
+    pub async fn delete_dataset_version(&self, id: String) -> Result<(), CoreError> {
+        self.update_status::<DatasetVersion>(id.as_str(), &Status::Deleting)
             .await?;
 
-        let query = doc! {};
+        // Only the revisions that are part of the version are updated instead of the whole collection
+        let query = doc! {
+            "dataset_versions": id.as_str()
+        };
         let update = doc! {
             "$pull": {
                 "dataset_versions": id.as_str()
@@ -100,15 +202,12 @@ where
         };
 
         self.database_client.delete::<DatasetVersion>(query).await?;
+        self.emit_event::<DatasetVersion>(EventType::Deleted, id.as_str());
         return Ok(());
     }
 
-    pub async fn delete_dataset(&self, id: String) -> Result<(), tonic::Status> {
-        self.database_client
-            .update_status::<DatasetEntry>(
-                id.as_str(),
-                crate::models::common_models::Status::Deleting,
-            )
+    pub async fn delete_dataset(&self, id: String) -> Result<(), CoreError> {
+        self.update_status::<DatasetEntry>(id.as_str(), &Status::Deleting)
             .await?;
         let dataset_versions = self
             .read_from_parent_entry::<DatasetVersion>(id.as_str())
@@ -136,10 +235,270 @@ where
         }
 
         let query = doc! {
-            "id": id
+            "id": id.as_str()
         };
 
         self.database_client.delete::<DatasetEntry>(query).await?;
+        self.emit_event::<DatasetEntry>(EventType::Deleted, id.as_str());
+        return Ok(());
+    }
+
+    /// Hard-deletes all soft-deleted entries that have been marked as deleting for longer than the given duration
+    /// Returns the number of purged entries
+    pub async fn purge_expired(&self, older_than: Duration) -> Result<u64, CoreError> {
+        let older_than = match chrono::Duration::from_std(older_than) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(CoreError::InvalidArgument(
+                    "retention period out of range".to_string(),
+                ));
+            }
+        };
+
+        return self
+            .purge_deleted_before(self.clock.now() - older_than)
+            .await;
+    }
+
+    /// Hard-deletes all entries that have been marked as deleting before the given point in time
+    /// Entries that fail to be deleted are logged and skipped, they are retried on the next purge
+    pub async fn purge_deleted_before(&self, cutoff: DateTime<Utc>) -> Result<u64, CoreError> {
+        let mut purged = 0;
+
+        // Datasets and object groups are purged first, their deletion already removes all contained entries
+        for dataset in self.read_expired::<DatasetEntry>(cutoff).await? {
+            purged += purge_result(
+                dataset.id.as_str(),
+                self.delete_dataset(dataset.id.clone()).await,
+            );
+        }
+
+        for object_group in self.read_expired::<ObjectGroup>(cutoff).await? {
+            purged += purge_result(
+                object_group.id.as_str(),
+                self.delete_object_group(object_group.id.clone()).await,
+            );
+        }
+
+        for revision in self.read_expired::<ObjectGroupRevision>(cutoff).await? {
+            purged += purge_result(
+                revision.id.as_str(),
+                self.delete_object_revision(revision.id.clone()).await,
+            );
+        }
+
+        for version in self.read_expired::<DatasetVersion>(cutoff).await? {
+            purged += purge_result(
+                version.id.as_str(),
+                self.delete_dataset_version(version.id.clone()).await,
+            );
+        }
+
+        return Ok(purged);
+    }
+
+    /// Deletes all revisions whose ttl has passed before the given point in time together with their objects
+    /// Released revisions never expire. Returns the number of deleted revisions, failed deletions are retried
+    /// on the next sweep
+    pub async fn delete_expired_revisions(&self, now: DateTime<Utc>) -> Result<u64, CoreError> {
+        let query = doc! {
+            "dataset_versions": {
+                "$size": 0
+            },
+            "expires_at": {
+                "$ne": null
+            }
+        };
+
+        // Timestamps are stored as strings, the expiry is therefore compared after reading the revisions
+        let expired_revisions: Vec<ObjectGroupRevision> = self
+            .database_client
+            .find_by_key::<ObjectGroupRevision>(query)
+            .await?
+            .into_iter()
+            .filter(|revision| match revision.expires_at {
+                Some(expires_at) => expires_at < now,
+                None => false,
+            })
+            .collect();
+        if expired_revisions.is_empty() {
+            return Ok(0);
+        }
+
+        // The head revision is kept like in compact_revisions, it expires once a newer revision has been added
+        let object_group_ids: Vec<&str> = expired_revisions
+            .iter()
+            .map(|revision| revision.object_group_id.as_str())
+            .collect();
+        let head_ids: HashSet<String> = self
+            .database_client
+            .find_by_key::<ObjectGroup>(doc! {"id": {"$in": object_group_ids}})
+            .await?
+            .into_iter()
+            .map(|object_group| object_group.head_id)
+            .collect();
+
+        let mut deleted = 0;
+        for revision in expired_revisions {
+            if head_ids.contains(&revision.id) {
+                continue;
+            }
+            deleted += purge_result(
+                revision.id.as_str(),
+                self.delete_object_revision(revision.id.clone()).await,
+            );
+        }
+
+        return Ok(deleted);
+    }
+
+    /// Compacts the revision history of an object group to the number of revisions configured in
+    /// Revisions.Compaction.KeepRevisions, fails with failed_precondition if compaction is not enabled
+    pub async fn compact_revision_history(&self, object_group_id: &str) -> Result<u64, CoreError> {
+        let keep_revisions = match keep_revisions_from_config() {
+            Some(value) => value,
+            None => {
+                return Err(CoreError::FailedPrecondition(
+                    "revision history compaction is disabled".to_string(),
+                ))
+            }
+        };
+
+        return self
+            .compact_revisions(object_group_id, keep_revisions)
+            .await;
+    }
+
+    /// Deletes all revisions of an object group except the keep_revisions most recent ones together with their objects
+    /// Revisions that are part of a released dataset version are always kept. Returns the number of deleted revisions,
+    /// failed deletions are logged and retried on the next compaction
+    pub async fn compact_revisions(
+        &self,
+        object_group_id: &str,
+        keep_revisions: i64,
+    ) -> Result<u64, CoreError> {
+        let object_group = self
+            .read_entry_by_id::<ObjectGroup>(object_group_id)
+            .await?;
+
+        // The head revision has the number revision_counter - 1, it is kept even if keep_revisions is zero
+        let query = doc! {
+            "object_group_id": object_group_id,
+            "revision": {
+                "$lt": object_group.revision_counter - keep_revisions.max(1)
+            },
+            "dataset_versions": {
+                "$size": 0
+            }
+        };
+        let compacted_revisions = self
+            .database_client
+            .find_by_key::<ObjectGroupRevision>(query)
+            .await?;
+
+        let mut deleted = 0;
+        for revision in compacted_revisions {
+            deleted += purge_result(
+                revision.id.as_str(),
+                self.delete_object_revision(revision.id.clone()).await,
+            );
+        }
+
+        return Ok(deleted);
+    }
+
+    /// Deletes all api tokens of the user with a single delete, the tokens are invalidated immediately
+    /// Returns the number of revoked tokens
+    pub async fn revoke_all_user_tokens(&self, user_id: &str) -> Result<u64, CoreError> {
+        let revoked = self
+            .database_client
+            .delete_many::<APIToken>(doc! {"user_id": user_id})
+            .await?;
+
+        return Ok(revoked);
+    }
+
+    /// Deletes the stored data of an object that is removed from its revision
+    /// If the storage location is shared with other objects only the additional reference is removed
+    async fn delete_stored_object(&self, object: DatasetObject) -> Result<(), CoreError> {
+        // Linked objects are not stored in the object storage
+        if object.origin.is_link() {
+            return Ok(());
+        }
+
+        let reference_id = StorageReference::id_for_location(&object.location);
+        let query = doc! {
+            "id": reference_id.as_str(),
+            "ref_count": {"$gt": 0}
+        };
+        let update = doc! {
+            "$inc": {
+                "ref_count": -1
+            }
+        };
+
+        let released_references = self
+            .database_client
+            .update_field::<StorageReference>(query, update)
+            .await?;
+        if released_references > 0 {
+            return Ok(());
+        }
+
+        self.object_handler.delete_object(object.location).await?;
+        self.database_client
+            .delete::<StorageReference>(doc! {"id": reference_id.as_str()})
+            .await?;
+
         return Ok(());
     }
+
+    /// Reads all entries of type K that have been marked as deleting before the cutoff
+    async fn read_expired<'de, K: DatabaseModel<'de> + SoftDeletable>(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<Vec<K>, CoreError> {
+        let status = match to_bson(&Status::Deleting) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(CoreError::Backend(
+                    "error when reading deleted entries".to_string(),
+                ));
+            }
+        };
+
+        // Timestamps are stored as RFC 3339 strings, which only sort chronologically down to whole seconds. The query
+        // therefore uses the cutoff truncated to seconds and the exact cutoff is checked after reading the entries
+        let query = doc! {
+            "status": status,
+            "deleted_at": {
+                "$lt": cutoff.to_rfc3339_opts(SecondsFormat::Secs, true)
+            },
+        };
+
+        let entries = self
+            .database_client
+            .find_by_key::<K>(query)
+            .await?
+            .into_iter()
+            .filter(|entry| match entry.deleted_at() {
+                Some(deleted_at) => deleted_at < cutoff,
+                None => false,
+            })
+            .collect();
+
+        return Ok(entries);
+    }
+}
+
+fn purge_result(id: &str, result: Result<(), CoreError>) -> u64 {
+    match result {
+        Ok(_) => return 1,
+        Err(e) => {
+            error!("could not purge deleted entry {}: {:?}", id, e);
+            return 0;
+        }
+    }
 }
@@ -3,17 +3,39 @@ mod server_test {
     use std::sync::Arc;
 
     use bson::doc;
+    use prost::Message;
+    use scienceobjectsdb_rust_api::sciobjectsdbapi::models;
     use scienceobjectsdb_rust_api::sciobjectsdbapi::models::v1::Version;
     use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
     use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::ReleaseDatasetVersionRequest;
+    use tonic::metadata::MetadataMap;
 
+    use crate::auth::authenticator::AuthHandler;
+    use crate::auth::project_authorization_handler::{ProjectAuthzHandler, API_TOKEN_ENTRY_KEY};
+    use crate::clock::{Clock, MockClock};
+    use crate::database::database::Database;
+    use crate::database::memory_database::MemoryDatabase;
     use crate::handler::common::CommonHandler;
-    use crate::models::common_models::DatabaseModel;
-    use crate::models::dataset_model::DatasetEntry;
-    use crate::models::dataset_object_group::ObjectGroupRevision;
+    use crate::handler::load::{
+        multipart_threshold_from_config, recommend_part_size_for_target, PartRecommendation,
+        TEMPORARY_TAG_KEY,
+    };
+    use crate::models::common_models::{
+        add_default_labels_from, object_key_from_template, project_key_template, DatabaseModel,
+        Label, Metadata, OriginType, Resource, Right, SortOrder, Status, DEFAULT_KEY_TEMPLATE,
+        ORIGIN_LINK_LABEL_KEY,
+    };
+    use crate::models::dataset_model::{DatasetEntry, DatasetStats, DatasetSummary};
+    use crate::models::dataset_object_group::{ObjectGroup, ObjectGroupRevision, RevisionDiff};
     use crate::models::dataset_version::DatasetVersion;
-    use crate::test_util::init;
-    use crate::{database, objectstorage};
+    use crate::models::project_model::ProjectEntry;
+    use crate::models::storage_reference::StorageReference;
+    use crate::notifications::events::{EventType, LifecycleEvent, NatsEventEmitter};
+    use crate::notifications::webhook::{ObjectGroupEvent, WebhookDispatcher};
+    use crate::objectstorage::objectstorage::StorageHandler;
+    use crate::test_util::{init, mock_server};
+    use crate::validation::{decode_field_violations, MetadataLimits, METADATA_SCHEMA_LABEL_KEY};
+    use crate::{database, objectstorage, SETTINGS};
 
     async fn init_common_handler_for_test() -> CommonHandler<database::mongo_connector::MongoHandler>
     {
@@ -32,6 +54,14 @@ mod server_test {
         return common_handler;
     }
 
+    async fn init_memory_handler_for_test() -> CommonHandler<MemoryDatabase> {
+        init::test_init();
+
+        let s3_client = objectstorage::s3_objectstorage::S3Handler::new();
+
+        return CommonHandler::new(Arc::new(MemoryDatabase::new()), Arc::new(s3_client)).await;
+    }
+
     #[tokio::test]
     async fn dataset_test() {
         let handler = init_common_handler_for_test().await;
@@ -229,4 +259,3634 @@ mod server_test {
 
         assert_eq!(inserted_revision2, read_revision)
     }
+
+    #[tokio::test]
+    async fn released_revision_immutable() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let object1 = services::v1::CreateObjectRequest {
+            content_len: 3,
+            filename: "testfile1.bin".to_string(),
+            filetype: "bin".to_string(),
+            ..Default::default()
+        };
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![object1],
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let object_id = inserted_revision.objects[0].id.clone();
+        let metadata = vec![Metadata {
+            key: "testkey".to_string(),
+            metadata: "testmetadata".to_string(),
+            ..Default::default()
+        }];
+
+        handler
+            .update_object_metadata(object_id.as_str(), &metadata)
+            .await
+            .unwrap();
+
+        let release_version_request = ReleaseDatasetVersionRequest {
+            dataset_id: created_dataset.id,
+            revision_ids: vec![inserted_revision.id.clone()],
+            version: Some(Version {
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        handler
+            .create_datatset_version(&release_version_request)
+            .await
+            .unwrap();
+
+        let update_error = handler
+            .update_object_metadata(object_id.as_str(), &metadata)
+            .await
+            .unwrap_err();
+        assert_eq!(update_error.code(), tonic::Code::FailedPrecondition);
+
+        let delete_error = handler
+            .delete_object_revision(inserted_revision.id.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(delete_error.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn revision_dataset_versions() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let object1 = services::v1::CreateObjectRequest {
+            content_len: 3,
+            filename: "testfile1.bin".to_string(),
+            filetype: "bin".to_string(),
+            ..Default::default()
+        };
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![object1],
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let release_version_request = ReleaseDatasetVersionRequest {
+            dataset_id: created_dataset.id,
+            revision_ids: vec![inserted_revision.id.clone()],
+            version: Some(Version {
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let version1 = handler
+            .create_datatset_version(&release_version_request)
+            .await
+            .unwrap();
+        let version2 = handler
+            .create_datatset_version(&release_version_request)
+            .await
+            .unwrap();
+
+        let mut version_ids: Vec<String> = handler
+            .read_revision_dataset_versions(inserted_revision.id.as_str())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| x.id)
+            .collect();
+        version_ids.sort();
+
+        let mut expected_ids = vec![version1.id, version2.id];
+        expected_ids.sort();
+
+        assert_eq!(version_ids, expected_ids);
+    }
+
+    #[tokio::test]
+    async fn object_origin() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let external_link = "https://example.com/data/testfile2.bin".to_string();
+
+        let storage_object = services::v1::CreateObjectRequest {
+            content_len: 3,
+            filename: "testfile1.bin".to_string(),
+            filetype: "bin".to_string(),
+            ..Default::default()
+        };
+
+        let link_object = services::v1::CreateObjectRequest {
+            content_len: 5,
+            filename: "testfile2.bin".to_string(),
+            filetype: "bin".to_string(),
+            labels: vec![models::v1::Label {
+                key: ORIGIN_LINK_LABEL_KEY.to_string(),
+                value: external_link.clone(),
+            }],
+            ..Default::default()
+        };
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![storage_object, link_object],
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let storage_object = &inserted_revision.objects[0];
+        let link_object = &inserted_revision.objects[1];
+
+        assert_eq!(storage_object.origin.origin_type, OriginType::ObjectStorage);
+        assert_eq!(link_object.origin.origin_type, OriginType::ObjectLink);
+        assert_eq!(link_object.origin.link, external_link);
+
+        let storage_download_link = handler
+            .create_download_link(storage_object.id.as_str())
+            .await
+            .unwrap();
+        assert_ne!(storage_download_link, external_link);
+        assert!(storage_download_link.contains(storage_object.location.key.as_str()));
+
+        let link_download_link = handler
+            .create_download_link(link_object.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(link_download_link, external_link);
+
+        let upload_error = handler
+            .create_upload_link(link_object.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(upload_error.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn object_group_finish_webhook() {
+        let mut handler = init_common_handler_for_test().await;
+        let (webhook_url, mut webhook_receiver) = mock_server::start_mock_http_server().await;
+        handler.webhook_dispatcher = Arc::new(WebhookDispatcher::new_with_url(Some(webhook_url)));
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            project_id: "testproject".to_string(),
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        handler
+            .finish_object_group_upload(created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let payload =
+            tokio::time::timeout(std::time::Duration::from_secs(5), webhook_receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        let event: ObjectGroupEvent = serde_json::from_str(payload.as_str()).unwrap();
+
+        assert_eq!(
+            event,
+            ObjectGroupEvent {
+                project_id: "testproject".to_string(),
+                dataset_id: created_dataset.id,
+                object_group_id: created_object_group.id,
+                status: Status::Available,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn dataset_created_event() {
+        let mut handler = init_common_handler_for_test().await;
+        let (nats_url, mut nats_receiver) = mock_server::start_mock_nats_server().await;
+        let event_emitter =
+            NatsEventEmitter::new(nats_url.as_str(), "test.{resource}.{event}".to_string())
+                .await
+                .unwrap();
+        handler.event_emitter = Arc::new(event_emitter);
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let (subject, payload) =
+            tokio::time::timeout(std::time::Duration::from_secs(5), nats_receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+        let event: LifecycleEvent = serde_json::from_str(payload.as_str()).unwrap();
+
+        assert_eq!(subject, "test.Dataset.created");
+        assert_eq!(
+            event,
+            LifecycleEvent {
+                event_type: EventType::Created,
+                resource: DatasetEntry::get_model_name().unwrap(),
+                id: created_dataset.id,
+            }
+        );
+    }
+
+    #[test]
+    fn multipart_part_size_recommendation() {
+        const MB: i64 = 1024 * 1024;
+
+        let test_cases = vec![
+            (0, 10000, 5 * MB, 1),
+            (1, 10000, 5 * MB, 1),
+            (5 * MB, 10000, 5 * MB, 1),
+            (5 * MB + 1, 10000, 5 * MB, 2),
+            (100 * MB, 10000, 5 * MB, 20),
+            (100 * 1024 * MB, 10000, 11 * MB, 9310),
+            (100 * MB, 4, 25 * MB, 4),
+            (100 * MB, 100000, 5 * MB, 20),
+        ];
+
+        for (content_len, target_part_count, part_size, part_count) in test_cases {
+            assert_eq!(
+                recommend_part_size_for_target(content_len, target_part_count),
+                PartRecommendation {
+                    part_size: part_size,
+                    part_count: part_count,
+                }
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_object() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let empty_object = services::v1::CreateObjectRequest {
+            content_len: 0,
+            filename: "empty.bin".to_string(),
+            filetype: "bin".to_string(),
+            ..Default::default()
+        };
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![empty_object],
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let object_id = inserted_revision.objects[0].id.clone();
+
+        let multipart_error = handler
+            .init_multipart_upload(object_id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(multipart_error.code(), tonic::Code::InvalidArgument);
+
+        let upload_link = handler
+            .create_upload_link(object_id.as_str())
+            .await
+            .unwrap();
+
+        let client = reqwest::Client::new();
+        let resp = client.put(upload_link).body("").send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let download_link = handler
+            .create_download_link(object_id.as_str())
+            .await
+            .unwrap();
+
+        let resp = client.get(download_link).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert!(resp.bytes().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn move_revision() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+        let other_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let source_object_group = handler.create_object_group(&object_group).await.unwrap();
+        let target_object_group = handler.create_object_group(&object_group).await.unwrap();
+        let other_dataset_object_group = handler
+            .create_object_group(&services::v1::CreateObjectGroupRequest {
+                dataset_id: other_dataset.id.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, source_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let move_error = handler
+            .move_revision(
+                inserted_revision.id.as_str(),
+                other_dataset_object_group.id.as_str(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(move_error.code(), tonic::Code::FailedPrecondition);
+
+        let moved_revision = handler
+            .move_revision(
+                inserted_revision.id.as_str(),
+                target_object_group.id.as_str(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(moved_revision.object_group_id, target_object_group.id);
+        assert_eq!(moved_revision.datasete_id, created_dataset.id);
+
+        let target_revisions = handler
+            .read_from_parent_entry::<ObjectGroupRevision>(target_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(target_revisions.len(), 1);
+        assert_eq!(target_revisions[0].id, inserted_revision.id);
+
+        let source_revisions = handler
+            .read_from_parent_entry::<ObjectGroupRevision>(source_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert!(source_revisions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn find_object() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let objects = (0..3)
+            .map(|i| services::v1::CreateObjectRequest {
+                content_len: i,
+                filename: format!("testfile{}.bin", i),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            })
+            .collect();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: objects,
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let expected_object = inserted_revision.objects[1].clone();
+        let found_object = handler
+            .find_object(expected_object.id.as_str())
+            .await
+            .unwrap();
+
+        assert_eq!(found_object, expected_object);
+    }
+
+    #[tokio::test]
+    async fn object_key_template() {
+        assert_eq!(
+            object_key_from_template(
+                "{project}/{dataset}/{object}/{filename}",
+                "project1",
+                "dataset1",
+                "object1",
+                "file.bin"
+            ),
+            "project1/dataset1/object1/file.bin"
+        );
+        assert_eq!(
+            object_key_from_template(
+                "data/{dataset}-{filename}",
+                "project1",
+                "dataset1",
+                "object1",
+                "file.bin"
+            ),
+            "data/dataset1-file.bin"
+        );
+
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            project_id: "testproject".to_string(),
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 3,
+                filename: "testfile1.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let object = &inserted_revision.objects[0];
+
+        assert_eq!(
+            object.location.key,
+            object_key_from_template(
+                DEFAULT_KEY_TEMPLATE,
+                "testproject",
+                created_dataset.id.as_str(),
+                object.id.as_str(),
+                "testfile1.bin"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn download_by_index() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let objects = (0..2)
+            .map(|i| services::v1::CreateObjectRequest {
+                content_len: 8,
+                filename: format!("testfile{}.bin", i),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            })
+            .collect();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: objects,
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let expected_object = inserted_revision.objects[1].clone();
+
+        let upload_link = handler
+            .create_upload_link(expected_object.id.as_str())
+            .await
+            .unwrap();
+        let client = reqwest::Client::new();
+        let resp = client
+            .put(upload_link)
+            .body("testdata")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let (object, download_link) = handler
+            .create_download_link_by_index(created_object_group.id.as_str(), 1)
+            .await
+            .unwrap();
+        assert_eq!(object, expected_object);
+
+        let resp = client.get(download_link).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.text().await.unwrap(), "testdata");
+
+        let index_error = handler
+            .create_download_link_by_index(created_object_group.id.as_str(), 2)
+            .await
+            .unwrap_err();
+        assert_eq!(index_error.code(), tonic::Code::OutOfRange);
+    }
+
+    #[tokio::test]
+    async fn purge_expired() {
+        let start = chrono::DateTime::parse_from_rfc3339("2021-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let clock = Arc::new(MockClock::new(start));
+        let mut handler = init_common_handler_for_test().await;
+        handler.clock = clock.clone();
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+        let kept_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 3,
+                filename: "testfile1.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        handler
+            .update_status::<ObjectGroup>(created_object_group.id.as_str(), &Status::Deleting)
+            .await
+            .unwrap();
+
+        let deleted_object_group = handler
+            .read_entry_by_id::<ObjectGroup>(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(deleted_object_group.deleted_at, Some(start));
+
+        let retention = std::time::Duration::from_secs(24 * 60 * 60);
+        let purged = handler.purge_expired(retention).await.unwrap();
+        assert_eq!(purged, 0);
+
+        clock.advance(chrono::Duration::hours(23));
+        let purged = handler.purge_expired(retention).await.unwrap();
+        assert_eq!(purged, 0);
+
+        clock.advance(chrono::Duration::hours(2));
+        let purged = handler.purge_expired(retention).await.unwrap();
+        assert_eq!(purged, 1);
+
+        let object_group_error = handler
+            .read_entry_by_id::<ObjectGroup>(created_object_group.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(object_group_error.code(), tonic::Code::NotFound);
+
+        let revision_error = handler
+            .read_entry_by_id::<ObjectGroupRevision>(inserted_revision.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(revision_error.code(), tonic::Code::NotFound);
+
+        handler
+            .read_entry_by_id::<ObjectGroup>(kept_object_group.id.as_str())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_versioned_update() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+        assert_eq!(created_dataset.rev_version, 0);
+
+        let updated_dataset = handler
+            .update_versioned::<DatasetEntry, _>(
+                created_dataset.id.as_str(),
+                created_dataset.rev_version,
+                |mut dataset| {
+                    dataset.description = "first update".to_string();
+                    Ok(dataset)
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated_dataset.rev_version, 1);
+
+        let stale_update_error = handler
+            .update_versioned::<DatasetEntry, _>(
+                created_dataset.id.as_str(),
+                created_dataset.rev_version,
+                |mut dataset| {
+                    dataset.description = "second update".to_string();
+                    Ok(dataset)
+                },
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(stale_update_error.code(), tonic::Code::Aborted);
+
+        let read_dataset = handler
+            .read_entry_by_id::<DatasetEntry>(created_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(read_dataset.description, "first update");
+        assert_eq!(read_dataset.rev_version, 1);
+    }
+
+    #[tokio::test]
+    async fn delete_object_from_revision() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let objects = (0..2)
+            .map(|i| services::v1::CreateObjectRequest {
+                content_len: 8,
+                filename: format!("testfile{}.bin", i),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            })
+            .collect();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: objects,
+            ..Default::default()
+        };
+
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let client = reqwest::Client::new();
+        for object in &inserted_revision.objects {
+            let upload_link = handler
+                .create_upload_link(object.id.as_str())
+                .await
+                .unwrap();
+            let resp = client
+                .put(upload_link)
+                .body("testdata")
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 200);
+        }
+
+        let deleted_object = inserted_revision.objects[0].clone();
+        let kept_object = inserted_revision.objects[1].clone();
+
+        handler
+            .delete_object_from_revision(inserted_revision.id.as_str(), deleted_object.id.as_str())
+            .await
+            .unwrap();
+
+        let read_revision = handler
+            .read_entry_by_id::<ObjectGroupRevision>(inserted_revision.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(read_revision.objects_count, 1);
+        assert_eq!(read_revision.objects, vec![kept_object.clone()]);
+
+        let download_link = handler
+            .create_download_link(kept_object.id.as_str())
+            .await
+            .unwrap();
+        let resp = client.get(download_link).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.text().await.unwrap(), "testdata");
+
+        let delete_error = handler
+            .delete_object_from_revision(inserted_revision.id.as_str(), deleted_object.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(delete_error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn paginated_user_projects() {
+        let handler = init_common_handler_for_test().await;
+        let user_id = uuid::Uuid::new_v4().to_string();
+
+        let mut expected_ids = Vec::new();
+        for i in 0..7 {
+            let name = match i % 2 {
+                0 => format!("Even project {}", i),
+                _ => format!("odd project {}", i),
+            };
+            let project_request = services::v1::CreateProjectRequest {
+                name: name,
+                ..Default::default()
+            };
+            let project = handler
+                .create_project(&project_request, user_id.clone())
+                .await
+                .unwrap();
+            expected_ids.push(project.id);
+        }
+        handler
+            .create_project(
+                &services::v1::CreateProjectRequest {
+                    name: "other user project".to_string(),
+                    ..Default::default()
+                },
+                uuid::Uuid::new_v4().to_string(),
+            )
+            .await
+            .unwrap();
+        expected_ids.sort();
+
+        let mut read_ids = Vec::new();
+        let mut page_token = "".to_string();
+        let mut pages = 0;
+        loop {
+            let (projects, next_page_token) = handler
+                .read_user_projects_page(
+                    user_id.as_str(),
+                    3,
+                    page_token.as_str(),
+                    None,
+                    &SortOrder::default(),
+                )
+                .await
+                .unwrap();
+            assert!(projects.len() <= 3);
+            read_ids.extend(projects.into_iter().map(|project| project.id));
+            pages += 1;
+
+            if next_page_token.is_empty() {
+                break;
+            }
+            page_token = next_page_token;
+        }
+
+        assert_eq!(pages, 3);
+        assert_eq!(read_ids, expected_ids);
+
+        let (filtered_projects, next_page_token) = handler
+            .read_user_projects_page(
+                user_id.as_str(),
+                10,
+                "",
+                Some("EVEN"),
+                &SortOrder::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(filtered_projects.len(), 4);
+        assert!(next_page_token.is_empty());
+
+        let page_size_error = handler
+            .read_user_projects_page(user_id.as_str(), 0, "", None, &SortOrder::default())
+            .await
+            .unwrap_err();
+        assert_eq!(page_size_error.code(), tonic::Code::InvalidArgument);
+
+        // Page sizes above Server.MaxPageSize are clamped
+        let (all_projects, next_page_token) = handler
+            .read_user_projects_page(user_id.as_str(), i64::MAX, "", None, &SortOrder::default())
+            .await
+            .unwrap();
+        assert_eq!(all_projects.len(), expected_ids.len());
+        assert!(next_page_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn project_object_groups() {
+        let handler = init_common_handler_for_test().await;
+        let project_id = uuid::Uuid::new_v4().to_string();
+
+        let mut expected_ids = Vec::new();
+        let dataset_project_ids = vec![project_id.clone(), project_id.clone(), "other".to_string()];
+        for dataset_project_id in dataset_project_ids {
+            let dataset = handler
+                .create_dataset(&services::v1::CreateDatasetRequest {
+                    project_id: dataset_project_id.clone(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+
+            for _ in 0..2 {
+                let object_group = handler
+                    .create_object_group(&services::v1::CreateObjectGroupRequest {
+                        dataset_id: dataset.id.clone(),
+                        ..Default::default()
+                    })
+                    .await
+                    .unwrap();
+                if dataset_project_id == project_id {
+                    expected_ids.push(object_group.id);
+                }
+            }
+        }
+        expected_ids.sort();
+
+        let mut read_ids = Vec::new();
+        let mut page_token = "".to_string();
+        loop {
+            let (object_groups, next_page_token) = handler
+                .read_project_object_groups_page(
+                    project_id.as_str(),
+                    3,
+                    page_token.as_str(),
+                    &SortOrder::default(),
+                )
+                .await
+                .unwrap();
+            assert!(object_groups.len() <= 3);
+            read_ids.extend(
+                object_groups
+                    .into_iter()
+                    .map(|object_group| object_group.id),
+            );
+
+            if next_page_token.is_empty() {
+                break;
+            }
+            page_token = next_page_token;
+        }
+        assert_eq!(read_ids, expected_ids);
+
+        let (object_groups, next_page_token) = handler
+            .read_project_object_groups_page("emptyproject", 3, "", &SortOrder::default())
+            .await
+            .unwrap();
+        assert!(object_groups.is_empty());
+        assert!(next_page_token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fork_object_group() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let source_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+        let target_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: source_dataset.id.clone(),
+            name: "source group".to_string(),
+            ..Default::default()
+        };
+        let source_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 8,
+                filename: "testfile.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let source_revision = handler
+            .create_revision_for_group(&revision_request, source_group.id.as_str())
+            .await
+            .unwrap();
+        let source_object = source_revision.objects[0].clone();
+
+        let upload_link = handler
+            .create_upload_link(source_object.id.as_str())
+            .await
+            .unwrap();
+        let client = reqwest::Client::new();
+        let resp = client
+            .put(upload_link)
+            .body("testdata")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let (forked_group, forked_revision) = handler
+            .fork_object_group(source_group.id.as_str(), target_dataset.id.as_str())
+            .await
+            .unwrap();
+        let forked_revision = forked_revision.unwrap();
+        assert_ne!(forked_group.id, source_group.id);
+        assert_eq!(forked_group.dataset_id, target_dataset.id);
+        assert_eq!(forked_group.name, source_group.name);
+        assert_eq!(forked_revision.object_group_id, forked_group.id);
+        assert_eq!(forked_revision.objects_count, 1);
+
+        let forked_object = forked_revision.objects[0].clone();
+        assert_ne!(forked_object.id, source_object.id);
+        assert_eq!(forked_object.location, source_object.location);
+
+        let current_revision = handler
+            .read_current_revision(forked_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(current_revision.id, forked_revision.id);
+
+        // Deleting the source must not delete the data that is still referenced by the fork
+        handler
+            .delete_object_group(source_group.id.clone())
+            .await
+            .unwrap();
+
+        let download_link = handler
+            .create_download_link(forked_object.id.as_str())
+            .await
+            .unwrap();
+        let resp = client.get(download_link).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.text().await.unwrap(), "testdata");
+
+        let fork_error = handler
+            .fork_object_group(
+                forked_group.id.as_str(),
+                uuid::Uuid::new_v4().to_string().as_str(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(fork_error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn shared_object_references() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let source_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+        let target_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: source_dataset.id.clone(),
+            ..Default::default()
+        };
+        let source_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 8,
+                filename: "testfile.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let source_revision = handler
+            .create_revision_for_group(&revision_request, source_group.id.as_str())
+            .await
+            .unwrap();
+        let source_object = source_revision.objects[0].clone();
+
+        let upload_link = handler
+            .create_upload_link(source_object.id.as_str())
+            .await
+            .unwrap();
+        let client = reqwest::Client::new();
+        let resp = client
+            .put(upload_link)
+            .body("testdata")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let (_, forked_revision) = handler
+            .fork_object_group(source_group.id.as_str(), target_dataset.id.as_str())
+            .await
+            .unwrap();
+        let forked_revision = forked_revision.unwrap();
+
+        let reference_id = StorageReference::id_for_location(&source_object.location);
+        let reference = handler
+            .read_entry_by_id::<StorageReference>(reference_id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(reference.ref_count, 1);
+
+        // The source still references the data after the fork has been deleted
+        handler
+            .delete_object_revision(forked_revision.id.clone())
+            .await
+            .unwrap();
+
+        let reference = handler
+            .read_entry_by_id::<StorageReference>(reference_id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(reference.ref_count, 0);
+
+        // The link is created from the location, so that it can be reused after the last object has been deleted
+        let download_link = handler
+            .object_handler
+            .create_download_link(source_object.location.clone())
+            .await
+            .unwrap();
+        let resp = client.get(download_link.clone()).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.text().await.unwrap(), "testdata");
+
+        // Deleting the last reference deletes the data
+        handler
+            .delete_object_revision(source_revision.id.clone())
+            .await
+            .unwrap();
+
+        let resp = client.get(download_link).send().await.unwrap();
+        assert_eq!(resp.status(), 404);
+
+        let reference_error = handler
+            .read_entry_by_id::<StorageReference>(reference_id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(reference_error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn revision_manifest() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let objects = (0..3)
+            .map(|i| services::v1::CreateObjectRequest {
+                content_len: 9,
+                filename: format!("testfile{}.bin", i),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: objects,
+            ..Default::default()
+        };
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let client = reqwest::Client::new();
+        for (i, object) in inserted_revision.objects.iter().enumerate() {
+            let upload_link = handler
+                .create_upload_link(object.id.as_str())
+                .await
+                .unwrap();
+            let resp = client
+                .put(upload_link)
+                .body(format!("testdata{}", i))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 200);
+        }
+
+        let manifest = handler
+            .create_revision_manifest(inserted_revision.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(manifest.len(), 3);
+
+        for (i, entry) in manifest.iter().enumerate() {
+            assert_eq!(entry.object_id, inserted_revision.objects[i].id);
+            assert_eq!(entry.filename, format!("testfile{}.bin", i));
+            assert_eq!(entry.size, 9);
+
+            let resp = client.get(entry.url.as_str()).send().await.unwrap();
+            assert_eq!(resp.status(), 200);
+            assert_eq!(resp.text().await.unwrap(), format!("testdata{}", i));
+        }
+
+        let group_manifest = handler
+            .create_object_group_manifest(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let filenames: Vec<String> = group_manifest
+            .into_iter()
+            .map(|entry| entry.filename)
+            .collect();
+        let expected_filenames: Vec<String> =
+            manifest.into_iter().map(|entry| entry.filename).collect();
+        assert_eq!(filenames, expected_filenames);
+    }
+
+    #[tokio::test]
+    async fn rotate_api_token() {
+        let handler = init_common_handler_for_test().await;
+        SETTINGS
+            .write()
+            .unwrap()
+            .set(
+                "Authentication.OAuth2Auth.UserInfoEndpoint",
+                "http://localhost/userinfo",
+            )
+            .unwrap();
+
+        let project_id = uuid::Uuid::new_v4().to_string();
+        let api_token = handler
+            .create_api_token("testuser", vec![Right::Read], project_id.as_str())
+            .await
+            .unwrap();
+
+        let rotated_api_token = handler
+            .rotate_api_token(api_token.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(rotated_api_token.id, api_token.id);
+        assert_eq!(rotated_api_token.user_id, api_token.user_id);
+        assert_eq!(rotated_api_token.project_id, api_token.project_id);
+        assert_eq!(rotated_api_token.rights, api_token.rights);
+        assert_ne!(rotated_api_token.token, api_token.token);
+
+        let authz_handler = ProjectAuthzHandler::new(handler.database_client.clone()).unwrap();
+
+        let mut old_metadata = MetadataMap::new();
+        old_metadata.insert(API_TOKEN_ENTRY_KEY, api_token.token.parse().unwrap());
+        let error = authz_handler
+            .authorize(
+                &old_metadata,
+                Resource::Project,
+                Right::Read,
+                project_id.clone(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Unauthenticated);
+
+        let mut new_metadata = MetadataMap::new();
+        new_metadata.insert(
+            API_TOKEN_ENTRY_KEY,
+            rotated_api_token.token.parse().unwrap(),
+        );
+        authz_handler
+            .authorize(
+                &new_metadata,
+                Resource::Project,
+                Right::Read,
+                project_id.clone(),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn head_object() {
+        let handler = init_common_handler_for_test().await;
+        let created_dataset = handler
+            .create_dataset(&services::v1::CreateDatasetRequest {
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let created_object_group = handler
+            .create_object_group(&services::v1::CreateObjectGroupRequest {
+                dataset_id: created_dataset.id.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let test_data = "testdata for head requests";
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: test_data.len() as i64,
+                filename: "testfile.txt".to_string(),
+                filetype: "txt".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let object_id = inserted_revision.objects[0].id.clone();
+
+        let error = handler.head_object(object_id.as_str()).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::NotFound);
+
+        let upload_link = handler
+            .create_upload_link(object_id.as_str())
+            .await
+            .unwrap();
+        let resp = reqwest::Client::new()
+            .put(upload_link)
+            .body(test_data)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let (object, object_head) = handler.head_object(object_id.as_str()).await.unwrap();
+        assert_eq!(object.id, object_id);
+        assert_eq!(object_head.size, test_data.len() as i64);
+        assert!(object_head.etag.is_some());
+        assert!(object_head.last_modified.is_some());
+    }
+
+    #[tokio::test]
+    async fn default_labels() {
+        // A local configuration is used, default labels in SETTINGS would be added to all entries of the other tests
+        let mut settings = config::Config::default();
+        settings
+            .set("Defaults.Labels.default_label_test", "default")
+            .unwrap();
+        settings
+            .set("Defaults.Labels.default_label_override_test", "default")
+            .unwrap();
+
+        let mut labels = vec![Label {
+            key: "default_label_override_test".to_string(),
+            value: "client".to_string(),
+        }];
+        add_default_labels_from(&settings, &mut labels);
+
+        let label_value = |key: &str| {
+            labels
+                .iter()
+                .filter(|label| label.key == key)
+                .map(|label| label.value.clone())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(label_value("default_label_test"), vec!["default"]);
+        assert_eq!(label_value("default_label_override_test"), vec!["client"]);
+    }
+
+    #[tokio::test]
+    async fn generated_object_group_name() {
+        let handler = init_common_handler_for_test().await;
+        let created_dataset = handler
+            .create_dataset(&services::v1::CreateDatasetRequest {
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let unnamed_group = handler
+            .create_object_group(&services::v1::CreateObjectGroupRequest {
+                dataset_id: created_dataset.id.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            unnamed_group.name,
+            format!("group-{}", &unnamed_group.id[..8])
+        );
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                filename: "measurement.csv".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let file_named_group = handler
+            .create_object_group(&services::v1::CreateObjectGroupRequest {
+                dataset_id: created_dataset.id.clone(),
+                object_group_revision: Some(revision_request),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(file_named_group.name, "measurement.csv");
+
+        let named_group = handler
+            .create_object_group(&services::v1::CreateObjectGroupRequest {
+                dataset_id: created_dataset.id.clone(),
+                name: "named group".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(named_group.name, "named group");
+    }
+
+    #[tokio::test]
+    async fn memory_database_handler_flow() {
+        let handler = init_memory_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            name: "memory dataset".to_string(),
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+        let read_dataset = handler
+            .read_entry_by_id::<DatasetEntry>(created_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(read_dataset, created_dataset);
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let objects = (0..3)
+            .map(|i| services::v1::CreateObjectRequest {
+                content_len: i,
+                filename: format!("testfile{}.bin", i),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: objects,
+            ..Default::default()
+        };
+        let inserted_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let current_revision = handler
+            .read_current_revision(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(current_revision, inserted_revision);
+
+        let expected_object = inserted_revision.objects[1].clone();
+        let found_object = handler
+            .find_object(expected_object.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(found_object, expected_object);
+
+        let deleted_object = inserted_revision.objects[0].clone();
+        handler
+            .delete_object_from_revision(inserted_revision.id.as_str(), deleted_object.id.as_str())
+            .await
+            .unwrap();
+
+        let read_revision = handler
+            .read_entry_by_id::<ObjectGroupRevision>(inserted_revision.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(read_revision.objects_count, 2);
+        assert_eq!(
+            read_revision.objects,
+            inserted_revision.objects[1..].to_vec()
+        );
+
+        let delete_error = handler
+            .delete_object_from_revision(inserted_revision.id.as_str(), deleted_object.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(delete_error.code(), tonic::Code::NotFound);
+
+        handler
+            .delete_dataset(created_dataset.id.clone())
+            .await
+            .unwrap();
+
+        let read_error = handler
+            .read_entry_by_id::<DatasetEntry>(created_dataset.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(read_error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn user_datasets() {
+        let handler = init_common_handler_for_test().await;
+        let user_id = uuid::Uuid::new_v4().to_string();
+        let other_user_id = uuid::Uuid::new_v4().to_string();
+
+        let project_request = services::v1::CreateProjectRequest {
+            ..Default::default()
+        };
+        let own_project = handler
+            .create_project(&project_request, user_id.clone())
+            .await
+            .unwrap();
+        let shared_project = handler
+            .create_project(&project_request, other_user_id.clone())
+            .await
+            .unwrap();
+        let other_project = handler
+            .create_project(&project_request, other_user_id.clone())
+            .await
+            .unwrap();
+        handler
+            .add_user_to_project(&services::v1::AddUserToProjectRequest {
+                project_id: shared_project.id.clone(),
+                user_id: user_id.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut expected_datasets = Vec::new();
+        for project in vec![&own_project, &shared_project, &other_project] {
+            for i in 0..3 {
+                let dataset_request = services::v1::CreateDatasetRequest {
+                    name: format!("dataset {}", i),
+                    project_id: project.id.clone(),
+                    ..Default::default()
+                };
+                let dataset = handler.create_dataset(&dataset_request).await.unwrap();
+                if project.id != other_project.id {
+                    expected_datasets.push((dataset.id, project.id.clone()));
+                }
+            }
+        }
+        expected_datasets.sort();
+
+        let mut read_datasets = Vec::new();
+        let mut page_token = "".to_string();
+        loop {
+            let (datasets, next_page_token) = handler
+                .read_user_datasets_page::<DatasetEntry>(
+                    user_id.as_str(),
+                    4,
+                    page_token.as_str(),
+                    &SortOrder::default(),
+                )
+                .await
+                .unwrap();
+            assert!(datasets.len() <= 4);
+            read_datasets.extend(
+                datasets
+                    .into_iter()
+                    .map(|dataset| (dataset.id, dataset.project_id)),
+            );
+
+            if next_page_token.is_empty() {
+                break;
+            }
+            page_token = next_page_token;
+        }
+
+        assert_eq!(read_datasets, expected_datasets);
+    }
+
+    #[tokio::test]
+    async fn dataset_stats() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let empty_stats = handler
+            .read_dataset_stats(created_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(empty_stats, DatasetStats::default());
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let mut revisions = Vec::new();
+        for revision_index in 1..=2 {
+            let objects = (0..revision_index + 1)
+                .map(|i| services::v1::CreateObjectRequest {
+                    content_len: 10 * revision_index + i,
+                    filename: format!("testfile{}.bin", i),
+                    filetype: "bin".to_string(),
+                    ..Default::default()
+                })
+                .collect();
+            let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+                objects: objects,
+                ..Default::default()
+            };
+            let revision = handler
+                .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+                .await
+                .unwrap();
+            revisions.push(revision);
+        }
+
+        let expected_stats = DatasetStats {
+            objects_count: revisions
+                .iter()
+                .map(|revision| revision.objects_count)
+                .sum(),
+            content_len: revisions
+                .iter()
+                .flat_map(|revision| revision.objects.iter())
+                .map(|object| object.content_len)
+                .sum(),
+        };
+        assert_eq!(expected_stats.objects_count, 5);
+
+        let stats = handler
+            .read_dataset_stats(created_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(stats, expected_stats);
+    }
+
+    #[tokio::test]
+    async fn expiring_revisions() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 3,
+                filename: "testfile1.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let ttl_error = handler
+            .create_expiring_revision_for_group(
+                &revision_request,
+                created_object_group.id.as_str(),
+                std::time::Duration::from_secs(0),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(ttl_error.code(), tonic::Code::InvalidArgument);
+
+        let expiring_revision = handler
+            .create_expiring_revision_for_group(
+                &revision_request,
+                created_object_group.id.as_str(),
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert!(expiring_revision.expires_at.is_some());
+
+        let released_revision = handler
+            .create_expiring_revision_for_group(
+                &revision_request,
+                created_object_group.id.as_str(),
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        let permanent_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert!(permanent_revision.expires_at.is_none());
+
+        let release_version_request = ReleaseDatasetVersionRequest {
+            dataset_id: created_dataset.id.clone(),
+            revision_ids: vec![released_revision.id.clone()],
+            version: Some(Version {
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        handler
+            .create_datatset_version(&release_version_request)
+            .await
+            .unwrap();
+
+        let deleted = handler
+            .delete_expired_revisions(chrono::Utc::now())
+            .await
+            .unwrap();
+        assert_eq!(deleted, 0);
+
+        let deleted = handler
+            .delete_expired_revisions(chrono::Utc::now() + chrono::Duration::seconds(2))
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+
+        let expired_error = handler
+            .read_entry_by_id::<ObjectGroupRevision>(expiring_revision.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(expired_error.code(), tonic::Code::NotFound);
+
+        let released_revision = handler
+            .read_entry_by_id::<ObjectGroupRevision>(released_revision.id.as_str())
+            .await
+            .unwrap();
+        assert!(released_revision.expires_at.is_none());
+        handler
+            .read_entry_by_id::<ObjectGroupRevision>(permanent_revision.id.as_str())
+            .await
+            .unwrap();
+
+        // The head revision is kept until a newer revision has been added
+        let expiring_head = handler
+            .create_expiring_revision_for_group(
+                &revision_request,
+                created_object_group.id.as_str(),
+                std::time::Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        let deleted = handler
+            .delete_expired_revisions(chrono::Utc::now() + chrono::Duration::seconds(2))
+            .await
+            .unwrap();
+        assert_eq!(deleted, 0);
+        handler
+            .read_entry_by_id::<ObjectGroupRevision>(expiring_head.id.as_str())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn children_of_missing_parent() {
+        let handler = init_memory_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_groups = handler
+            .read_children_of_existing_parent::<DatasetEntry, ObjectGroup>(
+                created_dataset.id.as_str(),
+            )
+            .await
+            .unwrap();
+        assert!(object_groups.is_empty());
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        handler.create_object_group(&object_group).await.unwrap();
+
+        let object_groups = handler
+            .read_children_of_existing_parent::<DatasetEntry, ObjectGroup>(
+                created_dataset.id.as_str(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(object_groups.len(), 1);
+
+        let missing_dataset_error = handler
+            .read_children_of_existing_parent::<DatasetEntry, ObjectGroup>("missing-dataset")
+            .await
+            .unwrap_err();
+        assert_eq!(missing_dataset_error.code(), tonic::Code::NotFound);
+
+        let missing_dataset_versions = handler
+            .read_from_parent_entry::<DatasetVersion>("missing-dataset")
+            .await
+            .unwrap();
+        assert!(missing_dataset_versions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mock_clock_expiry() {
+        init::test_init();
+
+        let start = chrono::DateTime::parse_from_rfc3339("2021-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let clock = Arc::new(MockClock::new(start));
+        let storage_handler =
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new());
+        let mut handler =
+            CommonHandler::new(Arc::new(MemoryDatabase::new()), storage_handler.clone()).await;
+        handler.clock = clock.clone();
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 3,
+                filename: "testfile1.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let expiring_revision = handler
+            .create_expiring_revision_for_group(
+                &revision_request,
+                created_object_group.id.as_str(),
+                std::time::Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+        assert_eq!(expiring_revision.date_create, Some(start));
+        assert_eq!(expiring_revision.objects[0].created, Some(start));
+        assert_eq!(
+            expiring_revision.expires_at,
+            Some(start + chrono::Duration::seconds(60))
+        );
+        // The head revision never expires, the expiring revision is replaced as head by a newer revision
+        handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        clock.advance(chrono::Duration::seconds(60));
+        let deleted = handler.delete_expired_revisions(clock.now()).await.unwrap();
+        assert_eq!(deleted, 0);
+
+        clock.advance(chrono::Duration::seconds(1));
+        let deleted = handler.delete_expired_revisions(clock.now()).await.unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(storage_handler.call_count("delete_object"), 1);
+
+        let expired_error = handler
+            .read_entry_by_id::<ObjectGroupRevision>(expiring_revision.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(expired_error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn find_objects() {
+        let handler = init_memory_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let objects = (0..2)
+            .map(|i| services::v1::CreateObjectRequest {
+                content_len: 3,
+                filename: format!("testfile{}.bin", i),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: objects,
+            ..Default::default()
+        };
+
+        let first_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let second_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let ids = vec![
+            second_revision.objects[1].id.clone(),
+            first_revision.objects[0].id.clone(),
+            first_revision.objects[1].id.clone(),
+        ];
+        let found_objects = handler.find_objects(ids.as_slice()).await.unwrap();
+        assert_eq!(
+            found_objects,
+            vec![
+                second_revision.objects[1].clone(),
+                first_revision.objects[0].clone(),
+                first_revision.objects[1].clone(),
+            ]
+        );
+
+        let missing_ids = vec![ids[0].clone(), "missing-object".to_string()];
+        let missing_error = handler
+            .find_objects(missing_ids.as_slice())
+            .await
+            .unwrap_err();
+        assert_eq!(missing_error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn immutable_dataset_fields() {
+        let handler = init_memory_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            project_id: "own-project".to_string(),
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let move_error = handler
+            .update_dataset_fields(
+                created_dataset.id.as_str(),
+                created_dataset.rev_version,
+                doc! {"name": "moved", "project_id": "foreign-project"},
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(move_error.code(), tonic::Code::InvalidArgument);
+
+        for field in vec!["id", "created", "status"] {
+            let update_error = handler
+                .update_dataset_fields(
+                    created_dataset.id.as_str(),
+                    created_dataset.rev_version,
+                    doc! {field: "changed"},
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(update_error.code(), tonic::Code::InvalidArgument);
+        }
+
+        handler
+            .update_dataset_fields(
+                created_dataset.id.as_str(),
+                created_dataset.rev_version,
+                doc! {"name": "renamed"},
+            )
+            .await
+            .unwrap();
+
+        let read_dataset = handler
+            .read_entry_by_id::<DatasetEntry>(created_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(read_dataset.name, "renamed");
+        assert_eq!(read_dataset.project_id, "own-project");
+    }
+
+    #[tokio::test]
+    async fn release_version_tags_all_revisions() {
+        let handler = init_common_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 3,
+                filename: "testfile1.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let mut revision_ids = Vec::new();
+        for _ in 0..5 {
+            let revision = handler
+                .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+                .await
+                .unwrap();
+            revision_ids.push(revision.id);
+        }
+
+        let release_version_request = ReleaseDatasetVersionRequest {
+            dataset_id: created_dataset.id,
+            revision_ids: revision_ids.clone(),
+            version: Some(Version {
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let version = handler
+            .create_datatset_version(&release_version_request)
+            .await
+            .unwrap();
+
+        for revision_id in revision_ids {
+            let revision = handler
+                .read_entry_by_id::<ObjectGroupRevision>(revision_id.as_str())
+                .await
+                .unwrap();
+            assert_eq!(revision.dataset_versions, vec![version.id.clone()]);
+        }
+    }
+
+    #[tokio::test]
+    async fn finish_with_missing_upload() {
+        init::test_init();
+
+        let storage_handler =
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new());
+        let handler =
+            CommonHandler::new(Arc::new(MemoryDatabase::new()), storage_handler.clone()).await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![
+                services::v1::CreateObjectRequest {
+                    content_len: 3,
+                    filename: "uploaded.bin".to_string(),
+                    filetype: "bin".to_string(),
+                    ..Default::default()
+                },
+                services::v1::CreateObjectRequest {
+                    content_len: 5,
+                    filename: "missing.bin".to_string(),
+                    filetype: "bin".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        storage_handler.put_object(revision.objects[0].location.key.as_str(), 3);
+
+        let error = handler
+            .finish_object_group_upload(created_object_group.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::FailedPrecondition);
+        assert!(error.message().contains("missing.bin"));
+        assert!(!error.message().contains("uploaded.bin"));
+
+        let read_object_group = handler
+            .read_entry_by_id::<ObjectGroup>(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_ne!(read_object_group.status, Status::Available);
+
+        storage_handler.put_object(revision.objects[1].location.key.as_str(), 5);
+        handler
+            .finish_object_group_upload(created_object_group.id.as_str())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_delete_prevents_finish() {
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+        assert_eq!(created_object_group.status, Status::Initializing);
+
+        // The delete wins the race and moves the object group to Deleting first
+        let changed = handler
+            .database_client
+            .compare_and_set_status::<ObjectGroup>(
+                created_object_group.id.as_str(),
+                Status::Initializing,
+                Status::Deleting,
+                handler.clock.now(),
+            )
+            .await
+            .unwrap();
+        assert!(changed);
+
+        // A finish that has read the object group before the delete can not flip it to Available
+        let changed = handler
+            .database_client
+            .compare_and_set_status::<ObjectGroup>(
+                created_object_group.id.as_str(),
+                Status::Initializing,
+                Status::Available,
+                handler.clock.now(),
+            )
+            .await
+            .unwrap();
+        assert!(!changed);
+
+        let error = handler
+            .finish_object_group_upload(created_object_group.id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::FailedPrecondition);
+
+        let read_object_group = handler
+            .read_entry_by_id::<ObjectGroup>(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(read_object_group.status, Status::Deleting);
+    }
+
+    #[tokio::test]
+    async fn revisions_by_storage_key() {
+        let storage_handler =
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new());
+        let handler =
+            CommonHandler::new(Arc::new(MemoryDatabase::new()), storage_handler.clone()).await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 3,
+                filename: "testfile1.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let object = &revision.objects[0];
+        storage_handler.put_object(object.location.key.as_str(), 3);
+
+        let revisions = handler
+            .read_revisions_by_storage_key(object.location.key.as_str())
+            .await
+            .unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].id, revision.id);
+        assert_eq!(revisions[0].objects[0].id, object.id);
+
+        let error = handler
+            .read_revisions_by_storage_key("missing/key")
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn objects_count_consistency() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let object_request = services::v1::CreateObjectRequest {
+            content_len: 3,
+            filename: "object.bin".to_string(),
+            filetype: "bin".to_string(),
+            ..Default::default()
+        };
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![object_request.clone(), object_request.clone()],
+            ..Default::default()
+        };
+        let revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let added_object = handler
+            .add_object_to_revision(revision.id.as_str(), &object_request)
+            .await
+            .unwrap();
+        handler
+            .add_object_to_revision(revision.id.as_str(), &object_request)
+            .await
+            .unwrap();
+        handler
+            .delete_object_from_revision(revision.id.as_str(), revision.objects[0].id.as_str())
+            .await
+            .unwrap();
+        handler
+            .delete_object_from_revision(revision.id.as_str(), added_object.id.as_str())
+            .await
+            .unwrap();
+        handler
+            .add_object_to_revision(revision.id.as_str(), &object_request)
+            .await
+            .unwrap();
+
+        let read_revision = handler
+            .read_entry_by_id::<ObjectGroupRevision>(revision.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(read_revision.objects.len(), 3);
+        assert_eq!(
+            read_revision.objects_count,
+            read_revision.objects.len() as i64
+        );
+        assert!(!read_revision
+            .objects
+            .iter()
+            .any(|object| object.id == added_object.id));
+    }
+
+    #[tokio::test]
+    async fn cached_dataset_stats() {
+        init::test_init();
+
+        let start = chrono::DateTime::parse_from_rfc3339("2021-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+        let clock = Arc::new(MockClock::new(start));
+        let storage_handler =
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new());
+        let mut handler =
+            CommonHandler::new(Arc::new(MemoryDatabase::new()), storage_handler.clone()).await;
+        handler.clock = clock.clone();
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let object_request = |content_len: i64| services::v1::CreateObjectRequest {
+            content_len: content_len,
+            filename: "object.bin".to_string(),
+            filetype: "bin".to_string(),
+            ..Default::default()
+        };
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![object_request(3), object_request(7)],
+            ..Default::default()
+        };
+        let revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let mut uploaded_bytes = 0;
+        for object in revision.objects.iter() {
+            storage_handler.put_object(object.location.key.as_str(), object.content_len);
+            uploaded_bytes += object.content_len;
+        }
+
+        let stats = handler
+            .read_dataset_stats_cached(created_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(stats.content_len, uploaded_bytes);
+        assert_eq!(stats.objects_count, 2);
+
+        // The cached statistics are returned until they expire
+        handler
+            .add_object_to_revision(revision.id.as_str(), &object_request(5))
+            .await
+            .unwrap();
+        let cached_stats = handler
+            .read_dataset_stats_cached(created_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(cached_stats, stats);
+
+        clock.advance(chrono::Duration::minutes(5));
+        let updated_stats = handler
+            .read_dataset_stats_cached(created_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(updated_stats.content_len, uploaded_bytes + 5);
+        assert_eq!(updated_stats.objects_count, 3);
+    }
+
+    #[tokio::test]
+    async fn project_key_prefix() {
+        assert_eq!(
+            project_key_template("data/{dataset}-{filename}", true),
+            "{project}/data/{dataset}-{filename}"
+        );
+        assert_eq!(
+            project_key_template(DEFAULT_KEY_TEMPLATE, true),
+            DEFAULT_KEY_TEMPLATE
+        );
+        assert_eq!(
+            project_key_template("data/{dataset}-{filename}", false),
+            "data/{dataset}-{filename}"
+        );
+
+        init::test_init();
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            project_id: "isolatedproject".to_string(),
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let object_request = services::v1::CreateObjectRequest {
+            content_len: 3,
+            filename: "object.bin".to_string(),
+            filetype: "bin".to_string(),
+            ..Default::default()
+        };
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![object_request.clone()],
+            ..Default::default()
+        };
+        let revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let added_object = handler
+            .add_object_to_revision(revision.id.as_str(), &object_request)
+            .await
+            .unwrap();
+
+        for object in revision
+            .objects
+            .iter()
+            .chain(std::iter::once(&added_object))
+        {
+            assert!(object.location.key.starts_with("isolatedproject/"));
+        }
+    }
+
+    #[tokio::test]
+    async fn object_group_head() {
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            ..Default::default()
+        };
+        let first_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let read_object_group = handler
+            .read_entry_by_id::<ObjectGroup>(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(read_object_group.head_id, first_revision.id);
+
+        let second_revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let read_object_group = handler
+            .read_entry_by_id::<ObjectGroup>(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(read_object_group.head_id, second_revision.id);
+        assert_eq!(read_object_group.to_proto().head_id, second_revision.id);
+    }
+
+    #[tokio::test]
+    async fn repair_object_groups() {
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+        let intact_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            ..Default::default()
+        };
+        let mut revisions = Vec::new();
+        for object_group_id in [&created_object_group.id, &intact_object_group.id].iter() {
+            for _ in 0..2 {
+                let revision = handler
+                    .create_revision_for_group(&revision_request, object_group_id.as_str())
+                    .await
+                    .unwrap();
+                revisions.push(revision);
+            }
+        }
+
+        // Simulates an object group that has been created before its head was maintained
+        handler
+            .database_client
+            .update_field::<ObjectGroup>(
+                doc! {"id": created_object_group.id.as_str()},
+                doc! {"$set": {"head_id": revisions[0].id.as_str(), "revision_counter": 5}},
+            )
+            .await
+            .unwrap();
+
+        let repairs = handler.repair_object_groups().await.unwrap();
+        assert_eq!(repairs.len(), 1);
+        assert_eq!(repairs[0].object_group_id, created_object_group.id);
+        assert_eq!(repairs[0].previous_head_id, revisions[0].id);
+        assert_eq!(repairs[0].previous_revision_counter, 5);
+
+        let repaired_object_group = handler
+            .read_entry_by_id::<ObjectGroup>(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(repaired_object_group.head_id, revisions[1].id);
+        assert_eq!(repaired_object_group.revision_counter, 2);
+
+        assert!(handler.repair_object_groups().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn clone_dataset() {
+        init::test_init();
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            name: "source".to_string(),
+            project_id: "testproject".to_string(),
+            ..Default::default()
+        };
+        let source_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: source_dataset.id.clone(),
+            ..Default::default()
+        };
+        let source_object_group = handler.create_object_group(&object_group).await.unwrap();
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 3,
+                filename: "object.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let source_revision = handler
+            .create_revision_for_group(&revision_request, source_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let (cloned_dataset, cloned_object_groups) = handler
+            .clone_dataset(source_dataset.id.as_str(), "experiment")
+            .await
+            .unwrap();
+        assert_ne!(cloned_dataset.id, source_dataset.id);
+        assert_eq!(cloned_dataset.name, "experiment");
+        assert_eq!(cloned_dataset.project_id, "testproject");
+
+        let listed_object_groups = handler
+            .read_from_parent_entry::<ObjectGroup>(cloned_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(listed_object_groups, cloned_object_groups);
+        assert_eq!(listed_object_groups.len(), 1);
+        assert_ne!(listed_object_groups[0].id, source_object_group.id);
+
+        let source_object_groups = handler
+            .read_from_parent_entry::<ObjectGroup>(source_dataset.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(source_object_groups.len(), 1);
+        assert_eq!(source_object_groups[0].id, source_object_group.id);
+
+        // The cloned objects share the stored data with the source objects
+        let cloned_revision = handler
+            .read_current_revision(listed_object_groups[0].id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(cloned_revision.datasete_id, cloned_dataset.id);
+        assert_ne!(cloned_revision.objects[0].id, source_revision.objects[0].id);
+        assert_eq!(
+            cloned_revision.objects[0].location,
+            source_revision.objects[0].location
+        );
+    }
+
+    #[tokio::test]
+    async fn release_foreign_revision() {
+        init::test_init();
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let mut revision_ids = Vec::new();
+        let mut dataset_ids = Vec::new();
+        for _ in 0..2 {
+            let dataset_request = services::v1::CreateDatasetRequest {
+                ..Default::default()
+            };
+            let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+            let object_group = services::v1::CreateObjectGroupRequest {
+                dataset_id: created_dataset.id.clone(),
+                ..Default::default()
+            };
+            let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+            let revision = handler
+                .create_revision_for_group(
+                    &services::v1::CreateObjectGroupRevisionRequest::default(),
+                    created_object_group.id.as_str(),
+                )
+                .await
+                .unwrap();
+
+            dataset_ids.push(created_dataset.id);
+            revision_ids.push(revision.id);
+        }
+
+        let release_version_request = ReleaseDatasetVersionRequest {
+            dataset_id: dataset_ids[0].clone(),
+            revision_ids: revision_ids.clone(),
+            version: Some(Version {
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let error = handler
+            .create_datatset_version(&release_version_request)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+        assert!(error.message().contains(revision_ids[1].as_str()));
+
+        // The revision of the released dataset is not part of the rejected version
+        let revision = handler
+            .read_entry_by_id::<ObjectGroupRevision>(revision_ids[0].as_str())
+            .await
+            .unwrap();
+        assert!(revision.dataset_versions.is_empty());
+
+        let release_version_request = ReleaseDatasetVersionRequest {
+            dataset_id: dataset_ids[0].clone(),
+            revision_ids: vec![revision_ids[0].clone()],
+            version: Some(Version {
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        handler
+            .create_datatset_version(&release_version_request)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn multipart_threshold() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![
+                services::v1::CreateObjectRequest {
+                    content_len: 1024,
+                    filename: "small.bin".to_string(),
+                    filetype: "bin".to_string(),
+                    ..Default::default()
+                },
+                services::v1::CreateObjectRequest {
+                    content_len: multipart_threshold_from_config(),
+                    filename: "large.bin".to_string(),
+                    filetype: "bin".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let multipart_error = handler
+            .init_multipart_upload(revision.objects[0].id.as_str())
+            .await
+            .unwrap_err();
+        assert_eq!(multipart_error.code(), tonic::Code::InvalidArgument);
+
+        let large_object = handler
+            .init_multipart_upload(revision.objects[1].id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(large_object.id, revision.objects[1].id);
+    }
+
+    #[tokio::test]
+    async fn create_project_if_absent() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+        let user_id = uuid::Uuid::new_v4().to_string();
+        let other_user_id = uuid::Uuid::new_v4().to_string();
+
+        let project_request = services::v1::CreateProjectRequest {
+            name: "provisioned project".to_string(),
+            ..Default::default()
+        };
+        let created_project = handler
+            .create_project_if_absent(&project_request, user_id.clone())
+            .await
+            .unwrap();
+        let existing_project = handler
+            .create_project_if_absent(&project_request, user_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(existing_project.id, created_project.id);
+
+        let projects = handler.read_user_projects(user_id.as_str()).await.unwrap();
+        assert_eq!(projects.len(), 1);
+
+        // Projects of other users with the same name are not reused
+        let other_user_project = handler
+            .create_project_if_absent(&project_request, other_user_id)
+            .await
+            .unwrap();
+        assert_ne!(other_user_project.id, created_project.id);
+    }
+
+    #[tokio::test]
+    async fn revision_field_violations() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![
+                services::v1::CreateObjectRequest {
+                    content_len: 3,
+                    filename: "".to_string(),
+                    ..Default::default()
+                },
+                services::v1::CreateObjectRequest {
+                    content_len: 3,
+                    filename: "valid.bin".to_string(),
+                    ..Default::default()
+                },
+                services::v1::CreateObjectRequest {
+                    content_len: -1,
+                    filename: "negative.bin".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let error = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap_err();
+
+        let status = tonic::Status::from(error);
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let field_violations = decode_field_violations(status.details());
+        let fields: Vec<&str> = field_violations
+            .iter()
+            .map(|violation| violation.field.as_str())
+            .collect();
+        assert_eq!(
+            fields,
+            vec!["objects[0].filename", "objects[2].content_len"]
+        );
+
+        // The revision counter is not increased by rejected revisions
+        let object_group = handler
+            .read_entry_by_id::<ObjectGroup>(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(object_group.revision_counter, 0);
+    }
+
+    #[tokio::test]
+    async fn project_label_keys() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+        let project_id = uuid::Uuid::new_v4().to_string();
+
+        let datasets = vec![
+            (project_id.clone(), vec!["species", "assay"]),
+            (project_id.clone(), vec!["assay", "organism"]),
+            (project_id.clone(), vec![]),
+            (uuid::Uuid::new_v4().to_string(), vec!["other_project"]),
+        ];
+        for (dataset_project_id, label_keys) in datasets {
+            let dataset_request = services::v1::CreateDatasetRequest {
+                project_id: dataset_project_id,
+                labels: label_keys
+                    .into_iter()
+                    .map(|key| models::v1::Label {
+                        key: key.to_string(),
+                        value: "value".to_string(),
+                    })
+                    .collect(),
+                ..Default::default()
+            };
+            handler.create_dataset(&dataset_request).await.unwrap();
+        }
+
+        let label_keys = handler
+            .read_project_label_keys(project_id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(label_keys, vec!["assay", "organism", "species"]);
+    }
+
+    #[tokio::test]
+    async fn sorted_pages() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+        let user_id = uuid::Uuid::new_v4().to_string();
+        let project = handler
+            .create_project(
+                &services::v1::CreateProjectRequest {
+                    name: "sorted project".to_string(),
+                    ..Default::default()
+                },
+                user_id.clone(),
+            )
+            .await
+            .unwrap();
+
+        // Created out of order, the duplicated name is ordered by id
+        let mut datasets = Vec::new();
+        for name in vec!["delta", "alpha", "charlie", "bravo", "alpha"] {
+            let dataset = handler
+                .create_dataset(&services::v1::CreateDatasetRequest {
+                    name: name.to_string(),
+                    project_id: project.id.clone(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            datasets.push((dataset.name, dataset.id));
+        }
+        datasets.sort();
+        datasets.reverse();
+
+        let sort = SortOrder::parse::<DatasetEntry>("name:desc").unwrap();
+        let mut read_datasets = Vec::new();
+        let mut page_token = "".to_string();
+        loop {
+            let (page, next_page_token) = handler
+                .read_user_datasets_page::<DatasetEntry>(
+                    user_id.as_str(),
+                    2,
+                    page_token.as_str(),
+                    &sort,
+                )
+                .await
+                .unwrap();
+            read_datasets.extend(page.into_iter().map(|dataset| (dataset.name, dataset.id)));
+
+            if next_page_token.is_empty() {
+                break;
+            }
+            page_token = next_page_token;
+        }
+        assert_eq!(read_datasets, datasets);
+
+        let mut revision_counts = Vec::new();
+        for revision_count in vec![2_i64, 0, 3, 1] {
+            let object_group = handler
+                .create_object_group(&services::v1::CreateObjectGroupRequest {
+                    dataset_id: datasets[0].1.clone(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            for _ in 0..revision_count {
+                handler
+                    .create_revision_for_group(
+                        &services::v1::CreateObjectGroupRevisionRequest::default(),
+                        object_group.id.as_str(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            revision_counts.push(revision_count);
+        }
+        revision_counts.sort();
+
+        let sort = SortOrder::parse::<ObjectGroup>("revision_count:asc").unwrap();
+        let mut read_revision_counts = Vec::new();
+        let mut page_token = "".to_string();
+        loop {
+            let (page, next_page_token) = handler
+                .read_project_object_groups_page(project.id.as_str(), 3, page_token.as_str(), &sort)
+                .await
+                .unwrap();
+            read_revision_counts.extend(page.into_iter().map(|group| group.revision_counter));
+
+            if next_page_token.is_empty() {
+                break;
+            }
+            page_token = next_page_token;
+        }
+        assert_eq!(read_revision_counts, revision_counts);
+
+        let error = SortOrder::parse::<ObjectGroup>("description").unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn stale_multipart_uploads() {
+        init::test_init();
+
+        let storage_handler =
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new());
+        let handler =
+            CommonHandler::new(Arc::new(MemoryDatabase::new()), storage_handler.clone()).await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![
+                services::v1::CreateObjectRequest {
+                    content_len: multipart_threshold_from_config(),
+                    filename: "stale.bin".to_string(),
+                    filetype: "bin".to_string(),
+                    ..Default::default()
+                },
+                services::v1::CreateObjectRequest {
+                    content_len: multipart_threshold_from_config(),
+                    filename: "recent.bin".to_string(),
+                    filetype: "bin".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let revision = handler
+            .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        let stale_object = handler
+            .init_multipart_upload(revision.objects[0].id.as_str())
+            .await
+            .unwrap();
+        let recent_object = handler
+            .init_multipart_upload(revision.objects[1].id.as_str())
+            .await
+            .unwrap();
+
+        let now = handler.clock.now();
+        storage_handler.set_upload_initiated(
+            stale_object.upload_id.as_str(),
+            now - chrono::Duration::days(2),
+        );
+
+        let aborted = handler
+            .abort_stale_multipart_uploads(now - chrono::Duration::days(1))
+            .await
+            .unwrap();
+        assert_eq!(aborted, 1);
+        assert_eq!(storage_handler.call_count("abort_multipart_upload"), 1);
+
+        let pending_uploads = storage_handler.list_multipart_uploads().await.unwrap();
+        assert_eq!(pending_uploads.len(), 1);
+        assert_eq!(pending_uploads[0].upload_id, recent_object.upload_id);
+
+        let stored_revision = handler
+            .read_revision(created_object_group.id.as_str(), revision.revision)
+            .await
+            .unwrap();
+        assert_eq!(stored_revision.objects[0].upload_id, "");
+        assert_eq!(
+            stored_revision.objects[1].upload_id,
+            recent_object.upload_id
+        );
+    }
+
+    #[tokio::test]
+    async fn basic_dataset_view() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+        let user_id = uuid::Uuid::new_v4().to_string();
+
+        let project_request = services::v1::CreateProjectRequest {
+            ..Default::default()
+        };
+        let project = handler
+            .create_project(&project_request, user_id.clone())
+            .await
+            .unwrap();
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            name: "dataset".to_string(),
+            project_id: project.id.clone(),
+            metadata: vec![models::v1::Metadata {
+                key: "description".to_string(),
+                metadata: "a".repeat(4096).into_bytes(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let full_datasets = handler
+            .read_children_of_existing_parent::<ProjectEntry, DatasetEntry>(project.id.as_str())
+            .await
+            .unwrap();
+        let basic_datasets = handler
+            .read_children_of_existing_parent::<ProjectEntry, DatasetSummary>(project.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(basic_datasets.len(), 1);
+
+        let full_dataset = full_datasets[0].to_proto_dataset();
+        let basic_dataset = basic_datasets[0].to_proto_dataset();
+        assert_eq!(basic_dataset.id, dataset.id);
+        assert_eq!(basic_dataset.name, "dataset");
+        assert_eq!(basic_dataset.project_id, project.id);
+        assert_eq!(basic_dataset.status, full_dataset.status);
+        assert!(basic_dataset.metadata.is_empty());
+        assert!(basic_dataset.encoded_len() < full_dataset.encoded_len());
+
+        let (page, _) = handler
+            .read_user_datasets_page::<DatasetSummary>(
+                user_id.as_str(),
+                10,
+                "",
+                &SortOrder::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(page, basic_datasets);
+    }
+
+    #[tokio::test]
+    async fn diff_revisions() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let mut revisions = Vec::new();
+        for filenames in vec![
+            vec!["kept.txt", "removed.txt"],
+            vec!["kept.txt", "added.txt"],
+        ] {
+            let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+                objects: filenames
+                    .into_iter()
+                    .map(|filename| services::v1::CreateObjectRequest {
+                        content_len: 1024,
+                        filename: filename.to_string(),
+                        filetype: "txt".to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            };
+            let revision = handler
+                .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+                .await
+                .unwrap();
+            revisions.push(revision);
+        }
+
+        let diff = handler
+            .diff_revisions(
+                created_object_group.id.as_str(),
+                revisions[0].revision,
+                revisions[1].revision,
+            )
+            .await
+            .unwrap();
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].id, revisions[1].objects[1].id);
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].id, revisions[0].objects[1].id);
+        // kept.txt has been uploaded again to a new location, the content can not be assumed to be unchanged
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].from.id, revisions[0].objects[0].id);
+        assert_eq!(diff.modified[0].to.id, revisions[1].objects[0].id);
+
+        let unchanged = handler
+            .diff_revisions(
+                created_object_group.id.as_str(),
+                revisions[1].revision,
+                revisions[1].revision,
+            )
+            .await
+            .unwrap();
+        assert_eq!(unchanged, RevisionDiff::default());
+
+        let duplicate_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec!["duplicate.txt", "duplicate.txt"]
+                .into_iter()
+                .map(|filename| services::v1::CreateObjectRequest {
+                    content_len: 1024,
+                    filename: filename.to_string(),
+                    filetype: "txt".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let duplicate_revision = handler
+            .create_revision_for_group(&duplicate_request, created_object_group.id.as_str())
+            .await
+            .unwrap();
+        let error = handler
+            .diff_revisions(
+                created_object_group.id.as_str(),
+                revisions[1].revision,
+                duplicate_revision.revision,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::FailedPrecondition);
+
+        let error = handler
+            .diff_revisions(
+                created_object_group.id.as_str(),
+                revisions[0].revision,
+                duplicate_revision.revision + 1,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn metadata_validation() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+        let limits = MetadataLimits::from_config();
+
+        let json_schema_label = models::v1::Label {
+            key: METADATA_SCHEMA_LABEL_KEY.to_string(),
+            value: "json".to_string(),
+        };
+        let dataset_request = services::v1::CreateDatasetRequest {
+            metadata: vec![
+                models::v1::Metadata {
+                    key: "oversized".to_string(),
+                    metadata: vec![b'a'; limits.max_entry_size + 1],
+                    ..Default::default()
+                },
+                models::v1::Metadata {
+                    key: "binary".to_string(),
+                    metadata: vec![0xff, 0xfe],
+                    ..Default::default()
+                },
+                models::v1::Metadata {
+                    key: "invalid_json".to_string(),
+                    metadata: b"{\"key\":".to_vec(),
+                    labels: vec![json_schema_label.clone()],
+                    ..Default::default()
+                },
+                models::v1::Metadata {
+                    key: "json".to_string(),
+                    metadata: b"{\"key\": \"value\"}".to_vec(),
+                    labels: vec![json_schema_label],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let error = handler.create_dataset(&dataset_request).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+
+        let status = tonic::Status::from(error);
+        let fields: Vec<String> = decode_field_violations(status.details())
+            .into_iter()
+            .map(|violation| violation.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                "metadata[0].metadata",
+                "metadata[1].metadata",
+                "metadata[2].metadata"
+            ]
+        );
+
+        // The entries are within the entry limit, but exceed the limit of all metadata of an object together
+        let entry_count = limits.max_size / limits.max_entry_size + 1;
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                filename: "object.bin".to_string(),
+                metadata: (0..entry_count)
+                    .map(|index| models::v1::Metadata {
+                        key: format!("entry {}", index),
+                        metadata: vec![b'a'; limits.max_entry_size],
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let error = handler
+            .create_revision_for_group(&revision_request, "object_group")
+            .await
+            .unwrap_err();
+        let status = tonic::Status::from(error);
+        let field_violations = decode_field_violations(status.details());
+        assert_eq!(field_violations.len(), 1);
+        assert_eq!(field_violations[0].field, "objects[0].metadata");
+    }
+
+    #[tokio::test]
+    async fn delete_version_updates_referencing_revisions() {
+        init::test_init();
+
+        let database = Arc::new(MemoryDatabase::new());
+        let handler = CommonHandler::new(
+            database.clone(),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            ..Default::default()
+        };
+        let mut revision_ids = Vec::new();
+        for _ in 0..3 {
+            let revision = handler
+                .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+                .await
+                .unwrap();
+            revision_ids.push(revision.id);
+        }
+
+        let release_version_request = ReleaseDatasetVersionRequest {
+            dataset_id: created_dataset.id,
+            revision_ids: revision_ids[..2].to_vec(),
+            version: Some(Version {
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let version = handler
+            .create_datatset_version(&release_version_request)
+            .await
+            .unwrap();
+
+        let update_matches = database.update_matches::<ObjectGroupRevision>().unwrap();
+        handler
+            .delete_dataset_version(version.id.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            database.update_matches::<ObjectGroupRevision>().unwrap() - update_matches,
+            2
+        );
+
+        for revision_id in revision_ids {
+            let revision = handler
+                .read_entry_by_id::<ObjectGroupRevision>(revision_id.as_str())
+                .await
+                .unwrap();
+            assert!(revision.dataset_versions.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn compact_revision_history() {
+        init::test_init();
+
+        let handler = CommonHandler::new(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new()),
+        )
+        .await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let created_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            objects: vec![services::v1::CreateObjectRequest {
+                content_len: 3,
+                filename: "testfile.bin".to_string(),
+                filetype: "bin".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let mut revisions = Vec::new();
+        for _ in 0..20 {
+            let revision = handler
+                .create_revision_for_group(&revision_request, created_object_group.id.as_str())
+                .await
+                .unwrap();
+            revisions.push(revision);
+        }
+
+        let release_version_request = ReleaseDatasetVersionRequest {
+            dataset_id: created_dataset.id,
+            revision_ids: vec![revisions[2].id.clone(), revisions[7].id.clone()],
+            version: Some(Version {
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        handler
+            .create_datatset_version(&release_version_request)
+            .await
+            .unwrap();
+
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Revisions.Compaction.Enabled", true)
+            .unwrap();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Revisions.Compaction.KeepRevisions", 5)
+            .unwrap();
+
+        let deleted = handler
+            .compact_revision_history(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(deleted, 13);
+
+        let mut remaining_revisions: Vec<i64> = handler
+            .database_client
+            .find_by_key::<ObjectGroupRevision>(doc! {
+                "object_group_id": created_object_group.id.as_str()
+            })
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|revision| revision.revision)
+            .collect();
+        remaining_revisions.sort();
+
+        let mut expected_revisions = vec![revisions[2].revision, revisions[7].revision];
+        expected_revisions.extend(revisions[15..].iter().map(|revision| revision.revision));
+        assert_eq!(remaining_revisions, expected_revisions);
+
+        let deleted = handler
+            .compact_revision_history(created_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(deleted, 0);
+    }
+
+    #[tokio::test]
+    async fn revoke_all_user_tokens() {
+        let handler = init_common_handler_for_test().await;
+        SETTINGS
+            .write()
+            .unwrap()
+            .set(
+                "Authentication.OAuth2Auth.UserInfoEndpoint",
+                "http://localhost/userinfo",
+            )
+            .unwrap();
+
+        let user_id = uuid::Uuid::new_v4().to_string();
+        let project_id = uuid::Uuid::new_v4().to_string();
+        let mut api_tokens = Vec::new();
+        for _ in 0..3 {
+            let api_token = handler
+                .create_api_token(user_id.as_str(), vec![Right::Read], project_id.as_str())
+                .await
+                .unwrap();
+            api_tokens.push(api_token);
+        }
+        let other_api_token = handler
+            .create_api_token("otheruser", vec![Right::Read], project_id.as_str())
+            .await
+            .unwrap();
+
+        let revoked = handler
+            .revoke_all_user_tokens(user_id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(revoked, 3);
+        assert!(handler
+            .read_user_api_token(user_id.as_str())
+            .await
+            .unwrap()
+            .is_empty());
+
+        let authz_handler = ProjectAuthzHandler::new(handler.database_client.clone()).unwrap();
+        for api_token in api_tokens {
+            let mut metadata = MetadataMap::new();
+            metadata.insert(API_TOKEN_ENTRY_KEY, api_token.token.parse().unwrap());
+            let error = authz_handler
+                .authorize(
+                    &metadata,
+                    Resource::Project,
+                    Right::Read,
+                    project_id.clone(),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(error.code(), tonic::Code::Unauthenticated);
+        }
+
+        // Tokens of other users are not revoked
+        let mut metadata = MetadataMap::new();
+        metadata.insert(API_TOKEN_ENTRY_KEY, other_api_token.token.parse().unwrap());
+        authz_handler
+            .authorize(
+                &metadata,
+                Resource::Project,
+                Right::Read,
+                project_id.clone(),
+            )
+            .await
+            .unwrap();
+
+        let revoked = handler
+            .revoke_all_user_tokens(user_id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(revoked, 0);
+    }
+
+    #[tokio::test]
+    async fn head_after_move_and_delete() {
+        let handler = init_memory_handler_for_test().await;
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let created_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+
+        let object_group = services::v1::CreateObjectGroupRequest {
+            dataset_id: created_dataset.id.clone(),
+            ..Default::default()
+        };
+        let source_object_group = handler.create_object_group(&object_group).await.unwrap();
+        let target_object_group = handler.create_object_group(&object_group).await.unwrap();
+
+        let revision_request = services::v1::CreateObjectGroupRevisionRequest {
+            ..Default::default()
+        };
+        let first_revision = handler
+            .create_revision_for_group(&revision_request, source_object_group.id.as_str())
+            .await
+            .unwrap();
+        let second_revision = handler
+            .create_revision_for_group(&revision_request, source_object_group.id.as_str())
+            .await
+            .unwrap();
+
+        handler
+            .move_revision(
+                second_revision.id.as_str(),
+                target_object_group.id.as_str(),
+            )
+            .await
+            .unwrap();
+
+        let source_object_group = handler
+            .read_entry_by_id::<ObjectGroup>(source_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(source_object_group.head_id, first_revision.id);
+        let target_object_group = handler
+            .read_entry_by_id::<ObjectGroup>(target_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(target_object_group.head_id, second_revision.id);
+
+        handler
+            .delete_object_revision(first_revision.id.clone())
+            .await
+            .unwrap();
+        let source_object_group = handler
+            .read_entry_by_id::<ObjectGroup>(source_object_group.id.as_str())
+            .await
+            .unwrap();
+        assert_eq!(source_object_group.head_id, "");
+    }
+
+    #[tokio::test]
+    async fn fork_expiring_revision() {
+        init::test_init();
+
+        let storage_handler =
+            Arc::new(objectstorage::mock_objectstorage::MockStorageHandler::new());
+        let handler =
+            CommonHandler::new(Arc::new(MemoryDatabase::new()), storage_handler.clone()).await;
+
+        let dataset_request = services::v1::CreateDatasetRequest {
+            ..Default::default()
+        };
+        let source_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+        let target_dataset = handler.create_dataset(&dataset_request).await.unwrap();
+        let source_group = handler
+            .create_object_group(&services::v1::CreateObjectGroupRequest {
+                dataset_id: source_dataset.id.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let expiring_revision = handler
+            .create_expiring_revision_for_group(
+                &services::v1::CreateObjectGroupRevisionRequest {
+                    objects: vec![services::v1::CreateObjectRequest {
+                        content_len: 3,
+                        filename: "testfile.bin".to_string(),
+                        filetype: "bin".to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                source_group.id.as_str(),
+                std::time::Duration::from_secs(60),
+            )
+            .await
+            .unwrap();
+        // The objects of expiring revisions are tagged as temporary once they are uploaded
+        handler
+            .set_revision_storage_tags(&expiring_revision, false)
+            .await
+            .unwrap();
+        let key = expiring_revision.objects[0].location.key.clone();
+        let temporary_tag = (TEMPORARY_TAG_KEY.to_string(), "true".to_string());
+        assert!(storage_handler
+            .object_tags(key.as_str())
+            .contains(&temporary_tag));
+
+        let (_, forked_revision) = handler
+            .fork_object_group(source_group.id.as_str(), target_dataset.id.as_str())
+            .await
+            .unwrap();
+        let forked_revision = forked_revision.unwrap();
+        assert_eq!(forked_revision.expires_at, None);
+        assert_eq!(forked_revision.objects[0].location.key, key);
+        assert!(!storage_handler
+            .object_tags(key.as_str())
+            .contains(&temporary_tag));
+    }
 }
@@ -1,13 +1,94 @@
-use bson::doc;
+use bson::{doc, to_bson};
+use chrono::{DateTime, Utc};
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::{error, warn};
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::CompletedParts;
+use serde::Serialize;
 
 use crate::{
     database::database::Database,
-    models::dataset_object_group::{DatasetObject, ObjectGroup, ObjectGroupRevision},
+    models::{
+        common_models::{to_storage_tags, Status},
+        dataset_model::DatasetEntry,
+        dataset_object_group::{DatasetObject, ObjectGroup, ObjectGroupRevision},
+        download_token::DownloadToken,
+    },
+    notifications::{events::EventType, webhook::ObjectGroupEvent},
+    objectstorage::objectstorage::{DownloadConditions, ObjectData, ObjectHead},
+    SETTINGS,
 };
 
 use super::common::CommonHandler;
 
+/// Smallest part size accepted by S3 for all but the last part of a multipart upload
+const MIN_PART_SIZE: i64 = 5 * 1024 * 1024;
+/// Maximum number of parts of a single S3 multipart upload
+const MAX_PART_COUNT: i64 = 10000;
+const PART_SIZE_ALIGNMENT: i64 = 1024 * 1024;
+/// Smallest object size a multipart upload is started for if Storage.Multipart.MinObjectSize is not configured
+const DEFAULT_MULTIPART_THRESHOLD: i64 = 15 * 1024 * 1024;
+/// Tag of objects that belong to an expiring revision, a bucket lifecycle rule can filter on it to expire
+/// objects the expiry sweeper missed
+pub const TEMPORARY_TAG_KEY: &str = "temporary";
+
+/// Part size and number of parts that are recommended for a multipart upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartRecommendation {
+    pub part_size: i64,
+    pub part_count: i64,
+}
+
+/// Entry of a download manifest that lists all objects of a revision
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub object_id: String,
+    pub filename: String,
+    pub url: String,
+    pub size: i64,
+}
+
+/// Recommends a part size for a multipart upload of an object with the given size
+/// The parts are sized to reach the target part count without going below the minimum part size of 5MB
+/// The target part count is read from Storage.Multipart.TargetPartCount and is capped at 10000 parts
+pub fn recommend_part_size(content_len: i64) -> PartRecommendation {
+    let target_part_count = SETTINGS
+        .read()
+        .unwrap()
+        .get_int("Storage.Multipart.TargetPartCount")
+        .unwrap_or(MAX_PART_COUNT);
+
+    return recommend_part_size_for_target(content_len, target_part_count);
+}
+
+/// Reads the smallest object size a multipart upload is started for from Storage.Multipart.MinObjectSize
+/// Smaller objects are uploaded with a single upload link
+pub fn multipart_threshold_from_config() -> i64 {
+    return SETTINGS
+        .read()
+        .unwrap()
+        .get_int("Storage.Multipart.MinObjectSize")
+        .unwrap_or(DEFAULT_MULTIPART_THRESHOLD);
+}
+
+pub fn recommend_part_size_for_target(
+    content_len: i64,
+    target_part_count: i64,
+) -> PartRecommendation {
+    let target_part_count = target_part_count.max(1).min(MAX_PART_COUNT);
+    let content_len = content_len.max(0);
+
+    let mut part_size = (content_len + target_part_count - 1) / target_part_count;
+    part_size = part_size.max(MIN_PART_SIZE);
+    part_size = (part_size + PART_SIZE_ALIGNMENT - 1) / PART_SIZE_ALIGNMENT * PART_SIZE_ALIGNMENT;
+
+    let part_count = ((content_len + part_size - 1) / part_size).max(1);
+
+    return PartRecommendation {
+        part_size: part_size,
+        part_count: part_count,
+    };
+}
+
 /// Handles data load operations
 /// The data is stored in an object storage and access is negotiated via presigned URLs
 /// Uploads to a single link are limited in size by the underlaying object storage. In general it is recommended to
@@ -19,7 +100,14 @@ where
     T: Database,
 {
     pub async fn create_upload_link(&self, id: &str) -> Result<String, tonic::Status> {
+        self.ensure_object_mutable(id).await?;
         let object = self.database_client.find_object(id).await?;
+        if object.origin.is_link() {
+            return Err(tonic::Status::failed_precondition(
+                "object is a link to external data and can not be uploaded",
+            ));
+        }
+
         let link = self
             .object_handler
             .create_upload_link(object.location)
@@ -28,22 +116,245 @@ where
         return Ok(link);
     }
 
+    /// Creates a download link for an object
+    /// Objects that link to external data return the external url instead of a presigned url
     pub async fn create_download_link(&self, id: &str) -> Result<String, tonic::Status> {
+        return self
+            .create_conditional_download_link(id, &DownloadConditions::default())
+            .await;
+    }
+
+    /// Creates a download link that is only answered with the object if the conditions are not met
+    /// Otherwise the object storage answers with 304 Not Modified
+    pub async fn create_conditional_download_link(
+        &self,
+        id: &str,
+        conditions: &DownloadConditions,
+    ) -> Result<String, tonic::Status> {
         let object = self.database_client.find_object(id).await?;
+        return self.create_object_download_link(object, conditions).await;
+    }
+
+    /// Issues a token to download the object exactly once through the download server of this service
+    /// Returns the object together with the stored token
+    pub async fn create_download_token(
+        &self,
+        id: &str,
+    ) -> Result<(DatasetObject, DownloadToken), tonic::Status> {
+        let object = self.database_client.find_object(id).await?;
+        if object.origin.is_link() {
+            return Err(tonic::Status::failed_precondition(
+                "object is a link to external data, single-use downloads are only available for stored objects",
+            ));
+        }
+
+        let token = self
+            .database_client
+            .store(DownloadToken::new(object.id.as_str(), self.clock.now()))
+            .await?;
+
+        return Ok((object, token));
+    }
+
+    /// Redeems a download token and reads the data of its object, the token is the only credential of the download
+    /// Fails with permission_denied if the token is unknown or has already been used
+    pub async fn download_with_token(
+        &self,
+        token: &str,
+    ) -> Result<(DatasetObject, ObjectData), tonic::Status> {
+        let redeemed_at = match to_bson(&self.clock.now()) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal("could not redeem download token"));
+            }
+        };
+
+        // The token is only redeemed if it has not been used yet, concurrent redemptions can not both succeed
+        let query = doc! {
+            "id": token,
+            "redeemed_at": null
+        };
+        let update = doc! {
+            "$set": {
+                "redeemed_at": redeemed_at
+            }
+        };
+
+        let redeemed = self
+            .database_client
+            .update_field::<DownloadToken>(query, update)
+            .await?;
+        if redeemed == 0 {
+            return Err(tonic::Status::permission_denied(
+                "download token is invalid or has already been used",
+            ));
+        }
+
+        let download_token = self.read_entry_by_id::<DownloadToken>(token).await?;
+        let object = self
+            .database_client
+            .find_object(download_token.object_id.as_str())
+            .await?;
+        let data = self.object_handler.get_object(&object.location).await?;
+
+        return Ok((object, data));
+    }
+
+    /// Creates a download link for the object at the given index of the current revision of an object group
+    /// Returns the selected object together with its download link
+    pub async fn create_download_link_by_index(
+        &self,
+        object_group_id: &str,
+        index: i64,
+    ) -> Result<(DatasetObject, String), tonic::Status> {
+        let revision = self.read_current_revision(object_group_id).await?;
+
+        if index < 0 || index >= revision.objects.len() as i64 {
+            return Err(tonic::Status::out_of_range(format!(
+                "object index {} out of range, the current revision has {} objects",
+                index,
+                revision.objects.len()
+            )));
+        }
+
+        let object = revision.objects[index as usize].clone();
         let link = self
+            .create_object_download_link(object.clone(), &DownloadConditions::default())
+            .await?;
+
+        return Ok((object, link));
+    }
+
+    /// Creates a download manifest for all objects of a revision
+    /// The manifest contains a download link for each object in the order of the objects in the revision
+    #[allow(dead_code)]
+    pub async fn create_revision_manifest(
+        &self,
+        revision_id: &str,
+    ) -> Result<Vec<ManifestEntry>, tonic::Status> {
+        let revision = self
+            .read_entry_by_id::<ObjectGroupRevision>(revision_id)
+            .await?;
+        return self.create_manifest(revision).await;
+    }
+
+    /// Creates a download manifest for all objects of the current revision of an object group
+    #[allow(dead_code)]
+    pub async fn create_object_group_manifest(
+        &self,
+        object_group_id: &str,
+    ) -> Result<Vec<ManifestEntry>, tonic::Status> {
+        let revision = self.read_current_revision(object_group_id).await?;
+        return self.create_manifest(revision).await;
+    }
+
+    async fn create_manifest(
+        &self,
+        revision: ObjectGroupRevision,
+    ) -> Result<Vec<ManifestEntry>, tonic::Status> {
+        // The links of all stored objects are signed in one batch, links to external data are returned unchanged
+        let stored_locations = revision
+            .objects
+            .iter()
+            .filter(|object| !object.origin.is_link())
+            .map(|object| object.location.clone())
+            .collect();
+        let mut stored_links = self
             .object_handler
-            .create_download_link(object.location)
+            .create_download_links(stored_locations)
+            .await?
+            .into_iter();
+
+        let mut manifest = Vec::with_capacity(revision.objects.len());
+        for object in revision.objects {
+            let url = if object.origin.is_link() {
+                object.origin.link.clone()
+            } else {
+                match stored_links.next() {
+                    Some(value) => value,
+                    None => {
+                        return Err(tonic::Status::internal(
+                            "missing download link of stored object",
+                        ))
+                    }
+                }
+            };
+            manifest.push(ManifestEntry {
+                object_id: object.id,
+                filename: object.filename,
+                url: url,
+                size: object.content_len,
+            });
+        }
+
+        return Ok(manifest);
+    }
+
+    async fn create_object_download_link(
+        &self,
+        object: DatasetObject,
+        conditions: &DownloadConditions,
+    ) -> Result<String, tonic::Status> {
+        // Links to external data are returned unchanged, conditional requests are up to the external source
+        if object.origin.is_link() {
+            return Ok(object.origin.link);
+        }
+
+        let link = self
+            .object_handler
+            .create_conditional_download_link(object.location, conditions)
             .await?;
 
         return Ok(link);
     }
 
+    /// Reads the stored object together with the metadata reported by the object storage
+    /// No presigned url is created, the metadata is read directly from the storage
+    pub async fn head_object(
+        &self,
+        id: &str,
+    ) -> Result<(DatasetObject, ObjectHead), tonic::Status> {
+        let object = self.database_client.find_object(id).await?;
+        if object.origin.is_link() {
+            return Err(tonic::Status::failed_precondition(
+                "object is a link to external data, its metadata is not available",
+            ));
+        }
+
+        let object_head = self.object_handler.head_object(&object.location).await?;
+
+        return Ok((object, object_head));
+    }
+
     /// Initiates a multipart upload. It returns the object which is associated with the uploaded object
     /// If a multipart upload is initiated the upload_id field is set
     /// This upload_id can be used to generate individual upload links with the create_multipart_upload_link
     /// The underlaying object storage implementation usually sets limits for the minimum required part size
+    /// Objects smaller than the configured multipart threshold have to be uploaded with a single upload link
     pub async fn init_multipart_upload(&self, id: &str) -> Result<DatasetObject, tonic::Status> {
+        self.ensure_object_mutable(id).await?;
         let object = self.database_client.find_object(id).await?;
+        if object.origin.is_link() {
+            return Err(tonic::Status::failed_precondition(
+                "object is a link to external data and can not be uploaded",
+            ));
+        }
+
+        if object.content_len == 0 {
+            return Err(tonic::Status::invalid_argument(
+                "multipart uploads require at least one part, please upload empty objects with a single upload link",
+            ));
+        }
+
+        let multipart_threshold = multipart_threshold_from_config();
+        if object.content_len < multipart_threshold {
+            return Err(tonic::Status::invalid_argument(format!(
+                "object with {} bytes is smaller than the multipart threshold of {} bytes, please upload it with a single upload link",
+                object.content_len, multipart_threshold
+            )));
+        }
+
         let upload_id = self.object_handler.init_multipart_upload(&object).await?;
 
         let upload_id_update_query = doc! {
@@ -52,7 +363,7 @@ where
 
         let upload_id_update = doc! {
             "$set": {
-                "objects.$": upload_id,
+                "objects.$.upload_id": upload_id,
             }
         };
 
@@ -64,15 +375,22 @@ where
     }
 
     /// Creates a multipart upload link
+    /// If a base64 encoded SHA256 checksum of the part is given the storage verifies the uploaded part against it
     pub async fn create_multipart_upload_link(
         &self,
         id: &str,
         upload_part: i64,
+        checksum_sha256: Option<&str>,
     ) -> Result<String, tonic::Status> {
         let object = self.database_client.find_object(id).await?;
         let upload_url = self
             .object_handler
-            .upload_multipart_part_link(&object.location, object.upload_id.as_str(), upload_part)
+            .upload_multipart_part_link(
+                &object.location,
+                object.upload_id.as_str(),
+                upload_part,
+                checksum_sha256,
+            )
             .await?;
 
         Ok(upload_url)
@@ -84,6 +402,7 @@ where
         id: &str,
         objects: &Vec<CompletedParts>,
     ) -> Result<(), tonic::Status> {
+        self.ensure_object_mutable(id).await?;
         let object = self.database_client.find_object(id).await?;
         self.object_handler
             .finish_multipart_upload(&object.location, objects, object.upload_id.as_str())
@@ -92,12 +411,179 @@ where
         Ok(())
     }
 
+    /// Aborts the multipart uploads of the bucket that have been initiated before the cutoff
+    /// Uploads that are never completed keep their parts in the storage. The upload id of the objects of aborted uploads
+    /// is reset, a new multipart upload has to be started for them. Returns the number of aborted uploads.
+    pub async fn abort_stale_multipart_uploads(
+        &self,
+        initiated_before: DateTime<Utc>,
+    ) -> Result<usize, tonic::Status> {
+        let pending_uploads = self.object_handler.list_multipart_uploads().await?;
+
+        let mut aborted = 0;
+        for upload in pending_uploads {
+            if upload.initiated >= initiated_before {
+                continue;
+            }
+
+            self.object_handler
+                .abort_multipart_upload(upload.key.as_str(), upload.upload_id.as_str())
+                .await?;
+            aborted += 1;
+
+            let upload_id_reset_query = doc! {
+                "objects.upload_id": upload.upload_id.as_str(),
+            };
+            let upload_id_reset = doc! {
+                "$set": {
+                    "objects.$.upload_id": "",
+                }
+            };
+            self.database_client
+                .update_field::<ObjectGroupRevision>(upload_id_reset_query, upload_id_reset)
+                .await?;
+        }
+
+        return Ok(aborted);
+    }
+
     /// Marks an object group as available
     /// This is required to allow the user to indicate a finished upload
-    /// The system itself is not able to determine if all objects of an object group are already uploaded
+    /// Unless Storage.VerifyUploadsOnFinish is disabled, finishing fails if an object of the current revision
+    /// has not been uploaded
+    /// If the object group transitions to available a notification is sent to the configured webhook
+    /// The transition only succeeds if the status has not been changed in the meantime, so that a finish can not
+    /// overwrite a concurrent deletion
     pub async fn finish_object_group_upload(&self, id: &str) -> Result<(), tonic::Status> {
-        self.update_status::<ObjectGroup>(id, &crate::models::common_models::Status::Available)
-            .await?;
+        let object_group = self.read_entry_by_id::<ObjectGroup>(id).await?;
+        if object_group.status == Status::Deleting {
+            return Err(tonic::Status::failed_precondition(
+                "object group is being deleted",
+            ));
+        }
+
+        if object_group.revision_counter > 0 {
+            let revision = self.read_current_revision(id).await?;
+            let verify_uploads = SETTINGS
+                .read()
+                .unwrap()
+                .get_bool("Storage.VerifyUploadsOnFinish")
+                .unwrap_or(true);
+            if verify_uploads {
+                self.ensure_objects_uploaded(&revision).await?;
+            }
+            // Tags only drive storage lifecycle policies, a failed tagging does not fail the finish
+            if let Err(e) = self.set_revision_storage_tags(&revision, false).await {
+                warn!(
+                    "could not set storage tags of revision {}: {:?}",
+                    revision.id, e
+                );
+            }
+        }
+
+        if object_group.status != Status::Available {
+            let changed = self
+                .database_client
+                .compare_and_set_status::<ObjectGroup>(
+                    id,
+                    object_group.status.clone(),
+                    Status::Available,
+                    self.clock.now(),
+                )
+                .await?;
+            if !changed {
+                return Err(tonic::Status::aborted(
+                    "the status of the object group has been changed concurrently",
+                ));
+            }
+
+            let dataset = self
+                .read_entry_by_id::<DatasetEntry>(object_group.dataset_id.as_str())
+                .await?;
+
+            self.emit_event::<ObjectGroup>(EventType::Finished, object_group.id.as_str());
+            self.webhook_dispatcher.dispatch(ObjectGroupEvent {
+                project_id: dataset.project_id,
+                dataset_id: dataset.id,
+                object_group_id: object_group.id,
+                status: Status::Available,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks that all objects of the revision that are stored in the object storage have been uploaded
+    /// The objects are checked concurrently, failed_precondition listing the missing objects is returned otherwise
+    async fn ensure_objects_uploaded(
+        &self,
+        revision: &ObjectGroupRevision,
+    ) -> Result<(), tonic::Status> {
+        let mut head_object_futures = FuturesUnordered::new();
+        for object in &revision.objects {
+            if object.origin.is_link() {
+                continue;
+            }
+
+            head_object_futures.push(async move {
+                let head_result = self.object_handler.head_object(&object.location).await;
+                (object, head_result)
+            });
+        }
+
+        let mut missing_objects = Vec::new();
+        while let Some((object, head_result)) = head_object_futures.next().await {
+            match head_result {
+                Ok(_) => {}
+                Err(e) if e.code() == tonic::Code::NotFound => {
+                    missing_objects.push(format!("{} ({})", object.id, object.filename))
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if !missing_objects.is_empty() {
+            missing_objects.sort();
+            return Err(tonic::Status::failed_precondition(format!(
+                "objects have not been uploaded: {}",
+                missing_objects.join(", ")
+            )));
+        }
+
+        return Ok(());
+    }
+
+    /// Stores the labels of a revision and its objects that are marked as storage tags as native object tags
+    /// Objects of expiring revisions are additionally tagged as temporary. If replace_existing is set, objects
+    /// without tags get their existing tags removed, otherwise they are skipped.
+    pub(crate) async fn set_revision_storage_tags(
+        &self,
+        revision: &ObjectGroupRevision,
+        replace_existing: bool,
+    ) -> Result<(), tonic::Status> {
+        let mut revision_tags = to_storage_tags(&revision.labels);
+        if revision.expires_at.is_some() {
+            revision_tags.push((TEMPORARY_TAG_KEY.to_string(), "true".to_string()));
+        }
+
+        for object in &revision.objects {
+            if object.origin.is_link() {
+                continue;
+            }
+
+            let mut tags = revision_tags.clone();
+            for metadata in &object.metadata {
+                tags.append(&mut to_storage_tags(&metadata.labels));
+            }
+
+            if tags.is_empty() && !replace_existing {
+                continue;
+            }
+
+            self.object_handler
+                .set_object_tags(&object.location, tags)
+                .await?;
+        }
 
         Ok(())
     }
@@ -3,6 +3,7 @@ pub mod create;
 pub mod delete;
 pub mod load;
 pub mod read;
+pub mod stats_cache;
 pub mod update;
 
 mod handler_test;
@@ -1,17 +1,39 @@
-use bson::doc;
+use std::collections::HashMap;
+
+use bson::{doc, from_document, Bson, Document};
+use log::error;
 
 use crate::{
     database::database::Database,
+    error::CoreError,
     models::{
         apitoken::APIToken,
-        common_models::DatabaseModel,
-        dataset_object_group::{DatasetObject, ObjectGroup, ObjectGroupRevision},
+        common_models::{DatabaseModel, SortOrder},
+        dataset_model::{DatasetEntry, DatasetStats},
+        dataset_object_group::{
+            DatasetObject, ModifiedObject, ObjectGroup, ObjectGroupRevision, RevisionDiff,
+        },
+        dataset_version::DatasetVersion,
         project_model::ProjectEntry,
     },
+    SETTINGS,
 };
 
 use super::common::CommonHandler;
 
+/// Largest page size of paginated listings if Server.MaxPageSize is not configured
+const DEFAULT_MAX_PAGE_SIZE: i64 = 1000;
+
+/// Reads the largest page size of paginated listings from Server.MaxPageSize
+pub fn max_page_size_from_config() -> i64 {
+    let max_page_size = SETTINGS
+        .read()
+        .unwrap()
+        .get_int("Server.MaxPageSize")
+        .unwrap_or(DEFAULT_MAX_PAGE_SIZE);
+    return max_page_size.max(1);
+}
+
 pub type ReadHandler<T> = CommonHandler<T>;
 
 impl<T> ReadHandler<T>
@@ -21,40 +43,87 @@ where
     pub async fn read_entry_by_id<'de, K: DatabaseModel<'de>>(
         &self,
         id: &str,
-    ) -> Result<K, tonic::Status> {
+    ) -> Result<K, CoreError> {
         let query = doc! {
             "id": id
         };
 
-        return self.database_client.find_one_by_key(query).await;
+        return Ok(self.database_client.find_one_by_key(query).await?);
     }
 
     pub async fn read_entries_by_id<'de, K: DatabaseModel<'de>>(
         &self,
         id: &str,
-    ) -> Result<Vec<K>, tonic::Status> {
+    ) -> Result<Vec<K>, CoreError> {
         let query = doc! {
             "id": id
         };
 
-        return self.database_client.find_by_key(query).await;
+        return Ok(self.database_client.find_by_key(query).await?);
     }
 
     pub async fn read_from_parent_entry<'de, K: DatabaseModel<'de>>(
         &self,
         parent_id: &str,
-    ) -> Result<Vec<K>, tonic::Status> {
+    ) -> Result<Vec<K>, CoreError> {
         let query = doc! {
             K::get_parent_field_name()?: parent_id,
         };
 
-        return self.database_client.find_by_key(query).await;
+        return Ok(self.database_client.find_by_key(query).await?);
     }
 
-    pub async fn read_user_projects(
+    /// Reads all children of type K of a parent of type P
+    /// Fails with NotFound if the parent does not exist, an empty list means that the parent has no children
+    pub async fn read_children_of_existing_parent<
+        'de,
+        P: DatabaseModel<'de>,
+        K: DatabaseModel<'de>,
+    >(
         &self,
-        user_id: &str,
-    ) -> Result<Vec<ProjectEntry>, tonic::Status> {
+        parent_id: &str,
+    ) -> Result<Vec<K>, CoreError> {
+        let parent_query = doc! {
+            "id": parent_id
+        };
+        if !self.database_client.exists::<P>(parent_query).await? {
+            return Err(CoreError::NotFound(format!(
+                "{} {} not found",
+                P::get_model_name()?,
+                parent_id
+            )));
+        }
+
+        return self.read_from_parent_entry::<K>(parent_id).await;
+    }
+
+    /// Reads the distinct label keys of all datasets of a project, sorted by key
+    pub async fn read_project_label_keys(
+        &self,
+        project_id: &str,
+    ) -> Result<Vec<String>, CoreError> {
+        let query = doc! {
+            "project_id": project_id
+        };
+
+        let values = self
+            .database_client
+            .distinct::<DatasetEntry>("labels.key", query)
+            .await?;
+
+        let mut label_keys: Vec<String> = values
+            .into_iter()
+            .filter_map(|value| match value {
+                Bson::String(key) => Some(key),
+                _ => None,
+            })
+            .collect();
+        label_keys.sort();
+
+        return Ok(label_keys);
+    }
+
+    pub async fn read_user_projects(&self, user_id: &str) -> Result<Vec<ProjectEntry>, CoreError> {
         let query = doc! {
             "users.user_id": user_id
         };
@@ -67,35 +136,362 @@ where
         return Ok(projects);
     }
 
-    pub async fn read_user_api_token(&self, user_id: &str) -> Result<Vec<APIToken>, tonic::Status> {
+    /// Reads a page of the projects of a user in the given sort order
+    /// The page token is returned by the previous page, an empty token starts at the first page. For listings ordered
+    /// by id the token is the id of the last project of the previous page.
+    /// If name_filter is set only projects whose name contains the filter, ignoring case, are returned
+    /// Returns the projects and the token of the next page, the token is empty if there is no further page
+    pub async fn read_user_projects_page(
+        &self,
+        user_id: &str,
+        page_size: i64,
+        page_token: &str,
+        name_filter: Option<&str>,
+        sort: &SortOrder,
+    ) -> Result<(Vec<ProjectEntry>, String), CoreError> {
+        if page_size <= 0 {
+            return Err(CoreError::InvalidArgument(
+                "page size has to be greater than zero".to_string(),
+            ));
+        }
+
+        let mut query = doc! {
+            "users.user_id": user_id
+        };
+        if let Some(name_filter) = name_filter {
+            query.insert(
+                "name",
+                doc! {"$regex": escape_regex(name_filter), "$options": "i"},
+            );
+        }
+
+        return self
+            .read_page::<ProjectEntry>(query, page_size, page_token, sort)
+            .await;
+    }
+
+    /// Reads a page of the object groups of all datasets of a project in the given sort order
+    /// Paging behaves like read_user_projects_page
+    pub async fn read_project_object_groups_page(
+        &self,
+        project_id: &str,
+        page_size: i64,
+        page_token: &str,
+        sort: &SortOrder,
+    ) -> Result<(Vec<ObjectGroup>, String), CoreError> {
+        if page_size <= 0 {
+            return Err(CoreError::InvalidArgument(
+                "page size has to be greater than zero".to_string(),
+            ));
+        }
+
+        let dataset_ids: Vec<String> = self
+            .database_client
+            .find_by_key::<DatasetEntry>(doc! {"project_id": project_id})
+            .await?
+            .into_iter()
+            .map(|dataset| dataset.id)
+            .collect();
+        if dataset_ids.is_empty() {
+            return Ok((Vec::new(), "".to_string()));
+        }
+
+        let query = doc! {
+            "dataset_id": {
+                "$in": dataset_ids
+            }
+        };
+
+        return self
+            .read_page::<ObjectGroup>(query, page_size, page_token, sort)
+            .await;
+    }
+
+    /// Reads a page of the datasets of all projects of a user in the given sort order
+    /// Paging behaves like read_user_projects_page
+    /// The datasets are read as K, e.g. DatasetSummary to only read the fields required for an index
+    pub async fn read_user_datasets_page<'de, K: DatabaseModel<'de>>(
+        &self,
+        user_id: &str,
+        page_size: i64,
+        page_token: &str,
+        sort: &SortOrder,
+    ) -> Result<(Vec<K>, String), CoreError> {
+        if page_size <= 0 {
+            return Err(CoreError::InvalidArgument(
+                "page size has to be greater than zero".to_string(),
+            ));
+        }
+
+        let project_ids: Vec<String> = self
+            .read_user_projects(user_id)
+            .await?
+            .into_iter()
+            .map(|project| project.id)
+            .collect();
+        if project_ids.is_empty() {
+            return Ok((Vec::new(), "".to_string()));
+        }
+
+        let query = doc! {
+            "project_id": {
+                "$in": project_ids
+            }
+        };
+
+        return self
+            .read_page::<K>(query, page_size, page_token, sort)
+            .await;
+    }
+
+    /// Reads the page of entries matching the query that follows the entry encoded in page_token
+    /// Returns the entries and the token of the next page, the token is empty if there is no further page
+    async fn read_page<'de, K: DatabaseModel<'de>>(
+        &self,
+        mut query: Document,
+        page_size: i64,
+        page_token: &str,
+        sort: &SortOrder,
+    ) -> Result<(Vec<K>, String), CoreError> {
+        if !page_token.is_empty() {
+            let (value, id) = decode_page_token(sort, page_token)?;
+            query.extend(sort.after_query(value, id.as_str()));
+        }
+
+        // Larger requests are rejected by the endpoints, the clamp also keeps the additional entry below from overflowing
+        let page_size = page_size.min(max_page_size_from_config());
+
+        // Reads one additional entry to find out if there is a next page
+        let mut entries = self
+            .database_client
+            .find_page::<K>(query, sort, page_size + 1)
+            .await?;
+
+        let mut next_page_token = "".to_string();
+        if entries.len() as i64 > page_size {
+            entries.truncate(page_size as usize);
+            next_page_token = encode_page_token(sort, entries.last().unwrap())?;
+        }
+
+        return Ok((entries, next_page_token));
+    }
+
+    /// Computes the number of objects and their total size over all revisions of a dataset in the database
+    pub async fn read_dataset_stats(&self, dataset_id: &str) -> Result<DatasetStats, CoreError> {
+        let pipeline = vec![
+            doc! {
+                "$match": {"datasete_id": dataset_id}
+            },
+            doc! {
+                "$group": {
+                    "_id": null,
+                    "objects_count": {"$sum": "$objects_count"},
+                    "content_len": {"$sum": {"$sum": "$objects.content_len"}},
+                }
+            },
+        ];
+
+        let document = match self
+            .database_client
+            .aggregate::<ObjectGroupRevision>(pipeline)
+            .await?
+            .into_iter()
+            .next()
+        {
+            Some(value) => value,
+            None => return Ok(DatasetStats::default()),
+        };
+
+        match from_document(document) {
+            Ok(value) => return Ok(value),
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(CoreError::Backend(
+                    "could not read dataset statistics".to_string(),
+                ));
+            }
+        }
+    }
+
+    /// Returns the statistics of a dataset, the statistics are recomputed if the cached statistics have expired
+    pub async fn read_dataset_stats_cached(
+        &self,
+        dataset_id: &str,
+    ) -> Result<DatasetStats, CoreError> {
+        if let Some(stats) = self.dataset_stats_cache.get(dataset_id, self.clock.now()) {
+            return Ok(stats);
+        }
+
+        let stats = self.read_dataset_stats(dataset_id).await?;
+        self.dataset_stats_cache
+            .insert(dataset_id, stats.clone(), self.clock.now());
+
+        return Ok(stats);
+    }
+
+    pub async fn read_user_api_token(&self, user_id: &str) -> Result<Vec<APIToken>, CoreError> {
         let query = doc! {
             "user_id": user_id
         };
 
-        return self.database_client.find_by_key(query).await;
+        return Ok(self.database_client.find_by_key(query).await?);
     }
 
-    pub async fn find_object(&self, id: &str) -> Result<DatasetObject, tonic::Status> {
-        return self.database_client.find_object(id).await;
+    pub async fn find_object(&self, id: &str) -> Result<DatasetObject, CoreError> {
+        return Ok(self.database_client.find_object(id).await?);
+    }
+
+    /// Reads the objects with the given ids with a single query, the objects are returned in the order of the ids
+    /// Fails with NotFound if any of the objects does not exist
+    pub async fn find_objects(&self, ids: &[String]) -> Result<Vec<DatasetObject>, CoreError> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = doc! {
+            "objects.id": {
+                "$in": ids
+            }
+        };
+
+        let revisions = self
+            .database_client
+            .find_by_key::<ObjectGroupRevision>(query)
+            .await?;
+
+        let found_objects: HashMap<String, DatasetObject> = revisions
+            .into_iter()
+            .flat_map(|revision| revision.objects.into_iter())
+            .map(|object| (object.id.clone(), object))
+            .collect();
+
+        let mut objects = Vec::with_capacity(ids.len());
+        for id in ids {
+            // Duplicate ids are answered with the same object
+            match found_objects.get(id) {
+                Some(object) => objects.push(object.clone()),
+                None => return Err(CoreError::NotFound(format!("object {} not found", id))),
+            }
+        }
+
+        return Ok(objects);
+    }
+
+    /// Reads the revisions that contain an object stored under the given object storage key, the oldest revision
+    /// comes first. Forks reference the stored data of their source, the first revision is therefore the one the
+    /// object has been uploaded to. Fails with NotFound if no object is stored under the key
+    pub async fn read_revisions_by_storage_key(
+        &self,
+        key: &str,
+    ) -> Result<Vec<ObjectGroupRevision>, CoreError> {
+        let mut revisions = self.database_client.find_object_by_key(key).await?;
+        if revisions.is_empty() {
+            return Err(CoreError::NotFound(format!(
+                "no object stored under key {}",
+                key
+            )));
+        }
+
+        revisions.sort_by_key(|revision| revision.date_create);
+
+        return Ok(revisions);
+    }
+
+    /// Reads the revision that contains the object with the given id
+    pub async fn read_revision_of_object(
+        &self,
+        object_id: &str,
+    ) -> Result<ObjectGroupRevision, CoreError> {
+        let query = doc! {
+            "objects.id": object_id
+        };
+
+        return Ok(self.database_client.find_one_by_key(query).await?);
+    }
+
+    /// Reads all dataset versions that the revision with the given id has been released in
+    pub async fn read_revision_dataset_versions(
+        &self,
+        revision_id: &str,
+    ) -> Result<Vec<DatasetVersion>, CoreError> {
+        let revision = self
+            .read_entry_by_id::<ObjectGroupRevision>(revision_id)
+            .await?;
+
+        if revision.dataset_versions.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query = doc! {
+            "id": {
+                "$in": revision.dataset_versions
+            }
+        };
+
+        return Ok(self.database_client.find_by_key(query).await?);
     }
 
     pub async fn read_revision(
         &self,
         object_group_id: &str,
         revision: i64,
-    ) -> Result<ObjectGroupRevision, tonic::Status> {
+    ) -> Result<ObjectGroupRevision, CoreError> {
         let query = doc! {
             "object_group_id": object_group_id,
             "revision": revision
         };
 
-        return self.database_client.find_one_by_key(query).await;
+        return Ok(self.database_client.find_one_by_key(query).await?);
+    }
+
+    /// Compares two revisions of an object group, the objects of both revisions are matched by filename
+    /// Matched objects are modified if their size, storage location or link differs. Objects have no stored
+    /// checksum, an object is only unchanged if it still refers to the same stored data. Fails with
+    /// failed_precondition if a filename occurs more than once in a revision.
+    #[allow(dead_code)]
+    pub async fn diff_revisions(
+        &self,
+        object_group_id: &str,
+        from_revision: i64,
+        to_revision: i64,
+    ) -> Result<RevisionDiff, CoreError> {
+        let from = self.read_revision(object_group_id, from_revision).await?;
+        let to = self.read_revision(object_group_id, to_revision).await?;
+
+        let from_objects = objects_by_filename(&from)?;
+        let to_objects = objects_by_filename(&to)?;
+
+        let mut diff = RevisionDiff::default();
+        for object in &to.objects {
+            match from_objects.get(object.filename.as_str()) {
+                Some(from_object) => {
+                    if from_object.content_len != object.content_len
+                        || from_object.location != object.location
+                        || from_object.origin.link != object.origin.link
+                    {
+                        diff.modified.push(ModifiedObject {
+                            from: (*from_object).clone(),
+                            to: object.clone(),
+                        });
+                    }
+                }
+                None => diff.added.push(object.clone()),
+            }
+        }
+
+        for object in &from.objects {
+            if !to_objects.contains_key(object.filename.as_str()) {
+                diff.removed.push(object.clone());
+            }
+        }
+
+        return Ok(diff);
     }
 
     pub async fn read_current_revision(
         &self,
         object_group_id: &str,
-    ) -> Result<ObjectGroupRevision, tonic::Status> {
+    ) -> Result<ObjectGroupRevision, CoreError> {
         let object_group = self
             .read_entry_by_id::<ObjectGroup>(object_group_id)
             .await?;
@@ -103,4 +499,122 @@ where
             .read_revision(object_group_id, object_group.revision_counter - 1)
             .await;
     }
+
+    /// Reads an object group together with its current revision
+    /// The revision is None if no revision has been added to the object group yet
+    pub async fn read_object_group_with_current_revision(
+        &self,
+        object_group_id: &str,
+    ) -> Result<(ObjectGroup, Option<ObjectGroupRevision>), CoreError> {
+        let object_group = self
+            .read_entry_by_id::<ObjectGroup>(object_group_id)
+            .await?;
+
+        if object_group.revision_counter == 0 {
+            return Ok((object_group, None));
+        }
+
+        let revision = self
+            .read_revision(object_group_id, object_group.revision_counter - 1)
+            .await?;
+
+        return Ok((object_group, Some(revision)));
+    }
+}
+
+/// Escapes all characters with a special meaning in MongoDB regular expressions
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+
+    return escaped;
+}
+
+/// Encodes the position of the entry in the sort order as page token
+/// Listings ordered by id use the id as token, otherwise the value of the sort field and the id are encoded
+fn encode_page_token<'de, K: DatabaseModel<'de>>(
+    sort: &SortOrder,
+    entry: &K,
+) -> Result<String, CoreError> {
+    let document = match entry.to_document() {
+        Ok(value) => value,
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(CoreError::Backend(
+                "could not encode page token".to_string(),
+            ));
+        }
+    };
+
+    let id = document.get_str("id").unwrap_or("").to_string();
+    if sort.field == "id" {
+        return Ok(id);
+    }
+
+    let value = document
+        .get(sort.field.as_str())
+        .cloned()
+        .unwrap_or(Bson::Null);
+    let token = match serde_json::to_vec(&doc! {"value": value, "id": id}) {
+        Ok(value) => value,
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(CoreError::Backend(
+                "could not encode page token".to_string(),
+            ));
+        }
+    };
+
+    return Ok(base64::encode_config(token, base64::URL_SAFE_NO_PAD));
+}
+
+fn decode_page_token(sort: &SortOrder, page_token: &str) -> Result<(Bson, String), CoreError> {
+    if sort.field == "id" {
+        return Ok((Bson::String(page_token.to_string()), page_token.to_string()));
+    }
+
+    let invalid_token = CoreError::InvalidArgument(format!(
+        "invalid page token {} for listings sorted by {}",
+        page_token, sort.field
+    ));
+
+    let token = match base64::decode_config(page_token, base64::URL_SAFE_NO_PAD) {
+        Ok(value) => value,
+        Err(_) => return Err(invalid_token),
+    };
+    let mut document: Document = match serde_json::from_slice(&token) {
+        Ok(value) => value,
+        Err(_) => return Err(invalid_token),
+    };
+
+    let id = match document.get_str("id") {
+        Ok(value) => value.to_string(),
+        Err(_) => return Err(invalid_token),
+    };
+    match document.remove("value") {
+        Some(value) => return Ok((value, id)),
+        None => return Err(invalid_token),
+    }
+}
+
+/// Maps the objects of a revision by their filename, fails if a filename is not unique within the revision
+fn objects_by_filename(
+    revision: &ObjectGroupRevision,
+) -> Result<HashMap<&str, &DatasetObject>, CoreError> {
+    let mut objects = HashMap::new();
+    for object in &revision.objects {
+        if objects.insert(object.filename.as_str(), object).is_some() {
+            return Err(CoreError::FailedPrecondition(format!(
+                "filename {} occurs more than once in revision {}",
+                object.filename, revision.id
+            )));
+        }
+    }
+
+    return Ok(objects);
 }
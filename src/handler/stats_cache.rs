@@ -0,0 +1,53 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{models::dataset_model::DatasetStats, SETTINGS};
+
+/// Seconds the statistics of a dataset are cached if Storage.StatsCacheSecs is not configured
+const DEFAULT_STATS_CACHE_SECS: i64 = 30;
+
+/// Caches the statistics of datasets for a short time, computing them aggregates over all revisions of a dataset
+pub struct DatasetStatsCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (DateTime<Utc>, DatasetStats)>>,
+}
+
+impl DatasetStatsCache {
+    pub fn new(ttl: Duration) -> Self {
+        return DatasetStatsCache {
+            ttl: ttl,
+            entries: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Reads the time to live from Storage.StatsCacheSecs, 0 disables the cache
+    pub fn new_from_config() -> Self {
+        let ttl_secs = SETTINGS
+            .read()
+            .unwrap()
+            .get_int("Storage.StatsCacheSecs")
+            .unwrap_or(DEFAULT_STATS_CACHE_SECS);
+
+        return DatasetStatsCache::new(Duration::seconds(ttl_secs.max(0)));
+    }
+
+    /// Returns the cached statistics of the dataset if they have been computed less than the time to live before now
+    pub fn get(&self, dataset_id: &str, now: DateTime<Utc>) -> Option<DatasetStats> {
+        let entries = self.entries.lock().unwrap();
+        return match entries.get(dataset_id) {
+            Some((computed_at, stats)) if now - *computed_at < self.ttl => Some(stats.clone()),
+            _ => None,
+        };
+    }
+
+    pub fn insert(&self, dataset_id: &str, stats: DatasetStats, now: DateTime<Utc>) {
+        let mut entries = self.entries.lock().unwrap();
+
+        // Drops expired entries to keep the cache from growing with every dataset that was ever requested
+        let ttl = self.ttl;
+        entries.retain(|_, (computed_at, _)| now - *computed_at < ttl);
+
+        entries.insert(dataset_id.to_string(), (now, stats));
+    }
+}
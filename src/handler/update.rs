@@ -1,16 +1,40 @@
-use bson::{doc, to_bson};
+use bson::{doc, to_bson, Document};
 use log::error;
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::AddUserToProjectRequest;
 
 use crate::{
     database::database::Database,
-    models::common_models::{DatabaseModel, Status},
+    error::CoreError,
+    models::{
+        common_models::{DatabaseModel, Metadata, SortDirection, SortOrder, Status, Versioned},
+        dataset_model::DatasetEntry,
+        dataset_object_group::{ObjectGroup, ObjectGroupRevision},
+    },
 };
 
 use super::common::CommonHandler;
 
+/// Number of object groups that are read at once by repair_object_groups
+const REPAIR_PAGE_SIZE: i64 = 1000;
+
+/// Fields of a dataset that can be changed by clients
+const UPDATABLE_DATASET_FIELDS: [&str; 5] =
+    ["name", "description", "is_public", "labels", "metadata"];
+/// Fields that identify a dataset or place it in a project, changing them could move a dataset into a foreign project
+const IMMUTABLE_DATASET_FIELDS: [&str; 3] = ["id", "project_id", "created"];
+
 pub type UpdateHandler<T> = CommonHandler<T>;
 
+/// Head and revision counter of an object group before and after a repair
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectGroupRepair {
+    pub object_group_id: String,
+    pub previous_head_id: String,
+    pub head_id: String,
+    pub previous_revision_counter: i64,
+    pub revision_counter: i64,
+}
+
 impl<T> UpdateHandler<T>
 where
     T: Database,
@@ -20,31 +44,389 @@ where
         id: &str,
         status: &Status,
     ) -> Result<(), tonic::Status> {
-        let query = doc! {
-            "id": id
+        return self
+            .database_client
+            .update_status::<K>(id, status.clone(), self.clock.now())
+            .await;
+    }
+
+    /// Rewrites the entry with the given id if its rev_version still matches the expected version
+    /// The change is applied to the stored entry and the result replaces the whole document with an incremented
+    /// rev_version. Status changes like the deletion are not versioned, the rewrite is therefore also guarded by the
+    /// status that has been read. If the entry has been updated in the meantime the update is rejected with aborted,
+    /// the caller has to read the entry again and retry
+    pub async fn update_versioned<'de, K, F>(
+        &self,
+        id: &str,
+        expected_rev_version: i64,
+        change: F,
+    ) -> Result<K, tonic::Status>
+    where
+        K: DatabaseModel<'de> + Versioned,
+        F: FnOnce(K) -> Result<K, tonic::Status> + Send,
+    {
+        let entry = self.read_entry_by_id::<K>(id).await?;
+        if entry.rev_version() != expected_rev_version {
+            return Err(concurrent_modification::<K>(id, expected_rev_version)?);
+        }
+
+        let status = match entry.to_document() {
+            Ok(value) => value.get("status").cloned(),
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(
+                    "error when reading versioned entry",
+                ));
+            }
+        };
+
+        // Entries created before the introduction of rev_version do not have the field yet
+        let mut query = match expected_rev_version {
+            0 => doc! {
+                "id": id,
+                "$or": [{"rev_version": 0}, {"rev_version": {"$exists": false}}]
+            },
+            _ => doc! {
+                "id": id,
+                "rev_version": expected_rev_version
+            },
         };
+        if let Some(status) = status {
+            query.insert("status", status);
+        }
 
-        let enum_value = match to_bson(status) {
+        let mut replacement = change(entry)?;
+        replacement.set_rev_version(expected_rev_version + 1);
+
+        match self.database_client.replace::<K>(query, replacement).await {
+            Ok(value) => return Ok(value),
+            Err(e) if e.code() == tonic::Code::NotFound => {
+                return Err(concurrent_modification::<K>(id, expected_rev_version)?);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    /// Sets the given fields of a dataset, the dataset is rewritten guarded by its rev_version
+    /// Only the fields in UPDATABLE_DATASET_FIELDS can be set, all other fields are rejected with invalid_argument
+    pub async fn update_dataset_fields(
+        &self,
+        id: &str,
+        expected_rev_version: i64,
+        fields: Document,
+    ) -> Result<DatasetEntry, tonic::Status> {
+        if fields.is_empty() {
+            return Err(tonic::Status::invalid_argument(
+                "at least one field has to be updated",
+            ));
+        }
+
+        for field in fields.keys() {
+            if IMMUTABLE_DATASET_FIELDS.contains(&field.as_str()) {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "field {} of a dataset can not be updated",
+                    field
+                )));
+            }
+            if !UPDATABLE_DATASET_FIELDS.contains(&field.as_str()) {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "unknown dataset field {}",
+                    field
+                )));
+            }
+        }
+
+        return self
+            .update_versioned::<DatasetEntry, _>(id, expected_rev_version, |dataset| {
+                let mut document = match dataset.to_document() {
+                    Ok(value) => value,
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return Err(tonic::Status::internal("error when updating dataset"));
+                    }
+                };
+                for (field, value) in fields {
+                    document.insert(field, value);
+                }
+
+                match DatasetEntry::new_from_document(document) {
+                    Ok(value) => return Ok(value),
+                    Err(_) => {
+                        return Err(tonic::Status::invalid_argument(
+                            "updated fields do not match the types of the dataset fields",
+                        ))
+                    }
+                }
+            })
+            .await;
+    }
+
+    /// Updates the status of a revision
+    /// Fails if the revision is already part of a released dataset version
+    #[allow(dead_code)]
+    pub async fn update_revision_status(
+        &self,
+        id: &str,
+        status: &Status,
+    ) -> Result<(), tonic::Status> {
+        self.ensure_revision_mutable(id).await?;
+        return self.update_status::<ObjectGroupRevision>(id, status).await;
+    }
+
+    /// Replaces the metadata of a single object
+    /// Fails if the revision of the object is already part of a released dataset version
+    #[allow(dead_code)]
+    pub async fn update_object_metadata(
+        &self,
+        object_id: &str,
+        metadata: &Vec<Metadata>,
+    ) -> Result<(), tonic::Status> {
+        self.ensure_object_mutable(object_id).await?;
+
+        let metadata_value = match to_bson(metadata) {
             Ok(value) => value,
             Err(e) => {
                 error!("{:?}", e);
-                return Err(tonic::Status::internal("error on status update"));
+                return Err(tonic::Status::internal("error on metadata update"));
             }
         };
 
+        let query = doc! {
+            "objects.id": object_id
+        };
+
         let update = doc! {
             "$set": {
-                "status": enum_value
+                "objects.$.metadata": metadata_value
             }
         };
 
         self.database_client
-            .update_field::<K>(query, update)
+            .update_field::<ObjectGroupRevision>(query, update)
             .await?;
 
         return Ok(());
     }
 
+    /// Moves a revision to another object group of the same dataset
+    /// The revision becomes the latest revision of the target object group. Parent and revision number are changed in
+    /// one update. If updating the heads fails afterwards, a retry updates the head of the target object group and
+    /// repair_object_groups repairs the head of the source object group.
+    pub async fn move_revision(
+        &self,
+        revision_id: &str,
+        target_object_group_id: &str,
+    ) -> Result<ObjectGroupRevision, tonic::Status> {
+        let revision = self
+            .read_entry_by_id::<ObjectGroupRevision>(revision_id)
+            .await?;
+        check_mutable(&revision)?;
+
+        let target_object_group = self
+            .read_entry_by_id::<ObjectGroup>(target_object_group_id)
+            .await?;
+        if target_object_group.dataset_id != revision.datasete_id {
+            return Err(tonic::Status::failed_precondition(
+                "revisions can only be moved between object groups of the same dataset",
+            ));
+        }
+
+        if revision.object_group_id == target_object_group_id {
+            self.update_head(&revision).await?;
+            return Ok(revision);
+        }
+
+        // The revision number is reserved first, a failed move only leaves a gap in the revision numbers of the target
+        let query = doc! {
+            "id": target_object_group_id
+        };
+
+        let update = doc! {
+            "$inc": {
+                "revision_counter": 1
+            }
+        };
+
+        let target_object_group = self
+            .database_client
+            .update_on_field::<ObjectGroup>(query, update)
+            .await?;
+
+        // An interrupted move never leaves the revision in the target object group with its number of the source group
+        let query = doc! {
+            "id": revision_id,
+            "object_group_id": revision.object_group_id.as_str(),
+            "dataset_versions": {
+                "$size": 0
+            }
+        };
+
+        let parent_fields = doc! {
+            "object_group_id": target_object_group_id,
+            "datasete_id": target_object_group.dataset_id.as_str(),
+            "revision": target_object_group.revision_counter
+        };
+
+        let moved_revision = match self
+            .database_client
+            .reparent::<ObjectGroupRevision>(query, parent_fields)
+            .await?
+        {
+            Some(value) => value,
+            None => {
+                return Err(tonic::Status::aborted(format!(
+                    "object group revision {} has been moved or released concurrently",
+                    revision_id
+                )))
+            }
+        };
+
+        self.update_head(&moved_revision).await?;
+        self.refresh_head(revision.object_group_id.as_str()).await?;
+
+        return Ok(moved_revision);
+    }
+
+    /// Points the head of an object group to its latest remaining revision after a revision has been moved or deleted
+    /// The head is left unchanged if a revision is added concurrently, the new revision updates the head itself
+    pub async fn refresh_head(&self, object_group_id: &str) -> Result<(), tonic::Status> {
+        let object_group = match self.read_entry_by_id::<ObjectGroup>(object_group_id).await {
+            Ok(value) => value,
+            // Deleted object groups have no head
+            Err(CoreError::NotFound(_)) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let latest_revision_order = SortOrder {
+            field: "revision".to_string(),
+            direction: SortDirection::Descending,
+        };
+        let head_id = match self
+            .database_client
+            .find_page::<ObjectGroupRevision>(
+                doc! {"object_group_id": object_group_id},
+                &latest_revision_order,
+                1,
+            )
+            .await?
+            .pop()
+        {
+            Some(revision) => revision.id,
+            None => "".to_string(),
+        };
+        if object_group.head_id == head_id {
+            return Ok(());
+        }
+
+        let query = doc! {
+            "id": object_group_id,
+            "revision_counter": object_group.revision_counter
+        };
+        let update = doc! {
+            "$set": {
+                "head_id": head_id.as_str()
+            }
+        };
+
+        self.database_client
+            .update_field::<ObjectGroup>(query, update)
+            .await?;
+
+        return Ok(());
+    }
+
+    /// Checks if the revision with the given id can still be modified
+    pub async fn ensure_revision_mutable(&self, id: &str) -> Result<(), tonic::Status> {
+        let revision = self.read_entry_by_id::<ObjectGroupRevision>(id).await?;
+        return check_mutable(&revision);
+    }
+
+    /// Checks if the revision that contains the object with the given id can still be modified
+    pub async fn ensure_object_mutable(&self, object_id: &str) -> Result<(), tonic::Status> {
+        let revision = self.read_revision_of_object(object_id).await?;
+        return check_mutable(&revision);
+    }
+
+    /// Recomputes the revision counter and head of all object groups from their stored revisions
+    /// Object groups created before head_id was maintained point to no or stale revisions. Groups that receive a new
+    /// revision during the repair are skipped, the new revision already updates their head.
+    /// Returns the repaired object groups.
+    pub async fn repair_object_groups(&self) -> Result<Vec<ObjectGroupRepair>, tonic::Status> {
+        let mut repairs = Vec::new();
+        let mut last_id = "".to_string();
+        loop {
+            let object_groups = self
+                .database_client
+                .find_page::<ObjectGroup>(
+                    doc! {"id": {"$gt": last_id.as_str()}},
+                    &SortOrder::default(),
+                    REPAIR_PAGE_SIZE,
+                )
+                .await?;
+            let last_object_group = match object_groups.last() {
+                Some(value) => value.id.clone(),
+                None => break,
+            };
+
+            for object_group in object_groups {
+                if let Some(repair) = self.repair_object_group(&object_group).await? {
+                    repairs.push(repair);
+                }
+            }
+
+            last_id = last_object_group;
+        }
+
+        return Ok(repairs);
+    }
+
+    async fn repair_object_group(
+        &self,
+        object_group: &ObjectGroup,
+    ) -> Result<Option<ObjectGroupRepair>, tonic::Status> {
+        let latest_revision = self
+            .database_client
+            .find_by_key::<ObjectGroupRevision>(doc! {"object_group_id": object_group.id.as_str()})
+            .await?
+            .into_iter()
+            .max_by_key(|revision| revision.revision);
+
+        let (head_id, revision_counter) = match latest_revision {
+            Some(revision) => (revision.id, revision.revision + 1),
+            None => ("".to_string(), 0),
+        };
+        if object_group.head_id == head_id && object_group.revision_counter == revision_counter {
+            return Ok(None);
+        }
+
+        let query = doc! {
+            "id": object_group.id.as_str(),
+            "revision_counter": object_group.revision_counter
+        };
+        let update = doc! {
+            "$set": {
+                "head_id": head_id.as_str(),
+                "revision_counter": revision_counter
+            }
+        };
+
+        let modified = self
+            .database_client
+            .update_field::<ObjectGroup>(query, update)
+            .await?;
+        if modified == 0 {
+            return Ok(None);
+        }
+
+        return Ok(Some(ObjectGroupRepair {
+            object_group_id: object_group.id.clone(),
+            previous_head_id: object_group.head_id.clone(),
+            head_id: head_id,
+            previous_revision_counter: object_group.revision_counter,
+            revision_counter: revision_counter,
+        }));
+    }
+
     pub async fn add_user_to_project(
         &self,
         add_user_request: &AddUserToProjectRequest,
@@ -52,3 +434,29 @@ where
         return self.database_client.add_user(add_user_request).await;
     }
 }
+
+/// Central immutability check for revisions
+/// Released revisions must not be modified, the corresponding request is rejected with failed_precondition
+pub fn check_mutable(revision: &ObjectGroupRevision) -> Result<(), tonic::Status> {
+    if revision.is_immutable() {
+        return Err(tonic::Status::failed_precondition(format!(
+            "object group revision {} is part of a released dataset version and can not be modified",
+            revision.id
+        )));
+    }
+
+    return Ok(());
+}
+
+/// Error of a versioned update that has been rejected because the entry has been modified concurrently
+fn concurrent_modification<'de, K: DatabaseModel<'de>>(
+    id: &str,
+    expected_rev_version: i64,
+) -> Result<tonic::Status, tonic::Status> {
+    return Ok(tonic::Status::aborted(format!(
+        "{} {} has been modified concurrently, expected rev_version {}",
+        K::get_model_name()?,
+        id,
+        expected_rev_version
+    )));
+}
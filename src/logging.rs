@@ -0,0 +1,198 @@
+use std::{fmt::Display, io::Write};
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use log::Record;
+
+use crate::{server::request_id::current_request_id, SETTINGS};
+
+/// Format of the log lines written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines, the default
+    Text,
+    /// One JSON object per line for log aggregation
+    Json,
+}
+
+/// Reads the log format from Logging.Format, either text or json, the default is text
+pub fn log_format_from_config() -> Result<LogFormat, tonic::Status> {
+    let format = match SETTINGS.read().unwrap().get_str("Logging.Format") {
+        Ok(value) => value,
+        Err(_) => return Ok(LogFormat::Text),
+    };
+
+    match format.as_str() {
+        "text" => return Ok(LogFormat::Text),
+        "json" => return Ok(LogFormat::Json),
+        _ => {
+            return Err(tonic::Status::failed_precondition(format!(
+                "unknown Logging.Format {}, expected text or json",
+                format
+            )))
+        }
+    }
+}
+
+/// Initializes the global logger with the configured log format
+pub fn init_logger() -> Result<(), tonic::Status> {
+    let mut builder = env_logger::Builder::new();
+    match log_format_from_config()? {
+        LogFormat::Text => builder.format(|buf, record| {
+            writeln!(
+                buf,
+                "{}",
+                text_log_line(record, chrono::Local::now(), current_request_id())
+            )
+        }),
+        LogFormat::Json => builder.format(|buf, record| {
+            writeln!(
+                buf,
+                "{}",
+                json_log_line(record, Utc::now(), current_request_id())
+            )
+        }),
+    };
+    builder.init();
+
+    return Ok(());
+}
+
+/// Formats the record as a human readable line
+/// The request id is only part of lines that are logged while a request is handled
+fn text_log_line<Tz: TimeZone>(
+    record: &Record,
+    timestamp: DateTime<Tz>,
+    request_id: Option<String>,
+) -> String
+where
+    Tz::Offset: Display,
+{
+    let request_id = match request_id {
+        Some(request_id) => format!(" [{}]", request_id),
+        None => String::new(),
+    };
+
+    return format!(
+        "{}:{} {} [{}]{} - {}",
+        record.file().unwrap_or("unknown"),
+        record.line().unwrap_or(0),
+        timestamp.format("%Y-%m-%dT%H:%M:%S"),
+        record.level(),
+        request_id,
+        record.args()
+    );
+}
+
+/// Formats the record as a single line JSON object
+/// The request id is only part of lines that are logged while a request is handled
+fn json_log_line(record: &Record, timestamp: DateTime<Utc>, request_id: Option<String>) -> String {
+    let mut line = serde_json::json!({
+        "timestamp": timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "message": record.args().to_string(),
+    });
+    if let Some(request_id) = request_id {
+        line["request_id"] = serde_json::Value::String(request_id);
+    }
+
+    return line.to_string();
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use crate::{test_util::init::test_init, SETTINGS};
+
+    use super::{json_log_line, log_format_from_config, text_log_line, LogFormat};
+
+    #[test]
+    fn text_log_lines() {
+        let timestamp = Utc.ymd(2021, 7, 1).and_hms(12, 30, 0);
+        for (request_id, expected) in vec![
+            (
+                Some("test-request-id".to_string()),
+                "src/server/server.rs:42 2021-07-01T12:30:00 [WARN] [test-request-id] - request failed",
+            ),
+            (
+                None,
+                "src/server/server.rs:42 2021-07-01T12:30:00 [WARN] - request failed",
+            ),
+        ] {
+            let line = text_log_line(
+                &log::Record::builder()
+                    .level(log::Level::Warn)
+                    .file(Some("src/server/server.rs"))
+                    .line(Some(42))
+                    .args(format_args!("request failed"))
+                    .build(),
+                timestamp,
+                request_id,
+            );
+            assert_eq!(line, expected);
+        }
+    }
+
+    #[test]
+    fn json_log_lines() {
+        let timestamp = Utc.ymd(2021, 7, 1).and_hms(12, 30, 0);
+        let line = json_log_line(
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .target("scienceobjectsdb::server")
+                .args(format_args!("request \"{}\" failed\nretrying", "abc"))
+                .build(),
+            timestamp,
+            Some("test-request-id".to_string()),
+        );
+        assert!(!line.contains('\n'));
+
+        let parsed: serde_json::Value = serde_json::from_str(line.as_str()).unwrap();
+        assert_eq!(parsed["timestamp"], "2021-07-01T12:30:00.000Z");
+        assert_eq!(parsed["level"], "WARN");
+        assert_eq!(parsed["target"], "scienceobjectsdb::server");
+        assert_eq!(parsed["message"], "request \"abc\" failed\nretrying");
+        assert_eq!(parsed["request_id"], "test-request-id");
+
+        let line = json_log_line(
+            &log::Record::builder()
+                .level(log::Level::Info)
+                .args(format_args!("started"))
+                .build(),
+            timestamp,
+            None,
+        );
+        let parsed: serde_json::Value = serde_json::from_str(line.as_str()).unwrap();
+        assert_eq!(parsed["message"], "started");
+        assert!(parsed.get("request_id").is_none());
+    }
+
+    #[test]
+    fn log_format() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Logging.Format", "json")
+            .unwrap();
+        assert_eq!(log_format_from_config().unwrap(), LogFormat::Json);
+
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Logging.Format", "xml")
+            .unwrap();
+        assert_eq!(
+            log_format_from_config().unwrap_err().code(),
+            tonic::Code::FailedPrecondition
+        );
+
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Logging.Format", "text")
+            .unwrap();
+        assert_eq!(log_format_from_config().unwrap(), LogFormat::Text);
+    }
+}
@@ -2,20 +2,24 @@
 extern crate lazy_static;
 
 mod auth;
+mod clock;
 mod database;
+mod deadline;
+mod error;
 mod handler;
+mod logging;
 mod models;
+mod notifications;
 mod objectstorage;
 mod server;
 mod test_util;
+mod validation;
 
 use config::{Config, File};
 use std::sync::RwLock;
 
-use clap::{App, Arg};
-use server::server::start_server;
-
-use std::io::Write;
+use clap::{App, Arg, SubCommand};
+use server::server::{repair_object_groups, revoke_user_tokens, start_server};
 
 lazy_static! {
     static ref SETTINGS: RwLock<Config> = RwLock::new(Config::default());
@@ -23,28 +27,28 @@ lazy_static! {
 
 type ResultWrapper<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+/// Action requested on the command line, the server is started if no subcommand is given
+enum Command {
+    Serve,
+    Repair,
+    RevokeTokens(String),
+}
+
 #[tokio::main]
 async fn main() -> ResultWrapper<()> {
-    conf();
+    let (config_file, command) = conf();
 
-    env_logger::Builder::new()
-        .format(|buf, record| {
-            writeln!(
-                buf,
-                "{}:{} {} [{}] - {}",
-                record.file().unwrap_or("unknown"),
-                record.line().unwrap_or(0),
-                chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
-                record.level(),
-                record.args()
-            )
-        })
-        .init();
+    logging::init_logger()?;
 
-    start_server().await
+    match command {
+        Command::Serve => start_server(config_file).await,
+        Command::Repair => repair_object_groups().await,
+        Command::RevokeTokens(user_id) => revoke_user_tokens(user_id.as_str()).await,
+    }
 }
 
-fn conf() {
+/// Reads the configuration file, returns its path and the requested subcommand
+fn conf() -> (String, Command) {
     let cli = App::new("CORS server")
         .version("0.1")
         .author("Marius D.")
@@ -57,6 +61,20 @@ fn conf() {
                 .help("Sets a custom config file")
                 .takes_value(true),
         )
+        .subcommand(
+            SubCommand::with_name("repair")
+                .about("Repairs the head and revision counter of all object groups"),
+        )
+        .subcommand(
+            SubCommand::with_name("revoke-tokens")
+                .about("Revokes all api tokens of a compromised user")
+                .arg(
+                    Arg::with_name("user")
+                        .value_name("USER_ID")
+                        .help("Id of the user whose tokens are revoked")
+                        .required(true),
+                ),
+        )
         .get_matches();
 
     let conf_file = cli
@@ -70,4 +88,14 @@ fn conf() {
             .merge(File::with_name(conf_file))
             .unwrap();
     }
+
+    let command = match cli.subcommand() {
+        ("repair", _) => Command::Repair,
+        ("revoke-tokens", Some(args)) => {
+            Command::RevokeTokens(args.value_of("user").unwrap_or_default().to_string())
+        }
+        _ => Command::Serve,
+    };
+
+    return (conf_file.to_string(), command);
 }
@@ -1,4 +1,5 @@
 use super::common_models::{to_proto_rights, DatabaseModel, Right};
+use chrono::{DateTime, Utc};
 use rand::Rng;
 use scienceobjectsdb_rust_api::sciobjectsdbapi::models;
 use serde::{Deserialize, Serialize};
@@ -13,6 +14,9 @@ pub struct APIToken {
     pub token: String,
     pub rights: Vec<Right>,
     pub project_id: String,
+    /// Time the token has last authorized a request, None if it has never been used
+    #[serde(default)]
+    pub last_used_at: Option<DateTime<Utc>>,
 }
 
 impl DatabaseModel<'_> for APIToken {
@@ -36,11 +40,20 @@ impl APIToken {
             rights: rights,
             token: token,
             project_id: project_id.to_string(),
+            last_used_at: None,
         };
 
         Ok(dataset_entry)
     }
 
+    /// Returns a copy of the token with a newly generated secret, id, rights, project and user are preserved
+    pub fn new_rotated(&self) -> Self {
+        return APIToken {
+            token: generate_api_token(),
+            ..self.clone()
+        };
+    }
+
     pub fn to_proto(&self) -> models::v1::ApiToken {
         let api_token = models::v1::ApiToken {
             id: self.id.clone(),
@@ -1,12 +1,15 @@
 use std::str;
 
-use mongodb::bson::{doc, from_document, to_document, Document};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, from_document, to_bson, to_document, Bson, Document};
 use serde::{Deserialize, Serialize};
 
 use log::error;
 
 use scienceobjectsdb_rust_api::sciobjectsdbapi::models;
 
+use crate::SETTINGS;
+
 type ResultWrapperSync<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
@@ -15,7 +18,7 @@ pub struct User {
     pub rights: Vec<Right>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(untagged)]
 pub enum Resource {
     Project,
@@ -38,6 +41,59 @@ pub struct Label {
     pub value: String,
 }
 
+/// Adds the default labels configured in Defaults.Labels as a map from label key to value
+/// Labels with a key that is already present are not overridden
+pub fn add_default_labels(labels: &mut Vec<Label>) {
+    add_default_labels_from(&SETTINGS.read().unwrap(), labels);
+}
+
+/// Adds the default labels configured in Defaults.Labels of the given configuration
+pub fn add_default_labels_from(settings: &config::Config, labels: &mut Vec<Label>) {
+    let default_labels = match settings.get_table("Defaults.Labels") {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+
+    let mut default_labels: Vec<(String, config::Value)> = default_labels.into_iter().collect();
+    default_labels.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (key, value) in default_labels {
+        if labels.iter().any(|label| label.key == key) {
+            continue;
+        }
+
+        let value = match value.into_str() {
+            Ok(value) => value,
+            Err(e) => {
+                error!("could not read default label {}: {:?}", key, e);
+                continue;
+            }
+        };
+
+        labels.push(Label {
+            key: key,
+            value: value,
+        });
+    }
+}
+
+/// Labels with this key prefix are additionally stored as native tags in the object storage
+/// The prefix is removed from the key of the stored tag
+pub const STORAGE_TAG_LABEL_PREFIX: &str = "storage.tag.";
+
+/// Selects the labels that should be stored as object storage tags
+pub fn to_storage_tags(labels: &Vec<Label>) -> Vec<(String, String)> {
+    let mut tags = Vec::new();
+
+    for label in labels {
+        if let Some(tag_key) = label.key.strip_prefix(STORAGE_TAG_LABEL_PREFIX) {
+            tags.push((tag_key.to_string(), label.value.clone()));
+        }
+    }
+
+    return tags;
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct Metadata {
     pub key: String,
@@ -87,6 +143,51 @@ impl Default for Status {
     }
 }
 
+/// Models that are protected against concurrent updates by a rev_version counter
+/// The counter is incremented with every guarded update, see UpdateHandler::update_versioned
+pub trait Versioned {
+    fn rev_version(&self) -> i64;
+    fn set_rev_version(&mut self, rev_version: i64);
+}
+
+/// Models that store the time of their soft deletion
+pub trait SoftDeletable {
+    fn deleted_at(&self) -> Option<DateTime<Utc>>;
+}
+
+/// Creates the update document to change the status of an entry
+/// Entries that are marked as deleting additionally store the time of the deletion in deleted_at, this is used to
+/// purge soft-deleted entries after the retention period
+pub fn to_status_update(status: &Status, now: DateTime<Utc>) -> Result<Document, tonic::Status> {
+    let status_value = match to_bson(status) {
+        Ok(value) => value,
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(tonic::Status::internal("error on status update"));
+        }
+    };
+
+    let mut update_fields = doc! {
+        "status": status_value
+    };
+
+    if *status == Status::Deleting {
+        let deleted_at = match to_bson(&now) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal("error on status update"));
+            }
+        };
+
+        update_fields.insert("deleted_at", deleted_at);
+    }
+
+    return Ok(doc! {
+        "$set": update_fields
+    });
+}
+
 #[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
 pub struct Version {
     major: i32,
@@ -110,11 +211,41 @@ impl Default for VersionStage {
     }
 }
 
+/// Label key that registers an object as a link to externally stored data instead of uploading it
+pub const ORIGIN_LINK_LABEL_KEY: &str = "origin.link";
+
 #[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
 pub struct Origin {
-    link: String,
-    location: Location,
-    origin_type: OriginType,
+    pub link: String,
+    pub location: Location,
+    pub origin_type: OriginType,
+}
+
+impl Origin {
+    /// Creates the origin of an object whose data is stored at an external url
+    pub fn new_link(link: String) -> Self {
+        return Origin {
+            link: link,
+            origin_type: OriginType::ObjectLink,
+            ..Default::default()
+        };
+    }
+
+    /// Creates the origin from the labels of a create request
+    /// Objects with an ORIGIN_LINK_LABEL_KEY label are registered as links, all others are stored in the object storage
+    pub fn from_labels(labels: &Vec<Label>) -> Self {
+        for label in labels {
+            if label.key == ORIGIN_LINK_LABEL_KEY && !label.value.is_empty() {
+                return Origin::new_link(label.value.clone());
+            }
+        }
+
+        return Origin::default();
+    }
+
+    pub fn is_link(&self) -> bool {
+        return self.origin_type == OriginType::ObjectLink;
+    }
 }
 
 #[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
@@ -126,6 +257,57 @@ pub struct Location {
     pub index_location: IndexLocation,
 }
 
+/// Template used to build the object storage key if Storage.KeyTemplate is not configured
+pub const DEFAULT_KEY_TEMPLATE: &str = "{project}/{dataset}/{object}/{filename}";
+
+/// Prefix of all object storage keys of a project if Storage.ProjectKeyPrefix is enabled
+const PROJECT_KEY_PREFIX: &str = "{project}/";
+
+/// Builds the object storage key of an object from the template configured in Storage.KeyTemplate
+/// The keys are prefixed with the project id unless Storage.ProjectKeyPrefix is disabled
+pub fn object_key(project_id: &str, dataset_id: &str, object_id: &str, filename: &str) -> String {
+    let settings = SETTINGS.read().unwrap();
+    let template = settings
+        .get_str("Storage.KeyTemplate")
+        .unwrap_or(DEFAULT_KEY_TEMPLATE.to_string());
+    let project_key_prefix = settings
+        .get_bool("Storage.ProjectKeyPrefix")
+        .unwrap_or(true);
+
+    return object_key_from_template(
+        project_key_template(template.as_str(), project_key_prefix).as_str(),
+        project_id,
+        dataset_id,
+        object_id,
+        filename,
+    );
+}
+
+/// Prepends the project id to templates that do not start with it, so that the objects of a project can be
+/// isolated with prefix based IAM policies in a shared bucket
+pub fn project_key_template(template: &str, project_key_prefix: bool) -> String {
+    if !project_key_prefix || template.starts_with(PROJECT_KEY_PREFIX) {
+        return template.to_string();
+    }
+
+    return format!("{}{}", PROJECT_KEY_PREFIX, template);
+}
+
+/// Builds an object storage key by replacing the {project}, {dataset}, {object} and {filename} placeholders
+pub fn object_key_from_template(
+    template: &str,
+    project_id: &str,
+    dataset_id: &str,
+    object_id: &str,
+    filename: &str,
+) -> String {
+    return template
+        .replace("{project}", project_id)
+        .replace("{dataset}", dataset_id)
+        .replace("{object}", object_id)
+        .replace("{filename}", filename);
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum LocationType {
     Object,
@@ -179,6 +361,136 @@ pub trait DatabaseModel<'de>: serde::Serialize + serde::de::DeserializeOwned + S
     fn get_model_name() -> Result<String, tonic::Status>;
 
     fn get_parent_field_name() -> Result<String, tonic::Status>;
+
+    /// Maps the name of a field listings can be sorted by to the field of the stored document
+    /// Returns None for fields that are not sortable, all models can be sorted by id
+    fn get_sort_field(name: &str) -> Option<&'static str> {
+        match name {
+            "id" => Some("id"),
+            _ => None,
+        }
+    }
+
+    /// Fields that are read from the stored documents, None reads the whole document
+    /// Models that only contain a subset of the stored fields use it to avoid transferring the remaining fields
+    fn get_projection() -> Option<Document> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+/// Order of a paginated listing, entries with the same value of the sort field are ordered by id in the same direction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortOrder {
+    pub field: String,
+    pub direction: SortDirection,
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        return SortOrder {
+            field: "id".to_string(),
+            direction: SortDirection::Ascending,
+        };
+    }
+}
+
+impl SortOrder {
+    /// Parses a sort order of the form <field> or <field>:<asc|desc>, the field has to be sortable for the model
+    pub fn parse<'de, T: DatabaseModel<'de>>(value: &str) -> Result<Self, tonic::Status> {
+        let (name, direction) = match value.split_once(':') {
+            Some((name, "asc")) => (name, SortDirection::Ascending),
+            Some((name, "desc")) => (name, SortDirection::Descending),
+            Some((_, direction)) => {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "invalid sort direction {}, expected asc or desc",
+                    direction
+                )))
+            }
+            None => (value, SortDirection::Ascending),
+        };
+
+        let field = match T::get_sort_field(name) {
+            Some(value) => value,
+            None => {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "{} can not be sorted by {}",
+                    T::get_model_name()?,
+                    name
+                )))
+            }
+        };
+
+        return Ok(SortOrder {
+            field: field.to_string(),
+            direction: direction,
+        });
+    }
+
+    /// Sort document of the order including the id as tie breaker
+    pub fn to_document(&self) -> Document {
+        let direction = match self.direction {
+            SortDirection::Ascending => 1,
+            SortDirection::Descending => -1,
+        };
+
+        let mut sort = doc! {self.field.as_str(): direction};
+        sort.insert("id", direction);
+        return sort;
+    }
+
+    /// Query that matches all entries that follow the entry with the given sort value and id
+    pub fn after_query(&self, value: Bson, id: &str) -> Document {
+        let operator = match self.direction {
+            SortDirection::Ascending => "$gt",
+            SortDirection::Descending => "$lt",
+        };
+
+        if self.field == "id" {
+            return doc! {"id": {operator: id}};
+        }
+
+        return doc! {
+            "$or": [
+                {self.field.as_str(): {operator: value.clone()}},
+                {self.field.as_str(): value, "id": {operator: id}},
+            ]
+        };
+    }
+}
+
+/// Amount of detail of the entries returned by a listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListView {
+    /// Only the fields required to display an entry in an index, e.g. id, name and status
+    Basic,
+    /// All fields including labels and metadata
+    Full,
+}
+
+impl Default for ListView {
+    fn default() -> Self {
+        return ListView::Full;
+    }
+}
+
+impl ListView {
+    /// Parses a view of a listing, either basic or full
+    pub fn parse(value: &str) -> Result<Self, tonic::Status> {
+        match value.to_ascii_lowercase().as_str() {
+            "basic" => Ok(ListView::Basic),
+            "full" => Ok(ListView::Full),
+            _ => Err(tonic::Status::invalid_argument(format!(
+                "invalid view {}, expected basic or full",
+                value
+            ))),
+        }
+    }
 }
 
 pub fn to_metadata(proto_metadata: &Vec<models::v1::Metadata>) -> Vec<Metadata> {
@@ -3,10 +3,11 @@ use serde::{Deserialize, Serialize};
 
 use chrono::prelude::*;
 use chrono::DateTime;
+use mongodb::bson::{doc, Document};
 
 use super::common_models::{
     to_labels, to_metadata, to_proto_labels, to_proto_metadata, to_proto_status, DatabaseModel,
-    Label, Metadata, Status,
+    Label, Metadata, SoftDeletable, Status, Versioned,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -20,6 +21,9 @@ pub struct DatasetEntry {
     pub project_id: String,
     pub labels: Vec<Label>,
     pub metadata: Vec<Metadata>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub rev_version: i64,
 }
 
 impl DatabaseModel<'_> for DatasetEntry {
@@ -30,14 +34,46 @@ impl DatabaseModel<'_> for DatasetEntry {
     fn get_parent_field_name() -> Result<String, tonic::Status> {
         Ok("project_id".to_string())
     }
+
+    fn get_sort_field(name: &str) -> Option<&'static str> {
+        match name {
+            "id" => Some("id"),
+            "name" => Some("name"),
+            "created" => Some("created"),
+            _ => None,
+        }
+    }
+}
+
+/// Totals over all object group revisions of a dataset as computed by the database
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatasetStats {
+    pub objects_count: i64,
+    pub content_len: i64,
+}
+
+impl Versioned for DatasetEntry {
+    fn rev_version(&self) -> i64 {
+        return self.rev_version;
+    }
+
+    fn set_rev_version(&mut self, rev_version: i64) {
+        self.rev_version = rev_version;
+    }
+}
+
+impl SoftDeletable for DatasetEntry {
+    fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        return self.deleted_at;
+    }
 }
 
 impl DatasetEntry {
     pub fn new_from_proto_create(
         request: &services::v1::CreateDatasetRequest,
+        timestamp: DateTime<Utc>,
     ) -> Result<Self, tonic::Status> {
         let uuid = uuid::Uuid::new_v4();
-        let timestamp = Utc::now();
 
         let dataset_entry = DatasetEntry {
             id: uuid.to_string(),
@@ -49,11 +85,28 @@ impl DatasetEntry {
             metadata: to_metadata(&request.metadata),
             status: Status::Available,
             description: "".to_string(),
+            deleted_at: None,
+            rev_version: 0,
         };
 
         Ok(dataset_entry)
     }
 
+    /// Creates a copy of the dataset with the given name in the same project
+    pub fn new_clone(&self, name: &str, timestamp: DateTime<Utc>) -> Self {
+        let uuid = uuid::Uuid::new_v4();
+
+        return DatasetEntry {
+            id: uuid.to_string(),
+            name: name.to_string(),
+            created: timestamp,
+            status: Status::Available,
+            deleted_at: None,
+            rev_version: 0,
+            ..self.clone()
+        };
+    }
+
     pub fn to_proto_dataset(&self) -> models::v1::Dataset {
         let dataset = models::v1::Dataset {
             id: self.id.to_string(),
@@ -71,3 +124,53 @@ impl DatasetEntry {
         return dataset;
     }
 }
+
+/// Lightweight view of a stored dataset for listings
+/// Only the fields required to display a dataset are read, labels and metadata are omitted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DatasetSummary {
+    pub id: String,
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub status: Status,
+    pub project_id: String,
+}
+
+impl DatabaseModel<'_> for DatasetSummary {
+    fn get_model_name() -> Result<String, tonic::Status> {
+        DatasetEntry::get_model_name()
+    }
+
+    fn get_parent_field_name() -> Result<String, tonic::Status> {
+        DatasetEntry::get_parent_field_name()
+    }
+
+    fn get_sort_field(name: &str) -> Option<&'static str> {
+        DatasetEntry::get_sort_field(name)
+    }
+
+    fn get_projection() -> Option<Document> {
+        Some(doc! {
+            "_id": 0,
+            "id": 1,
+            "name": 1,
+            "created": 1,
+            "status": 1,
+            "project_id": 1,
+        })
+    }
+}
+
+impl DatasetSummary {
+    pub fn to_proto_dataset(&self) -> models::v1::Dataset {
+        let dataset = models::v1::Dataset {
+            id: self.id.to_string(),
+            name: self.name.to_string(),
+            project_id: self.project_id.to_string(),
+            status: to_proto_status(&self.status) as i32,
+            ..Default::default()
+        };
+
+        return dataset;
+    }
+}
@@ -1,20 +1,22 @@
 use std::time::SystemTime;
 
+use bson::{doc, to_bson, Document};
 use chrono::DateTime;
 use chrono::Utc;
+use log::error;
 use prost_types::Timestamp;
 use scienceobjectsdb_rust_api::sciobjectsdbapi::{models, services};
 use serde::{Deserialize, Serialize};
 
 use super::common_models::{
-    to_labels, to_metadata, to_proto_labels, to_proto_metadata, to_proto_status, DatabaseModel,
-    Label, Location, Metadata, Origin, Status, Version,
+    object_key, to_labels, to_metadata, to_proto_labels, to_proto_metadata, to_proto_status,
+    DatabaseModel, Label, Location, Metadata, Origin, SoftDeletable, Status, Version, Versioned,
 };
 
 use super::common_models;
 
 /// Here are all models that are used to store object related components
-/// A ObjectGroupVersions is used to keep track of the history of a set of DatasetObjectGroups
+/// An ObjectGroupRevision is used to keep track of the history of a set of DatasetObjectGroups
 
 /// Stores the history of object groups
 #[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
@@ -27,6 +29,30 @@ pub struct ObjectGroup {
     pub status: Status,
     pub head_id: String,
     pub revision_counter: i64,
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub rev_version: i64,
+}
+
+/// Returns the requested name of an object group, if the name is empty a name is generated
+/// The generated name is the filename of the first object of the initial revision, or group-{first 8 characters
+/// of the object group id} if the request does not contain an object
+fn object_group_name(request: &services::v1::CreateObjectGroupRequest, id: &str) -> String {
+    if !request.name.is_empty() {
+        return request.name.clone();
+    }
+
+    let first_filename = request
+        .object_group_revision
+        .as_ref()
+        .and_then(|revision| revision.objects.first())
+        .map(|object| object.filename.clone())
+        .filter(|filename| !filename.is_empty());
+
+    match first_filename {
+        Some(filename) => return filename,
+        None => return format!("group-{}", &id[..8]),
+    }
 }
 
 impl DatabaseModel<'_> for ObjectGroup {
@@ -37,9 +63,35 @@ impl DatabaseModel<'_> for ObjectGroup {
     fn get_parent_field_name() -> Result<String, tonic::Status> {
         Ok("dataset_id".to_string())
     }
+
+    fn get_sort_field(name: &str) -> Option<&'static str> {
+        match name {
+            "id" => Some("id"),
+            "name" => Some("name"),
+            "revision_count" => Some("revision_counter"),
+            _ => None,
+        }
+    }
+}
+
+impl Versioned for ObjectGroup {
+    fn rev_version(&self) -> i64 {
+        return self.rev_version;
+    }
+
+    fn set_rev_version(&mut self, rev_version: i64) {
+        self.rev_version = rev_version;
+    }
+}
+
+impl SoftDeletable for ObjectGroup {
+    fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        return self.deleted_at;
+    }
 }
 
 impl ObjectGroup {
+    /// Creates a new object group from the request, an empty name is replaced by a generated name
     pub fn new_from_proto_create(
         request: &services::v1::CreateObjectGroupRequest,
     ) -> Result<Self, tonic::Status> {
@@ -47,7 +99,7 @@ impl ObjectGroup {
 
         let object_group = ObjectGroup {
             id: uuid.to_string(),
-            name: request.name.clone(),
+            name: object_group_name(request, uuid.to_string().as_str()),
             labels: to_labels(&request.labels),
             dataset_id: request.dataset_id.clone(),
             status: Status::Initializing,
@@ -59,6 +111,23 @@ impl ObjectGroup {
         return Ok(object_group);
     }
 
+    /// Creates a copy of the object group in another dataset
+    /// The fork starts with the current revision of this object group as its first revision
+    pub fn new_fork(&self, target_dataset_id: &str, has_revision: bool) -> Self {
+        let uuid = uuid::Uuid::new_v4();
+
+        return ObjectGroup {
+            id: uuid.to_string(),
+            name: self.name.clone(),
+            dataset_id: target_dataset_id.to_string(),
+            labels: self.labels.clone(),
+            metadata: self.metadata.clone(),
+            status: self.status.clone(),
+            revision_counter: has_revision as i64,
+            ..Default::default()
+        };
+    }
+
     pub fn to_proto(&self) -> models::v1::ObjectGroup {
         let proto_object = models::v1::ObjectGroup {
             id: self.id.clone(),
@@ -89,6 +158,10 @@ pub struct ObjectGroupRevision {
     pub revision: i64,
     pub dataset_versions: Vec<String>,
     pub status: Status,
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Point in time after which the revision and its objects are deleted, unless the revision has been released
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 impl DatabaseModel<'_> for ObjectGroupRevision {
@@ -101,23 +174,67 @@ impl DatabaseModel<'_> for ObjectGroupRevision {
     }
 }
 
+impl SoftDeletable for ObjectGroupRevision {
+    fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        return self.deleted_at;
+    }
+}
+
 impl ObjectGroupRevision {
+    /// Update that appends the object to the objects of a stored revision
+    /// All updates of the objects of stored revisions have to use add_object_update or remove_object_update, so that
+    /// objects_count is always changed together with the objects
+    pub fn add_object_update(object: &DatasetObject) -> Result<Document, tonic::Status> {
+        let object_value = match to_bson(object) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal("could not convert object"));
+            }
+        };
+
+        return Ok(doc! {
+            "$push": {
+                "objects": object_value
+            },
+            "$inc": {
+                "objects_count": 1
+            }
+        });
+    }
+
+    /// Update that removes the object with the given id from the objects of a stored revision
+    /// The query of the update has to match the object id, otherwise objects_count is decremented without removing
+    /// an object
+    pub fn remove_object_update(object_id: &str) -> Document {
+        return doc! {
+            "$pull": {
+                "objects": {"id": object_id}
+            },
+            "$inc": {
+                "objects_count": -1
+            }
+        };
+    }
+
     pub fn new_from_proto_create(
         request: &services::v1::CreateObjectGroupRevisionRequest,
         object_group: &ObjectGroup,
+        project_id: &str,
         bucket: String,
+        timestamp: DateTime<Utc>,
     ) -> Result<Self, tonic::Status> {
         let uuid = uuid::Uuid::new_v4();
 
-        let timestamp = Utc::now();
-
         let mut objects = Vec::new();
 
         for create_object_request in &request.objects {
             let object = DatasetObject::new_from_proto_create(
                 &create_object_request,
+                project_id,
                 object_group.dataset_id.clone(),
                 bucket.clone(),
+                timestamp,
             )?;
             objects.push(object);
         }
@@ -137,11 +254,43 @@ impl ObjectGroupRevision {
             version: Default::default(),
             revision: object_group.revision_counter,
             dataset_versions: Vec::new(),
+            deleted_at: None,
+            expires_at: None,
         };
 
         return Ok(object_group);
     }
 
+    /// Creates a copy of the revision as first revision of a forked object group
+    /// The objects of the fork get new ids but reference the same stored data
+    pub fn new_fork(&self, object_group: &ObjectGroup, timestamp: DateTime<Utc>) -> Self {
+        let uuid = uuid::Uuid::new_v4();
+
+        let objects: Vec<DatasetObject> = self.objects.iter().map(|x| x.new_fork()).collect();
+
+        return ObjectGroupRevision {
+            id: uuid.to_string(),
+            datasete_id: object_group.dataset_id.clone(),
+            object_group_id: object_group.id.clone(),
+            date_create: Some(timestamp),
+            labels: self.labels.clone(),
+            metadata: self.metadata.clone(),
+            objects_count: objects.len() as i64,
+            objects: objects,
+            version: self.version.clone(),
+            revision: 0,
+            dataset_versions: Vec::new(),
+            status: self.status.clone(),
+            deleted_at: None,
+            expires_at: None,
+        };
+    }
+
+    /// A revision becomes immutable as soon as it is part of a released dataset version
+    pub fn is_immutable(&self) -> bool {
+        return !self.dataset_versions.is_empty();
+    }
+
     pub fn to_proto(&self) -> models::v1::ObjectGroupRevision {
         let mut proto_objects = Vec::new();
 
@@ -190,20 +339,34 @@ impl DatabaseModel<'_> for DatasetObject {
     }
 }
 
+/// Returns the filetype sent by the client, if it is empty the MIME type is guessed from the extension of the
+/// filename. Unknown extensions are stored as application/octet-stream.
+pub fn filetype_or_guess(filetype: &str, filename: &str) -> String {
+    if !filetype.is_empty() {
+        return filetype.to_string();
+    }
+
+    return mime_guess::from_path(filename)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string();
+}
+
 impl DatasetObject {
     pub fn new_from_proto_create(
         request: &services::v1::CreateObjectRequest,
+        project_id: &str,
         dataset_id: String,
         bucket: String,
+        timestamp: DateTime<Utc>,
     ) -> Result<Self, tonic::Status> {
-        let timestamp = Utc::now();
         let uuid = uuid::Uuid::new_v4();
 
-        let object_key = format!(
-            "{}/{}/{}",
-            dataset_id,
-            uuid.to_string().clone(),
-            request.filename.clone()
+        let object_key = object_key(
+            project_id,
+            dataset_id.as_str(),
+            uuid.to_string().as_str(),
+            request.filename.as_str(),
         );
 
         let location = Location {
@@ -220,8 +383,8 @@ impl DatasetObject {
         let object = DatasetObject {
             id: uuid.to_string().clone(),
             filename: request.filename.clone(),
-            filetype: request.filetype.clone(),
-            origin: Origin::default(),
+            filetype: filetype_or_guess(request.filetype.as_str(), request.filename.as_str()),
+            origin: Origin::from_labels(&to_labels(&request.labels)),
             content_len: request.content_len,
             location: location,
             created: Some(DateTime::from(timestamp)),
@@ -232,6 +395,17 @@ impl DatasetObject {
         Ok(object)
     }
 
+    /// Creates a copy of the object with a new id that references the same stored data
+    pub fn new_fork(&self) -> Self {
+        let uuid = uuid::Uuid::new_v4();
+
+        return DatasetObject {
+            id: uuid.to_string(),
+            upload_id: "".to_string(),
+            ..self.clone()
+        };
+    }
+
     pub fn to_proto_object(&self) -> models::v1::Object {
         let system_time: SystemTime = self.created.unwrap().into();
         let timestamp = Timestamp::from(system_time);
@@ -250,3 +424,37 @@ impl DatasetObject {
         return proto_object;
     }
 }
+
+/// Differences between the objects of two revisions of an object group
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RevisionDiff {
+    /// Objects of the newer revision whose filename does not occur in the older one
+    pub added: Vec<DatasetObject>,
+    /// Objects of the older revision whose filename does not occur in the newer one
+    pub removed: Vec<DatasetObject>,
+    /// Objects with the same filename in both revisions whose content differs
+    pub modified: Vec<ModifiedObject>,
+}
+
+/// An object whose content changed between two revisions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModifiedObject {
+    pub from: DatasetObject,
+    pub to: DatasetObject,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::filetype_or_guess;
+
+    #[test]
+    fn guess_filetype() {
+        assert_eq!(filetype_or_guess("", "image.png"), "image/png");
+        assert_eq!(filetype_or_guess("", "notes.txt"), "text/plain");
+        assert_eq!(
+            filetype_or_guess("", "data.unknownext"),
+            "application/octet-stream"
+        );
+        assert_eq!(filetype_or_guess("bin", "image.png"), "bin");
+    }
+}
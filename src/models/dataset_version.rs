@@ -13,7 +13,7 @@ use scienceobjectsdb_rust_api::sciobjectsdbapi::{
 
 use super::common_models::{
     to_labels, to_metadata, to_proto_labels, to_proto_metadata, to_proto_status, to_proto_version,
-    to_version, DatabaseModel, Label, Metadata, Status, Version,
+    to_version, DatabaseModel, Label, Metadata, SoftDeletable, Status, Version,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -28,6 +28,7 @@ pub struct DatasetVersion {
     pub object_group_ids: Vec<String>,
     pub object_count: i64,
     pub status: Status,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl DatabaseModel<'_> for DatasetVersion {
@@ -40,12 +41,18 @@ impl DatabaseModel<'_> for DatasetVersion {
     }
 }
 
+impl SoftDeletable for DatasetVersion {
+    fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        return self.deleted_at;
+    }
+}
+
 impl DatasetVersion {
     pub fn new_from_proto_create(
         request: &services::v1::ReleaseDatasetVersionRequest,
+        timestamp: DateTime<Utc>,
     ) -> Result<Self, tonic::Status> {
         let uuid = uuid::Uuid::new_v4();
-        let timestamp = Utc::now();
 
         let dataset_version = DatasetVersion {
             id: uuid.to_string(),
@@ -58,6 +65,7 @@ impl DatasetVersion {
             object_group_ids: request.object_group_ids.clone(),
             status: super::common_models::Status::Available,
             version: to_version(request.version.clone().unwrap()),
+            deleted_at: None,
         };
 
         return Ok(dataset_version);
@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::common_models::DatabaseModel;
+
+/// Token of a single-use download link, the object can be downloaded with it exactly once
+/// The id is the token itself. Redeemed tokens are kept as an audit record of the single-use downloads.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DownloadToken {
+    pub id: String,
+    pub object_id: String,
+    pub created_at: DateTime<Utc>,
+    /// Time the object has been downloaded with the token, None if it has not been used yet
+    pub redeemed_at: Option<DateTime<Utc>>,
+}
+
+impl DatabaseModel<'_> for DownloadToken {
+    fn get_model_name() -> Result<String, tonic::Status> {
+        Ok("DownloadToken".to_string())
+    }
+
+    fn get_parent_field_name() -> Result<String, tonic::Status> {
+        Ok("object_id".to_string())
+    }
+}
+
+impl DownloadToken {
+    pub fn new(object_id: &str, created_at: DateTime<Utc>) -> Self {
+        return DownloadToken {
+            id: uuid::Uuid::new_v4().to_string(),
+            object_id: object_id.to_string(),
+            created_at: created_at,
+            redeemed_at: None,
+        };
+    }
+}
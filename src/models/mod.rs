@@ -3,4 +3,6 @@ pub mod common_models;
 pub mod dataset_model;
 pub mod dataset_object_group;
 pub mod dataset_version;
+pub mod download_token;
 pub mod project_model;
+pub mod storage_reference;
@@ -26,6 +26,14 @@ impl DatabaseModel<'_> for ProjectEntry {
     fn get_parent_field_name() -> Result<String, tonic::Status> {
         Err(tonic::Status::internal("project does not have a parent"))
     }
+
+    fn get_sort_field(name: &str) -> Option<&'static str> {
+        match name {
+            "id" => Some("id"),
+            "name" => Some("name"),
+            _ => None,
+        }
+    }
 }
 
 impl ProjectEntry {
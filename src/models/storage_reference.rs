@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+use super::common_models::{DatabaseModel, Location};
+
+/// Counts the additional references to a stored object
+/// An object that is created by an upload owns its storage location, forks of the object share the location
+/// and are counted as additional references. Locations without a reference entry are only referenced by their owner.
+/// The stored data is only deleted if an object is deleted while the location has no additional references.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct StorageReference {
+    pub id: String,
+    pub bucket: String,
+    pub key: String,
+    pub ref_count: i64,
+}
+
+impl DatabaseModel<'_> for StorageReference {
+    fn get_model_name() -> Result<String, tonic::Status> {
+        Ok("StorageReference".to_string())
+    }
+
+    fn get_parent_field_name() -> Result<String, tonic::Status> {
+        Err(tonic::Status::internal(
+            "storage reference does not have a parent field",
+        ))
+    }
+}
+
+impl StorageReference {
+    /// Returns the id of the reference entry of a storage location
+    pub fn id_for_location(location: &Location) -> String {
+        return format!("{}/{}", location.bucket, location.key);
+    }
+}
@@ -0,0 +1,129 @@
+use std::sync::Arc;
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::SETTINGS;
+
+const DEFAULT_SUBJECT_TEMPLATE: &str = "objectsdb.{resource}.{event}";
+
+/// Lifecycle stage of a resource that is published as an event
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum EventType {
+    Created,
+    Finished,
+    Deleted,
+}
+
+impl EventType {
+    fn subject_name(&self) -> &'static str {
+        match self {
+            EventType::Created => "created",
+            EventType::Finished => "finished",
+            EventType::Deleted => "deleted",
+        }
+    }
+}
+
+/// Payload of a published lifecycle event
+/// The resource is the model name of the affected entry, e.g. Dataset or ObjectGroup
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LifecycleEvent {
+    pub event_type: EventType,
+    pub resource: String,
+    pub id: String,
+}
+
+/// Publishes lifecycle events of the stored resources
+/// Implementations must not block the caller, events are delivered best-effort
+pub trait EventEmitter: Send + Sync {
+    fn emit(&self, event: LifecycleEvent);
+}
+
+/// Drops all events, used if no message bus is configured
+pub struct NoopEventEmitter {}
+
+impl EventEmitter for NoopEventEmitter {
+    fn emit(&self, _event: LifecycleEvent) {}
+}
+
+/// Publishes events to a NATS server
+/// The subject is created from the subject template by replacing {resource} and {event}
+pub struct NatsEventEmitter {
+    connection: async_nats::Connection,
+    subject_template: String,
+}
+
+impl NatsEventEmitter {
+    pub async fn new(nats_url: &str, subject_template: String) -> Result<Self, tonic::Status> {
+        let connection = match async_nats::connect(nats_url).await {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal(
+                    "could not connect to event message bus",
+                ));
+            }
+        };
+
+        return Ok(NatsEventEmitter {
+            connection: connection,
+            subject_template: subject_template,
+        });
+    }
+
+    fn subject(&self, event: &LifecycleEvent) -> String {
+        return self
+            .subject_template
+            .replace("{resource}", event.resource.as_str())
+            .replace("{event}", event.event_type.subject_name());
+    }
+}
+
+impl EventEmitter for NatsEventEmitter {
+    fn emit(&self, event: LifecycleEvent) {
+        let payload = match serde_json::to_vec(&event) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return;
+            }
+        };
+
+        let subject = self.subject(&event);
+        let connection = self.connection.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.publish(subject.as_str(), payload).await {
+                error!("could not publish event to {}: {:?}", subject, e);
+            }
+        });
+    }
+}
+
+/// Creates the event emitter based on the configuration file
+/// Events are published to Events.NatsUrl if configured, otherwise all events are dropped
+/// Events are best-effort, if the message bus can not be reached the error is logged and all events are dropped
+pub async fn new_event_emitter() -> Arc<dyn EventEmitter> {
+    let nats_url = match SETTINGS.read().unwrap().get_str("Events.NatsUrl") {
+        Ok(value) => value,
+        Err(_) => return Arc::new(NoopEventEmitter {}),
+    };
+
+    let subject_template = SETTINGS
+        .read()
+        .unwrap()
+        .get_str("Events.SubjectTemplate")
+        .unwrap_or(DEFAULT_SUBJECT_TEMPLATE.to_string());
+
+    match NatsEventEmitter::new(nats_url.as_str(), subject_template).await {
+        Ok(emitter) => return Arc::new(emitter),
+        Err(e) => {
+            error!(
+                "could not connect to {}, lifecycle events are not published: {:?}",
+                nats_url, e
+            );
+            return Arc::new(NoopEventEmitter {});
+        }
+    }
+}
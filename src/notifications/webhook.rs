@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use log::{error, warn};
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde::{Deserialize, Serialize};
+
+use crate::{models::common_models::Status, SETTINGS};
+
+const DEFAULT_TIMEOUT_SECS: i64 = 5;
+const DEFAULT_MAX_RETRIES: i64 = 3;
+
+/// Payload that is sent to the configured webhook if the status of an object group changes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ObjectGroupEvent {
+    pub project_id: String,
+    pub dataset_id: String,
+    pub object_group_id: String,
+    pub status: Status,
+}
+
+/// Sends notifications to an external webhook
+/// Delivery is best-effort: each notification is sent in a separate task with a bounded number of retries,
+/// failed deliveries are only logged. If no webhook url is configured all notifications are dropped.
+pub struct WebhookDispatcher {
+    webhook_url: Option<String>,
+    client: Client,
+    max_retries: u32,
+}
+
+impl WebhookDispatcher {
+    /// Initiates a new webhook dispatcher
+    /// The webhook url is read from Notifications.WebhookUrl in the configuration file
+    pub fn new() -> Self {
+        let webhook_url = SETTINGS
+            .read()
+            .unwrap()
+            .get_str("Notifications.WebhookUrl")
+            .ok();
+
+        return WebhookDispatcher::new_with_url(webhook_url);
+    }
+
+    /// Initiates a new webhook dispatcher with the provided webhook url
+    /// Timeout and retries are read from the configuration file
+    pub fn new_with_url(webhook_url: Option<String>) -> Self {
+        let timeout = SETTINGS
+            .read()
+            .unwrap()
+            .get_int("Notifications.Timeout")
+            .unwrap_or(DEFAULT_TIMEOUT_SECS);
+        let max_retries = SETTINGS
+            .read()
+            .unwrap()
+            .get_int("Notifications.MaxRetries")
+            .unwrap_or(DEFAULT_MAX_RETRIES);
+
+        let client = match Client::builder()
+            .timeout(Duration::from_secs(timeout as u64))
+            .build()
+        {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                Client::new()
+            }
+        };
+
+        return WebhookDispatcher {
+            webhook_url: webhook_url.filter(|url| !url.is_empty()),
+            client: client,
+            max_retries: max_retries.max(1) as u32,
+        };
+    }
+
+    /// Sends the event to the configured webhook without waiting for the delivery
+    pub fn dispatch(&self, event: ObjectGroupEvent) {
+        let webhook_url = match &self.webhook_url {
+            Some(value) => value.clone(),
+            None => return,
+        };
+
+        let payload = match serde_json::to_string(&event) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return;
+            }
+        };
+
+        let client = self.client.clone();
+        let max_retries = self.max_retries;
+
+        tokio::spawn(async move {
+            for attempt in 1..=max_retries {
+                let response = client
+                    .post(webhook_url.as_str())
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(payload.clone())
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+
+                match response {
+                    Ok(_) => return,
+                    Err(e) => {
+                        warn!(
+                            "could not deliver webhook notification (attempt {} of {}): {:?}",
+                            attempt, max_retries, e
+                        );
+                    }
+                }
+
+                tokio::time::sleep(Duration::from_millis(100 * attempt as u64)).await;
+            }
+
+            error!(
+                "dropped webhook notification for object group {}",
+                event.object_group_id
+            );
+        });
+    }
+}
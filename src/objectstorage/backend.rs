@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use log::error;
+
+#[cfg(any(test, feature = "mock-storage"))]
+use super::mock_objectstorage::MockStorageHandler;
+use super::{objectstorage::StorageHandler, s3_objectstorage::S3Handler};
+use crate::SETTINGS;
+
+/// Object storage backends that can be selected with Storage.Backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    S3,
+    Local,
+    Mock,
+}
+
+impl StorageBackend {
+    pub fn parse(value: &str) -> Result<Self, tonic::Status> {
+        return match value {
+            "s3" => Ok(StorageBackend::S3),
+            "local" => Ok(StorageBackend::Local),
+            "mock" => Ok(StorageBackend::Mock),
+            _ => Err(tonic::Status::invalid_argument(format!(
+                "could not parse object storage backend: {}, expected one of s3, local or mock",
+                value
+            ))),
+        };
+    }
+
+    /// Reads the backend from Storage.Backend, defaults to s3 if no backend is configured
+    pub fn from_config() -> Result<Self, tonic::Status> {
+        return match SETTINGS.read().unwrap().get_str("Storage.Backend") {
+            Ok(value) => StorageBackend::parse(value.as_str()),
+            Err(_) => Ok(StorageBackend::S3),
+        };
+    }
+}
+
+/// Creates the storage handler of the backend configured in Storage.Backend
+pub async fn storage_handler_from_config() -> Result<Arc<dyn StorageHandler>, tonic::Status> {
+    let backend = StorageBackend::from_config()?;
+    return new_storage_handler(backend).await;
+}
+
+/// Creates the storage handler of the given backend
+/// The S3 handler validates that the configured bucket is accessible before it is returned
+pub async fn new_storage_handler(
+    backend: StorageBackend,
+) -> Result<Arc<dyn StorageHandler>, tonic::Status> {
+    return match backend {
+        StorageBackend::S3 => Ok(Arc::new(S3Handler::new_validated().await?)),
+        StorageBackend::Local => {
+            error!("the local object storage backend is not implemented yet");
+            Err(tonic::Status::unimplemented(
+                "the local object storage backend is not implemented yet",
+            ))
+        }
+        #[cfg(any(test, feature = "mock-storage"))]
+        StorageBackend::Mock => Ok(Arc::new(MockStorageHandler::new())),
+        #[cfg(not(any(test, feature = "mock-storage")))]
+        StorageBackend::Mock => Err(tonic::Status::failed_precondition(
+            "the mock object storage backend requires the mock-storage feature",
+        )),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_util::init::test_init, SETTINGS};
+
+    use super::{storage_handler_from_config, StorageBackend};
+
+    #[tokio::test]
+    async fn configured_storage_backend() {
+        test_init();
+
+        assert_eq!(StorageBackend::parse("s3").unwrap(), StorageBackend::S3);
+        assert_eq!(
+            StorageBackend::parse("local").unwrap(),
+            StorageBackend::Local
+        );
+        assert_eq!(StorageBackend::parse("mock").unwrap(), StorageBackend::Mock);
+        assert_eq!(
+            StorageBackend::parse("ftp").unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Storage.Backend", "mock")
+            .unwrap();
+        assert_eq!(StorageBackend::from_config().unwrap(), StorageBackend::Mock);
+
+        let storage_handler = storage_handler_from_config().await.unwrap();
+        assert_eq!(storage_handler.get_bucket(), "mock-bucket");
+    }
+}
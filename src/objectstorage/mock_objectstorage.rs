@@ -0,0 +1,417 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::CompletedParts;
+
+use super::objectstorage::{
+    check_presigned_url_count, index_range, max_presigned_urls_from_config,
+    validate_sha256_checksum, CorsRule, DownloadConditions, ObjectData, ObjectHead,
+    PendingMultipartUpload, StorageHandler,
+};
+use crate::models::{
+    common_models::{object_key, IndexLocation, Location, LocationType},
+    dataset_object_group::DatasetObject,
+};
+
+const MOCK_ENDPOINT: &str = "mock://objectstorage";
+const MOCK_BUCKET: &str = "mock-bucket";
+
+/// A call to the mock storage handler, key is the object key the call refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageCall {
+    pub method: &'static str,
+    pub key: String,
+}
+
+/// Parts of an initiated multipart upload for which upload links have been created
+struct MultipartUpload {
+    key: String,
+    parts: BTreeSet<i64>,
+    initiated: DateTime<Utc>,
+}
+
+/// Object storage handler that keeps all state in memory to run the handlers and the server without object storage
+/// Links are deterministic fake urls that are not meant to be requested, every call is recorded and can be inspected
+/// with calls. Uploads through links can not be observed, they are simulated with put_object.
+#[derive(Default)]
+pub struct MockStorageHandler {
+    calls: Mutex<Vec<StorageCall>>,
+    objects: Mutex<HashMap<String, ObjectHead>>,
+    data: Mutex<HashMap<String, Bytes>>,
+    tags: Mutex<HashMap<String, Vec<(String, String)>>>,
+    uploads: Mutex<HashMap<String, MultipartUpload>>,
+    upload_counter: AtomicU64,
+}
+
+impl MockStorageHandler {
+    pub fn new() -> Self {
+        return MockStorageHandler::default();
+    }
+
+    /// Returns all calls in the order in which they were made
+    pub fn calls(&self) -> Vec<StorageCall> {
+        return self.calls.lock().unwrap().clone();
+    }
+
+    /// Returns the number of calls of the method with the given name
+    pub fn call_count(&self, method: &str) -> usize {
+        return self
+            .calls
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|call| call.method == method)
+            .count();
+    }
+
+    /// Returns the tags that have been set on the object with the given key
+    pub fn object_tags(&self, key: &str) -> Vec<(String, String)> {
+        return self
+            .tags
+            .lock()
+            .unwrap()
+            .get(key)
+            .cloned()
+            .unwrap_or_default();
+    }
+
+    /// Simulates an upload of an object with the given size through an upload link
+    pub fn put_object(&self, key: &str, size: i64) {
+        self.objects.lock().unwrap().insert(
+            key.to_string(),
+            ObjectHead {
+                size: size,
+                etag: Some(format!("\"{}\"", key)),
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Simulates an upload of the given data through an upload link, the data can be read with get_object
+    pub fn put_object_data(&self, key: &str, data: &[u8]) {
+        self.put_object(key, data.len() as i64);
+        self.data
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), Bytes::copy_from_slice(data));
+    }
+
+    /// Backdates the initiation of a multipart upload, e.g. to let it appear stale
+    pub fn set_upload_initiated(&self, upload_id: &str, initiated: DateTime<Utc>) {
+        if let Some(upload) = self.uploads.lock().unwrap().get_mut(upload_id) {
+            upload.initiated = initiated;
+        }
+    }
+
+    /// Returns the ETag the storage would have returned for the upload of a part
+    pub fn part_etag(upload_id: &str, upload_part: i64) -> String {
+        return format!("\"{}-{}\"", upload_id, upload_part);
+    }
+
+    fn record(&self, method: &'static str, key: &str) {
+        self.calls.lock().unwrap().push(StorageCall {
+            method: method,
+            key: key.to_string(),
+        });
+    }
+
+    fn link(key: &str, operation: &str) -> String {
+        return format!(
+            "{}/{}/{}?operation={}",
+            MOCK_ENDPOINT, MOCK_BUCKET, key, operation
+        );
+    }
+}
+
+#[async_trait]
+impl StorageHandler for MockStorageHandler {
+    async fn create_location(
+        &self,
+        project_id: String,
+        dataset_id: String,
+        object_id: String,
+        filename: String,
+        _index: Option<IndexLocation>,
+    ) -> Result<Location, tonic::Status> {
+        let key = object_key(
+            project_id.as_str(),
+            dataset_id.as_str(),
+            object_id.as_str(),
+            filename.as_str(),
+        );
+        self.record("create_location", key.as_str());
+
+        return Ok(Location {
+            bucket: MOCK_BUCKET.to_string(),
+            key: key,
+            url: MOCK_ENDPOINT.to_string(),
+            location_type: LocationType::Object,
+            index_location: IndexLocation {
+                start_byte: 0,
+                end_byte: 0,
+            },
+        });
+    }
+
+    async fn create_download_link(&self, location: Location) -> Result<String, tonic::Status> {
+        return self
+            .create_conditional_download_link(location, &DownloadConditions::default())
+            .await;
+    }
+
+    async fn create_conditional_download_link(
+        &self,
+        location: Location,
+        conditions: &DownloadConditions,
+    ) -> Result<String, tonic::Status> {
+        self.record("create_download_link", location.key.as_str());
+
+        let mut link = MockStorageHandler::link(location.key.as_str(), "download");
+        if let Some(if_none_match) = &conditions.if_none_match {
+            link.push_str(format!("&if-none-match={}", if_none_match).as_str());
+        }
+        if let Some(if_modified_since) = &conditions.if_modified_since {
+            link.push_str(format!("&if-modified-since={}", if_modified_since).as_str());
+        }
+        if let Some(range) = index_range(&location)? {
+            link.push_str(format!("&range={}", range).as_str());
+        }
+
+        return Ok(link);
+    }
+
+    async fn create_download_links(
+        &self,
+        locations: Vec<Location>,
+    ) -> Result<Vec<String>, tonic::Status> {
+        check_presigned_url_count(locations.len(), max_presigned_urls_from_config())?;
+
+        let mut links = Vec::with_capacity(locations.len());
+        for location in locations {
+            links.push(self.create_download_link(location).await?);
+        }
+
+        return Ok(links);
+    }
+
+    async fn create_upload_link(&self, location: Location) -> Result<String, tonic::Status> {
+        self.record("create_upload_link", location.key.as_str());
+
+        return Ok(MockStorageHandler::link(location.key.as_str(), "upload"));
+    }
+
+    async fn init_multipart_upload(&self, object: &DatasetObject) -> Result<String, tonic::Status> {
+        self.record("init_multipart_upload", object.location.key.as_str());
+
+        let upload_id = format!(
+            "mock-upload-{}",
+            self.upload_counter.fetch_add(1, Ordering::SeqCst)
+        );
+        self.uploads.lock().unwrap().insert(
+            upload_id.clone(),
+            MultipartUpload {
+                key: object.location.key.clone(),
+                parts: BTreeSet::new(),
+                initiated: Utc::now(),
+            },
+        );
+
+        return Ok(upload_id);
+    }
+
+    async fn upload_multipart_part_link(
+        &self,
+        location: &Location,
+        upload_id: &str,
+        upload_part: i64,
+        checksum_sha256: Option<&str>,
+    ) -> Result<String, tonic::Status> {
+        self.record("upload_multipart_part_link", location.key.as_str());
+
+        if upload_part < 1 {
+            return Err(tonic::Status::invalid_argument(
+                "part numbers have to be greater than zero",
+            ));
+        }
+        if let Some(checksum) = checksum_sha256 {
+            validate_sha256_checksum(checksum)?;
+        }
+
+        let mut uploads = self.uploads.lock().unwrap();
+        let upload = match uploads.get_mut(upload_id) {
+            Some(value) if value.key == location.key => value,
+            _ => {
+                return Err(tonic::Status::not_found(format!(
+                    "no multipart upload {} for object",
+                    upload_id
+                )))
+            }
+        };
+        upload.parts.insert(upload_part);
+
+        return Ok(format!(
+            "{}&uploadId={}&partNumber={}",
+            MockStorageHandler::link(location.key.as_str(), "upload"),
+            upload_id,
+            upload_part
+        ));
+    }
+
+    async fn finish_multipart_upload(
+        &self,
+        location: &Location,
+        objects: &Vec<CompletedParts>,
+        upload_id: &str,
+    ) -> Result<(), tonic::Status> {
+        self.record("finish_multipart_upload", location.key.as_str());
+
+        let mut uploads = self.uploads.lock().unwrap();
+        let upload = match uploads.get(upload_id) {
+            Some(value) if value.key == location.key => value,
+            _ => {
+                return Err(tonic::Status::not_found(format!(
+                    "no multipart upload {} for object",
+                    upload_id
+                )))
+            }
+        };
+
+        if objects.is_empty() {
+            return Err(tonic::Status::invalid_argument(
+                "at least one part is required to finish a multipart upload",
+            ));
+        }
+        for part in objects {
+            if !upload.parts.contains(&part.part)
+                || part.etag != MockStorageHandler::part_etag(upload_id, part.part)
+            {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "part {} has not been uploaded",
+                    part.part
+                )));
+            }
+        }
+
+        uploads.remove(upload_id);
+        drop(uploads);
+        self.put_object(location.key.as_str(), 0);
+
+        return Ok(());
+    }
+
+    async fn list_multipart_uploads(&self) -> Result<Vec<PendingMultipartUpload>, tonic::Status> {
+        self.record("list_multipart_uploads", "");
+
+        let mut pending_uploads: Vec<PendingMultipartUpload> = self
+            .uploads
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(upload_id, upload)| PendingMultipartUpload {
+                key: upload.key.clone(),
+                upload_id: upload_id.clone(),
+                initiated: upload.initiated,
+            })
+            .collect();
+        pending_uploads.sort_by(|a, b| a.upload_id.cmp(&b.upload_id));
+
+        return Ok(pending_uploads);
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<(), tonic::Status> {
+        self.record("abort_multipart_upload", key);
+
+        let mut uploads = self.uploads.lock().unwrap();
+        if let Some(upload) = uploads.get(upload_id) {
+            if upload.key == key {
+                uploads.remove(upload_id);
+            }
+        }
+
+        return Ok(());
+    }
+
+    async fn head_object(&self, location: &Location) -> Result<ObjectHead, tonic::Status> {
+        self.record("head_object", location.key.as_str());
+
+        match self.objects.lock().unwrap().get(&location.key) {
+            Some(value) => return Ok(value.clone()),
+            None => {
+                return Err(tonic::Status::not_found(
+                    "object not found in object storage",
+                ))
+            }
+        }
+    }
+
+    async fn get_object(&self, location: &Location) -> Result<ObjectData, tonic::Status> {
+        self.record("get_object", location.key.as_str());
+
+        if !self.objects.lock().unwrap().contains_key(&location.key) {
+            return Err(tonic::Status::not_found(
+                "object not found in object storage",
+            ));
+        }
+        let mut data = self
+            .data
+            .lock()
+            .unwrap()
+            .get(&location.key)
+            .cloned()
+            .unwrap_or_default();
+        if index_range(location)?.is_some() {
+            let start = (location.index_location.start_byte as usize).min(data.len());
+            let end = (location.index_location.end_byte as usize).min(data.len());
+            data = data.slice(start..end);
+        }
+
+        return Ok(Box::pin(futures::stream::once(async move {
+            Ok::<Bytes, std::io::Error>(data)
+        })));
+    }
+
+    async fn delete_object(&self, location: Location) -> Result<(), tonic::Status> {
+        self.record("delete_object", location.key.as_str());
+        self.objects.lock().unwrap().remove(&location.key);
+        self.data.lock().unwrap().remove(&location.key);
+
+        return Ok(());
+    }
+
+    async fn set_object_tags(
+        &self,
+        location: &Location,
+        tags: Vec<(String, String)>,
+    ) -> Result<(), tonic::Status> {
+        self.record("set_object_tags", location.key.as_str());
+        self.tags.lock().unwrap().insert(location.key.clone(), tags);
+
+        return Ok(());
+    }
+
+    fn get_bucket(&self) -> String {
+        return MOCK_BUCKET.to_string();
+    }
+
+    async fn get_cors_rules(&self) -> Result<Vec<CorsRule>, tonic::Status> {
+        self.record("get_cors_rules", "");
+
+        return Ok(Vec::new());
+    }
+
+    async fn ping(&self) -> Result<(), tonic::Status> {
+        return Ok(());
+    }
+}
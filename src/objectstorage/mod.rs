@@ -1,2 +1,5 @@
+pub mod backend;
+#[cfg(any(test, feature = "mock-storage"))]
+pub mod mock_objectstorage;
 pub mod objectstorage;
 pub mod s3_objectstorage;
@@ -1,11 +1,153 @@
+use std::pin::Pin;
+
 use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::Stream;
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::CompletedParts;
 
-use crate::models::{
-    common_models::{IndexLocation, Location},
-    dataset_object_group::DatasetObject,
+use crate::{
+    models::{
+        common_models::{IndexLocation, Location, LocationType},
+        dataset_object_group::DatasetObject,
+    },
+    SETTINGS,
 };
 
+/// Number of presigned urls a single request can create if Storage.MaxPresignedUrlsPerRequest is not configured
+pub const DEFAULT_MAX_PRESIGNED_URLS: usize = 1000;
+
+/// CORS rule of the bucket that is used by the object storage
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CorsRule {
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+}
+
+impl CorsRule {
+    /// Checks if the rule allows requests with the given method from the given origin
+    /// Origins can contain a single * wildcard as supported by S3
+    pub fn allows(&self, origin: &str, method: &str) -> bool {
+        let method_allowed = self
+            .allowed_methods
+            .iter()
+            .any(|allowed_method| allowed_method.eq_ignore_ascii_case(method));
+
+        let origin_allowed = self
+            .allowed_origins
+            .iter()
+            .any(|allowed_origin| match allowed_origin.split_once('*') {
+                Some((prefix, suffix)) => {
+                    origin.len() >= prefix.len() + suffix.len()
+                        && origin.starts_with(prefix)
+                        && origin.ends_with(suffix)
+                }
+                None => allowed_origin == origin,
+            });
+
+        return method_allowed && origin_allowed;
+    }
+}
+
+/// Returns the methods that are not allowed for the origin by any of the rules
+pub fn missing_cors_methods(rules: &Vec<CorsRule>, origin: &str, methods: &[&str]) -> Vec<String> {
+    return methods
+        .iter()
+        .filter(|method| !rules.iter().any(|rule| rule.allows(origin, method)))
+        .map(|method| method.to_string())
+        .collect();
+}
+
+/// Conditions of a conditional download, the values are passed as the corresponding HTTP headers
+/// If a condition is set the client has to send the header with the same value, the storage then answers
+/// with 304 Not Modified if the object has not been changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadConditions {
+    /// ETag of the cached object, weak ETags are passed unchanged
+    pub if_none_match: Option<String>,
+    /// HTTP date of the cached object
+    pub if_modified_since: Option<String>,
+}
+
+/// Multipart upload that has been initiated but neither been completed nor aborted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingMultipartUpload {
+    pub key: String,
+    pub upload_id: String,
+    pub initiated: DateTime<Utc>,
+}
+
+/// Reads the number of presigned urls a single request can create from Storage.MaxPresignedUrlsPerRequest
+pub fn max_presigned_urls_from_config() -> usize {
+    return SETTINGS
+        .read()
+        .unwrap()
+        .get_int("Storage.MaxPresignedUrlsPerRequest")
+        .map(|value| value.max(0) as usize)
+        .unwrap_or(DEFAULT_MAX_PRESIGNED_URLS);
+}
+
+/// Checks that a request does not create more presigned urls than allowed
+pub fn check_presigned_url_count(count: usize, max_count: usize) -> Result<(), tonic::Status> {
+    if count > max_count {
+        return Err(tonic::Status::invalid_argument(format!(
+            "requested {} links, at most {} links can be created per request, please request the links in pages",
+            count, max_count
+        )));
+    }
+
+    return Ok(());
+}
+
+/// Returns the HTTP Range header value that limits a download to the byte range of an index location
+/// The end byte of the index location is exclusive, None is returned for locations of whole objects
+pub fn index_range(location: &Location) -> Result<Option<String>, tonic::Status> {
+    if location.location_type != LocationType::Index {
+        return Ok(None);
+    }
+
+    let index = &location.index_location;
+    if index.start_byte < 0 || index.end_byte <= index.start_byte {
+        return Err(tonic::Status::invalid_argument(format!(
+            "invalid index location {}..{}, the start byte has to be smaller than the end byte",
+            index.start_byte, index.end_byte
+        )));
+    }
+
+    return Ok(Some(format!(
+        "bytes={}-{}",
+        index.start_byte,
+        index.end_byte - 1
+    )));
+}
+
+/// Metadata of a stored object as reported by the object storage
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ObjectHead {
+    /// Size of the stored object in bytes
+    pub size: i64,
+    pub content_type: Option<String>,
+    /// HTTP date of the last modification
+    pub last_modified: Option<String>,
+    pub etag: Option<String>,
+}
+
+/// Data of a stored object, streamed in chunks as it is read from the object storage
+pub type ObjectData = Pin<Box<dyn Stream<Item = Result<Bytes, std::io::Error>> + Send>>;
+
+/// Checks that a client supplied SHA256 checksum is the base64 encoding of a 32 byte digest
+pub fn validate_sha256_checksum(checksum: &str) -> Result<(), tonic::Status> {
+    match base64::decode(checksum) {
+        Ok(value) if value.len() == 32 => return Ok(()),
+        _ => {
+            return Err(tonic::Status::invalid_argument(
+                "checksum has to be a base64 encoded SHA256 digest",
+            ))
+        }
+    }
+}
+
 #[async_trait]
 pub trait StorageHandler: Send + Sync {
     async fn create_location(
@@ -20,6 +162,19 @@ pub trait StorageHandler: Send + Sync {
         &self,
         location: Location,
     ) -> std::result::Result<String, tonic::Status>;
+    /// Creates a download link that includes the given conditions as signed headers
+    /// Links to index locations include the byte range of the index as signed Range header
+    async fn create_conditional_download_link(
+        &self,
+        location: Location,
+        conditions: &DownloadConditions,
+    ) -> std::result::Result<String, tonic::Status>;
+    /// Creates download links for a batch of locations in the order of the locations
+    /// Fails with invalid_argument if more links are requested than allowed per request
+    async fn create_download_links(
+        &self,
+        locations: Vec<Location>,
+    ) -> std::result::Result<Vec<String>, tonic::Status>;
     async fn create_upload_link(
         &self,
         location: Location,
@@ -28,11 +183,15 @@ pub trait StorageHandler: Send + Sync {
         &self,
         location: &DatasetObject,
     ) -> std::result::Result<String, tonic::Status>;
+    /// Creates an upload link for a single part of a multipart upload
+    /// If a base64 encoded SHA256 checksum is given it is signed into the link, the storage then rejects
+    /// uploads of the part whose content does not match the checksum
     async fn upload_multipart_part_link(
         &self,
         location: &Location,
         upload_id: &str,
         upload_part: i64,
+        checksum_sha256: Option<&str>,
     ) -> std::result::Result<String, tonic::Status>;
     async fn finish_multipart_upload(
         &self,
@@ -40,6 +199,27 @@ pub trait StorageHandler: Send + Sync {
         objects: &Vec<CompletedParts>,
         upload_id: &str,
     ) -> Result<(), tonic::Status>;
+    /// Lists the multipart uploads of the bucket that have neither been completed nor aborted
+    async fn list_multipart_uploads(&self) -> Result<Vec<PendingMultipartUpload>, tonic::Status>;
+    /// Aborts a multipart upload and discards its uploaded parts, aborting an unknown upload succeeds
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str)
+        -> Result<(), tonic::Status>;
+    /// Reads the metadata of a stored object directly from the storage without creating a presigned url
+    async fn head_object(&self, location: &Location) -> Result<ObjectHead, tonic::Status>;
+    /// Reads the data of a stored object through the server, index locations only read the byte range of the index
+    async fn get_object(&self, location: &Location) -> Result<ObjectData, tonic::Status>;
+    /// Deletes a stored object, deleting an object that does not exist succeeds so that interrupted deletes can be retried
     async fn delete_object(&self, location: Location) -> std::result::Result<(), tonic::Status>;
+    /// Replaces the native tags of a stored object
+    /// Backends with a limit on the number of tags only store the first tags up to their limit
+    async fn set_object_tags(
+        &self,
+        location: &Location,
+        tags: Vec<(String, String)>,
+    ) -> Result<(), tonic::Status>;
     fn get_bucket(&self) -> String;
+    /// Reads the CORS configuration of the bucket, an empty list is returned if no CORS rules are configured
+    async fn get_cors_rules(&self) -> Result<Vec<CorsRule>, tonic::Status>;
+    /// Checks if the object storage backend and the configured bucket are reachable
+    async fn ping(&self) -> Result<(), tonic::Status>;
 }
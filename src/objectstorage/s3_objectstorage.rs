@@ -1,26 +1,51 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use async_trait::async_trait;
 
-use log::error;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
 use rusoto_core::{
-    credential::{DefaultCredentialsProvider, ProvideAwsCredentials},
-    Region,
+    credential::{
+        AutoRefreshingProvider, AwsCredentials, ChainProvider, DefaultCredentialsProvider,
+        ProvideAwsCredentials,
+    },
+    param::{Params, ServiceParams},
+    request::{BufferedHttpResponse, HttpClient},
+    signature::SignedRequest,
+    Region, RusotoError,
 };
 use rusoto_s3::{
-    util::{PreSignedRequest, PreSignedRequestOption},
-    CompleteMultipartUploadRequest, CompletedMultipartUpload, CreateMultipartUploadRequest,
-    DeleteObjectRequest, GetObjectRequest, PutObjectRequest, S3Client, UploadPartRequest, S3,
+    AbortMultipartUploadError, AbortMultipartUploadRequest, CompleteMultipartUploadRequest,
+    CompletedMultipartUpload, CreateBucketRequest, CreateMultipartUploadRequest,
+    DeleteObjectRequest, GetBucketCorsOutput, GetBucketCorsRequest, GetObjectError,
+    GetObjectRequest, HeadBucketError, HeadBucketRequest, HeadObjectError, HeadObjectRequest,
+    ListMultipartUploadsRequest, PutObjectTaggingRequest, S3Client, Tag, Tagging, S3,
 };
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
+use tokio::sync::Mutex;
 
-use super::objectstorage::StorageHandler;
+use super::objectstorage::{
+    check_presigned_url_count, index_range, max_presigned_urls_from_config,
+    validate_sha256_checksum, CorsRule, DownloadConditions, ObjectData, ObjectHead,
+    PendingMultipartUpload, StorageHandler,
+};
 use crate::models::{
-    common_models::{IndexLocation, Location, LocationType},
+    common_models::{object_key, IndexLocation, Location, LocationType},
     dataset_object_group::DatasetObject,
 };
 
-use crate::SETTINGS;
+use crate::{deadline, SETTINGS};
+
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+/// Maximum number of tags S3 allows per object
+const S3_MAX_OBJECT_TAGS: usize = 10;
+const PRESIGNED_URL_EXPIRY: Duration = Duration::from_secs(3600);
+/// Cached credentials are refreshed if they expire within this time, so that presigned urls are not signed with
+/// credentials that expire shortly after
+const CREDENTIALS_REFRESH_MARGIN_SECS: i64 = 300;
 
 /// Handles S3-compatible object storage backends for storing data
 /// Access is entirely provided via presigned URLs
@@ -30,24 +55,84 @@ use crate::SETTINGS;
 /// TODO: Update object status after finished upload.
 /// TODO: Check if object has already been uploaded, an upload should not never occur with the same key twice to avoid consistency problems
 pub struct S3Handler {
-    client: S3Client,
+    /// Client of the endpoint, it is replaced if the endpoint can not be reached, see reconnect_on_failure
+    client: RwLock<S3Client>,
     bucket: String,
     endpoint: String,
+    /// Endpoint external clients reach the object storage at, presigned urls are signed for it if it is set
+    public_endpoint: Option<String>,
     region: Region,
-    credentials: DefaultCredentialsProvider,
+    credentials: Arc<dyn ProvideAwsCredentials + Send + Sync>,
+    cached_credentials: Mutex<Option<AwsCredentials>>,
+    path_style: bool,
+    max_presigned_urls: usize,
 }
 
 impl S3Handler {
     pub fn new() -> Self {
+        let bucket = SETTINGS.read().unwrap().get_str("Storage.Bucket").unwrap();
+
+        return S3Handler::new_with_bucket(bucket);
+    }
+
+    /// Initiates a new S3 handler from the configuration and validates that the configured bucket is accessible
+    /// A missing bucket is created if Storage.AutoCreateBucket is set, otherwise a descriptive error is returned
+    /// that should abort the startup
+    pub async fn new_validated() -> Result<Self, tonic::Status> {
+        let bucket = match SETTINGS.read().unwrap().get_str("Storage.Bucket") {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::failed_precondition(
+                    "no object storage bucket configured, please set Storage.Bucket",
+                ));
+            }
+        };
+        let auto_create_bucket = SETTINGS
+            .read()
+            .unwrap()
+            .get_bool("Storage.AutoCreateBucket")
+            .unwrap_or(false);
+
+        let s3_handler = S3Handler::new_with_bucket(bucket);
+        s3_handler.ensure_bucket(auto_create_bucket).await?;
+
+        return Ok(s3_handler);
+    }
+
+    fn new_with_bucket(bucket: String) -> Self {
         let endpoint = SETTINGS
             .read()
             .unwrap()
             .get_str("Storage.Endpoint")
             .unwrap_or("localhost".to_string());
-        let bucket = SETTINGS.read().unwrap().get_str("Storage.Bucket").unwrap();
-        let region = "RegionOne".to_string();
+        let path_style = SETTINGS
+            .read()
+            .unwrap()
+            .get_bool("Storage.PathStyle")
+            .unwrap_or(true);
+
+        return S3Handler::new_with_endpoint(endpoint, bucket, path_style);
+    }
+
+    /// Initiates a new S3 handler for the provided endpoint and bucket
+    /// Presigned urls are created with path-style addressing (endpoint/bucket/key) if path_style is set,
+    /// otherwise with virtual-hosted addressing (bucket.endpoint/key)
+    pub fn new_with_endpoint(endpoint: String, bucket: String, path_style: bool) -> Self {
+        // Credentials from IAM roles or STS expire, they are cached and refreshed shortly before they expire
+        let creds = AutoRefreshingProvider::new(ChainProvider::new()).unwrap();
+
+        return S3Handler::new_with_credentials(endpoint, bucket, path_style, Arc::new(creds));
+    }
 
-        let creds = DefaultCredentialsProvider::new().unwrap();
+    /// Initiates a new S3 handler that signs presigned urls with the credentials of the provided provider
+    pub fn new_with_credentials(
+        endpoint: String,
+        bucket: String,
+        path_style: bool,
+        credentials: Arc<dyn ProvideAwsCredentials + Send + Sync>,
+    ) -> Self {
+        let region = "RegionOne".to_string();
 
         let region = Region::Custom {
             name: region.clone(),
@@ -55,15 +140,253 @@ impl S3Handler {
         };
 
         let s3_handler = S3Handler {
-            client: S3Client::new(region.clone()),
+            client: RwLock::new(new_s3_client(&region).unwrap()),
             bucket: bucket,
             endpoint: endpoint,
+            public_endpoint: public_endpoint_from_config(),
             region: region,
-            credentials: creds,
+            credentials: credentials,
+            cached_credentials: Mutex::new(None),
+            path_style: path_style,
+            max_presigned_urls: max_presigned_urls_from_config(),
         };
 
         return s3_handler;
     }
+
+    /// Checks that the bucket of the handler exists and is accessible
+    /// If the bucket does not exist it is created if auto_create is set, otherwise an error is returned
+    pub async fn ensure_bucket(&self, auto_create: bool) -> Result<(), tonic::Status> {
+        let head_bucket = deadline::timeout(self.client().head_bucket(HeadBucketRequest {
+            bucket: self.bucket.clone(),
+            ..Default::default()
+        }))
+        .await?;
+
+        match head_bucket {
+            Ok(_) => return Ok(()),
+            Err(RusotoError::Service(HeadBucketError::NoSuchBucket(_))) => {}
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 404 => {}
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 403 => {
+                return Err(tonic::Status::permission_denied(format!(
+                    "access to bucket {} at {} is denied, please check the object storage credentials",
+                    self.bucket, self.endpoint
+                )));
+            }
+            Err(e) => {
+                self.reconnect_on_failure(&e);
+                error!("{:?}", e.to_string());
+                return Err(tonic::Status::unavailable(format!(
+                    "could not access bucket {} at {}",
+                    self.bucket, self.endpoint
+                )));
+            }
+        }
+
+        if !auto_create {
+            return Err(tonic::Status::failed_precondition(format!(
+                "bucket {} does not exist at {}, please create it or set Storage.AutoCreateBucket",
+                self.bucket, self.endpoint
+            )));
+        }
+
+        info!("creating bucket {} at {}", self.bucket, self.endpoint);
+        match deadline::timeout(self.client().create_bucket(CreateBucketRequest {
+            bucket: self.bucket.clone(),
+            ..Default::default()
+        }))
+        .await?
+        {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                self.reconnect_on_failure(&e);
+                error!("{:?}", e.to_string());
+                return Err(tonic::Status::unavailable(format!(
+                    "could not create bucket {} at {}",
+                    self.bucket, self.endpoint
+                )));
+            }
+        }
+    }
+
+    /// Returns the client of the endpoint, the client may be replaced between calls
+    fn client(&self) -> S3Client {
+        return self.client.read().unwrap().clone();
+    }
+
+    /// Replaces the client if a request failed because the endpoint could not be reached or the credentials could not
+    /// be loaded. Connections to a restarted endpoint and expired credentials are not reused by later requests, so
+    /// that the handler recovers without restarting the server.
+    fn reconnect_on_failure<E>(&self, error: &RusotoError<E>) {
+        match error {
+            RusotoError::HttpDispatch(_) | RusotoError::Credentials(_) => self.reconnect(),
+            _ => {}
+        }
+    }
+
+    /// Replaces the client with a new client, failures are logged and the current client is kept
+    fn reconnect(&self) {
+        match new_s3_client(&self.region) {
+            Ok(client) => {
+                info!("recreating object storage client for {}", self.endpoint);
+                *self.client.write().unwrap() = client;
+            }
+            Err(e) => warn!("could not recreate object storage client: {:?}", e),
+        }
+    }
+
+    /// Returns the region presigned urls for the given bucket have to be signed for
+    /// Urls are signed for the public endpoint if one is configured, the signature covers the host and rewriting the
+    /// host of an url signed for the internal endpoint would invalidate it
+    /// For virtual-hosted addressing the bucket is prepended to the host of the endpoint
+    fn presign_region(&self, bucket: &str) -> Region {
+        let presign_endpoint = match &self.public_endpoint {
+            Some(value) => value,
+            None => &self.endpoint,
+        };
+
+        let endpoint = if self.path_style {
+            presign_endpoint.clone()
+        } else {
+            match presign_endpoint.split_once("://") {
+                Some((scheme, host)) => format!("{}://{}.{}", scheme, bucket, host),
+                None => format!("{}.{}", bucket, presign_endpoint),
+            }
+        };
+
+        return Region::Custom {
+            name: self.region.name().to_string(),
+            endpoint: endpoint,
+        };
+    }
+
+    /// Returns the cached credentials, they are only fetched from the provider if they are missing or expire soon
+    /// The lock is held during the fetch, so that concurrent presign calls wait for a single fetch
+    async fn fetch_credentials(&self) -> Result<AwsCredentials, tonic::Status> {
+        let mut cached_credentials = self.cached_credentials.lock().await;
+        if let Some(credentials) = cached_credentials.as_ref() {
+            let refresh_at =
+                Utc::now() + chrono::Duration::seconds(CREDENTIALS_REFRESH_MARGIN_SECS);
+            match credentials.expires_at() {
+                Some(expires_at) if *expires_at <= refresh_at => {}
+                _ => return Ok(credentials.clone()),
+            }
+        }
+
+        match self.credentials.credentials().await {
+            Ok(value) => {
+                *cached_credentials = Some(value.clone());
+                return Ok(value);
+            }
+            Err(e) => {
+                error!("could not fetch object storage credentials: {:?}", e);
+                return Err(tonic::Status::unavailable(format!(
+                    "could not fetch object storage credentials, they may have expired or be misconfigured: {}",
+                    e
+                )));
+            }
+        }
+    }
+
+    /// Creates a presigned url for the given method and object in the configured addressing style
+    async fn create_presigned_url(
+        &self,
+        method: &str,
+        bucket: &str,
+        key: &str,
+        params: Params,
+        headers: Vec<(&str, String)>,
+    ) -> Result<String, tonic::Status> {
+        let credentials = self.fetch_credentials().await?;
+
+        return Ok(self.sign_presigned_url(&credentials, method, bucket, key, params, headers));
+    }
+
+    /// Signs a presigned url with already fetched credentials, so that batches of urls share a single fetch
+    /// Rusoto only creates path-style presigned urls, the request is therefore signed directly
+    fn sign_presigned_url(
+        &self,
+        credentials: &AwsCredentials,
+        method: &str,
+        bucket: &str,
+        key: &str,
+        params: Params,
+        headers: Vec<(&str, String)>,
+    ) -> String {
+        let path = match self.path_style {
+            true => format!("/{}/{}", bucket, key),
+            false => format!("/{}", key),
+        };
+
+        let region = self.presign_region(bucket);
+        let mut request = SignedRequest::new(method, "s3", &region, path.as_str());
+        for (name, value) in headers {
+            request.add_header(name, value.as_str());
+        }
+        request.set_params(params);
+
+        return request.generate_presigned_url(credentials, &PRESIGNED_URL_EXPIRY, false);
+    }
+}
+
+/// Creates an S3 client with its own connection pool and credentials
+/// Clients created with S3Client::new share a global connection pool, a recreated client would reuse its connections
+fn new_s3_client(region: &Region) -> Result<S3Client, tonic::Status> {
+    let http_client = match HttpClient::new() {
+        Ok(value) => value,
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(tonic::Status::internal(
+                "could not create object storage http client",
+            ));
+        }
+    };
+    let credentials = match DefaultCredentialsProvider::new() {
+        Ok(value) => value,
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(tonic::Status::unavailable(
+                "could not load object storage credentials",
+            ));
+        }
+    };
+
+    return Ok(S3Client::new_with(http_client, credentials, region.clone()));
+}
+
+/// Reads the endpoint external clients reach the object storage at from Storage.PublicEndpoint
+/// The server itself always talks to Storage.Endpoint, the public endpoint is only used for presigned urls
+fn public_endpoint_from_config() -> Option<String> {
+    return SETTINGS
+        .read()
+        .unwrap()
+        .get_str("Storage.PublicEndpoint")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty());
+}
+
+/// Converts the CORS configuration returned by S3 into the storage independent representation
+pub fn to_cors_rules(cors_output: GetBucketCorsOutput) -> Vec<CorsRule> {
+    let mut rules = Vec::new();
+
+    for s3_rule in cors_output.cors_rules.unwrap_or_default() {
+        let rule = CorsRule {
+            allowed_origins: s3_rule.allowed_origins,
+            allowed_methods: s3_rule.allowed_methods,
+            allowed_headers: s3_rule.allowed_headers.unwrap_or_default(),
+        };
+
+        rules.push(rule);
+    }
+
+    return rules;
+}
+
+/// Checks if an error response of S3 reports a missing key
+/// Other 404 responses like NoSuchBucket are failures and must not be treated as deleted objects
+pub fn is_no_such_key(response: &BufferedHttpResponse) -> bool {
+    return response.status.as_u16() == 404
+        && response.body_as_str().contains("<Code>NoSuchKey</Code>");
 }
 
 #[async_trait]
@@ -76,7 +399,12 @@ impl StorageHandler for S3Handler {
         filename: String,
         _index: Option<crate::models::common_models::IndexLocation>,
     ) -> Result<crate::models::common_models::Location, tonic::Status> {
-        let object_key = format!("{}/{}/{}/{}", project_id, dataset_id, object_id, filename);
+        let object_key = object_key(
+            project_id.as_str(),
+            dataset_id.as_str(),
+            object_id.as_str(),
+            filename.as_str(),
+        );
         let location = Location {
             bucket: self.bucket.clone(),
             key: object_key,
@@ -95,52 +423,82 @@ impl StorageHandler for S3Handler {
         &self,
         location: crate::models::common_models::Location,
     ) -> Result<String, tonic::Status> {
-        let object_request = GetObjectRequest {
-            bucket: location.bucket,
-            key: location.key,
-            ..Default::default()
-        };
+        return self
+            .create_conditional_download_link(location, &DownloadConditions::default())
+            .await;
+    }
 
-        let presign_options = PreSignedRequestOption {
-            expires_in: Duration::from_secs(3600),
-        };
+    async fn create_conditional_download_link(
+        &self,
+        location: crate::models::common_models::Location,
+        conditions: &DownloadConditions,
+    ) -> Result<String, tonic::Status> {
+        let mut headers = Vec::new();
+        if let Some(if_none_match) = &conditions.if_none_match {
+            headers.push(("If-None-Match", if_none_match.clone()));
+        }
+        if let Some(if_modified_since) = &conditions.if_modified_since {
+            headers.push(("If-Modified-Since", if_modified_since.clone()));
+        }
+        if let Some(range) = index_range(&location)? {
+            headers.push(("Range", range));
+        }
 
-        let credentials = match self.credentials.credentials().await {
-            Ok(value) => value,
-            Err(e) => {
-                error!("{:?}", e);
-                return Err(tonic::Status::internal("error when creating download link"));
+        return self
+            .create_presigned_url(
+                "GET",
+                location.bucket.as_str(),
+                location.key.as_str(),
+                Params::new(),
+                headers,
+            )
+            .await;
+    }
+
+    async fn create_download_links(
+        &self,
+        locations: Vec<Location>,
+    ) -> Result<Vec<String>, tonic::Status> {
+        check_presigned_url_count(locations.len(), self.max_presigned_urls)?;
+        if locations.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let credentials = self.fetch_credentials().await?;
+
+        let mut links = Vec::with_capacity(locations.len());
+        for location in locations {
+            let mut headers = Vec::new();
+            if let Some(range) = index_range(&location)? {
+                headers.push(("Range", range));
             }
-        };
 
-        let url = object_request.get_presigned_url(&self.region, &credentials, &presign_options);
-        Ok(url)
+            links.push(self.sign_presigned_url(
+                &credentials,
+                "GET",
+                location.bucket.as_str(),
+                location.key.as_str(),
+                Params::new(),
+                headers,
+            ));
+        }
+
+        return Ok(links);
     }
 
     async fn create_upload_link(
         &self,
         location: crate::models::common_models::Location,
     ) -> Result<String, tonic::Status> {
-        let object_request = PutObjectRequest {
-            bucket: location.bucket,
-            key: location.key,
-            ..Default::default()
-        };
-
-        let presign_options = PreSignedRequestOption {
-            expires_in: Duration::from_secs(3600),
-        };
-
-        let credentials = match self.credentials.credentials().await {
-            Ok(value) => value,
-            Err(e) => {
-                error!("{:?}", e);
-                return Err(tonic::Status::internal("error when creating upload link"));
-            }
-        };
-
-        let url = object_request.get_presigned_url(&self.region, &credentials, &presign_options);
-        Ok(url)
+        return self
+            .create_presigned_url(
+                "PUT",
+                location.bucket.as_str(),
+                location.key.as_str(),
+                Params::new(),
+                Vec::new(),
+            )
+            .await;
     }
 
     async fn init_multipart_upload(
@@ -153,17 +511,17 @@ impl StorageHandler for S3Handler {
             ..Default::default()
         };
 
-        let create_resp = match self
-            .client
-            .create_multipart_upload(multipart_create_req)
-            .await
-        {
-            Ok(value) => value,
-            Err(e) => {
-                log::error!("{:?}", e.to_string());
-                return Err(tonic::Status::internal("error initiating multipart upload"));
-            }
-        };
+        let create_resp =
+            match deadline::timeout(self.client().create_multipart_upload(multipart_create_req))
+                .await?
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    self.reconnect_on_failure(&e);
+                    log::error!("{:?}", e.to_string());
+                    return Err(tonic::Status::internal("error initiating multipart upload"));
+                }
+            };
 
         let object_id = object.id.clone();
 
@@ -185,38 +543,77 @@ impl StorageHandler for S3Handler {
         return self.bucket.clone();
     }
 
-    async fn upload_multipart_part_link(
-        &self,
-        location: &Location,
-        upload_id: &str,
-        upload_part: i64,
-    ) -> std::result::Result<String, tonic::Status> {
-        let presign_options = PreSignedRequestOption {
-            expires_in: Duration::from_secs(3600),
-        };
-
-        let credentials = match self.credentials.credentials().await {
-            Ok(value) => value,
+    async fn get_cors_rules(&self) -> Result<Vec<CorsRule>, tonic::Status> {
+        match deadline::timeout(self.client().get_bucket_cors(GetBucketCorsRequest {
+            bucket: self.bucket.clone(),
+            ..Default::default()
+        }))
+        .await?
+        {
+            Ok(value) => return Ok(to_cors_rules(value)),
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 404 => {
+                return Ok(Vec::new());
+            }
             Err(e) => {
+                self.reconnect_on_failure(&e);
                 log::error!("{:?}", e.to_string());
                 return Err(tonic::Status::internal(
-                    "error creating object storage credentials",
+                    "error reading bucket cors configuration",
                 ));
             }
-        };
+        }
+    }
 
-        let upload_request = UploadPartRequest {
-            bucket: location.bucket.clone(),
-            key: location.key.clone(),
-            upload_id: upload_id.to_string(),
-            part_number: upload_part,
+    async fn ping(&self) -> Result<(), tonic::Status> {
+        let client = self.client();
+        let head_bucket = client.head_bucket(HeadBucketRequest {
+            bucket: self.bucket.clone(),
             ..Default::default()
-        };
+        });
+
+        match tokio::time::timeout(PING_TIMEOUT, head_bucket).await {
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(e)) => {
+                self.reconnect_on_failure(&e);
+                log::error!("{:?}", e.to_string());
+                return Err(tonic::Status::unavailable(
+                    "object storage is not reachable",
+                ));
+            }
+            Err(e) => {
+                self.reconnect();
+                log::error!("{:?}", e);
+                return Err(tonic::Status::unavailable("object storage ping timed out"));
+            }
+        }
+    }
 
-        let upload_link =
-            upload_request.get_presigned_url(&self.region, &credentials, &presign_options);
+    async fn upload_multipart_part_link(
+        &self,
+        location: &Location,
+        upload_id: &str,
+        upload_part: i64,
+        checksum_sha256: Option<&str>,
+    ) -> std::result::Result<String, tonic::Status> {
+        let mut params = Params::new();
+        params.put("partNumber", upload_part.to_string());
+        params.put("uploadId", upload_id);
+
+        let mut headers = Vec::new();
+        if let Some(checksum_sha256) = checksum_sha256 {
+            validate_sha256_checksum(checksum_sha256)?;
+            headers.push(("x-amz-checksum-sha256", checksum_sha256.to_string()));
+        }
 
-        return Ok(upload_link);
+        return self
+            .create_presigned_url(
+                "PUT",
+                location.bucket.as_str(),
+                location.key.as_str(),
+                params,
+                headers,
+            )
+            .await;
     }
 
     async fn finish_multipart_upload(
@@ -248,33 +645,187 @@ impl StorageHandler for S3Handler {
             ..Default::default()
         };
 
-        let _completed_reponse = match self
-            .client
-            .complete_multipart_upload(completion_request)
-            .await
+        let _completed_reponse =
+            match deadline::timeout(self.client().complete_multipart_upload(completion_request))
+                .await?
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    self.reconnect_on_failure(&e);
+                    log::error!("{:?}", e.to_string());
+                    return Err(tonic::Status::internal("error completing multipart upload"));
+                }
+            };
+
+        return Ok(());
+    }
+
+    async fn list_multipart_uploads(&self) -> Result<Vec<PendingMultipartUpload>, tonic::Status> {
+        let mut pending_uploads = Vec::new();
+        let mut key_marker = None;
+        let mut upload_id_marker = None;
+        loop {
+            let output = match deadline::timeout(self.client().list_multipart_uploads(
+                ListMultipartUploadsRequest {
+                    bucket: self.bucket.clone(),
+                    key_marker: key_marker,
+                    upload_id_marker: upload_id_marker,
+                    ..Default::default()
+                },
+            ))
+            .await?
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    self.reconnect_on_failure(&e);
+                    log::error!("{:?}", e.to_string());
+                    return Err(tonic::Status::internal("error listing multipart uploads"));
+                }
+            };
+
+            for upload in output.uploads.unwrap_or_default() {
+                let (key, upload_id, initiated) =
+                    match (upload.key, upload.upload_id, upload.initiated) {
+                        (Some(key), Some(upload_id), Some(initiated)) => {
+                            (key, upload_id, initiated)
+                        }
+                        _ => continue,
+                    };
+
+                let initiated = match DateTime::parse_from_rfc3339(initiated.as_str()) {
+                    Ok(value) => value.with_timezone(&Utc),
+                    Err(e) => {
+                        log::warn!(
+                            "could not parse initiation time {} of multipart upload {}: {:?}",
+                            initiated,
+                            upload_id,
+                            e
+                        );
+                        continue;
+                    }
+                };
+
+                pending_uploads.push(PendingMultipartUpload {
+                    key: key,
+                    upload_id: upload_id,
+                    initiated: initiated,
+                });
+            }
+
+            if !output.is_truncated.unwrap_or(false) {
+                break;
+            }
+            key_marker = output.next_key_marker;
+            upload_id_marker = output.next_upload_id_marker;
+        }
+
+        return Ok(pending_uploads);
+    }
+
+    async fn abort_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<(), tonic::Status> {
+        match deadline::timeout(
+            self.client()
+                .abort_multipart_upload(AbortMultipartUploadRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_string(),
+                    upload_id: upload_id.to_string(),
+                    ..Default::default()
+                }),
+        )
+        .await?
+        {
+            Ok(_) => return Ok(()),
+            // The upload has been completed or aborted in the meantime
+            Err(RusotoError::Service(AbortMultipartUploadError::NoSuchUpload(_))) => return Ok(()),
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 404 => return Ok(()),
+            Err(e) => {
+                self.reconnect_on_failure(&e);
+                log::error!("{:?}", e.to_string());
+                return Err(tonic::Status::internal("error aborting multipart upload"));
+            }
+        }
+    }
+
+    async fn head_object(&self, location: &Location) -> Result<ObjectHead, tonic::Status> {
+        let head_output = match deadline::timeout(self.client().head_object(HeadObjectRequest {
+            bucket: location.bucket.clone(),
+            key: location.key.clone(),
+            ..Default::default()
+        }))
+        .await?
         {
             Ok(value) => value,
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => {
+                return Err(tonic::Status::not_found("object has not been uploaded"));
+            }
+            Err(RusotoError::Unknown(response)) if response.status.as_u16() == 404 => {
+                return Err(tonic::Status::not_found("object has not been uploaded"));
+            }
             Err(e) => {
+                self.reconnect_on_failure(&e);
                 log::error!("{:?}", e.to_string());
-                return Err(tonic::Status::internal("error completing multipart upload"));
+                return Err(tonic::Status::internal("error reading object metadata"));
             }
         };
 
-        return Ok(());
+        let object_head = ObjectHead {
+            size: head_output.content_length.unwrap_or(0),
+            content_type: head_output.content_type,
+            last_modified: head_output.last_modified,
+            etag: head_output.e_tag,
+        };
+
+        return Ok(object_head);
+    }
+
+    async fn get_object(&self, location: &Location) -> Result<ObjectData, tonic::Status> {
+        let get_output = match deadline::timeout(self.client().get_object(GetObjectRequest {
+            bucket: location.bucket.clone(),
+            key: location.key.clone(),
+            range: index_range(location)?,
+            ..Default::default()
+        }))
+        .await?
+        {
+            Ok(value) => value,
+            Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => {
+                return Err(tonic::Status::not_found("object has not been uploaded"));
+            }
+            Err(e) => {
+                self.reconnect_on_failure(&e);
+                log::error!("{:?}", e.to_string());
+                return Err(tonic::Status::internal("error reading object"));
+            }
+        };
+
+        match get_output.body {
+            Some(body) => return Ok(Box::pin(body)),
+            None => return Ok(Box::pin(futures::stream::empty())),
+        }
     }
 
     async fn delete_object(&self, location: Location) -> std::result::Result<(), tonic::Status> {
-        match self
-            .client
-            .delete_object(DeleteObjectRequest {
-                bucket: location.bucket.clone(),
-                key: location.key.clone(),
-                ..Default::default()
-            })
-            .await
+        match deadline::timeout(self.client().delete_object(DeleteObjectRequest {
+            bucket: location.bucket.clone(),
+            key: location.key.clone(),
+            ..Default::default()
+        }))
+        .await?
         {
             Ok(_) => (),
+            // S3 itself answers deletes of missing keys with success, other backends answer with NoSuchKey
+            Err(RusotoError::Unknown(response)) if is_no_such_key(&response) => {
+                info!(
+                    "object {} has already been deleted from bucket {}",
+                    location.key, location.bucket
+                );
+            }
             Err(e) => {
+                self.reconnect_on_failure(&e);
                 log::error!("{:?}", e.to_string());
                 return Err(tonic::Status::internal("error deleting object"));
             }
@@ -282,25 +833,140 @@ impl StorageHandler for S3Handler {
 
         return Ok(());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::{env, iter::FromIterator, path::PathBuf, sync::Once};
+    async fn set_object_tags(
+        &self,
+        location: &Location,
+        tags: Vec<(String, String)>,
+    ) -> Result<(), tonic::Status> {
+        if tags.len() > S3_MAX_OBJECT_TAGS {
+            log::warn!(
+                "object {} has {} tags, only the first {} are stored",
+                location.key,
+                tags.len(),
+                S3_MAX_OBJECT_TAGS
+            );
+        }
 
-    use config::File;
-    use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
+        let tag_set = tags
+            .into_iter()
+            .take(S3_MAX_OBJECT_TAGS)
+            .map(|(key, value)| Tag {
+                key: key,
+                value: value,
+            })
+            .collect();
 
-    use crate::{
-        models::dataset_object_group::DatasetObject, objectstorage::objectstorage::StorageHandler,
-    };
+        match deadline::timeout(self.client().put_object_tagging(PutObjectTaggingRequest {
+            bucket: location.bucket.clone(),
+            key: location.key.clone(),
+            tagging: Tagging { tag_set: tag_set },
+            ..Default::default()
+        }))
+        .await?
+        {
+            Ok(_) => (),
+            Err(e) => {
+                self.reconnect_on_failure(&e);
+                log::error!("{:?}", e.to_string());
+                return Err(tonic::Status::internal("error setting object tags"));
+            }
+        }
+
+        return Ok(());
+    }
+}
 
-    use super::S3Handler;
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        iter::FromIterator,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Once,
+        },
+    };
+
+    use async_trait::async_trait;
+    use config::File;
+    use futures::stream::{FuturesUnordered, StreamExt};
+    use rusoto_core::{
+        credential::{AwsCredentials, CredentialsError, ProvideAwsCredentials},
+        request::BufferedHttpResponse,
+    };
+    use rusoto_s3::{
+        CORSRule, DeleteBucketRequest, GetBucketCorsOutput, GetObjectTaggingRequest, S3,
+    };
+    use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::{
+        models::dataset_object_group::DatasetObject,
+        objectstorage::objectstorage::{missing_cors_methods, DownloadConditions, StorageHandler},
+        test_util::init::test_init,
+    };
+
+    use super::{is_no_such_key, to_cors_rules, IndexLocation, Location, LocationType, S3Handler};
 
     use crate::SETTINGS;
 
     static INIT: Once = Once::new();
 
+    /// Forwards all connections to the target until the task is aborted, aborting closes all forwarded connections
+    /// This simulates an object storage endpoint that goes away and comes back
+    async fn run_proxy(listener: TcpListener, target: String) {
+        let mut connections = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (mut inbound, _) = match accepted {
+                        Ok(value) => value,
+                        Err(_) => continue,
+                    };
+                    let target = target.clone();
+                    connections.push(async move {
+                        if let Ok(mut outbound) = TcpStream::connect(target).await {
+                            let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                        }
+                    });
+                }
+                Some(_) = connections.next(), if !connections.is_empty() => {}
+            }
+        }
+    }
+
+    /// Credentials provider that fails like a provider whose session token can not be refreshed
+    struct FailingCredentialsProvider {}
+
+    #[async_trait]
+    impl ProvideAwsCredentials for FailingCredentialsProvider {
+        async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+            return Err(CredentialsError::new("the security token has expired"));
+        }
+    }
+
+    /// Credentials provider that counts how often the credentials are fetched
+    #[derive(Default)]
+    struct CountingCredentialsProvider {
+        fetches: AtomicUsize,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    }
+
+    #[async_trait]
+    impl ProvideAwsCredentials for CountingCredentialsProvider {
+        async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            return Ok(AwsCredentials::new(
+                "access-key",
+                "secret-key",
+                None,
+                self.expires_at,
+            ));
+        }
+    }
+
     #[tokio::test]
     async fn test_s3_download() {
         INIT.call_once(|| {
@@ -391,6 +1057,234 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_s3_ping() {
+        test_init();
+
+        let s3_handler = S3Handler::new();
+        s3_handler.ping().await.unwrap();
+
+        let unreachable_handler = S3Handler::new_with_endpoint(
+            "http://127.0.0.1:1".to_string(),
+            s3_handler.get_bucket(),
+            true,
+        );
+        let error = unreachable_handler.ping().await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn delete_missing_object() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let s3_handler = S3Handler::new();
+        let location = s3_handler
+            .create_location(
+                "testproject".to_string(),
+                uuid.to_string(),
+                uuid.to_string(),
+                "missing".to_string(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        // The object has never been uploaded, repeated deletes succeed as well
+        s3_handler.delete_object(location.clone()).await.unwrap();
+        s3_handler.delete_object(location).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_s3_object_tags() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let s3_handler = S3Handler::new();
+        let location = s3_handler
+            .create_location(
+                "testproject".to_string(),
+                uuid.to_string(),
+                uuid.to_string(),
+                "tagged".to_string(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let upload_link = s3_handler
+            .create_upload_link(location.clone())
+            .await
+            .unwrap();
+        let resp = reqwest::Client::new()
+            .put(upload_link)
+            .body("testdata")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let tags: Vec<(String, String)> = (0..12)
+            .map(|i| (format!("key{:02}", i), format!("value{}", i)))
+            .collect();
+        s3_handler
+            .set_object_tags(&location, tags.clone())
+            .await
+            .unwrap();
+
+        let mut stored_tags: Vec<(String, String)> = s3_handler
+            .client()
+            .get_object_tagging(GetObjectTaggingRequest {
+                bucket: location.bucket.clone(),
+                key: location.key.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .tag_set
+            .into_iter()
+            .map(|tag| (tag.key, tag.value))
+            .collect();
+        stored_tags.sort();
+
+        assert_eq!(stored_tags, tags[..10].to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_s3_conditional_download() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let s3_handler = S3Handler::new();
+        let location = s3_handler
+            .create_location(
+                "testproject".to_string(),
+                uuid.to_string(),
+                uuid.to_string(),
+                "conditional".to_string(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let upload_link = s3_handler
+            .create_upload_link(location.clone())
+            .await
+            .unwrap();
+        let client = reqwest::Client::new();
+        let resp = client
+            .put(upload_link)
+            .body("testdata")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let download_link = s3_handler
+            .create_download_link(location.clone())
+            .await
+            .unwrap();
+        let resp = client.get(download_link).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let etag = resp
+            .headers()
+            .get(reqwest::header::ETAG)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let conditions = DownloadConditions {
+            if_none_match: Some(etag.clone()),
+            ..Default::default()
+        };
+        let conditional_link = s3_handler
+            .create_conditional_download_link(location.clone(), &conditions)
+            .await
+            .unwrap();
+        let resp = client
+            .get(conditional_link)
+            .header(reqwest::header::IF_NONE_MATCH, etag)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 304);
+
+        let conditions = DownloadConditions {
+            if_none_match: Some("\"outdated-etag\"".to_string()),
+            ..Default::default()
+        };
+        let conditional_link = s3_handler
+            .create_conditional_download_link(location, &conditions)
+            .await
+            .unwrap();
+        let resp = client
+            .get(conditional_link)
+            .header(reqwest::header::IF_NONE_MATCH, "\"outdated-etag\"")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.text().await.unwrap(), "testdata");
+    }
+
+    #[test]
+    fn no_such_key() {
+        let error_response = |status: u16, code: &str| BufferedHttpResponse {
+            status: http::StatusCode::from_u16(status).unwrap(),
+            body: format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Error><Code>{}</Code></Error>",
+                code
+            )
+            .into(),
+            headers: Default::default(),
+        };
+
+        assert!(is_no_such_key(&error_response(404, "NoSuchKey")));
+        assert!(!is_no_such_key(&error_response(404, "NoSuchBucket")));
+        assert!(!is_no_such_key(&error_response(403, "AccessDenied")));
+    }
+
+    #[test]
+    fn test_s3_cors_rules() {
+        let cors_output = GetBucketCorsOutput {
+            cors_rules: Some(vec![
+                CORSRule {
+                    allowed_origins: vec!["https://*.example.com".to_string()],
+                    allowed_methods: vec!["GET".to_string(), "PUT".to_string()],
+                    ..Default::default()
+                },
+                CORSRule {
+                    allowed_origins: vec!["https://viewer.example.org".to_string()],
+                    allowed_methods: vec!["GET".to_string()],
+                    ..Default::default()
+                },
+            ]),
+        };
+
+        let rules = to_cors_rules(cors_output);
+        assert_eq!(rules.len(), 2);
+
+        let methods = ["GET", "PUT"];
+        assert!(missing_cors_methods(&rules, "https://app.example.com", &methods).is_empty());
+        assert_eq!(
+            missing_cors_methods(&rules, "https://viewer.example.org", &methods),
+            vec!["PUT".to_string()]
+        );
+        assert_eq!(
+            missing_cors_methods(&rules, "https://other.org", &methods),
+            vec!["GET".to_string(), "PUT".to_string()]
+        );
+        assert_eq!(
+            missing_cors_methods(
+                &to_cors_rules(GetBucketCorsOutput { cors_rules: None }),
+                "https://app.example.com",
+                &methods
+            ),
+            vec!["GET".to_string(), "PUT".to_string()]
+        );
+    }
+
     #[tokio::test]
     async fn test_s3_multipart() {
         INIT.call_once(|| {
@@ -454,19 +1348,21 @@ mod tests {
 
         let object = DatasetObject::new_from_proto_create(
             &create_object_req,
+            "testproject",
             uuid.to_string(),
             s3_bucket.clone(),
+            chrono::Utc::now(),
         )
         .unwrap();
 
         let upload_id = s3_handler.init_multipart_upload(&object).await.unwrap();
 
         let upload_link_1 = s3_handler
-            .upload_multipart_part_link(&object.location, upload_id.as_str(), 1)
+            .upload_multipart_part_link(&object.location, upload_id.as_str(), 1, None)
             .await
             .unwrap();
         let upload_link_2 = s3_handler
-            .upload_multipart_part_link(&object.location, upload_id.as_str(), 2)
+            .upload_multipart_part_link(&object.location, upload_id.as_str(), 2, None)
             .await
             .unwrap();
 
@@ -543,4 +1439,392 @@ mod tests {
             panic!("downloaded data does not match uploaded rata")
         }
     }
+
+    #[tokio::test]
+    async fn presigned_url_addressing_style() {
+        test_init();
+
+        let location = Location {
+            bucket: "test-bucket".to_string(),
+            key: "testproject/testdataset/testobject/test.txt".to_string(),
+            ..Default::default()
+        };
+
+        let path_style_handler = S3Handler::new_with_endpoint(
+            "http://minio:9000".to_string(),
+            location.bucket.clone(),
+            true,
+        );
+        let download_link = path_style_handler
+            .create_download_link(location.clone())
+            .await
+            .unwrap();
+        let url = reqwest::Url::parse(download_link.as_str()).unwrap();
+        assert_eq!(url.scheme(), "http");
+        assert_eq!(url.host_str(), Some("minio"));
+        assert_eq!(url.port(), Some(9000));
+        assert_eq!(
+            url.path(),
+            "/test-bucket/testproject/testdataset/testobject/test.txt"
+        );
+
+        let virtual_hosted_handler = S3Handler::new_with_endpoint(
+            "http://minio:9000".to_string(),
+            location.bucket.clone(),
+            false,
+        );
+        let upload_link = virtual_hosted_handler
+            .upload_multipart_part_link(&location, "testupload", 1, None)
+            .await
+            .unwrap();
+        let url = reqwest::Url::parse(upload_link.as_str()).unwrap();
+        assert_eq!(url.scheme(), "http");
+        assert_eq!(url.host_str(), Some("test-bucket.minio"));
+        assert_eq!(url.port(), Some(9000));
+        assert_eq!(url.path(), "/testproject/testdataset/testobject/test.txt");
+        assert!(url
+            .query_pairs()
+            .any(|(key, value)| key == "uploadId" && value == "testupload"));
+    }
+
+    #[tokio::test]
+    async fn presigned_url_public_endpoint() {
+        test_init();
+
+        let endpoint = SETTINGS
+            .read()
+            .unwrap()
+            .get_str("Storage.Endpoint")
+            .unwrap();
+        let s3_handler = S3Handler::new();
+        let location = Location {
+            bucket: s3_handler.get_bucket(),
+            key: format!("testproject/{}/public_endpoint.txt", uuid::Uuid::new_v4()),
+            ..Default::default()
+        };
+
+        let client = reqwest::Client::new();
+        let upload_link = s3_handler
+            .create_upload_link(location.clone())
+            .await
+            .unwrap();
+        let resp = client
+            .put(upload_link)
+            .body("testdata")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        // The internal endpoint is unreachable, the download only succeeds if the url is signed for the public one
+        let mut public_endpoint_handler = S3Handler::new_with_endpoint(
+            "http://127.0.0.1:1".to_string(),
+            s3_handler.get_bucket(),
+            true,
+        );
+        public_endpoint_handler.public_endpoint = Some(endpoint.clone());
+
+        let download_link = public_endpoint_handler
+            .create_download_link(location.clone())
+            .await
+            .unwrap();
+        let url = reqwest::Url::parse(download_link.as_str()).unwrap();
+        let public_url = reqwest::Url::parse(endpoint.as_str()).unwrap();
+        assert_eq!(url.host_str(), public_url.host_str());
+        assert_eq!(url.port(), public_url.port());
+
+        let resp = client.get(download_link).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.text().await.unwrap(), "testdata");
+
+        s3_handler.delete_object(location).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reconnect_to_restarted_endpoint() {
+        test_init();
+
+        let endpoint = SETTINGS
+            .read()
+            .unwrap()
+            .get_str("Storage.Endpoint")
+            .unwrap();
+        let endpoint_url = reqwest::Url::parse(endpoint.as_str()).unwrap();
+        let target = format!(
+            "{}:{}",
+            endpoint_url.host_str().unwrap(),
+            endpoint_url.port_or_known_default().unwrap()
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_address = listener.local_addr().unwrap();
+        let proxy = tokio::spawn(run_proxy(listener, target.clone()));
+
+        let s3_handler = S3Handler::new_with_endpoint(
+            format!("http://{}", proxy_address),
+            S3Handler::new().get_bucket(),
+            true,
+        );
+        let location = Location {
+            bucket: s3_handler.get_bucket(),
+            key: format!("testproject/{}/missing.txt", uuid::Uuid::new_v4()),
+            ..Default::default()
+        };
+        s3_handler.ping().await.unwrap();
+
+        proxy.abort();
+        let _ = proxy.await;
+        let error = s3_handler.ping().await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Unavailable);
+        let error = s3_handler.head_object(&location).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Internal);
+
+        // The endpoint comes back at the same address
+        let listener = TcpListener::bind(proxy_address).await.unwrap();
+        let proxy = tokio::spawn(run_proxy(listener, target));
+
+        s3_handler.ping().await.unwrap();
+        let error = s3_handler.head_object(&location).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::NotFound);
+
+        proxy.abort();
+    }
+
+    #[tokio::test]
+    async fn validate_bucket() {
+        test_init();
+
+        let endpoint = SETTINGS
+            .read()
+            .unwrap()
+            .get_str("Storage.Endpoint")
+            .unwrap();
+        let bucket = format!("missing-{}", uuid::Uuid::new_v4());
+        let s3_handler = S3Handler::new_with_endpoint(endpoint, bucket.clone(), true);
+
+        let error = s3_handler.ensure_bucket(false).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::FailedPrecondition);
+        assert!(error.message().contains(bucket.as_str()));
+        assert!(s3_handler.ping().await.is_err());
+
+        s3_handler.ensure_bucket(true).await.unwrap();
+        s3_handler.ping().await.unwrap();
+        s3_handler.ensure_bucket(false).await.unwrap();
+
+        s3_handler
+            .client()
+            .delete_bucket(DeleteBucketRequest {
+                bucket: bucket,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn multipart_part_checksum() {
+        test_init();
+
+        let location = Location {
+            bucket: "test-bucket".to_string(),
+            key: "testproject/testdataset/testobject/test.txt".to_string(),
+            ..Default::default()
+        };
+        let s3_handler = S3Handler::new();
+
+        // base64 encoded SHA256 digest of "testdata"
+        let checksum = "gQ/y+yQqXe5CIPLLDmpRmJH7Z/L4KKbKtO+IlGM7H1A=";
+        let upload_link = s3_handler
+            .upload_multipart_part_link(&location, "testupload", 1, Some(checksum))
+            .await
+            .unwrap();
+        let url = reqwest::Url::parse(upload_link.as_str()).unwrap();
+        let signed_headers = url
+            .query_pairs()
+            .find(|(key, _)| key == "X-Amz-SignedHeaders")
+            .map(|(_, value)| value.to_string())
+            .unwrap();
+        assert!(signed_headers
+            .split(';')
+            .any(|header| header == "x-amz-checksum-sha256"));
+
+        let upload_link = s3_handler
+            .upload_multipart_part_link(&location, "testupload", 1, None)
+            .await
+            .unwrap();
+        assert!(!upload_link.contains("x-amz-checksum-sha256"));
+
+        for invalid_checksum in ["not base64!", "dGVzdGRhdGE="].iter() {
+            let error = s3_handler
+                .upload_multipart_part_link(&location, "testupload", 1, Some(invalid_checksum))
+                .await
+                .unwrap_err();
+            assert_eq!(error.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[tokio::test]
+    async fn credential_fetch_failure() {
+        test_init();
+
+        let s3_handler = S3Handler::new_with_credentials(
+            "http://minio:9000".to_string(),
+            "test-bucket".to_string(),
+            true,
+            Arc::new(FailingCredentialsProvider {}),
+        );
+        let location = Location {
+            bucket: "test-bucket".to_string(),
+            key: "testproject/testdataset/testobject/test.txt".to_string(),
+            ..Default::default()
+        };
+
+        let error = s3_handler.create_download_link(location).await.unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Unavailable);
+        assert!(error.message().contains("object storage credentials"));
+        assert!(error.message().contains("the security token has expired"));
+    }
+
+    #[tokio::test]
+    async fn index_location_download() {
+        test_init();
+
+        let uuid = uuid::Uuid::new_v4();
+        let s3_handler = S3Handler::new();
+        let location = s3_handler
+            .create_location(
+                "testproject".to_string(),
+                uuid.to_string(),
+                uuid.to_string(),
+                "packed".to_string(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        let upload_link = s3_handler
+            .create_upload_link(location.clone())
+            .await
+            .unwrap();
+        let client = reqwest::Client::new();
+        let resp = client
+            .put(upload_link)
+            .body("testdata")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let index_location = Location {
+            location_type: LocationType::Index,
+            index_location: IndexLocation {
+                start_byte: 2,
+                end_byte: 6,
+            },
+            ..location
+        };
+        let download_link = s3_handler
+            .create_download_link(index_location)
+            .await
+            .unwrap();
+        let resp = client
+            .get(download_link)
+            .header(reqwest::header::RANGE, "bytes=2-5")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 206);
+        assert_eq!(resp.text().await.unwrap(), "stda");
+    }
+
+    #[tokio::test]
+    async fn download_link_batch() {
+        test_init();
+
+        let credentials = Arc::new(CountingCredentialsProvider::default());
+        let mut s3_handler = S3Handler::new_with_credentials(
+            "http://minio:9000".to_string(),
+            "test-bucket".to_string(),
+            true,
+            credentials.clone(),
+        );
+        s3_handler.max_presigned_urls = 100;
+
+        let locations: Vec<Location> = (0..100)
+            .map(|i| Location {
+                bucket: "test-bucket".to_string(),
+                key: format!("testproject/testdataset/object{}/test.txt", i),
+                ..Default::default()
+            })
+            .collect();
+
+        let links = s3_handler
+            .create_download_links(locations.clone())
+            .await
+            .unwrap();
+        assert_eq!(links.len(), 100);
+        assert!(links[42].contains("object42"));
+        assert_eq!(credentials.fetches.load(Ordering::SeqCst), 1);
+
+        let mut too_many_locations = locations;
+        too_many_locations.push(Location::default());
+        let error = s3_handler
+            .create_download_links(too_many_locations)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+        assert!(error.message().contains("pages"));
+        assert_eq!(credentials.fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cached_credentials() {
+        test_init();
+
+        let credentials = Arc::new(CountingCredentialsProvider::default());
+        let s3_handler = S3Handler::new_with_credentials(
+            "http://minio:9000".to_string(),
+            "test-bucket".to_string(),
+            true,
+            credentials.clone(),
+        );
+        let location = Location {
+            bucket: "test-bucket".to_string(),
+            key: "testproject/testdataset/testobject/test.txt".to_string(),
+            ..Default::default()
+        };
+
+        for i in 0..100 {
+            match i % 3 {
+                0 => s3_handler.create_download_link(location.clone()).await,
+                1 => s3_handler.create_upload_link(location.clone()).await,
+                _ => {
+                    s3_handler
+                        .upload_multipart_part_link(&location, "upload-id", i, None)
+                        .await
+                }
+            }
+            .unwrap();
+        }
+        assert_eq!(credentials.fetches.load(Ordering::SeqCst), 1);
+
+        // Credentials that expire before the refresh margin are fetched again for every link
+        let expiring_credentials = Arc::new(CountingCredentialsProvider {
+            expires_at: Some(chrono::Utc::now() + chrono::Duration::seconds(30)),
+            ..Default::default()
+        });
+        let s3_handler = S3Handler::new_with_credentials(
+            "http://minio:9000".to_string(),
+            "test-bucket".to_string(),
+            true,
+            expiring_credentials.clone(),
+        );
+        for _ in 0..2 {
+            s3_handler
+                .create_download_link(location.clone())
+                .await
+                .unwrap();
+        }
+        assert_eq!(expiring_credentials.fetches.load(Ordering::SeqCst), 2);
+    }
 }
@@ -0,0 +1,367 @@
+use std::{
+    future::Future,
+    io::Write,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use flate2::{write::GzEncoder, Compression};
+use http::{HeaderMap, HeaderValue, Request, Response};
+use http_body::Body;
+use log::error;
+use tonic::body::BoxBody;
+use tower::{Layer, Service};
+
+use crate::SETTINGS;
+
+const GRPC_ENCODING_KEY: &str = "grpc-encoding";
+const GRPC_ACCEPT_ENCODING_KEY: &str = "grpc-accept-encoding";
+const GZIP_ENCODING: &str = "gzip";
+/// Length of the prefix of every gRPC message, a compression flag followed by the message length
+const MESSAGE_PREFIX_LEN: usize = 5;
+/// Messages below this size are sent uncompressed, compressing them costs more than it saves
+const DEFAULT_MIN_COMPRESSION_SIZE: usize = 1024;
+
+/// Checks if the client accepts gzip compressed responses
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    return match headers.get(GRPC_ACCEPT_ENCODING_KEY) {
+        Some(value) => match value.to_str() {
+            Ok(value) => value
+                .split(',')
+                .any(|encoding| encoding.trim() == GZIP_ENCODING),
+            Err(_) => false,
+        },
+        None => false,
+    };
+}
+
+/// Compresses all messages of a gRPC body that are at least min_size bytes large with gzip
+/// The compression flag of every compressed message is set, smaller messages are left unchanged
+pub fn compress_messages(body: &[u8], min_size: usize) -> Result<Bytes, tonic::Status> {
+    let mut remaining = body;
+    let mut compressed_body = BytesMut::with_capacity(body.len());
+    while !remaining.is_empty() {
+        if remaining.len() < MESSAGE_PREFIX_LEN {
+            return Err(tonic::Status::internal("truncated grpc message prefix"));
+        }
+        let compressed = remaining.get_u8();
+        let message_len = remaining.get_u32() as usize;
+        if remaining.len() < message_len {
+            return Err(tonic::Status::internal("truncated grpc message"));
+        }
+        let (message, rest) = remaining.split_at(message_len);
+        remaining = rest;
+
+        if compressed == 1 || message_len < min_size {
+            compressed_body.put_u8(compressed);
+            compressed_body.put_u32(message_len as u32);
+            compressed_body.put_slice(message);
+            continue;
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let compressed_message = match encoder.write_all(message).and_then(|_| encoder.finish()) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{:?}", e);
+                return Err(tonic::Status::internal("could not compress response"));
+            }
+        };
+
+        compressed_body.put_u8(1);
+        compressed_body.put_u32(compressed_message.len() as u32);
+        compressed_body.put_slice(compressed_message.as_slice());
+    }
+
+    return Ok(compressed_body.freeze());
+}
+
+/// Returns the length of the complete messages at the start of a gRPC body
+fn complete_messages_len(body: &[u8]) -> usize {
+    let mut len = 0;
+    let mut remaining = body;
+    while remaining.len() >= MESSAGE_PREFIX_LEN {
+        remaining.advance(1);
+        let message_len = remaining.get_u32() as usize;
+        if remaining.len() < message_len {
+            break;
+        }
+
+        remaining.advance(message_len);
+        len += MESSAGE_PREFIX_LEN + message_len;
+    }
+
+    return len;
+}
+
+/// Response body that compresses its messages as soon as they are complete
+/// At most one incomplete message is buffered, streaming responses keep streaming
+struct CompressedBody {
+    inner: BoxBody,
+    buffer: BytesMut,
+    min_size: usize,
+}
+
+impl Body for CompressedBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        loop {
+            let len = complete_messages_len(&self.buffer);
+            if len > 0 {
+                let messages = self.buffer.split_to(len);
+                return Poll::Ready(Some(compress_messages(&messages, self.min_size)));
+            }
+
+            match Pin::new(&mut self.inner).poll_data(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.buffer.put(chunk),
+                Poll::Ready(None) if !self.buffer.is_empty() => {
+                    return Poll::Ready(Some(Err(tonic::Status::internal(
+                        "truncated grpc message",
+                    ))))
+                }
+                other => return other,
+            }
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        return Pin::new(&mut self.inner).poll_trailers(cx);
+    }
+}
+
+/// Compresses the messages of responses with gzip if the client accepts gzip compressed responses
+/// tonic 0.5 does not support compression itself, the messages are therefore compressed one by one after they
+/// have been encoded. Compressed requests are not supported.
+#[derive(Clone)]
+pub struct ResponseCompressionLayer {
+    enabled: bool,
+    min_size: usize,
+}
+
+impl ResponseCompressionLayer {
+    pub fn new(min_size: usize) -> Self {
+        return ResponseCompressionLayer {
+            enabled: true,
+            min_size: min_size,
+        };
+    }
+
+    /// Compression is enabled with Server.EnableCompression, responses are not compressed by default
+    /// Server.CompressionMinSize sets the size in bytes from which on messages are compressed
+    pub fn new_from_config() -> Self {
+        let settings = SETTINGS.read().unwrap();
+        let enabled = settings
+            .get_bool("Server.EnableCompression")
+            .unwrap_or(false);
+        let min_size = settings
+            .get_int("Server.CompressionMinSize")
+            .map(|value| value.max(0) as usize)
+            .unwrap_or(DEFAULT_MIN_COMPRESSION_SIZE);
+
+        return ResponseCompressionLayer {
+            enabled: enabled,
+            min_size: min_size,
+        };
+    }
+}
+
+impl<S> Layer<S> for ResponseCompressionLayer {
+    type Service = ResponseCompressionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return ResponseCompressionService {
+            inner: inner,
+            enabled: self.enabled,
+            min_size: self.min_size,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct ResponseCompressionService<S> {
+    inner: S,
+    enabled: bool,
+    min_size: usize,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for ResponseCompressionService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        if !self.enabled || !accepts_gzip(request.headers()) {
+            return Box::pin(self.inner.call(request));
+        }
+
+        let min_size = self.min_size;
+        let response_future = self.inner.call(request);
+
+        return Box::pin(async move {
+            let response = response_future.await?;
+            if response.headers().contains_key(GRPC_ENCODING_KEY) {
+                return Ok(response);
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let compressed_body = CompressedBody {
+                inner: body,
+                buffer: BytesMut::new(),
+                min_size: min_size,
+            };
+
+            parts
+                .headers
+                .insert(GRPC_ENCODING_KEY, HeaderValue::from_static(GZIP_ENCODING));
+
+            return Ok(Response::from_parts(parts, compressed_body.boxed()));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, io::Read};
+
+    use bytes::{BufMut, Bytes, BytesMut};
+    use flate2::read::GzDecoder;
+    use futures::{stream, StreamExt};
+    use http::{HeaderMap, HeaderValue, Request, Response};
+    use http_body::Body;
+    use prost::Message;
+    use scienceobjectsdb_rust_api::sciobjectsdbapi::{models, services};
+    use tonic::body::BoxBody;
+    use tower::{Layer, Service, ServiceExt};
+
+    use super::ResponseCompressionLayer;
+    use crate::test_util::body::BufferedBody;
+
+    fn large_list_response() -> Bytes {
+        let datasets = (0..500)
+            .map(|i| models::v1::Dataset {
+                id: format!("dataset-{}", i),
+                name: format!("dataset {}", i),
+                description: "highly compressible description of a dataset".to_string(),
+                ..Default::default()
+            })
+            .collect();
+        let response = services::v1::GetProjectDatasetsResponse {
+            dataset: datasets,
+            ..Default::default()
+        };
+
+        let message = response.encode_to_vec();
+        let mut body = BytesMut::new();
+        body.put_u8(0);
+        body.put_u32(message.len() as u32);
+        body.put_slice(message.as_slice());
+
+        return body.freeze();
+    }
+
+    fn request(accept_encoding: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder().uri("/services.v1.ProjectService/GetProjectDatasets");
+        if let Some(accept_encoding) = accept_encoding {
+            builder = builder.header("grpc-accept-encoding", accept_encoding);
+        }
+
+        return builder.body(()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn compress_large_responses() {
+        let list_service = tower::service_fn(|_request: Request<()>| async move {
+            let mut trailers = HeaderMap::new();
+            trailers.insert("grpc-status", HeaderValue::from_static("0"));
+            let body = BufferedBody::new(large_list_response(), Some(trailers));
+
+            return Ok::<Response<BoxBody>, Infallible>(Response::new(body.boxed()));
+        });
+        let mut service = ResponseCompressionLayer::new(1024).layer(list_service);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Some("identity, gzip")))
+            .await
+            .unwrap();
+        assert_eq!(response.headers().get("grpc-encoding").unwrap(), "gzip");
+
+        let (data, trailers) = BufferedBody::read(response.into_body()).await.unwrap();
+        assert_eq!(trailers.unwrap().get("grpc-status").unwrap(), "0");
+        assert_eq!(data[0], 1);
+        assert!(data.len() < large_list_response().len());
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&data[5..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        let decoded =
+            services::v1::GetProjectDatasetsResponse::decode(decompressed.as_slice()).unwrap();
+        assert_eq!(decoded.dataset.len(), 500);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(None))
+            .await
+            .unwrap();
+        assert!(response.headers().get("grpc-encoding").is_none());
+
+        let (data, _) = BufferedBody::read(response.into_body()).await.unwrap();
+        assert_eq!(data, large_list_response());
+    }
+
+    #[tokio::test]
+    async fn compress_streaming_responses() {
+        // The response stream never ends, its first message has to be compressed without waiting for the end
+        let streaming_service = tower::service_fn(|_request: Request<()>| async move {
+            let messages = stream::iter(vec![Ok::<Bytes, Infallible>(large_list_response())])
+                .chain(stream::pending());
+            let body = tonic::transport::Body::wrap_stream(messages)
+                .map_err(|_| tonic::Status::internal("unreachable"))
+                .boxed();
+
+            return Ok::<Response<BoxBody>, Infallible>(Response::new(body));
+        });
+        let mut service = ResponseCompressionLayer::new(1024).layer(streaming_service);
+
+        let mut body = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Some("gzip")))
+            .await
+            .unwrap()
+            .into_body();
+        let data = body.data().await.unwrap().unwrap();
+        assert_eq!(data[0], 1);
+
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&data[5..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        let decoded =
+            services::v1::GetProjectDatasetsResponse::decode(decompressed.as_slice()).unwrap();
+        assert_eq!(decoded.dataset.len(), 500);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::{Request, Response};
+use log::warn;
+use tokio::sync::Semaphore;
+use tonic::body::BoxBody;
+use tower::{Layer, Service};
+
+use crate::SETTINGS;
+
+/// Limits the number of requests that are processed concurrently
+/// Requests above the limit are shed with resource_exhausted instead of being queued,
+/// so that bursts can not exhaust the connections to the database and the object storage
+#[derive(Clone)]
+pub struct ConcurrencyLimitLayer {
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl ConcurrencyLimitLayer {
+    pub fn new(max_concurrent_requests: usize) -> Self {
+        return ConcurrencyLimitLayer {
+            permits: Some(Arc::new(Semaphore::new(max_concurrent_requests))),
+        };
+    }
+
+    /// Reads the limit from Server.MaxConcurrentRequests, requests are not limited if no limit is configured
+    pub fn new_from_config() -> Self {
+        let max_concurrent_requests = SETTINGS
+            .read()
+            .unwrap()
+            .get_int("Server.MaxConcurrentRequests")
+            .ok();
+
+        match max_concurrent_requests {
+            Some(value) => return ConcurrencyLimitLayer::new(value.max(1) as usize),
+            None => return ConcurrencyLimitLayer { permits: None },
+        }
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return ConcurrencyLimitService {
+            inner: inner,
+            permits: self.permits.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct ConcurrencyLimitService<S> {
+    inner: S,
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for ConcurrencyLimitService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let permits = match &self.permits {
+            Some(value) => value.clone(),
+            None => return Box::pin(self.inner.call(request)),
+        };
+
+        let permit = match permits.try_acquire_owned() {
+            Ok(value) => value,
+            Err(_) => {
+                warn!(
+                    "shedding request to {}, too many concurrent requests",
+                    request.uri().path()
+                );
+                let status = tonic::Status::resource_exhausted(
+                    "too many concurrent requests, please try again later",
+                );
+                return Box::pin(async move { Ok(status.to_http()) });
+            }
+        };
+
+        let response_future = self.inner.call(request);
+
+        return Box::pin(async move {
+            let response = response_future.await;
+            drop(permit);
+            return response;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, sync::Arc};
+
+    use http::{Request, Response};
+    use tokio::sync::Notify;
+    use tonic::body::BoxBody;
+    use tower::{Layer, Service, ServiceExt};
+
+    use super::ConcurrencyLimitLayer;
+
+    fn response_code(response: Response<BoxBody>) -> tonic::Code {
+        return tonic::Status::from_header_map(response.headers())
+            .unwrap()
+            .code();
+    }
+
+    #[tokio::test]
+    async fn shed_excess_requests() {
+        let release = Arc::new(Notify::new());
+        let service_release = release.clone();
+        let blocking_service = tower::service_fn(move |request: Request<()>| {
+            let release = service_release.clone();
+            async move {
+                if request.uri().path() == "/block" {
+                    release.notified().await;
+                }
+                let status = tonic::Status::new(tonic::Code::Ok, "");
+                return Ok::<Response<BoxBody>, Infallible>(status.to_http());
+            }
+        });
+        let mut service = ConcurrencyLimitLayer::new(1).layer(blocking_service);
+
+        let blocked_request = Request::builder().uri("/block").body(()).unwrap();
+        let blocked_response = tokio::spawn(service.ready().await.unwrap().call(blocked_request));
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(()))
+            .await
+            .unwrap();
+        assert_eq!(response_code(response), tonic::Code::ResourceExhausted);
+
+        release.notify_one();
+        let response = blocked_response.await.unwrap().unwrap();
+        assert_eq!(response_code(response), tonic::Code::Ok);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(()))
+            .await
+            .unwrap();
+        assert_eq!(response_code(response), tonic::Code::Ok);
+    }
+}
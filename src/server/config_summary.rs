@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use log::{log, Level};
+
+use super::request_logging::RequestLogWriter;
+use crate::SETTINGS;
+
+const UNSET_VALUE: &str = "<unset>";
+const REDACTED_VALUE: &str = "<redacted>";
+
+/// Settings that are included in the summary of the effective configuration
+const SUMMARY_KEYS: [&str; 9] = [
+    "Server.Host",
+    "Server.Port",
+    "Storage.Backend",
+    "Storage.Endpoint",
+    "Storage.Bucket",
+    "Authentication.Type",
+    "Database.Mongo.Host",
+    "Database.Mongo.Port",
+    "Database.Mongo.Database",
+];
+
+/// Secret settings, the summary only shows whether they are set
+const SECRET_KEYS: [&str; 1] = ["Authentication.ServiceAccount.Key"];
+
+/// Returns a summary of the effective configuration, secrets are redacted
+pub fn config_summary() -> String {
+    let settings = SETTINGS.read().unwrap();
+
+    let mut entries = Vec::new();
+    for key in SUMMARY_KEYS.iter() {
+        let value = settings
+            .get_str(key)
+            .unwrap_or_else(|_| UNSET_VALUE.to_string());
+        entries.push(format!("{}={}", key, value));
+    }
+    for key in SECRET_KEYS.iter() {
+        let value = match settings.get_str(key) {
+            Ok(_) => REDACTED_VALUE,
+            Err(_) => UNSET_VALUE,
+        };
+        entries.push(format!("{}={}", key, value));
+    }
+
+    return entries.join(", ");
+}
+
+/// Logs the effective configuration at startup to show which configuration file and environment took effect
+pub fn log_config_summary() {
+    write_config_summary(Arc::new(|level, line| log!(level, "{}", line)));
+}
+
+/// Writes the summary line of the effective configuration to the given writer, e.g. to capture the startup log
+pub fn write_config_summary(writer: RequestLogWriter) {
+    writer(
+        Level::Info,
+        format!("Starting with configuration: {}", config_summary()).as_str(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use log::Level;
+
+    use crate::{test_util::init::test_init, SETTINGS};
+
+    use super::write_config_summary;
+
+    #[test]
+    fn summary_contains_settings() {
+        test_init();
+        let service_key = uuid::Uuid::new_v4().to_string();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Authentication.ServiceAccount.Key", service_key.as_str())
+            .unwrap();
+
+        let captured_logs = Arc::new(Mutex::new(Vec::new()));
+        let captured_logs_writer = captured_logs.clone();
+        write_config_summary(Arc::new(move |level, line| {
+            captured_logs_writer
+                .lock()
+                .unwrap()
+                .push((level, line.to_string()))
+        }));
+
+        let captured_logs = captured_logs.lock().unwrap();
+        assert_eq!(captured_logs.len(), 1);
+        let (level, summary) = &captured_logs[0];
+        assert_eq!(*level, Level::Info);
+        assert!(summary.starts_with("Starting with configuration: "));
+        assert!(summary.contains("Storage.Bucket=test-bucket"));
+        assert!(summary.contains("Authentication.Type=debug"));
+        assert!(summary.contains("Authentication.ServiceAccount.Key=<redacted>"));
+        assert!(!summary.contains(service_key.as_str()));
+    }
+}
@@ -1,14 +1,15 @@
-use std::sync::Arc;
-
-use futures::stream::FuturesUnordered;
-use futures::stream::StreamExt;
+use std::{str::FromStr, sync::Arc};
 
+use bson::{to_document, Document};
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::dataset_service_server::DatasetService;
+use tonic::metadata::{MetadataMap, MetadataValue};
 use tonic::Response;
 
 use crate::database::database::Database;
 use crate::handler::common::HandlerWrapper;
+use crate::server::project_api::{NEXT_PAGE_TOKEN_KEY, PAGE_SIZE_KEY, PAGE_TOKEN_KEY, SORT_KEY};
+use crate::server::util;
 use crate::{
     auth::authenticator::AuthHandler,
     models::{
@@ -19,6 +20,36 @@ use crate::{
     },
 };
 
+/// Request metadata key to list the object groups of all datasets of a project with get_dataset_object_groups
+/// If it is set the id of the request is interpreted as the id of the project and the result is paginated
+/// with the page-size and page-token metadata keys, the token of the next page is returned in next-page-token
+pub const PROJECT_SCOPE_KEY: &str = "project-scope";
+/// Request metadata key to list the released dataset versions of a revision with get_dataset_versions
+/// If it is set the id of the request is interpreted as the id of the revision and read access on the revision is
+/// required instead of the dataset
+pub const REVISION_SCOPE_KEY: &str = "revision-scope";
+/// Request metadata key to create a dataset as clone of the dataset with the given id with create_dataset
+/// The clone is created with the name of the request in the project of the source dataset, the object groups of the
+/// source are copied together with their current revisions and share the stored objects with the source
+pub const CLONE_SOURCE_KEY: &str = "clone-source";
+/// Request metadata key to include the storage usage of the dataset in the response of get_dataset
+/// If it is set the number of objects and their total size in bytes are returned in objects-count and content-len,
+/// the values are cached for a short time
+pub const INCLUDE_STATS_KEY: &str = "include-stats";
+pub const OBJECTS_COUNT_KEY: &str = "objects-count";
+pub const CONTENT_LEN_KEY: &str = "content-len";
+/// Request and response metadata key with the rev_version of a dataset
+/// get_dataset returns the current rev_version, update_dataset_field requires the rev_version the update is based on
+/// and returns the rev_version after the update. Updates based on an outdated rev_version are rejected with aborted.
+pub const REV_VERSION_KEY: &str = "rev-version";
+/// Request metadata key of update_dataset_field with the id of the updated dataset
+pub const DATASET_ID_KEY: &str = "dataset-id";
+/// Request metadata key of update_dataset_field with a JSON object of the dataset fields to set
+/// Only name, description, is_public, labels and metadata can be updated
+pub const UPDATE_FIELDS_KEY: &str = "update-fields";
+/// Page size of project wide object group listings if no page size is requested
+const DEFAULT_OBJECT_GROUP_PAGE_SIZE: i64 = 1000;
+
 pub struct DatasetsServer<T: Database + 'static> {
     pub handler_wrapper: Arc<HandlerWrapper<T>>,
     pub auth_handler: Arc<dyn AuthHandler>,
@@ -41,6 +72,10 @@ impl<T: Database> DatasetService for DatasetsServer<T> {
             )
             .await?;
 
+        if let Some(source_dataset_id) = util::metadata_str(request.metadata(), CLONE_SOURCE_KEY)? {
+            return self.clone_dataset(&request, source_dataset_id).await;
+        }
+
         let dataset = self
             .handler_wrapper
             .create_handler
@@ -77,7 +112,25 @@ impl<T: Database> DatasetService for DatasetsServer<T> {
             dataset: Some(dataset.to_proto_dataset()),
         };
 
-        return Ok(Response::new(response));
+        let mut response = Response::new(response);
+        response
+            .metadata_mut()
+            .insert(REV_VERSION_KEY, MetadataValue::from(dataset.rev_version));
+        if request.metadata().contains_key(INCLUDE_STATS_KEY) {
+            let stats = self
+                .handler_wrapper
+                .read_handler
+                .read_dataset_stats_cached(dataset.id.as_str())
+                .await?;
+            response
+                .metadata_mut()
+                .insert(OBJECTS_COUNT_KEY, MetadataValue::from(stats.objects_count));
+            response
+                .metadata_mut()
+                .insert(CONTENT_LEN_KEY, MetadataValue::from(stats.content_len));
+        }
+
+        return Ok(response);
     }
 
     async fn get_dataset_versions(
@@ -85,6 +138,10 @@ impl<T: Database> DatasetService for DatasetsServer<T> {
         request: tonic::Request<services::v1::GetDatasetVersionsRequest>,
     ) -> Result<Response<services::v1::GetDatasetVersionsResponse>, tonic::Status> {
         let inner_request = request.get_ref();
+        if request.metadata().contains_key(REVISION_SCOPE_KEY) {
+            return self.get_revision_dataset_versions(&request).await;
+        }
+
         self.auth_handler
             .authorize(
                 request.metadata(),
@@ -97,7 +154,9 @@ impl<T: Database> DatasetService for DatasetsServer<T> {
         let dataset_versions = self
             .handler_wrapper
             .read_handler
-            .read_from_parent_entry::<DatasetVersion>(inner_request.id.as_str())
+            .read_children_of_existing_parent::<DatasetEntry, DatasetVersion>(
+                inner_request.id.as_str(),
+            )
             .await?;
         let dataset_versions_proto = dataset_versions.into_iter().map(|x| x.to_proto()).collect();
 
@@ -113,6 +172,10 @@ impl<T: Database> DatasetService for DatasetsServer<T> {
         request: tonic::Request<services::v1::GetDatasetObjectGroupsRequest>,
     ) -> Result<Response<services::v1::GetDatasetObjectGroupsResponse>, tonic::Status> {
         let inner_request = request.get_ref();
+        if request.metadata().contains_key(PROJECT_SCOPE_KEY) {
+            return self.get_project_object_groups(&request).await;
+        }
+
         self.auth_handler
             .authorize(
                 request.metadata(),
@@ -125,7 +188,9 @@ impl<T: Database> DatasetService for DatasetsServer<T> {
         let object_groups: Vec<ObjectGroup> = self
             .handler_wrapper
             .read_handler
-            .read_from_parent_entry(inner_request.id.as_str())
+            .read_children_of_existing_parent::<DatasetEntry, ObjectGroup>(
+                inner_request.id.as_str(),
+            )
             .await?;
         let object_groups_proto = object_groups.into_iter().map(|x| x.to_proto()).collect();
 
@@ -140,8 +205,44 @@ impl<T: Database> DatasetService for DatasetsServer<T> {
         &self,
         request: tonic::Request<services::v1::UpdateDatasetFieldRequest>,
     ) -> Result<Response<services::v1::UpdateDatasetFieldResponse>, tonic::Status> {
-        let _inner_request = request.get_ref();
-        return Err(tonic::Status::unimplemented("not implemented"));
+        // The update is described by the metadata of the request
+        let dataset_id = required_metadata_str(request.metadata(), DATASET_ID_KEY)?;
+        let rev_version = match required_metadata_str(request.metadata(), REV_VERSION_KEY)?.parse()
+        {
+            Ok(value) => value,
+            Err(_) => {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "{} has to be an integer",
+                    REV_VERSION_KEY
+                )))
+            }
+        };
+        let fields = update_fields(required_metadata_str(
+            request.metadata(),
+            UPDATE_FIELDS_KEY,
+        )?)?;
+
+        self.auth_handler
+            .authorize(
+                request.metadata(),
+                Resource::Dataset,
+                Right::Write,
+                dataset_id.to_string(),
+            )
+            .await?;
+
+        let dataset = self
+            .handler_wrapper
+            .update_handler
+            .update_dataset_fields(dataset_id, rev_version, fields)
+            .await?;
+
+        let mut response = Response::new(services::v1::UpdateDatasetFieldResponse::default());
+        response
+            .metadata_mut()
+            .insert(REV_VERSION_KEY, MetadataValue::from(dataset.rev_version));
+
+        return Ok(response);
     }
 
     async fn delete_dataset(
@@ -180,25 +281,21 @@ impl<T: Database> DatasetService for DatasetsServer<T> {
             )
             .await?;
 
-        let mut poll_authz_queue = FuturesUnordered::new();
-        for revision_id in inner_request.revision_ids.clone() {
-            let authz_request = self.auth_handler.authorize(
-                request.metadata(),
-                Resource::ObjectGroupRevision,
-                Right::Write,
-                revision_id,
-            );
-
-            poll_authz_queue.push(authz_request);
-
-            if poll_authz_queue.len() == 100 {
-                poll_authz_queue.next().await.unwrap()?;
-            }
-        }
+        let revision_authz_requests = inner_request
+            .revision_ids
+            .iter()
+            .map(|revision_id| {
+                (
+                    Resource::ObjectGroupRevision,
+                    Right::Write,
+                    revision_id.clone(),
+                )
+            })
+            .collect();
 
-        while let Some(value) = poll_authz_queue.next().await {
-            value?
-        }
+        self.auth_handler
+            .authorize_batch(request.metadata(), revision_authz_requests)
+            .await?;
 
         let version = self
             .handler_wrapper
@@ -283,3 +380,152 @@ impl<T: Database> DatasetService for DatasetsServer<T> {
         todo!()
     }
 }
+
+impl<T: Database> DatasetsServer<T> {
+    /// Lists a page of the object groups of all datasets of the project with the id of the request
+    async fn get_project_object_groups(
+        &self,
+        request: &tonic::Request<services::v1::GetDatasetObjectGroupsRequest>,
+    ) -> Result<Response<services::v1::GetDatasetObjectGroupsResponse>, tonic::Status> {
+        let project_id = request.get_ref().id.clone();
+        self.auth_handler
+            .authorize(
+                request.metadata(),
+                Resource::Project,
+                Right::Read,
+                project_id.clone(),
+            )
+            .await?;
+
+        let page_size = util::metadata_page_size(request.metadata(), PAGE_SIZE_KEY)?
+            .unwrap_or(DEFAULT_OBJECT_GROUP_PAGE_SIZE);
+        let page_token = util::metadata_str(request.metadata(), PAGE_TOKEN_KEY)?.unwrap_or("");
+        let sort = util::metadata_sort_order::<ObjectGroup>(request.metadata(), SORT_KEY)?;
+
+        let (object_groups, next_page_token) = self
+            .handler_wrapper
+            .read_handler
+            .read_project_object_groups_page(project_id.as_str(), page_size, page_token, &sort)
+            .await?;
+        let object_groups_proto = object_groups.into_iter().map(|x| x.to_proto()).collect();
+
+        let mut response = Response::new(services::v1::GetDatasetObjectGroupsResponse {
+            object_groups: object_groups_proto,
+        });
+        let next_page_token = match MetadataValue::from_str(next_page_token.as_str()) {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(tonic::Status::internal("could not encode next page token"));
+            }
+        };
+        response
+            .metadata_mut()
+            .insert(NEXT_PAGE_TOKEN_KEY, next_page_token);
+
+        return Ok(response);
+    }
+
+    /// Lists the released dataset versions of the revision with the id of the request
+    async fn get_revision_dataset_versions(
+        &self,
+        request: &tonic::Request<services::v1::GetDatasetVersionsRequest>,
+    ) -> Result<Response<services::v1::GetDatasetVersionsResponse>, tonic::Status> {
+        let revision_id = request.get_ref().id.clone();
+        self.auth_handler
+            .authorize(
+                request.metadata(),
+                Resource::ObjectGroupRevision,
+                Right::Read,
+                revision_id.clone(),
+            )
+            .await?;
+
+        let dataset_versions = self
+            .handler_wrapper
+            .read_handler
+            .read_revision_dataset_versions(revision_id.as_str())
+            .await?;
+        let dataset_versions_proto = dataset_versions.into_iter().map(|x| x.to_proto()).collect();
+
+        let version_list = services::v1::GetDatasetVersionsResponse {
+            dataset_versions: dataset_versions_proto,
+        };
+
+        return Ok(Response::new(version_list));
+    }
+
+    /// Answers a create_dataset request with a clone of the source dataset
+    /// The project of the request has already been authorized for write access
+    async fn clone_dataset(
+        &self,
+        request: &tonic::Request<services::v1::CreateDatasetRequest>,
+        source_dataset_id: &str,
+    ) -> Result<Response<services::v1::CreateDatasetResponse>, tonic::Status> {
+        let inner_request = request.get_ref();
+        self.auth_handler
+            .authorize(
+                request.metadata(),
+                Resource::Dataset,
+                Right::Read,
+                source_dataset_id.to_string(),
+            )
+            .await?;
+
+        let source_dataset = self
+            .handler_wrapper
+            .read_handler
+            .read_entry_by_id::<DatasetEntry>(source_dataset_id)
+            .await?;
+        if source_dataset.project_id != inner_request.project_id {
+            return Err(tonic::Status::invalid_argument(
+                "datasets can only be cloned within their project",
+            ));
+        }
+
+        let (dataset, _) = self
+            .handler_wrapper
+            .create_handler
+            .clone_dataset(source_dataset_id, inner_request.name.as_str())
+            .await?;
+
+        let response = services::v1::CreateDatasetResponse { id: dataset.id };
+
+        return Ok(Response::new(response));
+    }
+}
+
+/// Reads a metadata entry that is required by the request, fails with invalid_argument if it is missing
+fn required_metadata_str<'a>(
+    metadata: &'a MetadataMap,
+    key: &str,
+) -> Result<&'a str, tonic::Status> {
+    match util::metadata_str(metadata, key)? {
+        Some(value) => return Ok(value),
+        None => return Err(tonic::Status::invalid_argument(format!("{} required", key))),
+    }
+}
+
+/// Parses the JSON object with the dataset fields of an update
+fn update_fields(value: &str) -> Result<Document, tonic::Status> {
+    let fields: serde_json::Map<String, serde_json::Value> = match serde_json::from_str(value) {
+        Ok(value) => value,
+        Err(_) => {
+            return Err(tonic::Status::invalid_argument(format!(
+                "{} has to be a JSON object",
+                UPDATE_FIELDS_KEY
+            )))
+        }
+    };
+
+    match to_document(&fields) {
+        Ok(value) => return Ok(value),
+        Err(e) => {
+            log::error!("{:?}", e);
+            return Err(tonic::Status::invalid_argument(format!(
+                "could not convert {}",
+                UPDATE_FIELDS_KEY
+            )));
+        }
+    }
+}
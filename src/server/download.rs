@@ -0,0 +1,170 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::{header, HeaderValue, Method, Request, Response, StatusCode};
+use http_body::Body as HttpBody;
+use log::{error, info};
+use tonic::{
+    body::BoxBody,
+    transport::{Body, NamedService},
+};
+use tower::Service;
+
+use crate::{database::database::Database, handler::common::HandlerWrapper, SETTINGS};
+
+/// Path prefix of single-use downloads, the path continues with the download token
+pub const DOWNLOAD_PATH: &str = "/download/";
+
+/// Port of the download server and the url under which clients reach it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadServerConfig {
+    pub port: i64,
+    pub public_url: String,
+}
+
+/// Reads the download server from Server.Download, single-use downloads are only available if Port is set
+/// PublicUrl defaults to http://<Server.Host>:<Port> and has to be set if clients reach the server through a proxy
+pub fn download_server_from_config() -> Option<DownloadServerConfig> {
+    let settings = SETTINGS.read().unwrap();
+    let port = settings.get_int("Server.Download.Port").ok()?;
+    let public_url = match settings.get_str("Server.Download.PublicUrl") {
+        Ok(value) => value,
+        Err(_) => format!(
+            "http://{}:{}",
+            settings
+                .get_str("Server.Host")
+                .unwrap_or("127.0.0.1".to_string()),
+            port
+        ),
+    };
+
+    return Some(DownloadServerConfig {
+        port: port,
+        public_url: public_url.trim_end_matches('/').to_string(),
+    });
+}
+
+/// Returns the url of the single-use download of a token
+pub fn download_link(config: &DownloadServerConfig, token: &str) -> String {
+    return format!("{}{}{}", config.public_url, DOWNLOAD_PATH, token);
+}
+
+/// HTTP service that answers GET /download/<token> with the data of the object of a single-use download token
+/// The token is the only credential of the download. The data is streamed through the server so that the token
+/// is invalidated by the first download, every further request with the same token is answered with 403 Forbidden.
+pub struct DownloadService<T: Database + 'static> {
+    handler_wrapper: Arc<HandlerWrapper<T>>,
+}
+
+impl<T: Database> DownloadService<T> {
+    pub fn new(handler_wrapper: Arc<HandlerWrapper<T>>) -> Self {
+        return DownloadService {
+            handler_wrapper: handler_wrapper,
+        };
+    }
+}
+
+impl<T: Database> Clone for DownloadService<T> {
+    fn clone(&self) -> Self {
+        return DownloadService {
+            handler_wrapper: self.handler_wrapper.clone(),
+        };
+    }
+}
+
+impl<T: Database> NamedService for DownloadService<T> {
+    const NAME: &'static str = "download";
+}
+
+impl<T: Database> Service<Request<Body>> for DownloadService<T> {
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return Poll::Ready(Ok(()));
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let handler_wrapper = self.handler_wrapper.clone();
+
+        return Box::pin(async move { Ok(download(handler_wrapper, request).await) });
+    }
+}
+
+async fn download<T: Database>(
+    handler_wrapper: Arc<HandlerWrapper<T>>,
+    request: Request<Body>,
+) -> Response<BoxBody> {
+    if request.method() != Method::GET {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, "only GET is supported");
+    }
+
+    let token = match request.uri().path().strip_prefix(DOWNLOAD_PATH) {
+        Some(value) if !value.is_empty() => value,
+        _ => return error_response(StatusCode::NOT_FOUND, "unknown path"),
+    };
+
+    let (object, data) = match handler_wrapper
+        .load_handler
+        .download_with_token(token)
+        .await
+    {
+        Ok(value) => value,
+        Err(e) => return error_response(http_status(e.code()), e.message()),
+    };
+    info!("single-use download of object {}", object.id);
+
+    let body = Body::wrap_stream(data)
+        .map_err(|e| {
+            error!("{:?}", e);
+            tonic::Status::internal("error reading object")
+        })
+        .boxed();
+    let mut response = Response::new(body);
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/octet-stream"),
+    );
+    let disposition = format!(
+        "attachment; filename=\"{}\"",
+        object.filename.replace('"', "")
+    );
+    if let Ok(value) = HeaderValue::from_str(disposition.as_str()) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_DISPOSITION, value);
+    }
+
+    return response;
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<BoxBody> {
+    let body = Body::from(message.to_string())
+        .map_err(|e| {
+            error!("{:?}", e);
+            tonic::Status::internal("error writing response")
+        })
+        .boxed();
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+
+    return response;
+}
+
+/// Maps the gRPC status codes of the handlers to the HTTP status of the download response
+fn http_status(code: tonic::Code) -> StatusCode {
+    match code {
+        tonic::Code::PermissionDenied => return StatusCode::FORBIDDEN,
+        tonic::Code::NotFound => return StatusCode::NOT_FOUND,
+        tonic::Code::FailedPrecondition => return StatusCode::CONFLICT,
+        tonic::Code::DeadlineExceeded => return StatusCode::GATEWAY_TIMEOUT,
+        tonic::Code::Unavailable => return StatusCode::SERVICE_UNAVAILABLE,
+        _ => return StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
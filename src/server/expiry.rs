@@ -0,0 +1,36 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{info, warn};
+
+use crate::{database::database::Database, handler::common::HandlerWrapper};
+
+use super::timeout::duration_from_config;
+
+const DEFAULT_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Returns the interval of the expiry sweeper configured in Server.ExpirySweepIntervalMs, defaults to one minute
+pub fn sweep_interval_from_config() -> Duration {
+    return duration_from_config("Server.ExpirySweepIntervalMs")
+        .filter(|interval| interval.as_millis() > 0)
+        .unwrap_or(DEFAULT_SWEEP_INTERVAL);
+}
+
+/// Periodically deletes all revisions whose ttl has passed together with their objects
+pub async fn sweep_expired_revisions<T: Database>(
+    handler_wrapper: Arc<HandlerWrapper<T>>,
+    interval: Duration,
+) {
+    loop {
+        let delete_handler = &handler_wrapper.delete_handler;
+        match delete_handler
+            .delete_expired_revisions(delete_handler.clock.now())
+            .await
+        {
+            Ok(0) => {}
+            Ok(deleted) => info!("deleted {} expired revisions", deleted),
+            Err(e) => warn!("could not delete expired revisions: {:?}", e),
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
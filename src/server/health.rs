@@ -0,0 +1,82 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{info, warn};
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+use crate::{
+    database::database::Database,
+    objectstorage::objectstorage::{missing_cors_methods, StorageHandler},
+    SETTINGS,
+};
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Methods the browser clients use on the presigned download and upload urls
+const PRESIGNED_URL_METHODS: [&str; 2] = ["GET", "PUT"];
+
+/// Periodically pings the database and the object storage and reports the result to the gRPC health service
+/// The server is reported as serving only if both backends are reachable
+pub async fn report_backend_health<T: Database>(
+    mut health_reporter: HealthReporter,
+    database: Arc<T>,
+    object_storage: Arc<dyn StorageHandler>,
+) {
+    loop {
+        let database_status = database.ping().await;
+        let object_storage_status = object_storage.ping().await;
+
+        let status = match (database_status, object_storage_status) {
+            (Ok(_), Ok(_)) => ServingStatus::Serving,
+            (database_status, object_storage_status) => {
+                warn!(
+                    "backend not ready: database: {:?} object storage: {:?}",
+                    database_status, object_storage_status
+                );
+                ServingStatus::NotServing
+            }
+        };
+
+        health_reporter.set_service_status("", status).await;
+
+        tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+    }
+}
+
+/// Checks if the CORS configuration of the bucket allows the frontend origin configured in Storage.Cors.FrontendOrigin
+/// to use the presigned urls. The check is skipped if no frontend origin is configured.
+/// A misconfiguration does not prevent the server from starting, it is only logged
+pub async fn check_cors_configuration(object_storage: Arc<dyn StorageHandler>) {
+    let frontend_origin = match SETTINGS
+        .read()
+        .unwrap()
+        .get_str("Storage.Cors.FrontendOrigin")
+    {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+
+    let rules = match object_storage.get_cors_rules().await {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("could not check bucket cors configuration: {:?}", e);
+            return;
+        }
+    };
+
+    let missing_methods =
+        missing_cors_methods(&rules, frontend_origin.as_str(), &PRESIGNED_URL_METHODS);
+    if !missing_methods.is_empty() {
+        warn!(
+            "bucket {} does not allow {:?} requests from frontend origin {}, browser clients will fail to use presigned urls. configured cors rules: {:?}",
+            object_storage.get_bucket(),
+            missing_methods,
+            frontend_origin,
+            rules
+        );
+        return;
+    }
+
+    info!(
+        "bucket cors configuration allows frontend origin {}",
+        frontend_origin
+    );
+}
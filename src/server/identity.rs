@@ -0,0 +1,154 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::{HeaderMap, Request};
+use tonic::metadata::MetadataMap;
+use tower::{Layer, Service};
+
+use crate::auth::{
+    authenticator::AuthHandler,
+    project_authorization_handler::{API_TOKEN_ENTRY_KEY, USER_TOKEN_ENTRY_KEY},
+    service_account_handler::SERVICE_KEY_ENTRY_KEY,
+};
+
+/// Metadata entries that identify the caller of a request
+const CREDENTIAL_KEYS: [&str; 3] = [
+    API_TOKEN_ENTRY_KEY,
+    USER_TOKEN_ENTRY_KEY,
+    SERVICE_KEY_ENTRY_KEY,
+];
+
+/// Caller of a request, added to the request extensions by the IdentityLayer
+/// The user id is None if the request does not carry credentials or the credentials could not be resolved
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Identity {
+    pub user_id: Option<String>,
+}
+
+impl Identity {
+    /// Returns the identity that has been resolved for the request, requests that did not pass the
+    /// IdentityLayer are anonymous
+    pub fn from_request<B>(request: &Request<B>) -> Self {
+        return request
+            .extensions()
+            .get::<Identity>()
+            .cloned()
+            .unwrap_or_default();
+    }
+}
+
+/// Checks if the request metadata contains any credentials
+pub fn has_credentials(headers: &HeaderMap) -> bool {
+    return CREDENTIAL_KEYS.iter().any(|key| headers.contains_key(*key));
+}
+
+/// Resolves the user id of every request once and stores it as Identity in the request extensions
+/// Following layers like the rate limit and the request logging read the identity from the extensions instead of
+/// resolving the credentials again. Requests are not rejected here, the services authorize them as before.
+#[derive(Clone)]
+pub struct IdentityLayer {
+    auth_handler: Arc<dyn AuthHandler>,
+}
+
+impl IdentityLayer {
+    pub fn new(auth_handler: Arc<dyn AuthHandler>) -> Self {
+        return IdentityLayer {
+            auth_handler: auth_handler,
+        };
+    }
+}
+
+impl<S> Layer<S> for IdentityLayer {
+    type Service = IdentityService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return IdentityService {
+            inner: inner,
+            auth_handler: self.auth_handler.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct IdentityService<S> {
+    inner: S,
+    auth_handler: Arc<dyn AuthHandler>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for IdentityService<S>
+where
+    S: Service<Request<ReqBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        // The ready service is used for this request, the clone takes its place for the next one
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let auth_handler = self.auth_handler.clone();
+
+        return Box::pin(async move {
+            let mut user_id = None;
+            if has_credentials(request.headers()) {
+                let metadata = MetadataMap::from_headers(request.headers().clone());
+                user_id = auth_handler.user_id(&metadata).await.ok();
+            }
+            request
+                .extensions_mut()
+                .insert(Identity { user_id: user_id });
+
+            return inner.call(request).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, sync::Arc};
+
+    use http::Request;
+    use tower::{Layer, Service, ServiceExt};
+
+    use crate::auth::{
+        project_authorization_handler::API_TOKEN_ENTRY_KEY, test_authenticator::TestAuthenticator,
+    };
+
+    use super::{Identity, IdentityLayer};
+
+    #[tokio::test]
+    async fn resolved_identity() {
+        let identity_service = tower::service_fn(|request: Request<()>| async move {
+            return Ok::<Identity, Infallible>(Identity::from_request(&request));
+        });
+        let mut service =
+            IdentityLayer::new(Arc::new(TestAuthenticator {})).layer(identity_service);
+
+        let anonymous = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(()))
+            .await
+            .unwrap();
+        assert_eq!(anonymous.user_id, None);
+
+        let mut request = Request::new(());
+        request
+            .headers_mut()
+            .insert(API_TOKEN_ENTRY_KEY, "testtoken".parse().unwrap());
+        let authenticated = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(authenticated.user_id, Some("testuser".to_string()));
+    }
+}
@@ -1,13 +1,46 @@
 use crate::handler::common::HandlerWrapper;
 use crate::{auth::authenticator::AuthHandler, database::database::Database};
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::object_load_service_server::ObjectLoadService;
 
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
-use tonic::Response;
+use tonic::{metadata::MetadataValue, Response};
 
+use crate::handler::load::recommend_part_size;
 use crate::models::common_models::{Resource, Right};
+use crate::objectstorage::objectstorage::DownloadConditions;
+
+use crate::server::{
+    download::{download_link, download_server_from_config},
+    util,
+};
+
+/// Response metadata keys of the part size recommendation for multipart uploads
+pub const RECOMMENDED_PART_SIZE_KEY: &str = "recommended-part-size";
+pub const RECOMMENDED_PART_COUNT_KEY: &str = "recommended-part-count";
+/// Request metadata key to download an object by its index in the current revision of an object group
+/// If it is set the id of the download request is interpreted as the id of the object group
+pub const OBJECT_INDEX_KEY: &str = "object-index";
+/// Request metadata keys of conditional downloads
+/// The values are signed into the download link and have to be sent by the client as the corresponding HTTP headers
+pub const IF_NONE_MATCH_KEY: &str = "if-none-match";
+pub const IF_MODIFIED_SINCE_KEY: &str = "if-modified-since";
+/// Request metadata key to only read the metadata of an object with create_download_link
+/// If it is set no download link is created, the response contains the object and the metadata reported
+/// by the object storage in the object-size, content-type, last-modified and etag response metadata
+pub const HEAD_ONLY_KEY: &str = "head-only";
+pub const OBJECT_SIZE_KEY: &str = "object-size";
+pub const CONTENT_TYPE_KEY: &str = "content-type";
+pub const LAST_MODIFIED_KEY: &str = "last-modified";
+pub const ETAG_KEY: &str = "etag";
+/// Request metadata key with the base64 encoded SHA256 checksum of a multipart upload part
+/// The checksum is signed into the part link, the client has to send it in the x-amz-checksum-sha256 header
+pub const CHECKSUM_SHA256_KEY: &str = "checksum-sha256";
+/// Request metadata key to create a single-use download link instead of a presigned link
+/// The link points to the download server of this service and contains a download token as the only credential,
+/// the object data is streamed through the server and every further request of the link fails with 403 Forbidden
+pub const SINGLE_USE_KEY: &str = "single-use";
 
 pub struct LoadServer<T: Database + 'static> {
     pub wrapper: Arc<HandlerWrapper<T>>,
@@ -46,6 +79,41 @@ impl<T: Database> ObjectLoadService for LoadServer<T> {
         request: tonic::Request<services::v1::CreateDownloadLinkRequest>,
     ) -> Result<Response<services::v1::CreateDownloadLinkResponse>, tonic::Status> {
         let download_object = request.get_ref();
+
+        if let Some(object_index) = request.metadata().get(OBJECT_INDEX_KEY) {
+            let object_index: i64 = match object_index.to_str().ok().and_then(|x| x.parse().ok()) {
+                Some(value) => value,
+                None => {
+                    return Err(tonic::Status::invalid_argument(format!(
+                        "could not parse {} as object index",
+                        OBJECT_INDEX_KEY
+                    )))
+                }
+            };
+
+            self.auth_handler
+                .authorize(
+                    request.metadata(),
+                    Resource::ObjectGroup,
+                    Right::Read,
+                    download_object.id.clone(),
+                )
+                .await?;
+
+            let (object, link) = self
+                .wrapper
+                .load_handler
+                .create_download_link_by_index(download_object.id.as_str(), object_index)
+                .await?;
+
+            return Ok(tonic::Response::new(
+                services::v1::CreateDownloadLinkResponse {
+                    upload_link: link,
+                    object: Some(object.to_proto_object()),
+                },
+            ));
+        }
+
         self.auth_handler
             .authorize(
                 request.metadata(),
@@ -55,10 +123,30 @@ impl<T: Database> ObjectLoadService for LoadServer<T> {
             )
             .await?;
 
+        if request.metadata().contains_key(HEAD_ONLY_KEY) {
+            return self.head_object(download_object.id.as_str()).await;
+        }
+
+        let single_use = util::metadata_str(request.metadata(), SINGLE_USE_KEY)?
+            .map(|value| value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        if single_use {
+            return self
+                .create_download_token(download_object.id.as_str())
+                .await;
+        }
+
+        let conditions = DownloadConditions {
+            if_none_match: util::metadata_str(request.metadata(), IF_NONE_MATCH_KEY)?
+                .map(|value| value.to_string()),
+            if_modified_since: util::metadata_str(request.metadata(), IF_MODIFIED_SINCE_KEY)?
+                .map(|value| value.to_string()),
+        };
+
         let link = self
             .wrapper
             .load_handler
-            .create_download_link(download_object.id.as_str())
+            .create_conditional_download_link(download_object.id.as_str(), &conditions)
             .await?;
         let object = self
             .wrapper
@@ -94,11 +182,23 @@ impl<T: Database> ObjectLoadService for LoadServer<T> {
             .init_multipart_upload(download_object.id.as_str())
             .await?;
 
+        let part_recommendation = recommend_part_size(object.content_len);
+
         let response = services::v1::StartMultipartUploadResponse {
             object: Some(object.to_proto_object()),
         };
 
-        return Ok(Response::new(response));
+        let mut response = Response::new(response);
+        response.metadata_mut().insert(
+            RECOMMENDED_PART_SIZE_KEY,
+            MetadataValue::from(part_recommendation.part_size),
+        );
+        response.metadata_mut().insert(
+            RECOMMENDED_PART_COUNT_KEY,
+            MetadataValue::from(part_recommendation.part_count),
+        );
+
+        return Ok(response);
     }
 
     async fn get_multipart_upload_link(
@@ -126,6 +226,7 @@ impl<T: Database> ObjectLoadService for LoadServer<T> {
             .create_multipart_upload_link(
                 upload_request.object_id.as_str(),
                 upload_request.upload_part,
+                util::metadata_str(request.metadata(), CHECKSUM_SHA256_KEY)?,
             )
             .await?;
         return Ok(Response::new(
@@ -160,3 +261,64 @@ impl<T: Database> ObjectLoadService for LoadServer<T> {
         ));
     }
 }
+
+impl<T: Database> LoadServer<T> {
+    /// Answers a download request with the object and its storage metadata instead of a download link
+    async fn head_object(
+        &self,
+        id: &str,
+    ) -> Result<Response<services::v1::CreateDownloadLinkResponse>, tonic::Status> {
+        let (object, object_head) = self.wrapper.load_handler.head_object(id).await?;
+
+        let mut response = Response::new(services::v1::CreateDownloadLinkResponse {
+            upload_link: "".to_string(),
+            object: Some(object.to_proto_object()),
+        });
+
+        let metadata = response.metadata_mut();
+        metadata.insert(OBJECT_SIZE_KEY, MetadataValue::from(object_head.size));
+        let optional_values = vec![
+            (CONTENT_TYPE_KEY, object_head.content_type),
+            (LAST_MODIFIED_KEY, object_head.last_modified),
+            (ETAG_KEY, object_head.etag),
+        ];
+        for (key, value) in optional_values {
+            let value = match value {
+                Some(value) => value,
+                None => continue,
+            };
+
+            match MetadataValue::from_str(value.as_str()) {
+                Ok(value) => metadata.insert(key, value),
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    return Err(tonic::Status::internal("could not encode object metadata"));
+                }
+            };
+        }
+
+        return Ok(response);
+    }
+
+    /// Answers a download request with a single-use download link of the download server
+    async fn create_download_token(
+        &self,
+        id: &str,
+    ) -> Result<Response<services::v1::CreateDownloadLinkResponse>, tonic::Status> {
+        let download_server = match download_server_from_config() {
+            Some(value) => value,
+            None => {
+                return Err(tonic::Status::failed_precondition(
+                    "single-use downloads are not enabled",
+                ))
+            }
+        };
+
+        let (object, token) = self.wrapper.load_handler.create_download_token(id).await?;
+
+        return Ok(Response::new(services::v1::CreateDownloadLinkResponse {
+            upload_link: download_link(&download_server, token.id.as_str()),
+            object: Some(object.to_proto_object()),
+        }));
+    }
+}
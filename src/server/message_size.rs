@@ -0,0 +1,381 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::StreamExt;
+use http::{HeaderMap, Request, Response};
+use http_body::Body as HttpBody;
+use log::error;
+use tonic::{body::BoxBody, transport::Body};
+use tower::{Layer, Service};
+
+use crate::SETTINGS;
+
+/// Length of the prefix of every gRPC message, a compression flag followed by the message length
+const MESSAGE_PREFIX_LEN: usize = 5;
+
+/// Follows the length prefixes of the gRPC messages of a body while its chunks pass through
+/// Only the prefix of the next message is held back, the messages themselves are never buffered
+#[derive(Default)]
+pub struct MessageFrames {
+    /// Bytes of the prefix of the next message, they are held back until the prefix is complete
+    prefix: BytesMut,
+    /// Number of bytes of the current message that have not passed yet
+    remaining: usize,
+}
+
+impl MessageFrames {
+    /// Returns the data of the chunk that can be passed on, the bytes of an incomplete prefix are held back
+    /// Fails with resource_exhausted as soon as the prefix of a message larger than max_size is complete
+    pub fn pass(&mut self, mut chunk: Bytes, max_size: usize) -> Result<Bytes, tonic::Status> {
+        if self.remaining >= chunk.len() {
+            self.remaining -= chunk.len();
+            return Ok(chunk);
+        }
+
+        let mut passed = BytesMut::with_capacity(chunk.len() + MESSAGE_PREFIX_LEN);
+        while !chunk.is_empty() {
+            if self.remaining > 0 {
+                let len = self.remaining.min(chunk.len());
+                passed.put(chunk.split_to(len));
+                self.remaining -= len;
+                continue;
+            }
+
+            let len = (MESSAGE_PREFIX_LEN - self.prefix.len()).min(chunk.len());
+            self.prefix.put(chunk.split_to(len));
+            if self.prefix.len() < MESSAGE_PREFIX_LEN {
+                break;
+            }
+
+            let message_len = (&self.prefix[1..]).get_u32() as usize;
+            if message_len > max_size {
+                return Err(tonic::Status::resource_exhausted(format!(
+                    "message of {} bytes exceeds the maximum message size of {} bytes",
+                    message_len, max_size
+                )));
+            }
+            passed.put(self.prefix.split());
+            self.remaining = message_len;
+        }
+
+        return Ok(passed.freeze());
+    }
+}
+
+/// Response body that ends with resource_exhausted instead of sending a message larger than max_size
+/// The messages before the oversized message are sent unchanged, streaming responses keep streaming
+struct MessageSizeLimitBody {
+    inner: BoxBody,
+    frames: MessageFrames,
+    max_size: usize,
+    exceeded: Option<tonic::Status>,
+}
+
+impl HttpBody for MessageSizeLimitBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        if self.exceeded.is_some() {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            let chunk = match Pin::new(&mut self.inner).poll_data(cx) {
+                Poll::Ready(Some(Ok(value))) => value,
+                other => return other,
+            };
+
+            let max_size = self.max_size;
+            match self.frames.pass(chunk, max_size) {
+                Ok(passed) if passed.is_empty() => continue,
+                Ok(passed) => return Poll::Ready(Some(Ok(passed))),
+                Err(status) => {
+                    error!("{}", status.message());
+                    self.exceeded = Some(status);
+                    return Poll::Ready(None);
+                }
+            }
+        }
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let status = match self.exceeded.take() {
+            Some(value) => value,
+            None => return Pin::new(&mut self.inner).poll_trailers(cx),
+        };
+
+        let mut trailers = HeaderMap::new();
+        status.add_header(&mut trailers)?;
+
+        return Poll::Ready(Ok(Some(trailers)));
+    }
+}
+
+/// Limits the size of the messages the server decodes and encodes
+/// tonic 0.5 does not support message size limits on the services, the length prefixes of the messages are therefore
+/// checked while the bodies stream through. Reading a larger request message fails with resource_exhausted in the
+/// service, responses end with resource_exhausted instead of a larger message.
+#[derive(Clone, Default)]
+pub struct MessageSizeLimitLayer {
+    max_decoding_message_size: Option<usize>,
+    max_encoding_message_size: Option<usize>,
+}
+
+impl MessageSizeLimitLayer {
+    pub fn new(
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    ) -> Self {
+        return MessageSizeLimitLayer {
+            max_decoding_message_size: max_decoding_message_size,
+            max_encoding_message_size: max_encoding_message_size,
+        };
+    }
+
+    /// Reads the limits in bytes from Server.MaxDecodingMessageSize and Server.MaxEncodingMessageSize
+    /// Message sizes are not limited if no limit is configured
+    pub fn new_from_config() -> Self {
+        let settings = SETTINGS.read().unwrap();
+        let max_decoding_message_size = settings
+            .get_int("Server.MaxDecodingMessageSize")
+            .ok()
+            .map(|value| value.max(0) as usize);
+        let max_encoding_message_size = settings
+            .get_int("Server.MaxEncodingMessageSize")
+            .ok()
+            .map(|value| value.max(0) as usize);
+
+        return MessageSizeLimitLayer::new(max_decoding_message_size, max_encoding_message_size);
+    }
+}
+
+impl<S> Layer<S> for MessageSizeLimitLayer {
+    type Service = MessageSizeLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return MessageSizeLimitService {
+            inner: inner,
+            max_decoding_message_size: self.max_decoding_message_size,
+            max_encoding_message_size: self.max_encoding_message_size,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct MessageSizeLimitService<S> {
+    inner: S,
+    max_decoding_message_size: Option<usize>,
+    max_encoding_message_size: Option<usize>,
+}
+
+impl<S> Service<Request<Body>> for MessageSizeLimitService<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        // Errors of the request body reach the service as source of the body error, tonic decodes them as status
+        let request = match self.max_decoding_message_size {
+            Some(max_size) => {
+                let (parts, body) = request.into_parts();
+                let mut frames = MessageFrames::default();
+                let body = body.map(move |chunk| match chunk {
+                    Ok(value) => frames.pass(value, max_size),
+                    Err(e) => {
+                        error!("{:?}", e);
+                        Err(tonic::Status::internal("could not read request"))
+                    }
+                });
+
+                Request::from_parts(parts, Body::wrap_stream(body))
+            }
+            None => request,
+        };
+
+        let max_encoding_message_size = self.max_encoding_message_size;
+        let response_future = self.inner.call(request);
+
+        return Box::pin(async move {
+            let response = response_future.await?;
+            let max_size = match max_encoding_message_size {
+                Some(value) => value,
+                None => return Ok(response),
+            };
+
+            return Ok(response.map(|body| {
+                MessageSizeLimitBody {
+                    inner: body,
+                    frames: MessageFrames::default(),
+                    max_size: max_size,
+                    exceeded: None,
+                }
+                .boxed()
+            }));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, error::Error};
+
+    use bytes::{BufMut, Bytes, BytesMut};
+    use futures::stream;
+    use http::{Request, Response};
+    use http_body::Body as HttpBody;
+    use tonic::{body::BoxBody, transport::Body};
+    use tower::{Layer, Service, ServiceExt};
+
+    use super::{MessageFrames, MessageSizeLimitLayer};
+    use crate::test_util::body::BufferedBody;
+
+    fn message(len: usize) -> Bytes {
+        let mut body = BytesMut::new();
+        body.put_u8(0);
+        body.put_u32(len as u32);
+        body.put_slice(vec![1; len].as_slice());
+
+        return body.freeze();
+    }
+
+    /// Reads the request like a service, a status in the source of a body error is returned as the error
+    async fn read_request(mut body: Body) -> Result<Bytes, tonic::Status> {
+        let mut data = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            match chunk {
+                Ok(value) => data.put(value),
+                Err(e) => {
+                    return Err(e
+                        .source()
+                        .and_then(|source| source.downcast_ref::<tonic::Status>())
+                        .cloned()
+                        .unwrap())
+                }
+            }
+        }
+
+        return Ok(data.freeze());
+    }
+
+    #[test]
+    fn message_frames() {
+        let mut body = BytesMut::new();
+        body.put(message(10));
+        body.put(message(0));
+        body.put(message(20));
+        let body = body.freeze();
+
+        // Chunk boundaries within prefixes hold back the prefix until it is complete
+        let mut frames = MessageFrames::default();
+        let mut passed = BytesMut::new();
+        for chunk in body.chunks(3) {
+            passed.put(frames.pass(Bytes::copy_from_slice(chunk), 20).unwrap());
+        }
+        assert_eq!(passed.freeze(), body);
+
+        let mut frames = MessageFrames::default();
+        assert_eq!(frames.pass(body.slice(..17), 10).unwrap(), body.slice(..15));
+        let error = frames.pass(body.slice(17..), 10).unwrap_err();
+        assert_eq!(error.code(), tonic::Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn decoding_message_size() {
+        let echo_service = tower::service_fn(|request: Request<Body>| async move {
+            let response = match read_request(request.into_body()).await {
+                Ok(data) => Response::new(BufferedBody::new(data, None).boxed()),
+                Err(status) => status.to_http(),
+            };
+
+            return Ok::<Response<BoxBody>, Infallible>(response);
+        });
+        let mut service = MessageSizeLimitLayer::new(Some(1024), None).layer(echo_service);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::from(message(1000))))
+            .await
+            .unwrap();
+        assert!(response.headers().get("grpc-status").is_none());
+        let (data, _) = BufferedBody::read(response.into_body()).await.unwrap();
+        assert_eq!(data, message(1000));
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::from(message(2048))))
+            .await
+            .unwrap();
+        assert_eq!(response.headers().get("grpc-status").unwrap(), "8");
+    }
+
+    #[tokio::test]
+    async fn encoding_message_size() {
+        // The response stream never ends, its first message has to pass without waiting for the end of the body
+        let streaming_service = tower::service_fn(|_request: Request<Body>| async move {
+            let messages =
+                stream::iter(vec![Ok::<Bytes, Infallible>(message(10))]).chain(stream::pending());
+            let body = Body::wrap_stream(messages)
+                .map_err(|_| tonic::Status::internal("unreachable"))
+                .boxed();
+
+            return Ok::<Response<BoxBody>, Infallible>(Response::new(body));
+        });
+        let mut service = MessageSizeLimitLayer::new(None, Some(1024)).layer(streaming_service);
+
+        let mut body = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap()
+            .into_body();
+        assert_eq!(body.data().await.unwrap().unwrap(), message(10));
+
+        let large_response_service = tower::service_fn(|_request: Request<Body>| async move {
+            let mut data = BytesMut::new();
+            data.put(message(10));
+            data.put(message(2048));
+            let body = BufferedBody::new(data.freeze(), None);
+
+            return Ok::<Response<BoxBody>, Infallible>(Response::new(body.boxed()));
+        });
+        let mut service =
+            MessageSizeLimitLayer::new(None, Some(1024)).layer(large_response_service);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(Body::empty()))
+            .await
+            .unwrap();
+        let (data, trailers) = BufferedBody::read(response.into_body()).await.unwrap();
+        assert_eq!(data, message(10));
+        assert_eq!(trailers.unwrap().get("grpc-status").unwrap(), "8");
+    }
+}
@@ -1,8 +1,25 @@
+pub mod compression;
+pub mod concurrency_limit;
+pub mod config_summary;
 pub mod dataset_api;
+pub mod download;
+pub mod expiry;
+pub mod health;
+pub mod identity;
 pub mod load_api;
+pub mod message_size;
+pub mod multipart_cleanup;
 pub mod object_api;
 pub mod project_api;
+pub mod purge;
+pub mod rate_limit;
+pub mod read_only;
+pub mod reflection;
+pub mod request_id;
+pub mod request_logging;
+pub mod require_token;
 pub mod server;
+pub mod timeout;
 
 pub mod server_test;
 
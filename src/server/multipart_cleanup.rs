@@ -0,0 +1,66 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{info, warn};
+
+use crate::{database::database::Database, handler::common::HandlerWrapper, SETTINGS};
+
+use super::timeout::duration_from_config;
+
+const DEFAULT_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_MAX_UPLOAD_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Interval and age after which incomplete multipart uploads are aborted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultipartCleanupConfig {
+    pub interval: Duration,
+    pub max_upload_age: Duration,
+}
+
+/// Reads the cleanup of incomplete multipart uploads from Storage.MultipartCleanup
+/// The cleanup is only run if Enabled is set, IntervalMs defaults to one hour and MaxUploadAgeMs to one day
+pub fn multipart_cleanup_from_config() -> Option<MultipartCleanupConfig> {
+    let enabled = SETTINGS
+        .read()
+        .unwrap()
+        .get_bool("Storage.MultipartCleanup.Enabled")
+        .unwrap_or(false);
+    if !enabled {
+        return None;
+    }
+
+    return Some(MultipartCleanupConfig {
+        interval: duration_from_config("Storage.MultipartCleanup.IntervalMs")
+            .filter(|interval| interval.as_millis() > 0)
+            .unwrap_or(DEFAULT_CLEANUP_INTERVAL),
+        max_upload_age: duration_from_config("Storage.MultipartCleanup.MaxUploadAgeMs")
+            .unwrap_or(DEFAULT_MAX_UPLOAD_AGE),
+    });
+}
+
+/// Periodically aborts the multipart uploads that have been initiated longer than the maximum upload age ago
+pub async fn abort_stale_multipart_uploads<T: Database>(
+    handler_wrapper: Arc<HandlerWrapper<T>>,
+    config: MultipartCleanupConfig,
+) {
+    let max_upload_age = match chrono::Duration::from_std(config.max_upload_age) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("invalid maximum age of multipart uploads: {:?}", e);
+            return;
+        }
+    };
+
+    loop {
+        let load_handler = &handler_wrapper.load_handler;
+        match load_handler
+            .abort_stale_multipart_uploads(load_handler.clock.now() - max_upload_age)
+            .await
+        {
+            Ok(0) => {}
+            Ok(aborted) => info!("aborted {} stale multipart uploads", aborted),
+            Err(e) => warn!("could not abort stale multipart uploads: {:?}", e),
+        }
+
+        tokio::time::sleep(config.interval).await;
+    }
+}
@@ -1,14 +1,20 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
+use prost::Message;
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::dataset_objects_service_server::DatasetObjectsService;
-use tonic::Response;
+use scienceobjectsdb_rust_api::sciobjectsdbapi::{models, services};
+use tonic::{
+    metadata::{MetadataMap, MetadataValue},
+    Response,
+};
 
 use crate::database::database::Database;
+use crate::error::CoreError;
 use crate::handler::common::HandlerWrapper;
+use crate::handler::delete::keep_revisions_from_config;
 use crate::models::dataset_object_group::ObjectGroupRevision;
 use crate::{
-    auth::authenticator::AuthHandler,
+    auth::{admin::authorize_admin, authenticator::AuthHandler},
     models::{
         common_models::{Resource, Right},
         dataset_object_group::ObjectGroup,
@@ -17,11 +23,133 @@ use crate::{
 
 use crate::server::util;
 
+/// Request metadata key to embed the current revision into the get_object_group response
+pub const INCLUDE_CURRENT_REVISION_KEY: &str = "include-current-revision";
+/// Binary response metadata key that holds the protobuf encoded current revision of the object group
+/// The key is omitted if the object group does not have a revision yet
+pub const CURRENT_REVISION_KEY: &str = "current-revision-bin";
+/// Request metadata key to create a temporary revision that is deleted after the given number of seconds
+/// The revision does not expire once it is part of a released dataset version
+pub const TTL_SECONDS_KEY: &str = "ttl-seconds";
+/// Request metadata key with a comma separated list of object ids to read with get_object_group_revision
+/// If it is set the id of the request is ignored, the response contains a revision that only consists of the
+/// requested objects in the requested order
+pub const OBJECT_IDS_KEY: &str = "object-ids";
+/// Request metadata key with an object storage key to read the revision the object stored under the key has been
+/// uploaded to with get_object_group_revision, e.g. to investigate storage side issues
+/// If it is set the id of the request is ignored, the lookup is restricted to the admins in Authentication.AdminUsers
+pub const STORAGE_KEY_KEY: &str = "storage-key";
+/// Request metadata key with the id of a released dataset version to read a revision of the version with
+/// get_object_group_revision by its id, read access on the dataset version implies read access on its revisions
+pub const DATASET_VERSION_ID_KEY: &str = "dataset-version-id";
+
+/// Reads the optional ttl of a created revision from the request metadata
+fn ttl_from_metadata(metadata: &MetadataMap) -> Result<Option<Duration>, tonic::Status> {
+    match util::metadata_str(metadata, TTL_SECONDS_KEY)? {
+        Some(ttl) => match ttl.parse::<u64>() {
+            Ok(ttl) => Ok(Some(Duration::from_secs(ttl))),
+            Err(_) => Err(tonic::Status::invalid_argument(format!(
+                "{} has to be a positive number of seconds",
+                TTL_SECONDS_KEY
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
 pub struct ObjectServer<T: Database + 'static> {
     pub handler_wrapper: Arc<HandlerWrapper<T>>,
     pub auth_handler: Arc<dyn AuthHandler>,
 }
 
+impl<T: Database + 'static> ObjectServer<T> {
+    /// Creates a revision that expires after the ttl if one is given
+    async fn create_revision(
+        &self,
+        revision_request: &services::v1::CreateObjectGroupRevisionRequest,
+        object_group_id: &str,
+        ttl: Option<Duration>,
+    ) -> Result<ObjectGroupRevision, CoreError> {
+        let create_handler = &self.handler_wrapper.create_handler;
+        match ttl {
+            Some(ttl) => {
+                create_handler
+                    .create_expiring_revision_for_group(revision_request, object_group_id, ttl)
+                    .await
+            }
+            None => {
+                create_handler
+                    .create_revision_for_group(revision_request, object_group_id)
+                    .await
+            }
+        }
+    }
+
+    /// Answers a get_object_group_revision request with the objects of the given ids
+    async fn get_objects(
+        &self,
+        metadata: &MetadataMap,
+        object_ids: &str,
+    ) -> Result<Response<services::v1::GetObjectGroupRevisionResponse>, tonic::Status> {
+        let object_ids: Vec<String> = object_ids
+            .split(',')
+            .map(|id| id.trim())
+            .filter(|id| !id.is_empty())
+            .map(|id| id.to_string())
+            .collect();
+
+        let authz_requests = object_ids
+            .iter()
+            .map(|id| (Resource::Object, Right::Read, id.clone()))
+            .collect();
+        self.auth_handler
+            .authorize_batch(metadata, authz_requests)
+            .await?;
+
+        let objects = self
+            .handler_wrapper
+            .read_handler
+            .find_objects(object_ids.as_slice())
+            .await?;
+
+        let response = services::v1::GetObjectGroupRevisionResponse {
+            object_group_revision: Some(models::v1::ObjectGroupRevision {
+                objects: objects
+                    .iter()
+                    .map(|object| object.to_proto_object())
+                    .collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        return Ok(Response::new(response));
+    }
+
+    /// Answers a get_object_group_revision request with the revision an object storage key belongs to
+    /// Storage keys are not scoped to a project, the lookup is authorized before the key is resolved
+    async fn get_revision_by_storage_key(
+        &self,
+        metadata: &MetadataMap,
+        storage_key: &str,
+    ) -> Result<Response<services::v1::GetObjectGroupRevisionResponse>, tonic::Status> {
+        authorize_admin(self.auth_handler.as_ref(), metadata).await?;
+
+        let revisions = self
+            .handler_wrapper
+            .read_handler
+            .read_revisions_by_storage_key(storage_key)
+            .await?;
+
+        let response = services::v1::GetObjectGroupRevisionResponse {
+            object_group_revision: revisions.first().map(|revision| revision.to_proto()),
+            ..Default::default()
+        };
+
+        return Ok(Response::new(response));
+    }
+}
+
 #[tonic::async_trait]
 impl<'a, T: Database + 'static> DatasetObjectsService for ObjectServer<T> {
     async fn create_object_group(
@@ -46,6 +174,8 @@ impl<'a, T: Database + 'static> DatasetObjectsService for ObjectServer<T> {
             object_group_revision: inner_request.object_group_revision.clone(),
         };
 
+        let ttl = ttl_from_metadata(request.metadata())?;
+
         let object_group = self
             .handler_wrapper
             .create_handler
@@ -54,9 +184,7 @@ impl<'a, T: Database + 'static> DatasetObjectsService for ObjectServer<T> {
 
         let revision_id = match &inner_request.object_group_revision {
             Some(request) => {
-                self.handler_wrapper
-                    .create_handler
-                    .create_revision_for_group(request, object_group.id.as_str())
+                self.create_revision(request, object_group.id.as_str(), ttl)
                     .await?
                     .id
             }
@@ -88,12 +216,41 @@ impl<'a, T: Database + 'static> DatasetObjectsService for ObjectServer<T> {
         let revision_request =
             util::tonic_error_if_not_exists(&inner_request.group_revison, "group_version")?;
 
+        let ttl = ttl_from_metadata(request.metadata())?;
+
         let revision = self
-            .handler_wrapper
-            .create_handler
-            .create_revision_for_group(revision_request, inner_request.object_group_id.as_str())
+            .create_revision(
+                revision_request,
+                inner_request.object_group_id.as_str(),
+                ttl,
+            )
             .await?;
 
+        // The history is compacted in the background, failed compactions are retried with the next revision
+        if keep_revisions_from_config().is_some() {
+            let handler_wrapper = self.handler_wrapper.clone();
+            let object_group_id = inner_request.object_group_id.clone();
+            tokio::spawn(async move {
+                match handler_wrapper
+                    .delete_handler
+                    .compact_revision_history(object_group_id.as_str())
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(deleted) => log::info!(
+                        "compacted {} revisions of object group {}",
+                        deleted,
+                        object_group_id
+                    ),
+                    Err(e) => log::warn!(
+                        "could not compact revisions of object group {}: {:?}",
+                        object_group_id,
+                        e
+                    ),
+                }
+            });
+        }
+
         let revision_response = services::v1::AddRevisionToObjectGroupResponse {
             revision_id: revision.id,
             revision_number: revision.revision as u64,
@@ -116,17 +273,54 @@ impl<'a, T: Database + 'static> DatasetObjectsService for ObjectServer<T> {
             )
             .await?;
 
-        let object_group = self
+        let include_current_revision = request
+            .metadata()
+            .get(INCLUDE_CURRENT_REVISION_KEY)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        if !include_current_revision {
+            let object_group = self
+                .handler_wrapper
+                .read_handler
+                .read_entry_by_id::<ObjectGroup>(inner_request.id.as_str())
+                .await?;
+
+            let object_group_revision_response = services::v1::GetObjectGroupResponse {
+                object_group: Some(object_group.to_proto()),
+            };
+
+            return Ok(Response::new(object_group_revision_response));
+        }
+
+        let (object_group, revision) = self
             .handler_wrapper
             .read_handler
-            .read_entry_by_id::<ObjectGroup>(inner_request.id.as_str())
+            .read_object_group_with_current_revision(inner_request.id.as_str())
             .await?;
 
         let object_group_revision_response = services::v1::GetObjectGroupResponse {
             object_group: Some(object_group.to_proto()),
         };
 
-        return Ok(Response::new(object_group_revision_response));
+        let mut response = Response::new(object_group_revision_response);
+        if let Some(revision) = revision {
+            let mut encoded_revision = Vec::new();
+            if let Err(e) = revision.to_proto().encode(&mut encoded_revision) {
+                log::error!("{:?}", e);
+                return Err(tonic::Status::internal(
+                    "could not encode current object group revision",
+                ));
+            }
+
+            response.metadata_mut().insert_bin(
+                CURRENT_REVISION_KEY,
+                MetadataValue::from_bytes(encoded_revision.as_slice()),
+            );
+        }
+
+        return Ok(response);
     }
 
     async fn get_current_object_group_revision(
@@ -161,37 +355,72 @@ impl<'a, T: Database + 'static> DatasetObjectsService for ObjectServer<T> {
         &self,
         request: tonic::Request<services::v1::GetObjectGroupRevisionRequest>,
     ) -> Result<Response<services::v1::GetObjectGroupRevisionResponse>, tonic::Status> {
+        if let Some(object_ids) = util::metadata_str(request.metadata(), OBJECT_IDS_KEY)? {
+            return self.get_objects(request.metadata(), object_ids).await;
+        }
+        if let Some(storage_key) = util::metadata_str(request.metadata(), STORAGE_KEY_KEY)? {
+            return self
+                .get_revision_by_storage_key(request.metadata(), storage_key)
+                .await;
+        }
+
         let inner_request = request.get_ref();
 
-        let revision_result = match inner_request.reference_type() {
-            services::v1::ObjectGroupRevisionReferenceType::Version => Err(
-                tonic::Status::unimplemented("version revision type currently not implemented"),
-            ),
+        // Authorize before the revision is resolved, so that unauthorized callers can not tell existing from
+        // missing revisions
+        let resource = match inner_request.reference_type() {
+            services::v1::ObjectGroupRevisionReferenceType::Version => {
+                return Err(tonic::Status::unimplemented(
+                    "version revision type currently not implemented",
+                ))
+            }
+            services::v1::ObjectGroupRevisionReferenceType::Revision => Resource::ObjectGroup,
+            services::v1::ObjectGroupRevisionReferenceType::Id => Resource::ObjectGroupRevision,
+        };
+
+        match util::metadata_str(request.metadata(), DATASET_VERSION_ID_KEY)? {
+            Some(version_id) => {
+                if resource != Resource::ObjectGroupRevision {
+                    return Err(tonic::Status::invalid_argument(format!(
+                        "{} requires a revision id reference",
+                        DATASET_VERSION_ID_KEY
+                    )));
+                }
+                self.auth_handler
+                    .authorize_version_revisions(
+                        request.metadata(),
+                        version_id.to_string(),
+                        vec![inner_request.id.clone()],
+                    )
+                    .await?;
+            }
+            None => {
+                self.auth_handler
+                    .authorize(
+                        request.metadata(),
+                        resource,
+                        Right::Read,
+                        inner_request.id.clone(),
+                    )
+                    .await?;
+            }
+        }
+
+        let revision = match inner_request.reference_type() {
             services::v1::ObjectGroupRevisionReferenceType::Revision => {
                 self.handler_wrapper
                     .read_handler
                     .read_revision(inner_request.id.as_str(), inner_request.revision)
-                    .await
+                    .await?
             }
-            services::v1::ObjectGroupRevisionReferenceType::Id => {
+            _ => {
                 self.handler_wrapper
                     .read_handler
                     .read_entry_by_id(inner_request.id.as_str())
-                    .await
+                    .await?
             }
         };
 
-        let revision = revision_result?;
-
-        self.auth_handler
-            .authorize(
-                request.metadata(),
-                Resource::ObjectGroupRevision,
-                Right::Read,
-                revision.id.clone(),
-            )
-            .await?;
-
         let response = services::v1::GetObjectGroupRevisionResponse {
             object_group_revision: Some(revision.to_proto()),
             ..Default::default()
@@ -217,7 +446,9 @@ impl<'a, T: Database + 'static> DatasetObjectsService for ObjectServer<T> {
         let proto_revision = self
             .handler_wrapper
             .read_handler
-            .read_from_parent_entry::<ObjectGroupRevision>(inner_request.id.as_str())
+            .read_children_of_existing_parent::<ObjectGroup, ObjectGroupRevision>(
+                inner_request.id.as_str(),
+            )
             .await?
             .into_iter()
             .map(|x| x.to_proto())
@@ -1,25 +1,78 @@
-use std::sync::Arc;
+use std::{str::FromStr, sync::Arc};
 
 use crate::database::database::Database;
 use crate::handler::common::HandlerWrapper;
 
+use crate::server::util;
+
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::project_service_server::ProjectService;
+use tonic::metadata::{MetadataMap, MetadataValue};
 use tonic::Response;
 
 use crate::{
-    auth::authenticator::AuthHandler,
+    auth::{admin::authorize_admin, authenticator::AuthHandler},
     models::{
-        common_models::{Resource, Right},
-        dataset_model::DatasetEntry,
+        common_models::{ListView, Resource, Right},
+        dataset_model::{DatasetEntry, DatasetSummary},
+        project_model::ProjectEntry,
     },
+    server::read_only::ReadOnlyMode,
 };
 
+/// Request metadata key that enables the pagination of get_user_projects with the given page size
+/// The request proto has no pagination fields, pagination is therefore controlled via metadata
+pub const PAGE_SIZE_KEY: &str = "page-size";
+/// Request metadata key with the page token returned by the previous page
+pub const PAGE_TOKEN_KEY: &str = "page-token";
+/// Request metadata key with a substring the project names have to contain
+pub const NAME_FILTER_KEY: &str = "name-filter";
+/// Request metadata key with the sort order of paginated listings, <field> or <field>:<asc|desc>
+/// Projects and datasets can be sorted by name, datasets additionally by created and object groups by name or
+/// revision_count. Listings are ordered by id if no sort order is requested.
+pub const SORT_KEY: &str = "sort";
+/// Response metadata key with the token of the next page, it is empty on the last page
+pub const NEXT_PAGE_TOKEN_KEY: &str = "next-page-token";
+/// Response metadata key of get_api_token, one "<token id>=<RFC 3339 timestamp>" value per used token
+/// The token proto has no field for the last use of a token, tokens that have never been used are omitted
+pub const LAST_USED_AT_KEY: &str = "last-used-at";
+/// Request metadata key to list the datasets of all projects of the requesting user with get_project_datasets
+/// If it is set the id of the request is ignored and the result is paginated with the page-size and page-token
+/// metadata keys, the token of the next page is returned in next-page-token
+pub const USER_SCOPE_KEY: &str = "user-scope";
+/// Request metadata key of create_project, if it is set the id of an existing project of the requesting user with the
+/// same name is returned instead of creating another project
+pub const CREATE_IF_ABSENT_KEY: &str = "create-if-absent";
+/// Request metadata key of get_project_datasets to additionally return the distinct label keys of the datasets
+pub const INCLUDE_LABEL_KEYS_KEY: &str = "include-label-keys";
+/// Response metadata key with the label keys used by the datasets of a project, one value per key
+pub const LABEL_KEYS_KEY: &str = "label-keys";
+/// Request metadata key with the view of dataset listings, either basic or full (default)
+/// Basic datasets only contain id, name, status and project id, labels and metadata are not read from the database
+pub const VIEW_KEY: &str = "view";
+/// Request metadata key of get_api_token and delete_api_token with the id of the user whose tokens are listed or revoked
+/// Restricted to the admins in Authentication.AdminUsers. If it is set for delete_api_token the id of the request is
+/// ignored and all tokens of the user are revoked, the number of revoked tokens is returned in revoked-tokens.
+pub const TOKEN_USER_KEY: &str = "token-user";
+/// Response metadata key of delete_api_token with the number of revoked tokens of the user given in token-user
+pub const REVOKED_TOKENS_KEY: &str = "revoked-tokens";
+/// Request metadata key of create_api_token with the id of an api token of the requesting user to rotate
+/// If it is set the id of the request is ignored, the response contains the token with its new secret and the
+/// previous secret is invalidated
+pub const ROTATE_TOKEN_KEY: &str = "rotate-token";
+/// Request metadata key of get_user_projects to enable (true) or disable (false) the read-only mode of the server
+/// Restricted to the admins in Authentication.AdminUsers. If it is set no projects are listed and the response contains
+/// the resulting mode in the same key. Get requests pass the read-only mode, it can therefore be disabled again.
+pub const READ_ONLY_KEY: &str = "read-only";
+/// Page size of user wide dataset listings if no page size is requested
+const DEFAULT_DATASET_PAGE_SIZE: i64 = 1000;
+
 /// Handles the project related API endpoints
 /// The individual functions implemented are defined and documented in the API documentation
 pub struct ProjectServer<T: Database + 'static> {
     pub handler: Arc<HandlerWrapper<T>>,
     pub auth_handler: Arc<dyn AuthHandler>,
+    pub read_only_mode: ReadOnlyMode,
 }
 
 #[tonic::async_trait]
@@ -30,11 +83,16 @@ impl<T: Database> ProjectService for ProjectServer<T> {
     ) -> Result<tonic::Response<services::v1::CreateProjectResponse>, tonic::Status> {
         let user_id = self.auth_handler.user_id(request.metadata()).await?;
 
-        let project = self
-            .handler
-            .create_handler
-            .create_project(&request.into_inner(), user_id)
-            .await?;
+        let create_handler = &self.handler.create_handler;
+        let project = if request.metadata().contains_key(CREATE_IF_ABSENT_KEY) {
+            create_handler
+                .create_project_if_absent(request.get_ref(), user_id)
+                .await?
+        } else {
+            create_handler
+                .create_project(request.get_ref(), user_id)
+                .await?
+        };
 
         let response = services::v1::CreateProjectResponse {
             project: project.id,
@@ -72,6 +130,10 @@ impl<T: Database> ProjectService for ProjectServer<T> {
         request: tonic::Request<services::v1::GetProjectDatasetsRequest>,
     ) -> Result<tonic::Response<services::v1::GetProjectDatasetsResponse>, tonic::Status> {
         let get_request = request.get_ref();
+        if request.metadata().contains_key(USER_SCOPE_KEY) {
+            return self.get_user_datasets(&request).await;
+        }
+
         self.auth_handler
             .authorize(
                 request.metadata(),
@@ -81,19 +143,47 @@ impl<T: Database> ProjectService for ProjectServer<T> {
             )
             .await?;
 
-        let datasets = self
-            .handler
-            .read_handler
-            .read_from_parent_entry::<DatasetEntry>(get_request.id.as_str())
-            .await?;
-        let proto_datasets = datasets.into_iter().map(|x| x.to_proto_dataset()).collect();
+        let read_handler = &self.handler.read_handler;
+        let project_id = get_request.id.as_str();
+        let proto_datasets = match util::metadata_list_view(request.metadata(), VIEW_KEY)? {
+            ListView::Basic => read_handler
+                .read_children_of_existing_parent::<ProjectEntry, DatasetSummary>(project_id)
+                .await?
+                .into_iter()
+                .map(|x| x.to_proto_dataset())
+                .collect(),
+            ListView::Full => read_handler
+                .read_children_of_existing_parent::<ProjectEntry, DatasetEntry>(project_id)
+                .await?
+                .into_iter()
+                .map(|x| x.to_proto_dataset())
+                .collect(),
+        };
 
         let dataset_list = services::v1::GetProjectDatasetsResponse {
             dataset: proto_datasets,
             ..Default::default()
         };
 
-        return Ok(Response::new(dataset_list));
+        let mut response = Response::new(dataset_list);
+        if request.metadata().contains_key(INCLUDE_LABEL_KEYS_KEY) {
+            let label_keys = self
+                .handler
+                .read_handler
+                .read_project_label_keys(get_request.id.as_str())
+                .await?;
+            for label_key in label_keys {
+                match MetadataValue::from_str(label_key.as_str()) {
+                    Ok(value) => response.metadata_mut().append(LABEL_KEYS_KEY, value),
+                    Err(e) => {
+                        log::error!("{:?}", e);
+                        return Err(tonic::Status::internal("could not encode label key"));
+                    }
+                };
+            }
+        }
+
+        return Ok(response);
     }
 
     async fn get_user_projects(
@@ -101,12 +191,40 @@ impl<T: Database> ProjectService for ProjectServer<T> {
         request: tonic::Request<services::v1::GetUserProjectsRequest>,
     ) -> Result<tonic::Response<services::v1::GetUserProjectsResponse>, tonic::Status> {
         let _get_request = request.get_ref();
+        if let Some(read_only) = util::metadata_str(request.metadata(), READ_ONLY_KEY)? {
+            return self.set_read_only_mode(request.metadata(), read_only).await;
+        }
+
         let id = self.auth_handler.user_id(request.metadata()).await?;
 
-        let projects = self
+        let page_size = util::metadata_page_size(request.metadata(), PAGE_SIZE_KEY)?;
+
+        let page_size = match page_size {
+            Some(value) => value,
+            None => {
+                let projects = self
+                    .handler
+                    .read_handler
+                    .read_user_projects(id.as_str())
+                    .await?;
+                let proto_projects = projects.into_iter().map(|x| x.to_proto_project()).collect();
+
+                let project_list = services::v1::GetUserProjectsResponse {
+                    projects: proto_projects,
+                };
+
+                return Ok(tonic::Response::new(project_list));
+            }
+        };
+
+        let page_token = util::metadata_str(request.metadata(), PAGE_TOKEN_KEY)?.unwrap_or("");
+        let name_filter = util::metadata_str(request.metadata(), NAME_FILTER_KEY)?;
+        let sort = util::metadata_sort_order::<ProjectEntry>(request.metadata(), SORT_KEY)?;
+
+        let (projects, next_page_token) = self
             .handler
             .read_handler
-            .read_user_projects(id.as_str())
+            .read_user_projects_page(id.as_str(), page_size, page_token, name_filter, &sort)
             .await?;
         let proto_projects = projects.into_iter().map(|x| x.to_proto_project()).collect();
 
@@ -114,7 +232,19 @@ impl<T: Database> ProjectService for ProjectServer<T> {
             projects: proto_projects,
         };
 
-        Ok(tonic::Response::new(project_list))
+        let mut response = tonic::Response::new(project_list);
+        let next_page_token = match MetadataValue::from_str(next_page_token.as_str()) {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(tonic::Status::internal("could not encode next page token"));
+            }
+        };
+        response
+            .metadata_mut()
+            .insert(NEXT_PAGE_TOKEN_KEY, next_page_token);
+
+        Ok(response)
     }
 
     async fn delete_project(
@@ -138,6 +268,10 @@ impl<T: Database> ProjectService for ProjectServer<T> {
         &self,
         request: tonic::Request<services::v1::CreateApiTokenRequest>,
     ) -> Result<Response<services::v1::CreateApiTokenResponse>, tonic::Status> {
+        if let Some(token_id) = util::metadata_str(request.metadata(), ROTATE_TOKEN_KEY)? {
+            return self.rotate_api_token(&request, token_id).await;
+        }
+
         let get_request = request.get_ref();
         self.auth_handler
             .authorize(
@@ -168,26 +302,55 @@ impl<T: Database> ProjectService for ProjectServer<T> {
         &self,
         request: tonic::Request<services::v1::GetApiTokenRequest>,
     ) -> Result<Response<services::v1::GetApiTokenResponse>, tonic::Status> {
-        let user_id = self.auth_handler.user_id(request.metadata()).await?;
+        let user_id = match util::metadata_str(request.metadata(), TOKEN_USER_KEY)? {
+            Some(token_user_id) => {
+                authorize_admin(self.auth_handler.as_ref(), request.metadata()).await?;
+                token_user_id.to_string()
+            }
+            None => self.auth_handler.user_id(request.metadata()).await?,
+        };
 
-        let proto_token = self
+        let api_tokens = self
             .handler
             .read_handler
             .read_user_api_token(user_id.as_str())
-            .await?
-            .into_iter()
-            .map(|x| x.to_proto())
-            .collect();
+            .await?;
+        let proto_token = api_tokens.iter().map(|x| x.to_proto()).collect();
 
         let reponse_token_list = services::v1::GetApiTokenResponse { token: proto_token };
 
-        return Ok(Response::new(reponse_token_list));
+        let mut response = Response::new(reponse_token_list);
+        for api_token in &api_tokens {
+            let last_used_at = match api_token.last_used_at {
+                Some(value) => value,
+                None => continue,
+            };
+
+            let value = format!("{}={}", api_token.id, last_used_at.to_rfc3339());
+            match MetadataValue::from_str(value.as_str()) {
+                Ok(value) => response.metadata_mut().append(LAST_USED_AT_KEY, value),
+                Err(e) => {
+                    log::error!("{:?}", e);
+                    return Err(tonic::Status::internal(
+                        "could not encode last use of api token",
+                    ));
+                }
+            };
+        }
+
+        return Ok(response);
     }
 
     async fn delete_api_token(
         &self,
         request: tonic::Request<services::v1::DeleteApiTokenRequest>,
     ) -> Result<Response<services::v1::DeleteApiTokenResponse>, tonic::Status> {
+        if let Some(token_user_id) = util::metadata_str(request.metadata(), TOKEN_USER_KEY)? {
+            return self
+                .revoke_user_tokens(request.metadata(), token_user_id)
+                .await;
+        }
+
         let inner_request = request.get_ref();
         self.auth_handler
             .authorize(
@@ -201,3 +364,167 @@ impl<T: Database> ProjectService for ProjectServer<T> {
         unimplemented!();
     }
 }
+
+impl<T: Database> ProjectServer<T> {
+    /// Enables or disables the read-only mode of the server, only admins can change it
+    async fn set_read_only_mode(
+        &self,
+        metadata: &MetadataMap,
+        value: &str,
+    ) -> Result<Response<services::v1::GetUserProjectsResponse>, tonic::Status> {
+        let user_id = authorize_admin(self.auth_handler.as_ref(), metadata).await?;
+
+        let enabled = match value.to_ascii_lowercase().as_str() {
+            "true" => true,
+            "false" => false,
+            _ => {
+                return Err(tonic::Status::invalid_argument(format!(
+                    "could not parse {}: {}, expected true or false",
+                    READ_ONLY_KEY, value
+                )))
+            }
+        };
+
+        log::info!("user {} changed the read-only mode", user_id);
+        self.read_only_mode.set_enabled(enabled);
+
+        let mut response = Response::new(services::v1::GetUserProjectsResponse {
+            projects: Vec::new(),
+        });
+        response.metadata_mut().insert(
+            READ_ONLY_KEY,
+            MetadataValue::from_static(if enabled { "true" } else { "false" }),
+        );
+
+        return Ok(response);
+    }
+
+    /// Revokes all api tokens of a user, e.g. of a compromised account, only admins can revoke the tokens of a user
+    async fn revoke_user_tokens(
+        &self,
+        metadata: &MetadataMap,
+        user_id: &str,
+    ) -> Result<Response<services::v1::DeleteApiTokenResponse>, tonic::Status> {
+        let admin_id = authorize_admin(self.auth_handler.as_ref(), metadata).await?;
+
+        let revoked = self
+            .handler
+            .delete_handler
+            .revoke_all_user_tokens(user_id)
+            .await?;
+        log::info!(
+            "user {} revoked {} api tokens of user {}",
+            admin_id,
+            revoked,
+            user_id
+        );
+
+        let mut response = Response::new(services::v1::DeleteApiTokenResponse::default());
+        let revoked = match MetadataValue::from_str(revoked.to_string().as_str()) {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(tonic::Status::internal(
+                    "could not encode number of revoked tokens",
+                ));
+            }
+        };
+        response.metadata_mut().insert(REVOKED_TOKENS_KEY, revoked);
+
+        return Ok(response);
+    }
+
+    /// Rotates the secret of an api token, only the owner of the token can rotate it
+    async fn rotate_api_token(
+        &self,
+        request: &tonic::Request<services::v1::CreateApiTokenRequest>,
+        token_id: &str,
+    ) -> Result<Response<services::v1::CreateApiTokenResponse>, tonic::Status> {
+        let user_id = self.auth_handler.user_id(request.metadata()).await?;
+
+        // Tokens of other users are reported as missing to not reveal their existence
+        let api_tokens = self
+            .handler
+            .read_handler
+            .read_user_api_token(user_id.as_str())
+            .await?;
+        if !api_tokens.iter().any(|api_token| api_token.id == token_id) {
+            return Err(tonic::Status::not_found(format!(
+                "could not find api token {}",
+                token_id
+            )));
+        }
+
+        let rotated_token = self
+            .handler
+            .create_handler
+            .rotate_api_token(token_id)
+            .await?;
+
+        let response = services::v1::CreateApiTokenResponse {
+            token: Some(rotated_token.to_proto()),
+        };
+
+        return Ok(Response::new(response));
+    }
+
+    /// Lists a page of the datasets of all projects of the requesting user
+    /// Only projects the user is a member of are read, no further authorization is required
+    async fn get_user_datasets(
+        &self,
+        request: &tonic::Request<services::v1::GetProjectDatasetsRequest>,
+    ) -> Result<tonic::Response<services::v1::GetProjectDatasetsResponse>, tonic::Status> {
+        let user_id = self.auth_handler.user_id(request.metadata()).await?;
+
+        let page_size = util::metadata_page_size(request.metadata(), PAGE_SIZE_KEY)?
+            .unwrap_or(DEFAULT_DATASET_PAGE_SIZE);
+        let page_token = util::metadata_str(request.metadata(), PAGE_TOKEN_KEY)?.unwrap_or("");
+        let sort = util::metadata_sort_order::<DatasetEntry>(request.metadata(), SORT_KEY)?;
+
+        let read_handler = &self.handler.read_handler;
+        let (proto_datasets, next_page_token) =
+            match util::metadata_list_view(request.metadata(), VIEW_KEY)? {
+                ListView::Basic => {
+                    let (datasets, next_page_token) = read_handler
+                        .read_user_datasets_page::<DatasetSummary>(
+                            user_id.as_str(),
+                            page_size,
+                            page_token,
+                            &sort,
+                        )
+                        .await?;
+                    let proto_datasets = datasets.iter().map(|x| x.to_proto_dataset()).collect();
+                    (proto_datasets, next_page_token)
+                }
+                ListView::Full => {
+                    let (datasets, next_page_token) = read_handler
+                        .read_user_datasets_page::<DatasetEntry>(
+                            user_id.as_str(),
+                            page_size,
+                            page_token,
+                            &sort,
+                        )
+                        .await?;
+                    let proto_datasets = datasets.iter().map(|x| x.to_proto_dataset()).collect();
+                    (proto_datasets, next_page_token)
+                }
+            };
+
+        let mut response = Response::new(services::v1::GetProjectDatasetsResponse {
+            dataset: proto_datasets,
+            ..Default::default()
+        });
+        let next_page_token = match MetadataValue::from_str(next_page_token.as_str()) {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("{:?}", e);
+                return Err(tonic::Status::internal("could not encode next page token"));
+            }
+        };
+        response
+            .metadata_mut()
+            .insert(NEXT_PAGE_TOKEN_KEY, next_page_token);
+
+        return Ok(response);
+    }
+}
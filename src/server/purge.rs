@@ -0,0 +1,57 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{info, warn};
+
+use crate::{database::database::Database, handler::common::HandlerWrapper, SETTINGS};
+
+use super::timeout::duration_from_config;
+
+const DEFAULT_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Interval of the purge and retention period of soft-deleted entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PurgeConfig {
+    pub interval: Duration,
+    pub retention: Duration,
+}
+
+/// Reads the purge of soft-deleted entries from Server.Purge
+/// The purge is only run if Enabled is set, IntervalMs defaults to one hour and RetentionMs to seven days
+pub fn purge_from_config() -> Option<PurgeConfig> {
+    let enabled = SETTINGS
+        .read()
+        .unwrap()
+        .get_bool("Server.Purge.Enabled")
+        .unwrap_or(false);
+    if !enabled {
+        return None;
+    }
+
+    return Some(PurgeConfig {
+        interval: duration_from_config("Server.Purge.IntervalMs")
+            .filter(|interval| interval.as_millis() > 0)
+            .unwrap_or(DEFAULT_PURGE_INTERVAL),
+        retention: duration_from_config("Server.Purge.RetentionMs").unwrap_or(DEFAULT_RETENTION),
+    });
+}
+
+/// Periodically hard-deletes the entries that have been soft-deleted longer than the retention period ago
+pub async fn purge_expired_entries<T: Database>(
+    handler_wrapper: Arc<HandlerWrapper<T>>,
+    config: PurgeConfig,
+) {
+    loop {
+        match handler_wrapper
+            .delete_handler
+            .purge_expired(config.retention)
+            .await
+        {
+            Ok(0) => {}
+            Ok(purged) => info!("purged {} deleted entries", purged),
+            Err(e) => warn!("could not purge deleted entries: {:?}", e),
+        }
+
+        tokio::time::sleep(config.interval).await;
+    }
+}
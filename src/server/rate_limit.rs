@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use http::{Request, Response};
+use log::info;
+use tonic::{body::BoxBody, transport::server::TcpConnectInfo};
+use tower::{Layer, Service};
+
+use super::identity::Identity;
+use crate::SETTINGS;
+
+const UNKNOWN_CLIENT_KEY: &str = "unknown";
+const DEFAULT_EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Limits the number of requests per key within a fixed time window
+pub struct RateLimiter {
+    max_requests: u64,
+    window: Duration,
+    counters: Mutex<HashMap<String, (Instant, u64)>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u64, window: Duration) -> Self {
+        return RateLimiter {
+            max_requests: max_requests,
+            window: window,
+            counters: Mutex::new(HashMap::new()),
+        };
+    }
+
+    /// Reads the limit from the configuration file
+    /// The limit is read from <config_key>.Requests and <config_key>.WindowSecs, if no limit is configured None is returned
+    pub fn new_from_config(config_key: &str) -> Option<Self> {
+        let max_requests = SETTINGS
+            .read()
+            .unwrap()
+            .get_int(format!("{}.Requests", config_key).as_str())
+            .ok()?;
+        let window_secs = SETTINGS
+            .read()
+            .unwrap()
+            .get_int(format!("{}.WindowSecs", config_key).as_str())
+            .unwrap_or(60);
+
+        return Some(RateLimiter::new(
+            max_requests.max(0) as u64,
+            Duration::from_secs(window_secs.max(1) as u64),
+        ));
+    }
+
+    /// Counts a request for the given key and fails with resource_exhausted if the limit is exceeded
+    pub fn check(&self, key: &str) -> Result<(), tonic::Status> {
+        let now = Instant::now();
+        let mut counters = self.counters.lock().unwrap();
+
+        let (window_start, count) = counters.entry(key.to_string()).or_insert((now, 0));
+        if now.duration_since(*window_start) >= self.window {
+            *window_start = now;
+            *count = 0;
+        }
+
+        if *count >= self.max_requests {
+            return Err(tonic::Status::resource_exhausted(
+                "rate limit exceeded, please try again later",
+            ));
+        }
+
+        *count += 1;
+
+        return Ok(());
+    }
+
+    /// Drops the counters of expired windows, returns the number of dropped counters
+    pub fn evict_expired(&self, now: Instant) -> usize {
+        let mut counters = self.counters.lock().unwrap();
+        let before = counters.len();
+        counters.retain(|_, (window_start, _)| now.duration_since(*window_start) < self.window);
+
+        return before - counters.len();
+    }
+}
+
+/// Applies separate rate limits to anonymous and authenticated requests
+/// Authenticated requests are limited per user id, so that all tokens of a user share one limit. Anonymous requests
+/// and requests whose credentials could not be resolved are limited per client ip.
+/// Requests are not limited if the corresponding limit is not configured
+pub struct RequestRateLimiter {
+    anonymous: Option<RateLimiter>,
+    authenticated: Option<RateLimiter>,
+}
+
+impl RequestRateLimiter {
+    /// Reads the limits from RateLimit.Anonymous and RateLimit.Authenticated in the configuration file
+    pub fn new() -> Self {
+        return RequestRateLimiter {
+            anonymous: RateLimiter::new_from_config("RateLimit.Anonymous"),
+            authenticated: RateLimiter::new_from_config("RateLimit.Authenticated"),
+        };
+    }
+
+    pub fn check(
+        &self,
+        identity: &Identity,
+        remote_addr: Option<SocketAddr>,
+    ) -> Result<(), tonic::Status> {
+        match &identity.user_id {
+            Some(user_id) => match &self.authenticated {
+                Some(limiter) => limiter.check(user_id.as_str()),
+                None => Ok(()),
+            },
+            None => match &self.anonymous {
+                Some(limiter) => {
+                    let client_ip = match remote_addr {
+                        Some(addr) => addr.ip().to_string(),
+                        None => UNKNOWN_CLIENT_KEY.to_string(),
+                    };
+                    limiter.check(client_ip.as_str())
+                }
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// Drops the counters of expired windows of both limits, returns the number of dropped counters
+    pub fn evict_expired(&self, now: Instant) -> usize {
+        return self
+            .anonymous
+            .iter()
+            .chain(self.authenticated.iter())
+            .map(|limiter| limiter.evict_expired(now))
+            .sum();
+    }
+}
+
+/// Periodically drops expired counters, so that the map does not grow with every client that ever connected
+/// Requests only reset the counter of their own key and do not need to scan all counters
+pub async fn evict_expired_rate_limits(rate_limiter: Arc<RequestRateLimiter>) {
+    loop {
+        tokio::time::sleep(DEFAULT_EVICTION_INTERVAL).await;
+
+        let evicted = rate_limiter.evict_expired(Instant::now());
+        if evicted > 0 {
+            info!("evicted {} expired rate limit counters", evicted);
+        }
+    }
+}
+
+/// Rejects requests that exceed their rate limit with resource_exhausted
+/// Requires the IdentityLayer in front of it, requests without a resolved identity are limited per client ip
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    rate_limiter: Arc<RequestRateLimiter>,
+}
+
+impl RateLimitLayer {
+    pub fn new(rate_limiter: Arc<RequestRateLimiter>) -> Self {
+        return RateLimitLayer {
+            rate_limiter: rate_limiter,
+        };
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return RateLimitService {
+            inner: inner,
+            rate_limiter: self.rate_limiter.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    rate_limiter: Arc<RequestRateLimiter>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for RateLimitService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let remote_addr = request
+            .extensions()
+            .get::<TcpConnectInfo>()
+            .and_then(|connect_info| connect_info.remote_addr());
+        if let Err(status) = self
+            .rate_limiter
+            .check(&Identity::from_request(&request), remote_addr)
+        {
+            return Box::pin(async move { Ok(status.to_http()) });
+        }
+
+        return Box::pin(self.inner.call(request));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        time::{Duration, Instant},
+    };
+
+    use crate::server::identity::Identity;
+
+    use super::{RateLimiter, RequestRateLimiter};
+
+    #[test]
+    fn anonymous_rate_limit() {
+        let rate_limiter = RequestRateLimiter {
+            anonymous: Some(RateLimiter::new(3, Duration::from_secs(60))),
+            authenticated: Some(RateLimiter::new(10, Duration::from_secs(60))),
+        };
+
+        let client_addr: SocketAddr = "10.0.0.1:50000".parse().unwrap();
+        let other_client_addr: SocketAddr = "10.0.0.2:50000".parse().unwrap();
+        let anonymous = Identity::default();
+
+        for _ in 0..3 {
+            rate_limiter.check(&anonymous, Some(client_addr)).unwrap();
+        }
+
+        let error = rate_limiter
+            .check(&anonymous, Some(client_addr))
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::ResourceExhausted);
+
+        rate_limiter
+            .check(&anonymous, Some(other_client_addr))
+            .unwrap();
+
+        let authenticated = Identity {
+            user_id: Some("testuser".to_string()),
+        };
+        rate_limiter
+            .check(&authenticated, Some(client_addr))
+            .unwrap();
+    }
+
+    #[test]
+    fn authenticated_rate_limit() {
+        let rate_limiter = RequestRateLimiter {
+            anonymous: None,
+            authenticated: Some(RateLimiter::new(2, Duration::from_secs(60))),
+        };
+
+        // Requests with different tokens of the same user share one limit, independent of the client address
+        let user = Identity {
+            user_id: Some("testuser".to_string()),
+        };
+        rate_limiter
+            .check(&user, Some("10.0.0.1:50000".parse().unwrap()))
+            .unwrap();
+        rate_limiter
+            .check(&user, Some("10.0.0.2:50000".parse().unwrap()))
+            .unwrap();
+        let error = rate_limiter.check(&user, None).unwrap_err();
+        assert_eq!(error.code(), tonic::Code::ResourceExhausted);
+
+        let other_user = Identity {
+            user_id: Some("otheruser".to_string()),
+        };
+        rate_limiter.check(&other_user, None).unwrap();
+    }
+
+    #[test]
+    fn evict_expired_counters() {
+        let rate_limiter = RateLimiter::new(1, Duration::from_secs(60));
+        rate_limiter.check("client").unwrap();
+        assert!(rate_limiter.check("client").is_err());
+
+        assert_eq!(rate_limiter.evict_expired(Instant::now()), 0);
+        let expired = Instant::now() + Duration::from_secs(60);
+        assert_eq!(rate_limiter.evict_expired(expired), 1);
+        rate_limiter.check("client").unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use config::{Config, File};
+use http::{Request, Response};
+use log::{info, warn};
+use tokio::signal::unix::{signal, SignalKind};
+use tonic::body::BoxBody;
+use tower::{Layer, Service};
+
+use super::load_api::SINGLE_USE_KEY;
+use crate::SETTINGS;
+
+/// Methods that do not modify any state although their name does not start with Get
+const READ_METHODS: [&str; 1] = ["CreateDownloadLink"];
+
+/// Runtime switch for the read-only mode
+/// The initial state is read from Server.ReadOnly, clones share the same state
+#[derive(Clone, Default)]
+pub struct ReadOnlyMode {
+    enabled: Arc<AtomicBool>,
+}
+
+impl ReadOnlyMode {
+    pub fn new_from_config() -> Self {
+        let enabled = SETTINGS
+            .read()
+            .unwrap()
+            .get_bool("Server.ReadOnly")
+            .unwrap_or(false);
+
+        let read_only_mode = ReadOnlyMode::default();
+        read_only_mode.set_enabled(enabled);
+
+        return read_only_mode;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        return self.enabled.load(Ordering::SeqCst);
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        info!("read-only mode enabled: {}", enabled);
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+}
+
+/// Checks if the gRPC method with the given request path modifies any state
+/// Infrastructure services like the health service are never considered mutating
+pub fn is_mutating_method(path: &str) -> bool {
+    let (service, method) = match path.trim_start_matches('/').split_once('/') {
+        Some(value) => value,
+        None => return false,
+    };
+
+    if service.starts_with("grpc.") {
+        return false;
+    }
+
+    return !(method.starts_with("Get") || READ_METHODS.contains(&method));
+}
+
+/// Checks if the request modifies any state, single-use download links store a download token
+pub fn is_mutating_request<B>(request: &Request<B>) -> bool {
+    let path = request.uri().path();
+    if path.ends_with("/CreateDownloadLink") {
+        return match request.headers().get(SINGLE_USE_KEY) {
+            Some(value) => value.as_bytes().eq_ignore_ascii_case(b"true"),
+            None => false,
+        };
+    }
+
+    return is_mutating_method(path);
+}
+
+/// Reloads Server.ReadOnly from the config file whenever the process receives SIGHUP
+/// Only the read-only mode is reloaded, all other settings keep the values they had at startup
+pub async fn reload_read_only_mode_on_hangup(mode: ReadOnlyMode, config_file: String) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!(
+                "could not listen for SIGHUP, the read-only mode can not be reloaded: {:?}",
+                e
+            );
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        let mut config = Config::default();
+        let enabled = match config
+            .merge(File::with_name(config_file.as_str()))
+            .and_then(|config| config.get_bool("Server.ReadOnly"))
+        {
+            Ok(value) => value,
+            Err(config::ConfigError::NotFound(_)) => false,
+            Err(e) => {
+                warn!(
+                    "could not reload Server.ReadOnly from {}: {:?}",
+                    config_file, e
+                );
+                continue;
+            }
+        };
+
+        if let Err(e) = SETTINGS.write().unwrap().set("Server.ReadOnly", enabled) {
+            warn!("could not store Server.ReadOnly: {:?}", e);
+        }
+        mode.set_enabled(enabled);
+    }
+}
+
+/// Rejects all mutating requests with failed_precondition while the read-only mode is enabled
+#[derive(Clone)]
+pub struct ReadOnlyLayer {
+    mode: ReadOnlyMode,
+}
+
+impl ReadOnlyLayer {
+    pub fn new(mode: ReadOnlyMode) -> Self {
+        return ReadOnlyLayer { mode: mode };
+    }
+}
+
+impl<S> Layer<S> for ReadOnlyLayer {
+    type Service = ReadOnlyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return ReadOnlyService {
+            inner: inner,
+            mode: self.mode.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct ReadOnlyService<S> {
+    inner: S,
+    mode: ReadOnlyMode,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for ReadOnlyService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        if self.mode.is_enabled() && is_mutating_request(&request) {
+            let status = tonic::Status::failed_precondition("server in read-only mode");
+            return Box::pin(async move { Ok(status.to_http()) });
+        }
+
+        return Box::pin(self.inner.call(request));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use http::{Request, Response};
+    use tonic::body::BoxBody;
+    use tower::{Layer, Service, ServiceExt};
+
+    use super::{is_mutating_method, is_mutating_request, ReadOnlyLayer, ReadOnlyMode};
+
+    const CREATE_DATASET_PATH: &str = "/services.v1.DatasetService/CreateDataset";
+    const GET_DATASET_PATH: &str = "/services.v1.DatasetService/GetDataset";
+
+    fn request(path: &str) -> Request<()> {
+        return Request::builder().uri(path).body(()).unwrap();
+    }
+
+    fn response_code(response: Response<BoxBody>) -> tonic::Code {
+        return tonic::Status::from_header_map(response.headers())
+            .unwrap()
+            .code();
+    }
+
+    #[test]
+    fn mutating_methods() {
+        assert!(is_mutating_method(CREATE_DATASET_PATH));
+        assert!(is_mutating_method(
+            "/services.v1.DatasetService/ReleaseDatasetVersion"
+        ));
+        assert!(!is_mutating_method(GET_DATASET_PATH));
+        assert!(!is_mutating_method(
+            "/services.v1.ObjectLoadService/CreateDownloadLink"
+        ));
+        assert!(!is_mutating_method("/grpc.health.v1.Health/Check"));
+
+        let download_link_path = "/services.v1.ObjectLoadService/CreateDownloadLink";
+        assert!(!is_mutating_request(&request(download_link_path)));
+        let single_use_request = Request::builder()
+            .uri(download_link_path)
+            .header("single-use", "true")
+            .body(())
+            .unwrap();
+        assert!(is_mutating_request(&single_use_request));
+    }
+
+    #[tokio::test]
+    async fn read_only_mode() {
+        let ok_service = tower::service_fn(|_request: Request<()>| async move {
+            let status = tonic::Status::new(tonic::Code::Ok, "");
+            return Ok::<Response<BoxBody>, Infallible>(status.to_http());
+        });
+        let mode = ReadOnlyMode::default();
+        let mut service = ReadOnlyLayer::new(mode.clone()).layer(ok_service);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(CREATE_DATASET_PATH))
+            .await
+            .unwrap();
+        assert_eq!(response_code(response), tonic::Code::Ok);
+
+        mode.set_enabled(true);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(CREATE_DATASET_PATH))
+            .await
+            .unwrap();
+        assert_eq!(response_code(response), tonic::Code::FailedPrecondition);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(GET_DATASET_PATH))
+            .await
+            .unwrap();
+        assert_eq!(response_code(response), tonic::Code::Ok);
+
+        mode.set_enabled(false);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(CREATE_DATASET_PATH))
+            .await
+            .unwrap();
+        assert_eq!(response_code(response), tonic::Code::Ok);
+    }
+}
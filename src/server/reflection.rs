@@ -0,0 +1,151 @@
+use log::{error, info, warn};
+use prost::Message;
+use tonic_reflection::server::{ServerReflection, ServerReflectionServer};
+
+use crate::SETTINGS;
+
+/// Checks if gRPC server reflection is enabled via Server.EnableReflection
+/// Reflection is enabled by default and should be disabled in production deployments
+pub fn reflection_enabled() -> bool {
+    return SETTINGS
+        .read()
+        .unwrap()
+        .get_bool("Server.EnableReflection")
+        .unwrap_or(true);
+}
+
+/// Reads the encoded file descriptor set of the API from the path configured in Server.ReflectionDescriptorSet
+/// The API crate does not export the descriptors of its services, the set has to be generated from the API protos
+/// e.g. with protoc --include_imports --descriptor_set_out
+pub fn read_descriptor_set() -> Option<Vec<u8>> {
+    let path = SETTINGS
+        .read()
+        .unwrap()
+        .get_str("Server.ReflectionDescriptorSet")
+        .ok()?;
+
+    match std::fs::read(path.as_str()) {
+        Ok(value) => return Some(value),
+        Err(e) => {
+            error!("could not read descriptor set {}: {:?}", path, e);
+            return None;
+        }
+    }
+}
+
+/// Returns the fully qualified names of all services of an encoded file descriptor set
+pub fn service_names(descriptor_set: &[u8]) -> Result<Vec<String>, tonic::Status> {
+    let descriptor_set = match prost_types::FileDescriptorSet::decode(descriptor_set) {
+        Ok(value) => value,
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(tonic::Status::invalid_argument(
+                "could not decode file descriptor set",
+            ));
+        }
+    };
+
+    let mut names = Vec::new();
+    for file in descriptor_set.file {
+        for service in file.service {
+            let name = match file.package.as_deref() {
+                Some(package) if !package.is_empty() => format!("{}.{}", package, service.name()),
+                _ => service.name().to_string(),
+            };
+            names.push(name);
+        }
+    }
+
+    return Ok(names);
+}
+
+/// Builds the reflection service for the given encoded file descriptor set
+pub fn build_reflection_service(
+    descriptor_set: &'static [u8],
+) -> Result<ServerReflectionServer<impl ServerReflection>, tonic::Status> {
+    for name in service_names(descriptor_set)? {
+        info!("registering {} for server reflection", name);
+    }
+
+    match tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(descriptor_set)
+        .build()
+    {
+        Ok(value) => return Ok(value),
+        Err(e) => {
+            error!("{:?}", e);
+            return Err(tonic::Status::internal(
+                "could not build server reflection service",
+            ));
+        }
+    }
+}
+
+/// Builds the reflection service if it is enabled and a descriptor set is configured
+pub fn reflection_service_from_config() -> Option<ServerReflectionServer<impl ServerReflection>> {
+    if !reflection_enabled() {
+        return None;
+    }
+
+    let descriptor_set = match read_descriptor_set() {
+        Some(value) => value,
+        None => {
+            warn!("server reflection is enabled but no descriptor set is configured in Server.ReflectionDescriptorSet");
+            return None;
+        }
+    };
+
+    // The descriptor set is read once at startup and has to outlive the server
+    let descriptor_set: &'static [u8] = Box::leak(descriptor_set.into_boxed_slice());
+
+    return build_reflection_service(descriptor_set).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use prost::Message;
+    use prost_types::{FileDescriptorProto, FileDescriptorSet, ServiceDescriptorProto};
+
+    use super::{build_reflection_service, service_names};
+
+    const SERVICES: [&str; 4] = [
+        "ProjectService",
+        "DatasetService",
+        "DatasetObjectsService",
+        "ObjectLoadService",
+    ];
+
+    #[test]
+    fn reflection_services() {
+        let descriptor_set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("api/services/v1/services.proto".to_string()),
+                package: Some("services.v1".to_string()),
+                service: SERVICES
+                    .iter()
+                    .map(|name| ServiceDescriptorProto {
+                        name: Some(name.to_string()),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+        };
+
+        let mut encoded = Vec::new();
+        descriptor_set.encode(&mut encoded).unwrap();
+        let encoded: &'static [u8] = Box::leak(encoded.into_boxed_slice());
+
+        let names = service_names(encoded).unwrap();
+        let expected_names: Vec<String> = SERVICES
+            .iter()
+            .map(|name| format!("services.v1.{}", name))
+            .collect();
+        assert_eq!(names, expected_names);
+
+        build_reflection_service(encoded).unwrap();
+
+        let error = service_names(&[0xff, 0xff]).unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{HeaderMap, HeaderValue, Request, Response};
+use log::{debug, warn};
+use tower::{Layer, Service};
+
+/// Metadata key that identifies a request across client, server logs and the returned response
+pub const REQUEST_ID_KEY: &str = "x-request-id";
+const GRPC_STATUS_KEY: &str = "grpc-status";
+const GRPC_MESSAGE_KEY: &str = "grpc-message";
+
+tokio::task_local! {
+    /// Id of the request that is currently handled by the task
+    static CURRENT_REQUEST_ID: String;
+}
+
+/// Reads the request id from the given headers or metadata, returns None if it is absent or not a valid header value
+pub fn request_id(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(REQUEST_ID_KEY)?.to_str().ok()?;
+    if value.is_empty() {
+        return None;
+    }
+
+    return Some(value.to_string());
+}
+
+/// Returns the id of the request that is handled by the current task, None outside of requests
+pub fn current_request_id() -> Option<String> {
+    return CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok();
+}
+
+/// Generates a request id for every incoming request that does not provide one
+/// The id is added to the request metadata, so that the services and interceptors can read it,
+/// and returned in the response metadata, including the metadata of returned error statuses
+/// While the request is handled the id is available via current_request_id, e.g. for structured logs
+#[derive(Clone, Default)]
+pub struct RequestIdLayer {}
+
+impl<S> Layer<S> for RequestIdLayer {
+    type Service = RequestIdService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return RequestIdService { inner: inner };
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestIdService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, mut request: Request<ReqBody>) -> Self::Future {
+        let id = match request_id(request.headers()) {
+            Some(id) => id,
+            None => uuid::Uuid::new_v4().to_string(),
+        };
+        // The id is either a generated uuid or was read from a valid header value
+        let header_value = HeaderValue::from_str(id.as_str()).unwrap();
+        request
+            .headers_mut()
+            .insert(REQUEST_ID_KEY, header_value.clone());
+
+        debug!("[{}] {}", id, request.uri().path());
+
+        let response_future = CURRENT_REQUEST_ID.scope(id.clone(), self.inner.call(request));
+
+        return Box::pin(async move {
+            let mut response = response_future.await?;
+            let headers = response.headers_mut();
+            headers.insert(REQUEST_ID_KEY, header_value);
+
+            // Errors are returned as trailers only responses, the status is therefore part of the headers
+            if let Some(status) = headers.get(GRPC_STATUS_KEY) {
+                if status != "0" {
+                    warn!(
+                        "[{}] request failed with status {:?}: {:?}",
+                        id,
+                        status,
+                        headers.get(GRPC_MESSAGE_KEY)
+                    );
+                }
+            }
+
+            return Ok(response);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use http::{Request, Response};
+    use tower::{Layer, Service, ServiceExt};
+
+    use super::{current_request_id, request_id, RequestIdLayer, REQUEST_ID_KEY};
+
+    #[tokio::test]
+    async fn request_id_in_error_metadata() {
+        let failing_service = tower::service_fn(|request: Request<()>| async move {
+            // The service has to see the generated id as part of the request metadata
+            assert!(request_id(request.headers()).is_some());
+            assert_eq!(current_request_id(), request_id(request.headers()));
+
+            let status = tonic::Status::internal("internal error");
+            return Ok::<Response<()>, Infallible>(status.to_http().map(|_| ()));
+        });
+        let mut service = RequestIdLayer {}.layer(failing_service);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(()))
+            .await
+            .unwrap();
+        let status = tonic::Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), tonic::Code::Internal);
+        let generated_id = status.metadata().get(REQUEST_ID_KEY).unwrap();
+        assert!(!generated_id.is_empty());
+
+        let mut request = Request::new(());
+        request
+            .headers_mut()
+            .insert(REQUEST_ID_KEY, "client-request-id".parse().unwrap());
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        let status = tonic::Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(
+            status
+                .metadata()
+                .get(REQUEST_ID_KEY)
+                .unwrap()
+                .to_str()
+                .unwrap(),
+            "client-request-id"
+        );
+    }
+}
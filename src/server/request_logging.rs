@@ -0,0 +1,307 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use http::{HeaderMap, Request, Response};
+use log::{log, log_enabled, Level, LevelFilter};
+use tower::{Layer, Service};
+
+use super::{identity::Identity, request_id::REQUEST_ID_KEY};
+use crate::{
+    auth::{
+        project_authorization_handler::{API_TOKEN_ENTRY_KEY, USER_TOKEN_ENTRY_KEY},
+        service_account_handler::SERVICE_KEY_ENTRY_KEY,
+    },
+    SETTINGS,
+};
+
+const REDACTED_VALUE: &str = "<redacted>";
+const ANONYMOUS_USER_ID: &str = "<anonymous>";
+const GRPC_STATUS_KEY: &str = "grpc-status";
+
+/// Metadata entries that carry credentials, their values are never logged
+const REDACTED_KEYS: [&str; 4] = [
+    API_TOKEN_ENTRY_KEY,
+    USER_TOKEN_ENTRY_KEY,
+    SERVICE_KEY_ENTRY_KEY,
+    "authorization",
+];
+
+/// Receives the formatted log lines, writes to the log crate unless replaced
+pub type RequestLogWriter = Arc<dyn Fn(Level, &str) + Send + Sync>;
+
+/// Formats the metadata of a request, the values of credential entries are replaced with <redacted>
+/// Binary entries are skipped
+pub fn redacted_metadata(headers: &HeaderMap) -> String {
+    let mut entries = Vec::new();
+    for (key, value) in headers.iter() {
+        if key.as_str().ends_with("-bin") {
+            continue;
+        }
+
+        let value = if REDACTED_KEYS
+            .iter()
+            .any(|redacted_key| key.as_str().eq_ignore_ascii_case(redacted_key))
+        {
+            REDACTED_VALUE
+        } else {
+            value.to_str().unwrap_or(REDACTED_VALUE)
+        };
+        entries.push(format!("{}={}", key, value));
+    }
+
+    return entries.join(", ");
+}
+
+/// Logs every request with its method, request id, user id, metadata and resulting status
+/// The resource ids are part of the request messages, they are logged through the request id in the logs of the
+/// services. Credentials in the metadata are redacted, the user id is the identity resolved by the IdentityLayer.
+#[derive(Clone)]
+pub struct RequestLoggingLayer {
+    level: Option<Level>,
+    writer: RequestLogWriter,
+}
+
+impl RequestLoggingLayer {
+    /// Creates a layer that logs with the given level, requests are not logged if the level is None
+    pub fn new(level: Option<Level>) -> Self {
+        return RequestLoggingLayer {
+            level: level,
+            writer: Arc::new(|level, line| log!(level, "{}", line)),
+        };
+    }
+
+    /// Reads the level from Server.RequestLogging.Level, one of off, error, warn, info, debug or trace
+    /// Requests are logged at debug if no level is configured
+    pub fn new_from_config() -> Result<Self, tonic::Status> {
+        let level = match SETTINGS
+            .read()
+            .unwrap()
+            .get_str("Server.RequestLogging.Level")
+        {
+            Ok(value) => match LevelFilter::from_str(value.as_str()) {
+                Ok(level_filter) => level_filter.to_level(),
+                Err(_) => {
+                    return Err(tonic::Status::failed_precondition(format!(
+                        "could not parse Server.RequestLogging.Level: {}, expected one of off, error, warn, info, debug or trace",
+                        value
+                    )))
+                }
+            },
+            Err(_) => Some(Level::Debug),
+        };
+
+        return Ok(RequestLoggingLayer::new(level));
+    }
+
+    /// Replaces the writer of the log lines, e.g. to capture the logged requests
+    pub fn with_writer(mut self, writer: RequestLogWriter) -> Self {
+        self.writer = writer;
+        return self;
+    }
+}
+
+impl<S> Layer<S> for RequestLoggingLayer {
+    type Service = RequestLoggingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return RequestLoggingService {
+            inner: inner,
+            level: self.level,
+            writer: self.writer.clone(),
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestLoggingService<S> {
+    inner: S,
+    level: Option<Level>,
+    writer: RequestLogWriter,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestLoggingService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: std::fmt::Debug,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let level = match self.level {
+            Some(level) if log_enabled!(level) => level,
+            _ => return Box::pin(self.inner.call(request)),
+        };
+
+        let started = Instant::now();
+        let method = request.uri().path().to_string();
+        let headers = request.headers().clone();
+        let user_id = Identity::from_request(&request)
+            .user_id
+            .unwrap_or(ANONYMOUS_USER_ID.to_string());
+        let writer = self.writer.clone();
+
+        let response_future = self.inner.call(request);
+
+        return Box::pin(async move {
+            let request_id = headers
+                .get(REQUEST_ID_KEY)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("")
+                .to_string();
+            let redacted_metadata = redacted_metadata(&headers);
+
+            let response = match response_future.await {
+                Ok(value) => value,
+                Err(e) => {
+                    let line = format!(
+                        "[{}] {} user: {} error: {:?} duration: {}ms metadata: {}",
+                        request_id,
+                        method,
+                        user_id,
+                        e,
+                        started.elapsed().as_millis(),
+                        redacted_metadata
+                    );
+                    writer(level, line.as_str());
+
+                    return Err(e);
+                }
+            };
+
+            // Errors are returned as trailers only responses, successful responses carry their status in the trailers
+            let status = response
+                .headers()
+                .get(GRPC_STATUS_KEY)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("0");
+
+            let line = format!(
+                "[{}] {} user: {} status: {} duration: {}ms metadata: {}",
+                request_id,
+                method,
+                user_id,
+                status,
+                started.elapsed().as_millis(),
+                redacted_metadata
+            );
+            writer(level, line.as_str());
+
+            return Ok(response);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
+
+    use http::{Request, Response};
+    use log::Level;
+    use tower::{Layer, Service, ServiceExt};
+
+    use crate::{
+        auth::{
+            project_authorization_handler::{API_TOKEN_ENTRY_KEY, USER_TOKEN_ENTRY_KEY},
+            test_authenticator::TestAuthenticator,
+        },
+        server::identity::IdentityLayer,
+        test_util::init::test_init,
+    };
+
+    use super::RequestLoggingLayer;
+
+    #[tokio::test]
+    async fn tokens_are_redacted() {
+        test_init();
+
+        let captured_logs = Arc::new(Mutex::new(Vec::new()));
+        let captured_logs_writer = captured_logs.clone();
+
+        let failing_service = tower::service_fn(|_request: Request<()>| async move {
+            let status = tonic::Status::not_found("object group not found");
+            return Ok::<Response<()>, Infallible>(status.to_http().map(|_| ()));
+        });
+        // Errors are enabled in the logger of the tests, the lines are captured instead of written to the logger
+        let logging_service = RequestLoggingLayer::new(Some(Level::Error))
+            .with_writer(Arc::new(move |_level, line| {
+                captured_logs_writer.lock().unwrap().push(line.to_string())
+            }))
+            .layer(failing_service);
+        let mut service = IdentityLayer::new(Arc::new(TestAuthenticator {})).layer(logging_service);
+
+        let mut request = Request::new(());
+        *request.uri_mut() = "/services.v1.DatasetService/GetDataset".parse().unwrap();
+        request
+            .headers_mut()
+            .insert(API_TOKEN_ENTRY_KEY, "secret-api-token".parse().unwrap());
+        request
+            .headers_mut()
+            .insert(USER_TOKEN_ENTRY_KEY, "secret-access-token".parse().unwrap());
+        request
+            .headers_mut()
+            .insert("x-request-id", "logged-request".parse().unwrap());
+        service.ready().await.unwrap().call(request).await.unwrap();
+
+        let captured_logs = captured_logs.lock().unwrap();
+        assert_eq!(captured_logs.len(), 1);
+        let line = captured_logs[0].as_str();
+        assert!(line.contains("/services.v1.DatasetService/GetDataset"));
+        assert!(line.contains("logged-request"));
+        assert!(line.contains("user: testuser"));
+        assert!(line.contains("status: 5"));
+        assert!(line.contains("<redacted>"));
+        assert!(!line.contains("secret-api-token"));
+        assert!(!line.contains("secret-access-token"));
+    }
+
+    #[tokio::test]
+    async fn service_errors_are_logged() {
+        test_init();
+
+        let captured_logs = Arc::new(Mutex::new(Vec::new()));
+        let captured_logs_writer = captured_logs.clone();
+
+        let broken_service = tower::service_fn(|_request: Request<()>| async move {
+            return Err::<Response<()>, String>("connection reset".to_string());
+        });
+        let mut service = RequestLoggingLayer::new(Some(Level::Error))
+            .with_writer(Arc::new(move |_level, line| {
+                captured_logs_writer.lock().unwrap().push(line.to_string())
+            }))
+            .layer(broken_service);
+
+        let mut request = Request::new(());
+        *request.uri_mut() = "/services.v1.DatasetService/GetDataset".parse().unwrap();
+        let error = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap_err();
+        assert_eq!(error, "connection reset");
+
+        let captured_logs = captured_logs.lock().unwrap();
+        assert_eq!(captured_logs.len(), 1);
+        let line = captured_logs[0].as_str();
+        assert!(line.contains("/services.v1.DatasetService/GetDataset"));
+        assert!(line.contains("user: <anonymous>"));
+        assert!(line.contains("error: \"connection reset\""));
+    }
+}
@@ -0,0 +1,149 @@
+use crate::{
+    auth::{
+        project_authorization_handler::{API_TOKEN_ENTRY_KEY, USER_TOKEN_ENTRY_KEY},
+        service_account_handler::SERVICE_KEY_ENTRY_KEY,
+    },
+    SETTINGS,
+};
+
+/// Metadata keys that carry a credential, requests have to provide at least one of them
+const TOKEN_KEYS: [&str; 3] = [
+    API_TOKEN_ENTRY_KEY,
+    USER_TOKEN_ENTRY_KEY,
+    SERVICE_KEY_ENTRY_KEY,
+];
+
+/// Rejects requests without any credential with unauthenticated before they reach the services
+/// The services authorize every request, requests without credentials would only fail after the requested resources
+/// have been resolved. Public services like the health service are not intercepted.
+#[derive(Clone, Copy, Debug)]
+pub struct TokenRequirement {
+    enabled: bool,
+}
+
+impl TokenRequirement {
+    pub fn new(enabled: bool) -> Self {
+        return TokenRequirement { enabled: enabled };
+    }
+
+    /// Reads Authentication.RequireToken, tokens are required by default unless the debug authentication is used,
+    /// which authorizes requests without tokens
+    pub fn new_from_config(auth_type: &str) -> Self {
+        let enabled = SETTINGS
+            .read()
+            .unwrap()
+            .get_bool("Authentication.RequireToken")
+            .unwrap_or(auth_type != "debug");
+
+        return TokenRequirement::new(enabled);
+    }
+
+    /// Checks the metadata of an incoming request, can be used as a tonic interceptor
+    pub fn intercept(
+        &self,
+        request: tonic::Request<()>,
+    ) -> Result<tonic::Request<()>, tonic::Status> {
+        if !self.enabled {
+            return Ok(request);
+        }
+
+        let metadata = request.metadata();
+        if !TOKEN_KEYS.iter().any(|key| metadata.contains_key(*key)) {
+            return Err(tonic::Status::unauthenticated(format!(
+                "could not find authentication token, please provide a token in metadata either with {} or {}",
+                USER_TOKEN_ENTRY_KEY, API_TOKEN_ENTRY_KEY
+            )));
+        }
+
+        return Ok(request);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use http::Request;
+    use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::dataset_service_server::DatasetServiceServer;
+    use tonic::transport::Body;
+    use tower::{Service, ServiceExt};
+
+    use crate::{
+        auth::{
+            project_authorization_handler::API_TOKEN_ENTRY_KEY,
+            test_authenticator::TestAuthenticator,
+        },
+        database::memory_database::MemoryDatabase,
+        handler::common::HandlerWrapper,
+        objectstorage::mock_objectstorage::MockStorageHandler,
+        server::dataset_api::DatasetsServer,
+        test_util::init::test_init,
+    };
+
+    use super::TokenRequirement;
+
+    const GET_DATASET_PATH: &str = "/services.v1.DatasetService/GetDataset";
+
+    fn grpc_request(api_token: Option<&str>) -> Request<Body> {
+        let mut request = Request::builder()
+            .method("POST")
+            .uri(GET_DATASET_PATH)
+            .header("content-type", "application/grpc");
+        if let Some(api_token) = api_token {
+            request = request.header(API_TOKEN_ENTRY_KEY, api_token);
+        }
+
+        // Empty message of a GetDatasetRequest without id
+        return request.body(Body::from(vec![0u8, 0, 0, 0, 0])).unwrap();
+    }
+
+    fn grpc_status<B>(response: &http::Response<B>) -> Option<String> {
+        return response
+            .headers()
+            .get("grpc-status")
+            .map(|value| value.to_str().unwrap().to_string());
+    }
+
+    #[tokio::test]
+    async fn request_without_token() {
+        test_init();
+
+        let handler_wrapper = Arc::new(
+            HandlerWrapper::new(
+                Arc::new(MemoryDatabase::new()),
+                Arc::new(MockStorageHandler::new()),
+            )
+            .await
+            .unwrap(),
+        );
+        // The test authenticator authorizes all requests, only the interceptor can reject them
+        let dataset_endpoints = DatasetsServer {
+            handler_wrapper: handler_wrapper,
+            auth_handler: Arc::new(TestAuthenticator {}),
+        };
+        let token_requirement = TokenRequirement::new(true);
+        let mut service =
+            DatasetServiceServer::with_interceptor(dataset_endpoints, move |request| {
+                token_requirement.intercept(request)
+            });
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(grpc_request(None))
+            .await
+            .unwrap();
+        assert_eq!(grpc_status(&response), Some("16".to_string()));
+
+        // Requests with a token reach the service, which fails to find the dataset
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(grpc_request(Some("test-token")))
+            .await
+            .unwrap();
+        assert_ne!(grpc_status(&response), Some("16".to_string()));
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use log::info;
+use log::{error, info};
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::dataset_objects_service_server;
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::dataset_service_server;
 use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::object_load_service_server;
@@ -9,44 +9,73 @@ use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::project_service_se
 use tonic::transport::Server;
 
 use crate::handler::common::HandlerWrapper;
-use crate::objectstorage::s3_objectstorage::S3Handler;
+use crate::objectstorage::backend::storage_handler_from_config;
 
 use crate::auth::{
     authenticator::AuthHandler, project_authorization_handler::ProjectAuthzHandler,
-    test_authenticator::TestAuthenticator,
+    service_account_handler::ServiceAccountAuthHandler, test_authenticator::TestAuthenticator,
 };
 
 use super::{
-    dataset_api::DatasetsServer, load_api::LoadServer, object_api::ObjectServer,
+    compression::ResponseCompressionLayer,
+    concurrency_limit::ConcurrencyLimitLayer,
+    config_summary::log_config_summary,
+    dataset_api::DatasetsServer,
+    download::{download_server_from_config, DownloadService},
+    expiry::{sweep_expired_revisions, sweep_interval_from_config},
+    health::{check_cors_configuration, report_backend_health},
+    identity::IdentityLayer,
+    load_api::LoadServer,
+    message_size::MessageSizeLimitLayer,
+    multipart_cleanup::{abort_stale_multipart_uploads, multipart_cleanup_from_config},
+    object_api::ObjectServer,
     project_api::ProjectServer,
+    purge::{purge_expired_entries, purge_from_config},
+    rate_limit::{evict_expired_rate_limits, RateLimitLayer, RequestRateLimiter},
+    read_only::{reload_read_only_mode_on_hangup, ReadOnlyLayer, ReadOnlyMode},
+    reflection::reflection_service_from_config,
+    request_id::RequestIdLayer,
+    request_logging::RequestLoggingLayer,
+    require_token::TokenRequirement,
+    timeout::{duration_from_config, RequestTimeoutLayer},
 };
 
-use crate::database::mongo_connector::MongoHandler;
+use crate::database::{database::Database, mongo_connector::MongoHandler};
 
 use crate::SETTINGS;
 
 type ResultWrapper<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
 /// Starts the grpc server. The configuration is read from the config file handed over at startup
-pub async fn start_server() -> ResultWrapper<()> {
+/// The read-only mode is reloaded from the config file on SIGHUP, admins can also toggle it with get_user_projects
+pub async fn start_server(config_file: String) -> ResultWrapper<()> {
+    log_config_summary();
+
     let mongo_handler = Arc::new(MongoHandler::new().await?);
+    mongo_handler.migrate_legacy_collections().await?;
+    mongo_handler.create_indexes().await?;
 
-    let object_storage_handler = Arc::new(S3Handler::new());
+    let object_storage_handler = storage_handler_from_config().await?;
+    check_cors_configuration(object_storage_handler.clone()).await;
 
     let auth_type_handler = SETTINGS.read().unwrap().get_str("Authentication.Type")?;
-    let auth_type_handler_str = auth_type_handler.as_str();
-
-    let project_authz_handler: Arc<dyn AuthHandler> = match auth_type_handler_str {
-        "debug" => Arc::new(TestAuthenticator {}),
-        "oauth2" => Arc::new(ProjectAuthzHandler::new(mongo_handler.clone())?),
-        _ => panic!("Could not parse auth type: {}", auth_type_handler),
-    };
+    let project_authz_handler =
+        new_auth_handler(auth_type_handler.as_str(), mongo_handler.clone())?;
+    let project_authz_handler = ServiceAccountAuthHandler::wrap_from_config(project_authz_handler)?;
+    let request_logging_layer = RequestLoggingLayer::new_from_config()?;
 
     let handler_wrapper =
         Arc::new(HandlerWrapper::new(mongo_handler.clone(), object_storage_handler.clone()).await?);
+    let read_only_mode = ReadOnlyMode::new_from_config();
+    tokio::spawn(reload_read_only_mode_on_hangup(
+        read_only_mode.clone(),
+        config_file,
+    ));
+
     let project_endpoints = ProjectServer {
         auth_handler: project_authz_handler.clone(),
         handler: handler_wrapper.clone(),
+        read_only_mode: read_only_mode.clone(),
     };
 
     let dataset_endpoints = DatasetsServer {
@@ -71,21 +100,181 @@ pub async fn start_server() -> ResultWrapper<()> {
 
     info!("Starting webserver on {} port {}", &host, &port);
 
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(report_backend_health(
+        health_reporter,
+        mongo_handler.clone(),
+        object_storage_handler.clone(),
+    ));
+
+    tokio::spawn(sweep_expired_revisions(
+        handler_wrapper.clone(),
+        sweep_interval_from_config(),
+    ));
+
+    if let Some(multipart_cleanup) = multipart_cleanup_from_config() {
+        tokio::spawn(abort_stale_multipart_uploads(
+            handler_wrapper.clone(),
+            multipart_cleanup,
+        ));
+    }
+
+    if let Some(purge) = purge_from_config() {
+        tokio::spawn(purge_expired_entries(handler_wrapper.clone(), purge));
+    }
+
+    if let Some(download_server) = download_server_from_config() {
+        let download_addr = format!("{}:{}", &host, download_server.port).parse()?;
+        info!(
+            "Starting download server on {} port {}",
+            &host, download_server.port
+        );
+        let download_service = DownloadService::new(handler_wrapper.clone());
+        tokio::spawn(async move {
+            if let Err(e) = Server::builder()
+                .accept_http1(true)
+                .add_service(download_service)
+                .serve(download_addr)
+                .await
+            {
+                error!("download server failed: {:?}", e);
+            }
+        });
+    }
+
+    let token_requirement = TokenRequirement::new_from_config(auth_type_handler.as_str());
+    let request_interceptor = move |request| token_requirement.intercept(request);
+
+    let rate_limiter = Arc::new(RequestRateLimiter::new());
+    tokio::spawn(evict_expired_rate_limits(rate_limiter.clone()));
+
+    let tcp_keepalive = duration_from_config("Server.TcpKeepaliveMs");
+    let http2_keepalive_interval = duration_from_config("Server.Http2KeepaliveIntervalMs");
+    let http2_keepalive_timeout = duration_from_config("Server.Http2KeepaliveTimeoutMs");
+
     Server::builder()
-        .add_service(project_service_server::ProjectServiceServer::new(
-            project_endpoints,
-        ))
-        .add_service(dataset_service_server::DatasetServiceServer::new(
-            dataset_endpoints,
-        ))
+        .tcp_keepalive(tcp_keepalive)
+        .http2_keepalive_interval(http2_keepalive_interval)
+        .http2_keepalive_timeout(http2_keepalive_timeout)
+        .layer(RequestIdLayer::default())
+        .layer(IdentityLayer::new(project_authz_handler.clone()))
+        .layer(request_logging_layer)
+        .layer(RateLimitLayer::new(rate_limiter))
+        .layer(ConcurrencyLimitLayer::new_from_config())
+        .layer(RequestTimeoutLayer::new_from_config())
+        .layer(ReadOnlyLayer::new(read_only_mode))
+        .layer(ResponseCompressionLayer::new_from_config())
+        .layer(MessageSizeLimitLayer::new_from_config())
+        .add_service(health_service)
+        .add_optional_service(reflection_service_from_config())
+        .add_service(
+            project_service_server::ProjectServiceServer::with_interceptor(
+                project_endpoints,
+                request_interceptor.clone(),
+            ),
+        )
         .add_service(
-            dataset_objects_service_server::DatasetObjectsServiceServer::new(objects_endpoints),
+            dataset_service_server::DatasetServiceServer::with_interceptor(
+                dataset_endpoints,
+                request_interceptor.clone(),
+            ),
+        )
+        .add_service(
+            dataset_objects_service_server::DatasetObjectsServiceServer::with_interceptor(
+                objects_endpoints,
+                request_interceptor.clone(),
+            ),
+        )
+        .add_service(
+            object_load_service_server::ObjectLoadServiceServer::with_interceptor(
+                load_endpoints,
+                request_interceptor,
+            ),
         )
-        .add_service(object_load_service_server::ObjectLoadServiceServer::new(
-            load_endpoints,
-        ))
         .serve(addr)
         .await?;
 
     Ok(())
 }
+
+/// Repairs the head and revision counter of all object groups and logs the repaired object groups
+/// The repair can run while the server is running
+pub async fn repair_object_groups() -> ResultWrapper<()> {
+    log_config_summary();
+
+    let mongo_handler = Arc::new(MongoHandler::new().await?);
+    let object_storage_handler = storage_handler_from_config().await?;
+    let handler_wrapper = HandlerWrapper::new(mongo_handler, object_storage_handler).await?;
+
+    let repairs = handler_wrapper
+        .update_handler
+        .repair_object_groups()
+        .await?;
+    for repair in repairs.iter() {
+        info!(
+            "Repaired object group {}: head {:?} -> {:?}, revision counter {} -> {}",
+            repair.object_group_id,
+            repair.previous_head_id,
+            repair.head_id,
+            repair.previous_revision_counter,
+            repair.revision_counter
+        );
+    }
+    info!("Repaired {} object groups", repairs.len());
+
+    Ok(())
+}
+
+/// Revokes all api tokens of a user and logs the number of revoked tokens
+/// Tokens are invalidated immediately, also for a running server
+pub async fn revoke_user_tokens(user_id: &str) -> ResultWrapper<()> {
+    let mongo_handler = Arc::new(MongoHandler::new().await?);
+    let object_storage_handler = storage_handler_from_config().await?;
+    let handler_wrapper = HandlerWrapper::new(mongo_handler, object_storage_handler).await?;
+
+    let revoked = handler_wrapper
+        .delete_handler
+        .revoke_all_user_tokens(user_id)
+        .await?;
+    info!("Revoked {} api tokens of user {}", revoked, user_id);
+
+    Ok(())
+}
+
+/// Creates the auth handler of the given Authentication.Type, an error listing the valid types is returned for
+/// unknown types
+fn new_auth_handler<T: Database + 'static>(
+    auth_type: &str,
+    database: Arc<T>,
+) -> ResultWrapper<Arc<dyn AuthHandler>> {
+    return match auth_type {
+        "debug" => Ok(Arc::new(TestAuthenticator {})),
+        "oauth2" => Ok(Arc::new(ProjectAuthzHandler::new(database)?)),
+        _ => Err(format!(
+            "could not parse auth type: {}, valid options for Authentication.Type are debug and oauth2",
+            auth_type
+        )
+        .into()),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::database::memory_database::MemoryDatabase;
+
+    use super::new_auth_handler;
+
+    #[test]
+    fn unknown_auth_type() {
+        let error = match new_auth_handler("bogus", Arc::new(MemoryDatabase::new())) {
+            Ok(_) => panic!("unknown auth type has been accepted"),
+            Err(e) => e,
+        };
+
+        assert!(error.to_string().contains("bogus"));
+        assert!(error.to_string().contains("debug and oauth2"));
+        assert!(new_auth_handler("debug", Arc::new(MemoryDatabase::new())).is_ok());
+    }
+}
@@ -9,28 +9,43 @@ mod server_test {
     use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::object_load_service_server::ObjectLoadService;
     use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::project_service_server::ProjectService;
 
-    use scienceobjectsdb_rust_api::sciobjectsdbapi::services;
+    use scienceobjectsdb_rust_api::sciobjectsdbapi::{models, services};
 
+    use http_body::Body as HttpBody;
+    use prost::Message;
     use tonic::Request;
+    use tower::ServiceExt;
 
     use crate::handler::common::HandlerWrapper;
     use crate::handler::read::ReadHandler;
+    use crate::models::common_models::Right;
     use crate::test_util::init::test_init;
+    use crate::SETTINGS;
 
+    use crate::database::database::Database;
+    use crate::database::memory_database::MemoryDatabase;
     use crate::database::mongo_connector::MongoHandler;
+    use crate::objectstorage::mock_objectstorage::MockStorageHandler;
+    use crate::objectstorage::objectstorage::StorageHandler;
     use crate::objectstorage::s3_objectstorage::S3Handler;
     use crate::server::{
-        dataset_api::DatasetsServer, load_api::LoadServer, object_api::ObjectServer,
-        project_api::ProjectServer,
+        dataset_api::{DatasetsServer, DATASET_ID_KEY, REV_VERSION_KEY, UPDATE_FIELDS_KEY},
+        download::{DownloadService, DOWNLOAD_PATH},
+        load_api::{LoadServer, SINGLE_USE_KEY},
+        object_api::{ObjectServer, CURRENT_REVISION_KEY, INCLUDE_CURRENT_REVISION_KEY},
+        project_api::{
+            ProjectServer, READ_ONLY_KEY, REVOKED_TOKENS_KEY, ROTATE_TOKEN_KEY, TOKEN_USER_KEY,
+        },
+        read_only::{is_mutating_method, ReadOnlyMode},
     };
 
     use crate::auth::test_authenticator::TestAuthenticator;
 
-    struct TestEndpointStruct {
-        project_handler: ProjectServer<MongoHandler>,
-        dataset_handler: DatasetsServer<MongoHandler>,
-        object_handler: ObjectServer<MongoHandler>,
-        load_handler: LoadServer<MongoHandler>,
+    struct TestEndpointStruct<T: Database + 'static = MongoHandler> {
+        project_handler: ProjectServer<T>,
+        dataset_handler: DatasetsServer<T>,
+        object_handler: ObjectServer<T>,
+        load_handler: LoadServer<T>,
     }
 
     const TEST_DATA_REV1: &'static str = "testdata-revision-1";
@@ -39,10 +54,18 @@ mod server_test {
     async fn test_endpoint_structs() -> TestEndpointStruct {
         let mongo_handler = Arc::new(MongoHandler::new().await.unwrap());
         let object_storage_handler = Arc::new(S3Handler::new());
+
+        return endpoint_structs(mongo_handler, object_storage_handler).await;
+    }
+
+    async fn endpoint_structs<T: Database + 'static>(
+        database_handler: Arc<T>,
+        object_storage_handler: Arc<dyn StorageHandler>,
+    ) -> TestEndpointStruct<T> {
         let authz_handler = Arc::new(TestAuthenticator {});
 
         let handler_wrapper = Arc::new(
-            HandlerWrapper::new(mongo_handler.clone(), object_storage_handler.clone())
+            HandlerWrapper::new(database_handler, object_storage_handler)
                 .await
                 .unwrap(),
         );
@@ -50,6 +73,7 @@ mod server_test {
         let project_endpoints = ProjectServer {
             handler: handler_wrapper.clone(),
             auth_handler: authz_handler.clone(),
+            read_only_mode: ReadOnlyMode::default(),
         };
 
         let dataset_endpoints = DatasetsServer {
@@ -77,7 +101,7 @@ mod server_test {
         return endpoints;
     }
 
-    async fn project_test(endpoints: &TestEndpointStruct) -> String {
+    async fn project_test<T: Database + 'static>(endpoints: &TestEndpointStruct<T>) -> String {
         let create_project_request = Request::new(services::v1::CreateProjectRequest {
             name: "testproject1".to_string(),
             description: "Some description".to_string(),
@@ -117,7 +141,10 @@ mod server_test {
         return project.project;
     }
 
-    async fn dataset_test(test_project_id: String, endpoints: &TestEndpointStruct) -> String {
+    async fn dataset_test<T: Database + 'static>(
+        test_project_id: String,
+        endpoints: &TestEndpointStruct<T>,
+    ) -> String {
         let create_dataset_request = Request::new(services::v1::CreateDatasetRequest {
             project_id: test_project_id,
             name: "testdataset".to_string(),
@@ -489,4 +516,560 @@ mod server_test {
         let object_group_id = object_group_test(dataset_id.clone(), &endpoints).await;
         test_revisions(&endpoints, object_group_id).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn get_object_group_with_current_revision() {
+        test_init();
+
+        let endpoints = test_endpoint_structs().await;
+
+        let project_id = project_test(&endpoints).await;
+        let dataset_id = dataset_test(project_id, &endpoints).await;
+
+        let create_object_group_request = Request::new(services::v1::CreateObjectGroupRequest {
+            dataset_id: dataset_id,
+            name: "test_group".to_string(),
+            object_group_revision: Some(services::v1::CreateObjectGroupRevisionRequest {
+                objects: vec![services::v1::CreateObjectRequest {
+                    filename: "testobject.txt".to_string(),
+                    filetype: "txt".to_string(),
+                    content_len: 8,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let create_object_group_response = endpoints
+            .object_handler
+            .create_object_group(create_object_group_request)
+            .await
+            .unwrap()
+            .into_inner();
+
+        let mut get_object_group_request = Request::new(services::v1::GetObjectGroupRequest {
+            id: create_object_group_response.object_group_id.clone(),
+        });
+        get_object_group_request
+            .metadata_mut()
+            .insert(INCLUDE_CURRENT_REVISION_KEY, "true".parse().unwrap());
+
+        let response = endpoints
+            .object_handler
+            .get_object_group(get_object_group_request)
+            .await
+            .unwrap();
+
+        let encoded_revision = response
+            .metadata()
+            .get_bin(CURRENT_REVISION_KEY)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        let revision = models::v1::ObjectGroupRevision::decode(encoded_revision).unwrap();
+
+        assert_eq!(
+            response.get_ref().object_group.as_ref().unwrap().id,
+            create_object_group_response.object_group_id
+        );
+        assert_eq!(revision.id, create_object_group_response.revision_id);
+        assert_eq!(revision.objects.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn mock_storage_flow() {
+        test_init();
+
+        let storage_handler = Arc::new(MockStorageHandler::new());
+        let endpoints =
+            endpoint_structs(Arc::new(MemoryDatabase::new()), storage_handler.clone()).await;
+
+        let project_id = project_test(&endpoints).await;
+        let dataset_id = dataset_test(project_id, &endpoints).await;
+
+        let create_object_group_request = Request::new(services::v1::CreateObjectGroupRequest {
+            dataset_id: dataset_id,
+            name: "test_group".to_string(),
+            object_group_revision: Some(services::v1::CreateObjectGroupRevisionRequest {
+                objects: vec![
+                    services::v1::CreateObjectRequest {
+                        filename: "testobject.txt".to_string(),
+                        filetype: "txt".to_string(),
+                        content_len: 8,
+                        ..Default::default()
+                    },
+                    services::v1::CreateObjectRequest {
+                        filename: "testobject_large.txt".to_string(),
+                        filetype: "txt".to_string(),
+                        content_len: 16 * 1024 * 1024,
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let create_object_group_response = endpoints
+            .object_handler
+            .create_object_group(create_object_group_request)
+            .await
+            .unwrap()
+            .into_inner();
+
+        let revision = endpoints
+            .object_handler
+            .get_object_group_revision(Request::new(services::v1::GetObjectGroupRevisionRequest {
+                id: create_object_group_response.revision_id.clone(),
+                reference_type: services::v1::ObjectGroupRevisionReferenceType::Id as i32,
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .object_group_revision
+            .unwrap();
+        assert_eq!(revision.objects.len(), 2);
+        assert_eq!(storage_handler.call_count("create_location"), 2);
+
+        let object_id = revision.objects[0].id.clone();
+        let upload_link = endpoints
+            .load_handler
+            .create_upload_link(Request::new(services::v1::CreateUploadLinkRequest {
+                id: object_id.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .upload_link;
+        let download_link = endpoints
+            .load_handler
+            .create_download_link(Request::new(services::v1::CreateDownloadLinkRequest {
+                id: object_id.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .upload_link;
+        assert_ne!(upload_link, download_link);
+
+        let calls = storage_handler.calls();
+        let object_key = calls.last().unwrap().key.clone();
+        assert_eq!(calls.last().unwrap().method, "create_download_link");
+        assert!(upload_link.contains(object_key.as_str()));
+        assert!(download_link.contains(object_key.as_str()));
+
+        let multipart_object_id = revision.objects[1].id.clone();
+        endpoints
+            .load_handler
+            .start_multipart_upload(Request::new(services::v1::StartMultipartUploadRequest {
+                id: multipart_object_id.clone(),
+            }))
+            .await
+            .unwrap();
+
+        let mut parts = Vec::new();
+        for upload_part in 1..=2 {
+            let part_link = endpoints
+                .load_handler
+                .get_multipart_upload_link(Request::new(
+                    services::v1::GetMultipartUploadLinkRequest {
+                        object_id: multipart_object_id.clone(),
+                        upload_part: upload_part,
+                    },
+                ))
+                .await
+                .unwrap()
+                .into_inner()
+                .upload_link;
+            assert!(part_link.contains("uploadId=mock-upload-0"));
+
+            parts.push(services::v1::CompletedParts {
+                etag: MockStorageHandler::part_etag("mock-upload-0", upload_part),
+                part: upload_part,
+            });
+        }
+
+        endpoints
+            .load_handler
+            .complete_multipart_upload(Request::new(services::v1::CompleteMultipartUploadRequest {
+                object_id: multipart_object_id.clone(),
+                parts: parts.clone(),
+            }))
+            .await
+            .unwrap();
+        assert_eq!(storage_handler.call_count("finish_multipart_upload"), 1);
+
+        let error = endpoints
+            .load_handler
+            .complete_multipart_upload(Request::new(services::v1::CompleteMultipartUploadRequest {
+                object_id: multipart_object_id,
+                parts: parts,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn single_use_download_link() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Server.Download.PublicUrl", "http://downloads.example.com/")
+            .unwrap();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Server.Download.Port", 50052)
+            .unwrap();
+
+        let storage_handler = Arc::new(MockStorageHandler::new());
+        let endpoints =
+            endpoint_structs(Arc::new(MemoryDatabase::new()), storage_handler.clone()).await;
+
+        let project_id = project_test(&endpoints).await;
+        let dataset_id = dataset_test(project_id, &endpoints).await;
+
+        let create_object_group_response = endpoints
+            .object_handler
+            .create_object_group(Request::new(services::v1::CreateObjectGroupRequest {
+                dataset_id: dataset_id,
+                name: "test_group".to_string(),
+                object_group_revision: Some(services::v1::CreateObjectGroupRevisionRequest {
+                    objects: vec![services::v1::CreateObjectRequest {
+                        filename: "testobject.txt".to_string(),
+                        filetype: "txt".to_string(),
+                        content_len: 8,
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let object_id = endpoints
+            .object_handler
+            .get_object_group_revision(Request::new(services::v1::GetObjectGroupRevisionRequest {
+                id: create_object_group_response.revision_id,
+                reference_type: services::v1::ObjectGroupRevisionReferenceType::Id as i32,
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .object_group_revision
+            .unwrap()
+            .objects[0]
+            .id
+            .clone();
+        let object = endpoints
+            .load_handler
+            .wrapper
+            .read_handler
+            .find_object(object_id.as_str())
+            .await
+            .unwrap();
+        storage_handler.put_object_data(object.location.key.as_str(), b"testdata");
+
+        let mut link_request = Request::new(services::v1::CreateDownloadLinkRequest {
+            id: object_id.clone(),
+        });
+        link_request
+            .metadata_mut()
+            .insert(SINGLE_USE_KEY, "true".parse().unwrap());
+        let download_link = endpoints
+            .load_handler
+            .create_download_link(link_request)
+            .await
+            .unwrap()
+            .into_inner()
+            .upload_link;
+        let path = download_link
+            .strip_prefix("http://downloads.example.com")
+            .unwrap()
+            .to_string();
+        assert!(path.starts_with(DOWNLOAD_PATH));
+
+        let download_service = DownloadService::new(endpoints.load_handler.wrapper.clone());
+        let download = |path: String| {
+            download_service.clone().oneshot(
+                http::Request::get(path)
+                    .body(tonic::transport::Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let mut response = download(path.clone()).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let mut data = Vec::new();
+        while let Some(chunk) = response.body_mut().data().await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(data, b"testdata");
+
+        let response = download(path).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+        assert_eq!(storage_handler.call_count("get_object"), 1);
+    }
+
+    #[tokio::test]
+    async fn update_dataset_field() {
+        test_init();
+
+        let endpoints = endpoint_structs(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(MockStorageHandler::new()),
+        )
+        .await;
+        let dataset_id = dataset_test("testproject".to_string(), &endpoints).await;
+
+        let update_request = |rev_version: &str, fields: &str| {
+            let mut request = Request::new(services::v1::UpdateDatasetFieldRequest::default());
+            request
+                .metadata_mut()
+                .insert(DATASET_ID_KEY, dataset_id.parse().unwrap());
+            request
+                .metadata_mut()
+                .insert(REV_VERSION_KEY, rev_version.parse().unwrap());
+            request
+                .metadata_mut()
+                .insert(UPDATE_FIELDS_KEY, fields.parse().unwrap());
+            request
+        };
+
+        let get_response = endpoints
+            .dataset_handler
+            .get_dataset(Request::new(services::v1::GetDatasetRequest {
+                id: dataset_id.clone(),
+            }))
+            .await
+            .unwrap();
+        let rev_version = get_response
+            .metadata()
+            .get(REV_VERSION_KEY)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(rev_version, "0");
+
+        let update_response = endpoints
+            .dataset_handler
+            .update_dataset_field(update_request(
+                rev_version.as_str(),
+                r#"{"description": "updated", "is_public": true}"#,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            update_response
+                .metadata()
+                .get(REV_VERSION_KEY)
+                .unwrap()
+                .to_str()
+                .unwrap(),
+            "1"
+        );
+
+        // The second update is based on the rev_version before the first update
+        let error = endpoints
+            .dataset_handler
+            .update_dataset_field(update_request(
+                rev_version.as_str(),
+                r#"{"description": "stale"}"#,
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::Aborted);
+
+        let error = endpoints
+            .dataset_handler
+            .update_dataset_field(update_request("1", r#"{"project_id": "other"}"#))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+
+        let dataset = endpoints
+            .dataset_handler
+            .get_dataset(Request::new(services::v1::GetDatasetRequest {
+                id: dataset_id.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .dataset
+            .unwrap();
+        assert_eq!(dataset.description, "updated");
+        assert!(dataset.is_public);
+    }
+
+    #[tokio::test]
+    async fn rotate_api_token() {
+        test_init();
+
+        let endpoints = endpoint_structs(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(MockStorageHandler::new()),
+        )
+        .await;
+        let project_id = project_test(&endpoints).await;
+
+        let api_token = endpoints
+            .project_handler
+            .create_api_token(Request::new(services::v1::CreateApiTokenRequest {
+                id: project_id.clone(),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .token
+            .unwrap();
+
+        let rotate_request = |token_id: &str| {
+            let mut request = Request::new(services::v1::CreateApiTokenRequest::default());
+            request
+                .metadata_mut()
+                .insert(ROTATE_TOKEN_KEY, token_id.parse().unwrap());
+            request
+        };
+
+        let rotated_token = endpoints
+            .project_handler
+            .create_api_token(rotate_request(api_token.id.as_str()))
+            .await
+            .unwrap()
+            .into_inner()
+            .token
+            .unwrap();
+        assert_eq!(rotated_token.id, api_token.id);
+        assert_eq!(rotated_token.project_id, project_id);
+        assert_ne!(rotated_token.token, api_token.token);
+
+        let error = endpoints
+            .project_handler
+            .create_api_token(rotate_request("missing"))
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn toggle_read_only_mode() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Authentication.AdminUsers", vec!["admin", "testuser"])
+            .unwrap();
+
+        let endpoints = endpoint_structs(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(MockStorageHandler::new()),
+        )
+        .await;
+        // The toggle has to pass the read-only mode to disable it again
+        assert!(!is_mutating_method(
+            "/services.v1.ProjectService/GetUserProjects"
+        ));
+
+        for (value, enabled) in vec![("true", true), ("false", false)] {
+            let mut request = Request::new(services::v1::GetUserProjectsRequest {});
+            request
+                .metadata_mut()
+                .insert(READ_ONLY_KEY, value.parse().unwrap());
+            let response = endpoints
+                .project_handler
+                .get_user_projects(request)
+                .await
+                .unwrap();
+
+            assert!(response.get_ref().projects.is_empty());
+            assert_eq!(
+                response
+                    .metadata()
+                    .get(READ_ONLY_KEY)
+                    .unwrap()
+                    .to_str()
+                    .unwrap(),
+                value
+            );
+            assert_eq!(
+                endpoints.project_handler.read_only_mode.is_enabled(),
+                enabled
+            );
+        }
+
+        let mut request = Request::new(services::v1::GetUserProjectsRequest {});
+        request
+            .metadata_mut()
+            .insert(READ_ONLY_KEY, "maybe".parse().unwrap());
+        let error = endpoints
+            .project_handler
+            .get_user_projects(request)
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn revoke_user_api_tokens() {
+        test_init();
+        SETTINGS
+            .write()
+            .unwrap()
+            .set("Authentication.AdminUsers", vec!["admin", "testuser"])
+            .unwrap();
+
+        let endpoints = endpoint_structs(
+            Arc::new(MemoryDatabase::new()),
+            Arc::new(MockStorageHandler::new()),
+        )
+        .await;
+        let user_id = uuid::Uuid::new_v4().to_string();
+        let project_id = uuid::Uuid::new_v4().to_string();
+        for _ in 0..2 {
+            endpoints
+                .project_handler
+                .handler
+                .create_handler
+                .create_api_token(user_id.as_str(), vec![Right::Read], project_id.as_str())
+                .await
+                .unwrap();
+        }
+
+        let mut request = Request::new(services::v1::DeleteApiTokenRequest::default());
+        request
+            .metadata_mut()
+            .insert(TOKEN_USER_KEY, user_id.parse().unwrap());
+        let response = endpoints
+            .project_handler
+            .delete_api_token(request)
+            .await
+            .unwrap();
+        assert_eq!(
+            response
+                .metadata()
+                .get(REVOKED_TOKENS_KEY)
+                .unwrap()
+                .to_str()
+                .unwrap(),
+            "2"
+        );
+
+        let remaining_tokens = endpoints
+            .project_handler
+            .handler
+            .read_handler
+            .read_user_api_token(user_id.as_str())
+            .await
+            .unwrap();
+        assert!(remaining_tokens.is_empty());
+    }
 }
@@ -0,0 +1,207 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http::{Request, Response};
+use log::warn;
+use tonic::body::BoxBody;
+use tower::{Layer, Service};
+
+use crate::{
+    deadline::{parse_grpc_timeout, with_deadline, GRPC_TIMEOUT_HEADER},
+    SETTINGS,
+};
+
+use super::read_only::is_mutating_request;
+
+/// Reads a duration in milliseconds from the configuration file, returns None if it is not configured
+pub fn duration_from_config(config_key: &str) -> Option<Duration> {
+    let millis = SETTINGS.read().unwrap().get_int(config_key).ok()?;
+    return Some(Duration::from_millis(millis.max(0) as u64));
+}
+
+/// Aborts requests that are not answered within the timeout with deadline_exceeded
+/// Deadlines set by clients in the grpc-timeout header are applied as well, the earlier one wins. The deadline is
+/// propagated to the database and object storage calls of the request, see crate::deadline.
+/// Mutating requests are exempt from the server side timeout, aborting them midway could leave partially applied
+/// changes behind. They are only aborted if the client sets a deadline itself.
+#[derive(Clone)]
+pub struct RequestTimeoutLayer {
+    timeout: Option<Duration>,
+}
+
+impl RequestTimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        return RequestTimeoutLayer {
+            timeout: Some(timeout),
+        };
+    }
+
+    /// Reads the timeout from Server.RequestTimeoutMs, requests are not aborted if no timeout is configured
+    pub fn new_from_config() -> Self {
+        return RequestTimeoutLayer {
+            timeout: duration_from_config("Server.RequestTimeoutMs"),
+        };
+    }
+}
+
+impl<S> Layer<S> for RequestTimeoutLayer {
+    type Service = RequestTimeoutService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        return RequestTimeoutService {
+            inner: inner,
+            timeout: self.timeout,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestTimeoutService<S> {
+    inner: S,
+    timeout: Option<Duration>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for RequestTimeoutService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return self.inner.poll_ready(cx);
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let client_timeout = request
+            .headers()
+            .get(GRPC_TIMEOUT_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_grpc_timeout);
+        let server_timeout = match is_mutating_request(&request) {
+            true => None,
+            false => self.timeout,
+        };
+        let timeout = match (server_timeout, client_timeout) {
+            (Some(timeout), Some(client_timeout)) => timeout.min(client_timeout),
+            (Some(timeout), None) => timeout,
+            (None, Some(client_timeout)) => client_timeout,
+            (None, None) => return Box::pin(self.inner.call(request)),
+        };
+
+        let path = request.uri().path().to_string();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let response_future = with_deadline(deadline, self.inner.call(request));
+
+        return Box::pin(async move {
+            match tokio::time::timeout_at(deadline, response_future).await {
+                Ok(response) => return response,
+                Err(_) => {
+                    warn!("request to {} timed out after {:?}", path, timeout);
+                    let status = tonic::Status::deadline_exceeded("request timed out");
+                    return Ok(status.to_http());
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use http::{Request, Response};
+    use tonic::body::BoxBody;
+    use tower::{Layer, Service, ServiceExt};
+
+    use super::RequestTimeoutLayer;
+    use crate::deadline;
+
+    fn response_code(response: Response<BoxBody>) -> tonic::Code {
+        return tonic::Status::from_header_map(response.headers())
+            .unwrap()
+            .code();
+    }
+
+    #[tokio::test]
+    async fn request_timeout() {
+        let slow_service = tower::service_fn(|request: Request<()>| async move {
+            if request.uri().path() == "/slow" {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+            let status = tonic::Status::new(tonic::Code::Ok, "");
+            return Ok::<Response<BoxBody>, Infallible>(status.to_http());
+        });
+        let mut service = RequestTimeoutLayer::new(Duration::from_millis(50)).layer(slow_service);
+
+        let slow_request = Request::builder().uri("/slow").body(()).unwrap();
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(slow_request)
+            .await
+            .unwrap();
+        assert_eq!(response_code(response), tonic::Code::DeadlineExceeded);
+
+        let response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(Request::new(()))
+            .await
+            .unwrap();
+        assert_eq!(response_code(response), tonic::Code::Ok);
+    }
+
+    #[tokio::test]
+    async fn mutating_requests_are_not_aborted() {
+        let slow_service = tower::service_fn(|_: Request<()>| async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let status = tonic::Status::new(tonic::Code::Ok, "");
+            return Ok::<Response<BoxBody>, Infallible>(status.to_http());
+        });
+        let mut service = RequestTimeoutLayer::new(Duration::from_millis(50)).layer(slow_service);
+
+        let request = Request::builder()
+            .uri("/services.v1.DatasetService/CreateDataset")
+            .body(())
+            .unwrap();
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(response_code(response), tonic::Code::Ok);
+
+        let request = Request::builder()
+            .uri("/services.v1.DatasetService/GetDataset")
+            .body(())
+            .unwrap();
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(response_code(response), tonic::Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    async fn client_deadline() {
+        let slow_service = tower::service_fn(|_: Request<()>| async move {
+            // The deadline of the client is visible to the downstream calls of the request
+            assert!(deadline::remaining().unwrap() <= Duration::from_millis(50));
+            deadline::timeout(tokio::time::sleep(Duration::from_secs(5))).await?;
+
+            let status = tonic::Status::new(tonic::Code::Ok, "");
+            return Ok::<Response<BoxBody>, tonic::Status>(status.to_http());
+        });
+        let mut service = RequestTimeoutLayer::new(Duration::from_secs(60)).layer(slow_service);
+
+        let request = Request::builder()
+            .uri("/slow")
+            .header("grpc-timeout", "50m")
+            .body(())
+            .unwrap();
+        let response = service.ready().await.unwrap().call(request).await.unwrap();
+        assert_eq!(response_code(response), tonic::Code::DeadlineExceeded);
+    }
+}
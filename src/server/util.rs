@@ -1,3 +1,10 @@
+use tonic::metadata::MetadataMap;
+
+use crate::{
+    handler::read::max_page_size_from_config,
+    models::common_models::{DatabaseModel, ListView, SortOrder},
+};
+
 // A simple helper function to turn an option value into a tonic error. This can be used to check if a required field
 // that is defined as optional in the gRPC API is present in a request. Can be used to remove some boilerplate code.
 // The fieldname is used for the error message to indicate which field was missing.
@@ -17,3 +24,70 @@ pub fn tonic_error_if_not_exists<'a, T>(
 
     return Ok(value);
 }
+
+// Reads an optional metadata entry as string. Fails with invalid_argument if the value is not a valid ascii string.
+pub fn metadata_str<'a>(
+    metadata: &'a MetadataMap,
+    key: &str,
+) -> Result<Option<&'a str>, tonic::Status> {
+    match metadata.get(key) {
+        Some(value) => match value.to_str() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(tonic::Status::invalid_argument(format!(
+                "{} has to be an ascii string",
+                key
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+// Reads the page size of a paginated listing from the metadata entry with the given key. Returns None if the entry is
+// missing, fails with invalid_argument if the value is not a positive integer or exceeds Server.MaxPageSize.
+pub fn metadata_page_size(metadata: &MetadataMap, key: &str) -> Result<Option<i64>, tonic::Status> {
+    let value = match metadata_str(metadata, key)? {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let page_size: i64 = match value.parse() {
+        Ok(value) => value,
+        Err(_) => {
+            return Err(tonic::Status::invalid_argument(format!(
+                "could not parse {} as page size",
+                key
+            )))
+        }
+    };
+
+    let max_page_size = max_page_size_from_config();
+    if page_size <= 0 || page_size > max_page_size {
+        return Err(tonic::Status::invalid_argument(format!(
+            "{} has to be between 1 and {}",
+            key, max_page_size
+        )));
+    }
+
+    return Ok(Some(page_size));
+}
+
+// Reads the sort order of a paginated listing of K from the metadata entry with the given key. Listings are ordered by
+// id if the entry is missing, fails with invalid_argument if K can not be sorted by the requested field.
+pub fn metadata_sort_order<'de, K: DatabaseModel<'de>>(
+    metadata: &MetadataMap,
+    key: &str,
+) -> Result<SortOrder, tonic::Status> {
+    match metadata_str(metadata, key)? {
+        Some(value) => SortOrder::parse::<K>(value),
+        None => Ok(SortOrder::default()),
+    }
+}
+
+// Reads the view of a listing from the metadata entry with the given key. Listings return full entries if the entry is
+// missing, fails with invalid_argument for unknown views.
+pub fn metadata_list_view(metadata: &MetadataMap, key: &str) -> Result<ListView, tonic::Status> {
+    match metadata_str(metadata, key)? {
+        Some(value) => ListView::parse(value),
+        None => Ok(ListView::default()),
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http::HeaderMap;
+use http_body::Body;
+use tonic::body::BoxBody;
+
+/// Response body that has been read completely, it returns the data in a single frame followed by the trailers
+#[allow(dead_code)]
+pub struct BufferedBody {
+    data: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+#[allow(dead_code)]
+impl BufferedBody {
+    pub fn new(data: Bytes, trailers: Option<HeaderMap>) -> Self {
+        return BufferedBody {
+            data: Some(data),
+            trailers: trailers,
+        };
+    }
+
+    /// Reads the data and the trailers of the body
+    pub async fn read(mut body: BoxBody) -> Result<(Bytes, Option<HeaderMap>), tonic::Status> {
+        let mut data = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            data.put(chunk?);
+        }
+        let trailers = body.trailers().await?;
+
+        return Ok((data.freeze(), trailers));
+    }
+}
+
+impl Body for BufferedBody {
+    type Data = Bytes;
+    type Error = tonic::Status;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        return Poll::Ready(self.data.take().filter(|data| !data.is_empty()).map(Ok));
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        return Poll::Ready(Ok(self.trailers.take()));
+    }
+}
@@ -0,0 +1,126 @@
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::mpsc,
+};
+
+/// Starts a minimal HTTP server that answers every request with 200 OK
+/// The bodies of all received requests are forwarded to the returned receiver
+#[allow(dead_code)]
+pub async fn start_mock_http_server() -> (String, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = match listener.accept().await {
+                Ok(value) => value,
+                Err(_) => return,
+            };
+
+            let mut data = Vec::new();
+            let mut buffer = [0; 4096];
+            let body = loop {
+                let read = match socket.read(&mut buffer).await {
+                    Ok(0) | Err(_) => break None,
+                    Ok(value) => value,
+                };
+                data.extend_from_slice(&buffer[..read]);
+
+                let request = String::from_utf8_lossy(&data).to_string();
+                let header_end = match request.find("\r\n\r\n") {
+                    Some(value) => value + 4,
+                    None => continue,
+                };
+
+                let content_len = request[..header_end]
+                    .lines()
+                    .filter_map(|line| line.split_once(':'))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("content-length"))
+                    .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+
+                if data.len() >= header_end + content_len {
+                    break Some(request[header_end..].to_string());
+                }
+            };
+
+            let _ = socket
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await;
+
+            if let Some(body) = body {
+                let _ = sender.send(body);
+            }
+        }
+    });
+
+    return (format!("http://{}", addr), receiver);
+}
+
+/// Starts a minimal NATS server that accepts client connections and records published messages
+/// Subject and payload of all received PUB messages are forwarded to the returned receiver
+#[allow(dead_code)]
+pub async fn start_mock_nats_server() -> (String, mpsc::UnboundedReceiver<(String, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = match listener.accept().await {
+                Ok(value) => value,
+                Err(_) => return,
+            };
+
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let (read_half, mut write_half) = socket.into_split();
+                let mut reader = BufReader::new(read_half);
+
+                let info = format!(
+                    "INFO {{\"server_id\":\"mock\",\"version\":\"2.2.0\",\"go\":\"go1.16\",\"host\":\"{}\",\"port\":{},\"max_payload\":1048576,\"proto\":1}}\r\n",
+                    addr.ip(),
+                    addr.port()
+                );
+                if write_half.write_all(info.as_bytes()).await.is_err() {
+                    return;
+                }
+
+                loop {
+                    let mut line = String::new();
+                    match reader.read_line(&mut line).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(_) => {}
+                    }
+
+                    let parts: Vec<&str> = line.trim_end().split_whitespace().collect();
+                    match parts.first().map(|x| x.to_uppercase()).as_deref() {
+                        Some("PING") => {
+                            if write_half.write_all(b"PONG\r\n").await.is_err() {
+                                return;
+                            }
+                        }
+                        Some("PUB") => {
+                            let payload_len: usize = parts.last().unwrap().parse().unwrap();
+                            let mut payload = vec![0; payload_len + 2];
+                            if reader.read_exact(&mut payload).await.is_err() {
+                                return;
+                            }
+                            payload.truncate(payload_len);
+
+                            let _ = sender.send((
+                                parts[1].to_string(),
+                                String::from_utf8_lossy(&payload).to_string(),
+                            ));
+                        }
+                        _ => {}
+                    }
+                }
+            });
+        }
+    });
+
+    return (format!("nats://{}", addr), receiver);
+}
@@ -1 +1,3 @@
+pub mod body;
 pub mod init;
+pub mod mock_server;
@@ -0,0 +1,246 @@
+use prost::Message;
+use scienceobjectsdb_rust_api::sciobjectsdbapi::models::v1::Metadata;
+use scienceobjectsdb_rust_api::sciobjectsdbapi::services::v1::{
+    CreateObjectRequest, ReleaseDatasetVersionRequest,
+};
+
+use crate::{error::CoreError, SETTINGS};
+
+/// Type url of the BadRequest error details in the google.rpc.Status of an error
+pub const BAD_REQUEST_TYPE_URL: &str = "type.googleapis.com/google.rpc.BadRequest";
+
+/// Label key of a metadata entry that declares the schema of its content
+/// Entries labeled with the value json have to contain a valid JSON document
+pub const METADATA_SCHEMA_LABEL_KEY: &str = "metadata.schema";
+const JSON_METADATA_SCHEMA: &str = "json";
+
+/// Size limit of a single metadata entry if Validation.MaxMetadataEntrySize is not configured
+const DEFAULT_MAX_METADATA_ENTRY_SIZE: usize = 1024 * 1024;
+/// Size limit of all metadata entries of a single entity if Validation.MaxMetadataSize is not configured
+/// Stored documents are limited to 16MB by MongoDB, the metadata has to leave room for the remaining fields
+const DEFAULT_MAX_METADATA_SIZE: usize = 4 * 1024 * 1024;
+
+/// Invalid field of a request, mirrors google.rpc.BadRequest.FieldViolation
+/// The field is the path of the field within the request, e.g. objects[1].filename
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct FieldViolation {
+    #[prost(string, tag = "1")]
+    pub field: String,
+    #[prost(string, tag = "2")]
+    pub description: String,
+}
+
+/// Mirrors google.rpc.BadRequest
+#[derive(Clone, PartialEq, Message)]
+pub struct BadRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub field_violations: Vec<FieldViolation>,
+}
+
+/// Mirrors google.rpc.Status, gRPC clients read the error details of a status from it
+#[derive(Clone, PartialEq, Message)]
+pub struct RpcStatus {
+    #[prost(int32, tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub message: String,
+    #[prost(message, repeated, tag = "3")]
+    pub details: Vec<prost_types::Any>,
+}
+
+/// Encodes the field violations as the google.rpc.Status details of an error
+pub fn encode_status_details(
+    code: tonic::Code,
+    message: &str,
+    field_violations: &[FieldViolation],
+) -> Vec<u8> {
+    let bad_request = BadRequest {
+        field_violations: field_violations.to_vec(),
+    };
+
+    let rpc_status = RpcStatus {
+        code: code as i32,
+        message: message.to_string(),
+        details: vec![prost_types::Any {
+            type_url: BAD_REQUEST_TYPE_URL.to_string(),
+            value: bad_request.encode_to_vec(),
+        }],
+    };
+
+    return rpc_status.encode_to_vec();
+}
+
+/// Decodes the field violations from the details of a status, details that can not be decoded are ignored
+pub fn decode_field_violations(details: &[u8]) -> Vec<FieldViolation> {
+    let rpc_status = match RpcStatus::decode(details) {
+        Ok(value) => value,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut field_violations = Vec::new();
+    for detail in rpc_status.details {
+        if detail.type_url != BAD_REQUEST_TYPE_URL {
+            continue;
+        }
+
+        if let Ok(bad_request) = BadRequest::decode(detail.value.as_slice()) {
+            field_violations.extend(bad_request.field_violations);
+        }
+    }
+
+    return field_violations;
+}
+
+/// Collects all invalid fields of a request, so they can be returned together instead of failing on the first one
+#[derive(Debug, Default)]
+pub struct FieldViolations {
+    violations: Vec<FieldViolation>,
+}
+
+impl FieldViolations {
+    pub fn new() -> Self {
+        return FieldViolations::default();
+    }
+
+    pub fn add(&mut self, field: String, description: &str) {
+        self.violations.push(FieldViolation {
+            field: field,
+            description: description.to_string(),
+        });
+    }
+
+    /// Returns an invalid fields error with all collected violations, if there are any
+    pub fn into_result(self) -> Result<(), CoreError> {
+        if self.violations.is_empty() {
+            return Ok(());
+        }
+
+        let fields: Vec<String> = self
+            .violations
+            .iter()
+            .map(|violation| format!("{}: {}", violation.field, violation.description))
+            .collect();
+
+        return Err(CoreError::InvalidFields(
+            format!("invalid request: {}", fields.join(", ")),
+            self.violations,
+        ));
+    }
+}
+
+/// Size limits of the metadata of a single entity, e.g. a dataset or an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataLimits {
+    pub max_entry_size: usize,
+    pub max_size: usize,
+}
+
+impl MetadataLimits {
+    /// Reads the limits from Validation.MaxMetadataEntrySize and Validation.MaxMetadataSize in bytes
+    pub fn from_config() -> Self {
+        let settings = SETTINGS.read().unwrap();
+        let max_entry_size = settings
+            .get_int("Validation.MaxMetadataEntrySize")
+            .map(|value| value.max(0) as usize)
+            .unwrap_or(DEFAULT_MAX_METADATA_ENTRY_SIZE);
+        let max_size = settings
+            .get_int("Validation.MaxMetadataSize")
+            .map(|value| value.max(0) as usize)
+            .unwrap_or(DEFAULT_MAX_METADATA_SIZE);
+
+        return MetadataLimits {
+            max_entry_size: max_entry_size,
+            max_size: max_size,
+        };
+    }
+}
+
+/// Validates the metadata entries of a single entity, field_path is the path of the metadata within the request
+/// Metadata is stored as text and has to be valid UTF-8, entries with a json schema label have to be valid JSON
+pub fn validate_metadata(
+    metadata: &[Metadata],
+    field_path: &str,
+    violations: &mut FieldViolations,
+) {
+    let limits = MetadataLimits::from_config();
+
+    let mut size = 0;
+    for (index, entry) in metadata.iter().enumerate() {
+        let entry_path = format!("{}[{}].metadata", field_path, index);
+        size += entry.metadata.len();
+        if entry.metadata.len() > limits.max_entry_size {
+            violations.add(
+                entry_path,
+                format!(
+                    "metadata entry must not be larger than {} bytes",
+                    limits.max_entry_size
+                )
+                .as_str(),
+            );
+            continue;
+        }
+
+        if std::str::from_utf8(entry.metadata.as_slice()).is_err() {
+            violations.add(entry_path, "metadata must be valid UTF-8");
+            continue;
+        }
+
+        let is_json = entry.labels.iter().any(|label| {
+            label.key == METADATA_SCHEMA_LABEL_KEY && label.value == JSON_METADATA_SCHEMA
+        });
+        if is_json && serde_json::from_slice::<serde_json::Value>(&entry.metadata).is_err() {
+            violations.add(entry_path, "metadata must be a valid JSON document");
+        }
+    }
+
+    if size > limits.max_size {
+        violations.add(
+            field_path.to_string(),
+            format!("metadata must not be larger than {} bytes", limits.max_size).as_str(),
+        );
+    }
+}
+
+/// Validates the objects of a create request, field_path is the path of the objects within the request
+pub fn validate_create_objects(
+    objects: &[CreateObjectRequest],
+    field_path: &str,
+    violations: &mut FieldViolations,
+) {
+    for (index, object) in objects.iter().enumerate() {
+        validate_metadata(
+            &object.metadata,
+            format!("{}[{}].metadata", field_path, index).as_str(),
+            violations,
+        );
+
+        if object.filename.is_empty() {
+            violations.add(
+                format!("{}[{}].filename", field_path, index),
+                "filename must not be empty",
+            );
+        }
+
+        if object.content_len < 0 {
+            violations.add(
+                format!("{}[{}].content_len", field_path, index),
+                "content length must not be negative",
+            );
+        }
+    }
+}
+
+pub fn validate_release_request(request: &ReleaseDatasetVersionRequest) -> Result<(), CoreError> {
+    let mut violations = FieldViolations::new();
+    if request.dataset_id.is_empty() {
+        violations.add("dataset_id".to_string(), "dataset id must not be empty");
+    }
+
+    if request.version.is_none() {
+        violations.add("version".to_string(), "version is required");
+    }
+
+    validate_metadata(&request.metadata, "metadata", &mut violations);
+
+    return violations.into_result();
+}